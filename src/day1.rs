@@ -1,16 +1,25 @@
 // vim: set ai et ts=4 sts=4 sw=4:
 use crate::util;
+use clap::ArgMatches;
+use rayon::prelude::*;
 
-pub fn main() {
-    let input = util::file_read_i64s("input/day1.txt");
-    part1(&input);
-    part2(&input);
+pub fn main(args: &ArgMatches) {
+    // stream the masses straight from the file rather than collecting them first; once per part
+    let masses = || util::stream_parsed::<i64>("input/day1.txt").map(|m| m.unwrap());
+    if args.is_present("parallel") {
+        let masses: Vec<i64> = masses().collect();
+        println!("{}", total_fuel_parallel(&masses));
+        println!("{}", total_extended_fuel_parallel(&masses));
+    } else {
+        println!("{}", total_fuel(masses()));
+        println!("{}", total_extended_fuel(masses()));
+    }
 }
 
-fn fuel_needed(mass: i64) -> i64 {
+pub fn fuel_needed(mass: i64) -> i64 {
     mass/3-2
 }
-fn extended_fuel_needed(mass: i64) -> i64 {
+pub fn extended_fuel_needed(mass: i64) -> i64 {
     let mut total_fuel = fuel_needed(mass);
     let mut extra_fuel = total_fuel;
     loop {
@@ -22,16 +31,29 @@ fn extended_fuel_needed(mass: i64) -> i64 {
     total_fuel
 }
 
-fn part1(input: &Vec<i64>) {
-    println!("{}", input.iter().map(|&m| fuel_needed(m)).sum::<i64>());
+pub fn total_fuel<I>(masses: I) -> i64
+    where I: IntoIterator<Item=i64>
+{
+    masses.into_iter().map(fuel_needed).sum()
+}
+pub fn total_extended_fuel<I>(masses: I) -> i64
+    where I: IntoIterator<Item=i64>
+{
+    masses.into_iter().map(extended_fuel_needed).sum()
+}
+
+// parallel versions of the above; each worker folds its share of the masses into a partial sum
+pub fn total_fuel_parallel(masses: &[i64]) -> i64 {
+    masses.par_iter().fold(|| 0, |acc, &m| acc + fuel_needed(m)).sum()
 }
-fn part2(input: &Vec<i64>) {
-    println!("{}", input.iter().map(|&m| extended_fuel_needed(m)).sum::<i64>());
+pub fn total_extended_fuel_parallel(masses: &[i64]) -> i64 {
+    masses.par_iter().fold(|| 0, |acc, &m| acc + extended_fuel_needed(m)).sum()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::time::Instant;
 
     #[test]
     fn examples_1() {
@@ -47,4 +69,38 @@ mod tests {
         assert_eq!(extended_fuel_needed(1969), 966);
         assert_eq!(extended_fuel_needed(100756), 50346);
     }
+
+    #[test]
+    fn totals() {
+        assert_eq!(total_fuel(vec![12, 14, 1969, 100756]), 2+2+654+33583);
+        assert_eq!(total_extended_fuel([14, 1969, 100756].iter().cloned()), 2+966+50346);
+        assert_eq!(total_fuel(Vec::new()), 0);
+    }
+
+    #[test]
+    fn parallel() {
+        let masses = vec![12, 14, 1969, 100756];
+        assert_eq!(total_fuel_parallel(&masses), total_fuel(masses.clone()));
+        assert_eq!(total_extended_fuel_parallel(&masses), total_extended_fuel(masses.clone()));
+        assert_eq!(total_fuel_parallel(&[]), 0);
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_parallel() {
+        // run with: cargo test --release -- --ignored bench_parallel --nocapture
+        let masses: Vec<i64> = (0..20_000_000i64).map(|i| 1000 + (i * 7919) % 1_000_000).collect();
+
+        let start = Instant::now();
+        let serial = total_extended_fuel(masses.iter().cloned());
+        let serial_time = start.elapsed();
+
+        let start = Instant::now();
+        let parallel = total_extended_fuel_parallel(&masses);
+        let parallel_time = start.elapsed();
+
+        println!("{} masses: serial {:?}, parallel {:?} ({} threads)",
+                 masses.len(), serial_time, parallel_time, rayon::current_num_threads());
+        assert_eq!(serial, parallel);
+    }
 }
@@ -1,9 +1,15 @@
 // vim: set ai et ts=4 sts=4 sw=4:
 use crate::util::{gcd, file_read_lines, manhattan_distance};
+use crate::frames::{Frame, FrameRecorder};
+use clap::ArgMatches;
 use std::convert::From;
-use std::collections::HashMap;
-use std::f64::consts::PI;
+use rayon::prelude::*;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{HashMap, HashSet};
 use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::time::Duration;
 
 #[derive(PartialEq, Eq, Debug, Clone, Copy)]
 enum Status {
@@ -47,12 +53,25 @@ impl Dir {
             Dir { dx: self.dx/gcd, dy: self.dy/gcd }
         }
     }
-    fn angle(&self) -> f64 {
-        // clockwise angle relative to the 'up' direction
-        let rad = (-self.dy as f64).atan2(self.dx as f64); // -y because in our coord system the Y axis points down but atan2 expects it to point up
-        let rad = PI/2f64 - rad; // shift to an offset from the vertical axis
-        let rad = if rad < 0f64 { 2f64*PI + rad } else { rad }; // correct the negative part to a positive one (so that it 'goes around')
-        return rad;
+    fn quadrant(&self) -> u32 {
+        // quadrants in clockwise order starting from the 'up' direction (note that the Y axis points down in our
+        // coord system). each one includes the axis direction it starts at, but not the one it ends at.
+        match (self.dx.signum(), self.dy.signum()) {
+            (0, 0)                         => panic!("the (0,0) direction has no quadrant"),
+            (dx, dy) if dx >= 0 && dy < 0  => 0, // up to right
+            (dx, dy) if dx > 0  && dy >= 0 => 1, // right to down
+            (dx, dy) if dx <= 0 && dy > 0  => 2, // down to left
+            _                              => 3, // left to up
+        }
+    }
+    fn clockwise_cmp(&self, other: &Dir) -> Ordering {
+        // orders directions by their clockwise angle relative to the 'up' direction, without any floating point
+        // math: within the same quadrant, the angle between two directions is less than 90 degrees, so the sign
+        // of their cross product tells us which one comes first. directions along the same line compare equal.
+        self.quadrant().cmp(&other.quadrant()).then_with(|| {
+            let cross = (self.dx as i64)*(other.dy as i64) - (self.dy as i64)*(other.dx as i64);
+            0.cmp(&cross)
+        })
     }
 }
 
@@ -71,53 +90,18 @@ impl fmt::Display for Pos {
         write!(f, "(x={},y={})", self.x, self.y)
     }
 }
-struct Asteroid {
-    pub pos: Pos,
-    pub direction_map: HashMap<Dir, Vec<Pos>>, // maps direction to list of other asteroids along that direction
-}
-impl Asteroid {
-    pub fn new(x: i32, y: i32) -> Self {
-        Self {
-            pos: Pos::new(x,y),
-            direction_map: HashMap::new(),
-        }
-    }
-    pub fn record_asteroid_in_direction(&mut self, dir: &Dir, other_pos: Pos) {
-        let ndir = dir.normalized();
-        if !self.direction_map.contains_key(&ndir) {
-            self.direction_map.insert(ndir.clone(), Vec::new());
-        }
-        self.direction_map.get_mut(&ndir).unwrap()
-                          .push(other_pos);
-    }
-    pub fn sort_other_asteroids(&mut self) {
-        let pos_tuple = (self.pos.x, self.pos.y);
-        for (_, positions) in self.direction_map.iter_mut() {
-            positions.sort_by_key(|other| manhattan_distance(pos_tuple, (other.x, other.y)));
-        }
-    }
-}
-impl fmt::Display for Asteroid {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "A{}", self.pos)
-    }
-}
-
 struct Map {
     pub w: usize,
     pub h: usize,
-    pub asteroids: HashMap<Pos, Asteroid>,
+    pub asteroids: Vec<Pos>,
 }
 impl Map {
     pub fn new(lines: &Vec<String>) -> Self {
-        let mut asteroids = HashMap::new();
+        let mut asteroids = Vec::new();
         for (y, line) in lines.iter().enumerate() {
             for (x, c) in line.chars().enumerate() {
                 if c == '#' {
-                    asteroids.insert(
-                        Pos::new(x as i32, y as i32),
-                        Asteroid::new(x as i32, y as i32)
-                    );
+                    asteroids.push(Pos::new(x as i32, y as i32));
                 }
             }
         }
@@ -127,94 +111,252 @@ impl Map {
             asteroids,
         }
     }
-    pub fn asteroid_at_mut(&mut self, pos: &Pos) -> &mut Asteroid {
-        self.asteroids.get_mut(pos).unwrap()
-    }
-    pub fn compute_directions(&mut self) {
-        // for each asteroid (identified by (x,y) coords), find the directions to all other asteroids
-        // on the map. store each other asteroid in a list keyed by the normalized direction along which it lies.
-        let mut tuples: Vec<(Pos, Pos, Dir)> = Vec::new();
-        for a in self.asteroids.values() {
-            for o in self.asteroids.values().filter(|x| x.pos != a.pos) {
-                let dir = Dir { dx: o.pos.x - a.pos.x,
-                                dy: o.pos.y - a.pos.y };
-                //a.record_asteroid_in_direction(&dir, o);
-                tuples.push((a.pos.clone(), o.pos.clone(), dir));
-            }
-        }
-
-        for (a_pos, o_pos, dir) in tuples {
-            self.asteroid_at_mut(&a_pos).record_asteroid_in_direction(&dir, o_pos);
+    pub fn visible_from(&self, pos: Pos) -> usize {
+        // amount of other asteroids in direct line of sight, i.e. the amount of unique (normalized) directions
+        // to other asteroids
+        self.asteroids.iter()
+                      .filter(|&&o| o != pos)
+                      .map(|o| Dir { dx: o.x - pos.x, dy: o.y - pos.y }.normalized())
+                      .collect::<HashSet<Dir>>()
+                      .len()
+    }
+    pub fn directions_from(&self, pos: Pos) -> HashMap<Dir, Vec<Pos>> {
+        // all other asteroids on the map, keyed by the normalized direction along which they lie as seen from the
+        // given position, and sorted by their distance away from it
+        let mut direction_map: HashMap<Dir, Vec<Pos>> = HashMap::new();
+        for &o in self.asteroids.iter().filter(|&&o| o != pos) {
+            let dir = Dir { dx: o.x - pos.x, dy: o.y - pos.y };
+            direction_map.entry(dir.normalized()).or_default().push(o);
         }
-
-        // sort each asteroid's list of other asteroids in a direction by its distance away from it
-        for a in self.asteroids.values_mut() {
-            a.sort_other_asteroids();
+        for others in direction_map.values_mut() {
+            others.sort_by_key(|other| manhattan_distance((pos.x, pos.y), (other.x, other.y)));
         }
+        direction_map
     }
     #[allow(unused)]
     pub fn display(&self) -> String {
+        let asteroids: HashSet<Pos> = self.asteroids.iter().cloned().collect();
         let mut result = String::new();
         for y in 0..self.h {
             for x in 0..self.w {
                 let pos = Pos::new(x as i32, y as i32);
-                result.push_str(match self.asteroids.get(&pos) {
-                    None    => ". ",
-                    Some(_) => "# ",
-                });
+                result.push_str(if asteroids.contains(&pos) { "# " } else { ". " });
             }
             result.push_str("\n");
         }
         return result;
     }
 }
-pub fn main() {
+pub fn main(args: &ArgMatches) {
     let lines = file_read_lines("input/day10.txt");
-    let mut map = Map::new(&lines);
-    solve(&mut map);
-}
+    let map = Map::new(&lines);
 
-fn solve(map: &mut Map) {
-    map.compute_directions();
+    let (station, visible) = best_station(&map).expect("no asteroids on the map");
+    println!("{}", visible);
+    match nth_destroyed(&map, station, 200) {
+        Some(pos) => println!("{}", pos.x*100 + pos.y),
+        None      => println!("no solution, fewer than 200 asteroids destroyed from position {}", station),
+    }
 
-    // find which asteroids has the most unique (normalized) directions to other asteroids
-    #[allow(unused_assignments)]
-    let mut station_pos = Pos{x:-1,y:-1};
-    {
-        let station = map.asteroids.values()
-                                   .max_by_key(|a| a.direction_map.len())
-                                   .unwrap();
-        station_pos = station.pos.clone();
-        println!("{}", station.direction_map.len());
+    if args.is_present("render") || args.is_present("gif") {
+        let recorder = record_sweep(&map, station);
+        if args.is_present("render") {
+            recorder.play(&mut io::stdout(), Duration::from_millis(30)).unwrap();
+        }
+        if let Some(filename) = args.value_of("gif") {
+            let scale = args.value_of("scale").map_or(10, |s| s.parse().unwrap_or_else(|_| panic!("invalid scale: {}", s)));
+            File::create(filename).and_then(|f| recorder.write_gif(BufWriter::new(f), scale, Duration::from_millis(50), sweep_color))
+                                  .unwrap_or_else(|err| panic!("failed to write {}: {}", filename, err));
+        }
     }
+}
 
-    // from that location, determine the order of its unique directions in clockwise order
+fn best_station(map: &Map) -> Option<(Pos, usize)> {
+    // find which asteroid can see the most other asteroids, and how many that is. ties go to the topmost one, then
+    // the leftmost one, so the result doesn't depend on the order the work is spread over the threads in.
+    map.asteroids.par_iter()
+                 .map(|&pos| (pos, map.visible_from(pos)))
+                 .max_by_key(|&(pos, visible)| (visible, Reverse((pos.y, pos.x))))
+}
+
+fn vaporization_order(map: &Map, station: Pos) -> Vec<Pos> {
+    // from the station, determine the order of its unique directions in clockwise order
     // starting from the up direction. at each direction in turn, eliminate the closest asteroid
     // along that direction. repeat until the list of asteroids in all directions is empty.
-    let station: &mut Asteroid = map.asteroids.get_mut(&station_pos).unwrap();
-    let mut dir_order: Vec<Dir> = station.direction_map.keys().map(|&k| k).clone().collect();
-    dir_order.sort_by(|a,b| a.angle().partial_cmp(&b.angle()).unwrap());
+    let mut direction_map = map.directions_from(station);
+    let mut dir_order: Vec<Dir> = direction_map.keys().cloned().collect();
+    dir_order.sort_by(|a,b| a.clockwise_cmp(b));
 
+    // remaining asteroids in each direction, closest last so we can pop them off
+    let mut remaining: Vec<Vec<Pos>> = dir_order.iter()
+                                                .map(|dir| { let mut others = direction_map.remove(dir).unwrap();
+                                                             others.reverse();
+                                                             others })
+                                                .collect();
     let mut popped: Vec<Pos> = Vec::new(); // positions of asteroids destroyed so far
-    loop {
-        // visit each direction in order, and pop the first asteroid along that direction
-        // (already sorted by distance)
-        for dir in &dir_order {
-            let others_in_dir = station.direction_map.get_mut(dir).unwrap();
-            if others_in_dir.len() == 0 {
-                continue; // no more asteroids in this direction, move on to next one
-            }
-            popped.push(others_in_dir.remove(0));
-        }
-        if dir_order.iter().all(|&dir| station.direction_map.get(&dir).unwrap().len() == 0) {
-            break; // no more asteroids to destroy
+    while popped.len() < map.asteroids.len() - 1 {
+        // visit each direction in order, and pop the first asteroid along that direction (if any are left)
+        popped.extend(remaining.iter_mut().filter_map(|others_in_dir| others_in_dir.pop()));
+    }
+    popped
+}
+
+fn nth_destroyed(map: &Map, station: Pos, n: usize) -> Option<Pos> {
+    // position of the n-th asteroid to be vaporized from the station (counting from 1)
+    if n == 0 {
+        return None;
+    }
+    vaporization_order(map, station).get(n-1).cloned()
+}
+
+fn record_sweep(map: &Map, station: Pos) -> FrameRecorder {
+    // animation of the laser sweep: one frame for each asteroid being vaporized, with the laser beam pointing at it
+    let mut recorder = FrameRecorder::new();
+    let mut frame = Frame::new(map.w, map.h, '.');
+    for pos in &map.asteroids {
+        frame.set(pos.x as usize, pos.y as usize, '#');
+    }
+    frame.set(station.x as usize, station.y as usize, 'S');
+    recorder.record(frame.clone());
+
+    for target in vaporization_order(map, station) {
+        let dir = Dir { dx: target.x - station.x, dy: target.y - station.y }.normalized();
+        let beam = match (dir.dx.abs(), dir.dy.abs()) {
+            (dx, dy) if dx > 2*dy     => '-',
+            (dx, dy) if dy > 2*dx     => '|',
+            _ if (dir.dx < 0) == (dir.dy < 0) => '\\',
+            _                         => '/',
+        };
+        let mut beam_frame = frame.clone();
+        let mut pos = Pos::new(station.x + dir.dx, station.y + dir.dy);
+        while pos != target {
+            beam_frame.set(pos.x as usize, pos.y as usize, beam);
+            pos = Pos::new(pos.x + dir.dx, pos.y + dir.dy);
         }
+        beam_frame.set(target.x as usize, target.y as usize, '*');
+        recorder.record(beam_frame);
+        frame.set(target.x as usize, target.y as usize, '.');
     }
+    recorder.record(frame);
+    recorder
+}
 
-    if popped.len() < 200 {
-        println!("no solution, fewer than 200 asteroids destroyed from position {}", station);
-    } else {
-        println!("{}", popped[199].x*100 + popped[199].y);
+fn sweep_color(c: char) -> [u8; 3] {
+    match c {
+        '#' => [160, 160, 160],
+        'S' => [0, 200, 0],
+        '*' => [255, 40, 40],
+        '.' => [0, 0, 0],
+        _   => [255, 220, 0], // laser beam
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map_of(rows: &[&str]) -> Map {
+        Map::new(&rows.iter().map(|row| row.to_string()).collect())
+    }
+
+    #[test]
+    fn examples() {
+        let map = map_of(&[".#..#",
+                           ".....",
+                           "#####",
+                           "....#",
+                           "...##"]);
+        assert_eq!(best_station(&map), Some((Pos::new(3,4), 8)));
+
+        let map = map_of(&["......#.#.",
+                           "#..#.#....",
+                           "..#######.",
+                           ".#.#.###..",
+                           ".#..#.....",
+                           "..#....#.#",
+                           "#..#....#.",
+                           ".##.#..###",
+                           "##...#..#.",
+                           ".#....####"]);
+        assert_eq!(best_station(&map), Some((Pos::new(5,8), 33)));
+
+        let map = map_of(&[".#....#####...#..",
+                           "##...##.#####..##",
+                           "##...#...#.#####.",
+                           "..#.....#...###..",
+                           "..#.#.....#....##"]);
+        let station = Pos::new(8,3);
+        let order = vaporization_order(&map, station);
+        assert_eq!(order[..9], [Pos::new(8,1), Pos::new(9,0), Pos::new(9,1), Pos::new(10,0), Pos::new(9,2),
+                                Pos::new(11,1), Pos::new(12,1), Pos::new(11,2), Pos::new(15,1)]);
+        assert_eq!(order.len(), map.asteroids.len() - 1);
+        assert_eq!(nth_destroyed(&map, station, 1), Some(Pos::new(8,1)));
+        assert_eq!(nth_destroyed(&map, station, order.len()), order.last().cloned());
+        assert_eq!(nth_destroyed(&map, station, order.len() + 1), None);
+        assert_eq!(nth_destroyed(&map, station, 0), None);
+
+        assert_eq!(best_station(&map_of(&["..."])), None);
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_large_field() {
+        // run with: cargo test --release -- --ignored bench_large_field --nocapture
+        use std::time::Instant;
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        let rows: Vec<String> = (0..1000).map(|_| (0..1000).map(|_| {
+            // xorshift; roughly one in 100 positions holds an asteroid
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            if seed.is_multiple_of(100) { '#' } else { '.' }
+        }).collect()).collect();
+        let map = Map::new(&rows);
+
+        let start = Instant::now();
+        let (station, visible) = best_station(&map).unwrap();
+        let best_time = start.elapsed();
+        let start = Instant::now();
+        let order = vaporization_order(&map, station);
+        let order_time = start.elapsed();
+
+        println!("{} asteroids: best station {} sees {} ({:?}), vaporization order {:?} ({} threads)",
+                 map.asteroids.len(), station, visible, best_time, order_time, rayon::current_num_threads());
+        assert_eq!(order.len(), map.asteroids.len() - 1);
+    }
+
+    #[test]
+    fn sweep_animation() {
+        let map = map_of(&["#.#",
+                           ".#.",
+                           "#.."]);
+        let frames: Vec<String> = record_sweep(&map, Pos::new(0,2)).frames().iter().map(|f| f.to_string()).collect();
+        assert_eq!(frames, vec!["#.#\n.#.\nS..\n",
+                                "*.#\n|#.\nS..\n",  // straight up first
+                                "..#\n.*.\nS..\n",  // then the closest one in the diagonal direction ...
+                                "..*\n./.\nS..\n",  // ... and the one behind it on the next rotation
+                                "...\n...\nS..\n"]);
+    }
+
+    #[test]
+    fn clockwise_order() {
+        let dir = |dx, dy| Dir { dx, dy };
+        let expected = vec![dir(0,-1), dir(1,-2), dir(1,-1), dir(2,-1), dir(1,0), dir(2,1), dir(1,1), dir(1,2),
+                            dir(0,1), dir(-1,2), dir(-1,1), dir(-2,1), dir(-1,0), dir(-2,-1), dir(-1,-1), dir(-1,-2)];
+        let mut dirs = expected.clone();
+        dirs.reverse();
+        dirs.sort_by(|a,b| a.clockwise_cmp(b));
+        assert_eq!(dirs, expected);
+
+        // only the direction matters, not the distance
+        assert_eq!(dir(3,-3).clockwise_cmp(&dir(1,-1)), Ordering::Equal);
+        assert_eq!(dir(0,-5).clockwise_cmp(&dir(0,-1)), Ordering::Equal);
+        assert_eq!(dir(0,5).clockwise_cmp(&dir(0,-1)), Ordering::Greater);
+
+        // nearly collinear directions are still told apart exactly, including at the far ends of the range
+        assert_eq!(dir(100_000,-99_999).clockwise_cmp(&dir(99_999,-99_998)), Ordering::Less);
+        assert_eq!(dir(99_999,-99_998).clockwise_cmp(&dir(100_000,-99_999)), Ordering::Greater);
+        assert_eq!(dir(i32::MAX,-1).clockwise_cmp(&dir(i32::MAX-1,-1)), Ordering::Greater);
+        assert_eq!(dir(-1,i32::MIN+1).clockwise_cmp(&dir(-1,i32::MIN+2)), Ordering::Greater);
+    }
+}
@@ -1,42 +1,106 @@
 // vim: set ai et ts=4 sts=4 sw=4:
 use crate::util;
 use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::time::Duration;
 use crate::intcode::{CPU};
+use crate::ocr;
+use crate::frames::FrameRecorder;
+use crate::grid::{Bounds, SparseGrid};
+use clap::ArgMatches;
 
 #[derive(PartialEq, Eq, Copy, Clone, Debug, Hash)]
-enum Facing {
+pub enum Facing {
     Up,
     Down,
     Left,
     Right,
 }
 #[derive(PartialEq, Eq, Copy, Clone, Debug, Hash)]
-struct Pos {
-    pub x: i32,
-    pub y: i32,
+pub struct Pos {
+    pub x: i64,
+    pub y: i64,
+}
+impl Facing {
+    pub fn left(&self) -> Self {
+        match self {
+            Facing::Up    => Facing::Left,
+            Facing::Left  => Facing::Down,
+            Facing::Down  => Facing::Right,
+            Facing::Right => Facing::Up,
+        }
+    }
 }
 impl Pos {
-    pub fn up(&self)    -> Self { Self { x: self.x, y: self.y+1 } }
-    pub fn down(&self)  -> Self { Self { x: self.x, y: self.y-1 } }
+    // the Y axis points down, same as in the paint map
+    pub fn up(&self)    -> Self { Self { x: self.x, y: self.y-1 } }
+    pub fn down(&self)  -> Self { Self { x: self.x, y: self.y+1 } }
     pub fn left(&self)  -> Self { Self { x: self.x-1, y: self.y } }
     pub fn right(&self) -> Self { Self { x: self.x+1, y: self.y } }
 }
 
-struct Robot {
+#[derive(Debug, Clone)]
+pub struct RobotConfig {
+    pub start_color: Option<i64>, // color of the starting panel; unpainted (and therefore black) if none
+    pub start_pos: Pos,
+    pub start_facing: Facing,
+    pub max_steps: Option<usize>, // stop after this many paint operations, even if the program hasn't halted yet
+}
+impl Default for RobotConfig {
+    fn default() -> Self {
+        Self {
+            start_color: None,
+            start_pos: Pos { x: 0, y: 0 },
+            start_facing: Facing::Up,
+            max_steps: None,
+        }
+    }
+}
+
+pub struct Robot {
     cpu: CPU,
     pos: Pos,
     facing: Facing,
-    paint_map: HashMap<Pos, i64>,
+    paint_map: SparseGrid<i64>,
+    steps: usize,
+    max_steps: Option<usize>,
 }
 impl Robot {
     pub fn new(program: &Vec<i64>) -> Self {
+        Self::with_config(program, &RobotConfig::default())
+    }
+    pub fn with_config(program: &Vec<i64>, config: &RobotConfig) -> Self {
+        let mut paint_map = SparseGrid::new();
+        if let Some(color) = config.start_color {
+            paint_map.set(config.start_pos.x, config.start_pos.y, color);
+        }
         Self {
             cpu: CPU::new(program),
-            pos: Pos { x:0, y:0 },
-            facing: Facing::Up,
-            paint_map: HashMap::new(),
+            pos: config.start_pos,
+            facing: config.start_facing,
+            paint_map,
+            steps: 0,
+            max_steps: config.max_steps,
         }
     }
+    pub fn pos(&self) -> Pos {
+        self.pos
+    }
+    pub fn facing(&self) -> Facing {
+        self.facing
+    }
+    pub fn steps(&self) -> usize {
+        // amount of paint operations done so far
+        self.steps
+    }
+    pub fn is_halted(&self) -> bool {
+        self.cpu.is_halted()
+    }
+    pub fn paint_map(&self) -> &SparseGrid<i64> {
+        &self.paint_map
+    }
     pub fn turn(&mut self, dir: u32) { // dir 0 is turn left, 1 is right
         match self.facing {
             Facing::Up    => { self.facing = if dir == 0 { Facing::Left } else { Facing::Right };
@@ -53,65 +117,282 @@ impl Robot {
                              }
         }
     }
-    pub fn run(&mut self) {
-        loop {
+    pub fn run(&mut self) -> &SparseGrid<i64> {
+        // runs until the program halts or the maximum amount of steps is reached, and returns the paint map
+        self.run_with(|_, _, _| {});
+        &self.paint_map
+    }
+    pub fn run_with<F>(&mut self, mut on_paint: F)
+        where F: FnMut(&Robot, Pos, i64)
+    {
+        // same as run, but calls on_paint after every paint operation (and the turn that follows it) with the
+        // robot, the position of the panel that was just painted and the color it was painted
+        while self.max_steps.is_none_or(|max| self.steps < max) {
             // send current panel color as input
-            let current_panel_color = self.paint_map.get(&self.pos).unwrap_or(&0i64); // default to black
+            let current_panel_color = self.paint_map.get(self.pos.x, self.pos.y).unwrap_or(&0i64); // default to black
             self.cpu.send_input(*current_panel_color);
             self.cpu.run(); // let CPU run for a while until it halts or needs more input
-            if self.cpu.is_halted() {
+            if self.is_halted() {
                 break;
             }
             // if we didn't halt, the program is supposed to have produced output
             let new_color = self.cpu.consume_output().unwrap();
             let turn_dir = self.cpu.consume_output().unwrap();
 
-            self.paint_map.insert(self.pos.clone(), new_color);
+            let painted = self.pos;
+            self.paint_map.set(painted.x, painted.y, new_color);
             self.turn(turn_dir as u32);
+            self.steps += 1;
+            on_paint(self, painted, new_color);
         }
     }
     pub fn visualize_map(&self) -> String {
-        let mut result = String::new();
-        // determine the max extents of the painted area
-        let min_x = self.paint_map.keys().map(|&pos| pos.x).min().unwrap();
-        let max_x = self.paint_map.keys().map(|&pos| pos.x).max().unwrap();
-        let min_y = self.paint_map.keys().map(|&pos| pos.y).min().unwrap();
-        let max_y = self.paint_map.keys().map(|&pos| pos.y).max().unwrap();
-
-        let w = (max_x - min_x) + 1;
-        let h = (max_y - min_y) + 1;
-        for y in 0..h {
-            for x in 0..w {
-                let pos = Pos { x: min_x + x, y: max_y - y }; // max_y - y because the Y axis points up in our coord system
-                let color = self.paint_map.get(&pos).unwrap_or(&0); // default to 0
-                result.push_str(match color {
-                    0 => " ",
-                    1 => "#",
-                    _ => panic!("invalid color: {}", color),
-                });
-            }
-            result.push_str("\n");
+        match self.paint_map.bounds() {
+            Some(bounds) => self.paint_map.render(&bounds, |color| match color {
+                                None | Some(0) => ' ',
+                                Some(1)        => '#',
+                                Some(color)    => panic!("invalid color: {}", color),
+                            }).to_string(),
+            None         => String::new(),
         }
-        return result;
     }
 }
 
-pub fn main() {
+pub fn main(args: &ArgMatches) {
     let line: String = util::file_read_lines("input/day11.txt").into_iter().next().unwrap();
     let program: Vec<i64> = line.split(",").map(|s| s.parse().unwrap()).collect();
-    part1(&program);
-    part2(&program);
+    println!("{}", part1(&program));
+    let robot = part2(&program);
+    if args.is_present("verbose") {
+        print!("{}", robot.visualize_map());
+    }
+    println!("{}", read_identifier(robot.paint_map()).unwrap_or_else(|err| panic!("can't read the identifier: {}", err)));
+
+    if args.is_present("analyze") {
+        let part1_report = analyze(&program, &RobotConfig::default());
+        let part2_report = analyze(&program, &RobotConfig { start_color: Some(1), ..Default::default() });
+        if args.is_present("json") {
+            println!("{{\"part1\":{},\"part2\":{}}}", part1_report.to_json(), part2_report.to_json());
+        } else {
+            print!("part 1:\n{}part 2:\n{}", part1_report, part2_report);
+        }
+    }
+
+    if args.is_present("render") || args.is_present("gif") {
+        let recorder = record_painting(&program);
+        if args.is_present("render") {
+            recorder.play(&mut io::stdout(), Duration::from_millis(20)).unwrap();
+        }
+        if let Some(filename) = args.value_of("gif") {
+            let scale = args.value_of("scale").map_or(10, |s| s.parse().unwrap_or_else(|_| panic!("invalid scale: {}", s)));
+            File::create(filename).and_then(|f| recorder.write_gif(BufWriter::new(f), scale, Duration::from_millis(30), hull_color))
+                                  .unwrap_or_else(|err| panic!("failed to write {}: {}", filename, err));
+        }
+    }
 }
 
-fn part1(program: &Vec<i64>) {
-    let mut robot = Robot::new(program);
-    robot.run();
-    println!("{}", robot.paint_map.len());
+fn part1(program: &Vec<i64>) -> usize {
+    Robot::new(program).run().len()
 }
 
-fn part2(program: &Vec<i64>) {
-    let mut robot = Robot::new(program);
-    robot.paint_map.insert(robot.pos.clone(), 1i64); // start on a white panel this time
+fn part2(program: &Vec<i64>) -> Robot {
+    // start on a white panel this time
+    let mut robot = Robot::with_config(program, &RobotConfig { start_color: Some(1), ..Default::default() });
     robot.run();
-    println!("{}", robot.visualize_map());
+    robot
+}
+
+fn read_identifier(paint_map: &SparseGrid<i64>) -> Result<String, String> {
+    // the registration identifier is painted in white letters
+    let bounds = paint_map.bounds().ok_or("nothing was painted")?;
+    ocr::decode(bounds.width(), bounds.height(),
+                |x, y| paint_map.get(bounds.min_x + x as i64, bounds.min_y + y as i64) == Some(&1))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PaintReport {
+    pub steps: usize,                       // amount of paint operations; the robot moves one panel after each
+    pub panels_painted: usize,
+    pub repainted_panels: usize,            // panels that were painted more than once
+    pub left_turns: usize,
+    pub right_turns: usize,
+    pub bounds_history: Vec<(usize, Bounds)>, // bounding box of the painted panels, after each step that grew it
+}
+impl PaintReport {
+    pub fn distance(&self) -> usize {
+        self.steps
+    }
+    pub fn to_json(&self) -> String {
+        let bounds: Vec<String> = self.bounds_history.iter().map(|(step, b)| {
+            format!("{{\"step\":{},\"min_x\":{},\"min_y\":{},\"max_x\":{},\"max_y\":{}}}",
+                    step, b.min_x, b.min_y, b.max_x, b.max_y)
+        }).collect();
+        format!("{{\"steps\":{},\"panels_painted\":{},\"repainted_panels\":{},\"distance\":{},\"left_turns\":{},\"right_turns\":{},\"bounds\":[{}]}}",
+                self.steps, self.panels_painted, self.repainted_panels, self.distance(), self.left_turns, self.right_turns,
+                bounds.join(","))
+    }
+}
+impl fmt::Display for PaintReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "  panels painted: {} ({} more than once)", self.panels_painted, self.repainted_panels)?;
+        writeln!(f, "  distance traveled: {}", self.distance())?;
+        writeln!(f, "  turns: {} left, {} right", self.left_turns, self.right_turns)?;
+        writeln!(f, "  bounding box:")?;
+        for (step, b) in &self.bounds_history {
+            writeln!(f, "    after step {:>5}: {}x{} from ({},{}) to ({},{})",
+                     step, b.width(), b.height(), b.min_x, b.min_y, b.max_x, b.max_y)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn analyze(program: &Vec<i64>, config: &RobotConfig) -> PaintReport {
+    let mut robot = Robot::with_config(program, config);
+    let mut paint_counts: HashMap<Pos, usize> = HashMap::new();
+    let (mut left_turns, mut right_turns) = (0, 0);
+    let mut bounds: Option<Bounds> = robot.paint_map().bounds();
+    let mut bounds_history: Vec<(usize, Bounds)> = bounds.iter().map(|&b| (0, b)).collect();
+    let mut facing = robot.facing();
+
+    robot.run_with(|robot, painted, _| {
+        *paint_counts.entry(painted).or_insert(0) += 1;
+        if robot.facing() == facing.left() { left_turns += 1 } else { right_turns += 1 }
+        facing = robot.facing();
+
+        let grown = match bounds {
+            Some(b) if b.contains(painted.x, painted.y) => None,
+            Some(b) => Some(b.union(&Bounds::of(painted.x, painted.y))),
+            None    => Some(Bounds::of(painted.x, painted.y)),
+        };
+        if let Some(b) = grown {
+            bounds = Some(b);
+            bounds_history.push((robot.steps(), b));
+        }
+    });
+
+    PaintReport {
+        steps: robot.steps(),
+        panels_painted: robot.paint_map().len(),
+        repainted_panels: paint_counts.values().filter(|&&n| n > 1).count(),
+        left_turns,
+        right_turns,
+        bounds_history,
+    }
+}
+
+fn record_painting(program: &Vec<i64>) -> FrameRecorder {
+    // animation of the robot painting the registration identifier in part 2: one frame for the starting state, and
+    // one after every paint operation
+    let mut robot = Robot::with_config(program, &RobotConfig { start_color: Some(1), ..Default::default() });
+    let mut steps = vec![(robot.paint_map().clone(), robot.pos(), robot.facing())];
+    robot.run_with(|robot, _, _| {
+        steps.push((robot.paint_map().clone(), robot.pos(), robot.facing()));
+    });
+
+    // draw every frame at the same size, covering everywhere the robot has been
+    let bounds = steps.iter().fold(Bounds::of(0, 0), |bounds, (_, pos, _)| bounds.union(&Bounds::of(pos.x, pos.y)));
+    let mut recorder = FrameRecorder::new();
+    for (hull, pos, facing) in steps {
+        let mut frame = hull.render(&bounds, |color| if color == Some(&1) { '#' } else { '.' });
+        frame.set((pos.x - bounds.min_x) as usize, (pos.y - bounds.min_y) as usize, match facing {
+            Facing::Up    => '^',
+            Facing::Down  => 'v',
+            Facing::Left  => '<',
+            Facing::Right => '>',
+        });
+        recorder.record(frame);
+    }
+    recorder
+}
+
+fn hull_color(c: char) -> [u8; 3] {
+    match c {
+        '#' => [255, 255, 255],
+        '.' => [30, 30, 30],
+        _   => [255, 60, 60], // the robot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn robot() {
+        // paints every panel white and turns left, so it keeps going around in a little square
+        let circler = vec![3,100,104,1,104,0,1105,1,0];
+        let mut robot = Robot::with_config(&circler, &RobotConfig { max_steps: Some(10), ..Default::default() });
+        let painted = robot.run();
+        let mut panels: Vec<((i64, i64), i64)> = painted.iter().map(|(pos, &color)| (pos, color)).collect();
+        panels.sort();
+        assert_eq!(panels, vec![((-1,0),1), ((-1,1),1), ((0,0),1), ((0,1),1)]);
+        assert_eq!(robot.steps(), 10);
+        assert!(!robot.is_halted());
+        assert_eq!((robot.pos(), robot.facing()), (Pos { x: -1, y: 1 }, Facing::Down));
+
+        // paints the panel it's on in the color it sees, turns right and stops
+        let echo = vec![3,100,4,100,104,1,3,100,99];
+        let config = RobotConfig { start_color: Some(1), start_pos: Pos { x: 5, y: 5 }, start_facing: Facing::Right,
+                                   max_steps: None };
+        let mut robot = Robot::with_config(&echo, &config);
+        robot.run();
+        assert!(robot.is_halted());
+        assert_eq!((robot.pos(), robot.facing()), (Pos { x: 5, y: 6 }, Facing::Down));
+        let painted = robot.paint_map();
+        assert_eq!((painted.len(), painted.get(5, 5)), (1, Some(&1)));
+
+        // unpainted starting panels are black
+        let mut robot = Robot::new(&echo);
+        assert_eq!(robot.run().get(0, 0), Some(&0));
+        assert_eq!(robot.visualize_map(), " \n");
+    }
+
+    #[test]
+    fn identifier() {
+        let mut paint_map = SparseGrid::new();
+        for (y, row) in [".##.", "#..#", "#..#", "####", "#..#", "#..#"].iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                // painted black panels and unpainted ones are all the same
+                if c == '#' || x == 0 {
+                    paint_map.set(x as i64 - 10, y as i64 + 3, (c == '#') as i64);
+                }
+            }
+        }
+        assert_eq!(read_identifier(&paint_map), Ok("A".to_string()));
+        assert!(read_identifier(&SparseGrid::new()).is_err());
+
+        // the robot only paints where it goes, so the first letter's blank column may not even be part of the map
+        let mut paint_map = SparseGrid::new();
+        for (y, row) in [".###.###.", "..#..#..#", "..#..###.", "..#..#..#", "..#..#..#", ".###.###."].iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                if c == '#' {
+                    paint_map.set(x as i64, y as i64, 1);
+                }
+            }
+        }
+        assert_eq!(read_identifier(&paint_map), Ok("IB".to_string()));
+    }
+
+    #[test]
+    fn analysis() {
+        // same as the circler above, but alternating between a left and a right turn, so it moves diagonally
+        let zigzag = vec![3,100,104,1,104,0,3,100,104,0,104,1,1105,1,0];
+        let report = analyze(&zigzag, &RobotConfig { max_steps: Some(5), ..Default::default() });
+        assert_eq!(report.steps, 5);
+        assert_eq!(report.distance(), 5);
+        assert_eq!((report.left_turns, report.right_turns), (3, 2));
+        assert_eq!((report.panels_painted, report.repainted_panels), (5, 0));
+        assert_eq!(report.bounds_history.iter().map(|&(step, b)| (step, b.width(), b.height())).collect::<Vec<_>>(),
+                   vec![(1,1,1), (2,2,1), (3,2,2), (4,3,2), (5,3,3)]);
+
+        // going around in a square paints every panel again after four steps
+        let circler = vec![3,100,104,1,104,0,1105,1,0];
+        let report = analyze(&circler, &RobotConfig { start_color: Some(1), max_steps: Some(9), ..Default::default() });
+        assert_eq!((report.panels_painted, report.repainted_panels), (4, 4));
+        assert_eq!((report.left_turns, report.right_turns), (9, 0));
+        assert_eq!(report.bounds_history, vec![(0, Bounds::of(0, 0)),
+                                               (2, Bounds { min_x: -1, min_y: 0, max_x: 0, max_y: 0 }),
+                                               (3, Bounds { min_x: -1, min_y: 0, max_x: 0, max_y: 1 })]);
+        assert!(report.to_json().starts_with(r#"{"steps":9,"panels_painted":4,"repainted_panels":4,"distance":9,"left_turns":9,"right_turns":0,"bounds":[{"step":0,"min_x":0,"#));
+    }
 }
@@ -2,79 +2,35 @@
 use crate::util::*;
 use std::convert::From;
 use std::fmt;
-use num::bigint::BigInt;
+use clap::ArgMatches;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
 use num::integer::Integer;
-use num::traits::identities::One;
-
-#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
-pub struct Vec3 {
-    pub x: i64,
-    pub y: i64,
-    pub z: i64,
-}
-impl Vec3 {
-    pub fn new(x: i64, y: i64, z: i64) -> Self {
-        Self { x, y, z }
-    }
-    pub fn add(&mut self, other: &Vec3) -> &Self {
-        self.x += other.x;
-        self.y += other.y;
-        self.z += other.z;
-        self
-    }
-}
-impl From<&String> for Vec3 {
-    fn from(s: &String) -> Self {
-        let s = &s[1..s.len()-1]; // drop leading/trailing "<" and ">"
-        let mut result = Self::new(0,0,0);
-        for coord_s in s.split(",").map(|s| s.trim()).collect::<Vec<_>>() {
-            let parts = coord_s.split("=").collect::<Vec<_>>();
-            let value: i64 = parts[1].parse().unwrap();
-            match parts[0] {
-                "x" => { result.x = value; }
-                "y" => { result.y = value; }
-                "z" => { result.z = value; }
-                _   => { panic!(); }
-            }
-        }
-        result
-    }
-}
-impl fmt::Display for Vec3 {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "<x={:3}, y={:3}, z={:3}>", self.x, self.y, self.z)
-    }
-}
+use rayon::prelude::*;
 
 #[derive(PartialEq, Eq, Clone, Hash, Debug)]
 pub struct Body {
-    pub pos: Vec3,
-    pub vel: Vec3,
+    pub pos: Vec3<i64>,
+    pub vel: Vec3<i64>,
 }
 impl Body {
-    pub fn new(pos: Vec3) -> Self {
+    pub fn new(pos: Vec3<i64>) -> Self {
         Self { pos: pos, vel: Vec3::new(0,0,0) }
     }
-    pub fn update_position(&mut self, vel: Vec3) {
-        self.pos.x += vel.x;
-        self.pos.y += vel.y;
-        self.pos.z += vel.z;
+    pub fn update_position(&mut self, vel: Vec3<i64>) {
+        self.pos += vel;
     }
     pub fn pot_energy(&self) -> i64 {
-        self.pos.x.abs() + self.pos.y.abs() + self.pos.z.abs()
+        self.pos.manhattan_norm()
     }
     pub fn kin_energy(&self) -> i64 {
-        self.vel.x.abs() + self.vel.y.abs() + self.vel.z.abs()
+        self.vel.manhattan_norm()
     }
     pub fn total_energy(&self) -> i64 {
         self.pot_energy() * self.kin_energy()
     }
-    pub fn gravitational_velocity_change(&self, other: &Body) -> Vec3 {
-        Vec3::new(
-            (other.pos.x - self.pos.x).signum(), // -1 if we are bigger, 1 if we are smaller, 0 otherwise
-            (other.pos.y - self.pos.y).signum(),
-            (other.pos.z - self.pos.z).signum(),
-        )
+    pub fn gravitational_velocity_change(&self, other: &Body) -> Vec3<i64> {
+        (other.pos - self.pos).signum() // -1 if we are bigger, 1 if we are smaller, 0 otherwise (per axis)
     }
 }
 impl fmt::Display for Body {
@@ -91,14 +47,17 @@ impl From<&Vec<String>> for System {
     fn from(lines: &Vec<String>) -> Self {
         Self {
             tick: 0,
-            bodies: lines.iter().map(|line| Body::new(Vec3::from(line))).collect(),
+            bodies: lines.iter()
+                         .filter(|line| !line.trim().is_empty())
+                         .map(|line| Body::new(line.parse().unwrap()))
+                         .collect(),
         }
     }
 }
 impl System {
     pub fn step(&mut self) {
         // for each pair of bodies, adjust their velocity for gravity
-        let mut vel_changes = Vec::<(usize, Vec3)>::new(); // idx, {dvx, dvy, dvz}
+        let mut vel_changes = Vec::<(usize, Vec3<i64>)>::new(); // idx, {dvx, dvy, dvz}
         let n = self.bodies.len();
         for i in 0..n {
             for j in (i+1)..n {
@@ -109,7 +68,7 @@ impl System {
             }
         }
         for (idx, dv) in vel_changes {
-            self.bodies[idx].vel.add(&dv);
+            self.bodies[idx].vel += dv;
         }
 
         // all velocities are updated; now adjust positions
@@ -137,100 +96,135 @@ impl fmt::Display for System {
     }
 }
 
-pub fn main() {
+pub fn main(args: &ArgMatches) {
     let lines = file_read_lines("input/day12.txt");
-    part1(&lines);
-    part2(&lines);
-}
+    let steps: usize = args.value_of("steps")
+                           .map(|s| s.parse().unwrap_or_else(|_| panic!("invalid step count: {}", s)))
+                           .unwrap_or(1000);
+    println!("{}", part1(&lines, steps));
+    println!("{}", part2(&lines));
 
-fn part1(lines: &Vec<String>) {
-    let mut system = System::from(lines);
-    system.step_n(1000);
-    println!("{}", system.total_energy());
+    if let Some(filename) = args.value_of("csv") {
+        let mut system = System::from(&lines);
+        File::create(filename).and_then(|f| write_csv(&mut system, steps, BufWriter::new(f)))
+                              .unwrap_or_else(|err| panic!("failed to write {}: {}", filename, err));
+    }
 }
 
-fn part2(lines: &Vec<String>) {
+fn part1(lines: &Vec<String>, steps: usize) -> i64 {
     let mut system = System::from(lines);
-    // if there's going to be a cycle in the system in which both positions and velocities return to a previous
-    // state, then for each individual velocity component there has to be a sequence that both repeats and sums to 0:
-    //  - it must repeat in order for that velocity component to cycle
-    //  - it must sum to 0 in order for the underlying positions to cycle around as well when applied to them
-    // e.g. a velocities cycle of [0,-1,0,1] would work, but [1,2,3,4] would just cause the planets to drift
-    // further and further apart without ever repeating.
-
-    // if the system as a whole is to have a cycle, then the length of that cycle cannot be shorter than
-    // the least common multiple of the cycle lengths of each of the individual velocity components.
+    system.step_n(steps);
+    system.total_energy()
+}
 
-    // iterate the system and keep a history of each velocity component and planet separately (i.e. num_bodies * 3 values).
-    // at each iteration, see if there's a window size of values in which they start repeating
-    // (i.e. where the first recorded N values in the history equal the last recorded N values, for some value of 1 <= N <= history_size/2)
+fn part2(lines: &Vec<String>) -> u64 {
+    cycle_length(&System::from(lines))
+}
 
-    let mut vel_histories = Vec::<Vec<i64>>::new(); // hist[body_idx*3 + (0 for x, 1 for y, 2 for z)] -> values
-    let mut cycles = Vec::<Option<usize>>::new();   // cycles[<same idx>] = length of cycle if found
-    for _ in 0..system.bodies.len()*3 {
-        vel_histories.push(Vec::<i64>::new());
-        cycles.push(None);
+fn write_csv<W: Write>(system: &mut System, steps: usize, mut out: W) -> io::Result<()> {
+    // one row per tick, from the initial state up to and including the given number of steps, with the position
+    // and velocity of each body followed by the total energy of the system
+    let mut header = vec!["tick".to_string()];
+    for i in 0..system.bodies.len() {
+        header.extend(["x", "y", "z", "vx", "vy", "vz"].iter().map(|col| format!("{}{}", col, i)));
     }
+    header.push("energy".to_string());
+    writeln!(out, "{}", header.join(","))?;
 
     loop {
-        system.step();
-        for (body_idx, body) in system.bodies.iter().enumerate() {
-            let x_idx = body_idx*3 + 0;
-            let y_idx = body_idx*3 + 1;
-            let z_idx = body_idx*3 + 2;
-
-            if let None = cycles[x_idx] {
-                vel_histories[x_idx].push(body.vel.x);
-                if let Some(c) = find_sum0_cycle(&vel_histories[x_idx]) {
-                    cycles[x_idx] = Some(c);
-                }
-            }
-            if let None = cycles[y_idx] {
-                vel_histories[y_idx].push(body.vel.y);
-                if let Some(c) = find_sum0_cycle(&vel_histories[y_idx]) {
-                    cycles[y_idx] = Some(c);
-                }
-            }
-            if let None = cycles[z_idx] {
-                vel_histories[z_idx].push(body.vel.z);
-                if let Some(c) = find_sum0_cycle(&vel_histories[z_idx]) {
-                    cycles[z_idx] = Some(c);
-                }
-            }
+        let mut row = vec![system.tick.to_string()];
+        for b in &system.bodies {
+            row.extend((0..3).map(|axis| b.pos[axis].to_string()));
+            row.extend((0..3).map(|axis| b.vel[axis].to_string()));
         }
+        row.push(system.total_energy().to_string());
+        writeln!(out, "{}", row.join(","))?;
 
-        if cycles.iter().all(|v| v.is_some()) {
+        if system.tick >= steps {
             break;
         }
+        system.step();
     }
+    out.flush()
+}
 
-    let cycles = cycles.into_iter().map(|v| v.unwrap()).collect::<Vec<_>>();
-    println!("{}", cycles.iter().fold(One::one(), |acc, x| BigInt::from(acc).lcm(&BigInt::from(*x))));
+fn cycle_length(system: &System) -> u64 {
+    // the x, y and z components of the positions and velocities never influence each other, so each axis is a
+    // separate system with a period of its own. the whole system is back in its initial state once all three are,
+    // i.e. after the least common multiple of their periods.
+    // each step is reversible (the previous velocities follow from the current positions), so the first repeated
+    // state is always the initial one; there's no need to remember the ones in between.
+    // the axes are independent, so they're simulated in parallel.
+    (0..3).into_par_iter().map(|axis| {
+        let pos: Vec<i64> = system.bodies.iter().map(|b| b.pos[axis]).collect();
+        let vel: Vec<i64> = system.bodies.iter().map(|b| b.vel[axis]).collect();
+        axis_period(&pos, &vel)
+    }).reduce(|| 1, |a, b| a.lcm(&b))
 }
 
-#[allow(non_snake_case)]
-fn find_sum0_cycle(history: &Vec<i64>) -> Option<usize> {
-    // starting from the end of the history and going backwards, see if we can find a window size
-    // of values that repeats twice and sums to 0
-    let L = history.len();
-
-    // something like this should work, unsure why it doesn't ...
-    /*
-    for N in (2..L/2+1).rev() {
-        if    history[(L-2*N)..(L-N)] == history[(L-N)..] // repeats at the end
-           && history[(L-N)..].iter().sum::<i64>() == 0   // sums to 0
-        {
-            //println!("{}", history[(L-N)..].iter().map(|d| d.to_string()).collect::<Vec<_>>().join(","));
-            return Some(N);
+fn axis_period(initial_pos: &[i64], initial_vel: &[i64]) -> u64 {
+    // number of steps until the positions and velocities along a single axis return to their initial values
+    let mut pos = initial_pos.to_vec();
+    let mut vel = initial_vel.to_vec();
+    let mut steps = 0;
+    loop {
+        for i in 0..pos.len() {
+            vel[i] += pos.iter().map(|&other| (other - pos[i]).signum()).sum::<i64>();
+        }
+        for i in 0..pos.len() {
+            pos[i] += vel[i];
+        }
+        steps += 1;
+        if pos == initial_pos && vel == initial_vel {
+            return steps;
         }
     }
-    None
-    */
+}
 
-    let window_size = 40; // trial and error
-    if L > window_size && history[..window_size] == history[(L-window_size)..] {
-        return Some(history.len()-window_size);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn examples() {
+        let first = lines(&["<x=-1, y=0, z=2>", "<x=2, y=-10, z=-7>", "<x=4, y=-8, z=8>", "<x=3, y=5, z=-1>"]);
+        let second = lines(&["<x=-8, y=-10, z=0>", "<x=5, y=5, z=10>", "<x=2, y=-7, z=3>", "<x=9, y=-8, z=-3>"]);
+        assert_eq!(part1(&first, 10), 179);
+        assert_eq!(part1(&second, 100), 1940);
+        assert_eq!(part2(&first), 2772);
+        assert_eq!(part2(&second), 4686774924);
+    }
+
+    #[test]
+    fn csv() {
+        let mut system = System::from(&lines(&["<x=0, y=0, z=0>", "<x=3, y=0, z=-1>"]));
+        let mut out = Vec::new();
+        write_csv(&mut system, 2, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), concat!(
+            "tick,x0,y0,z0,vx0,vy0,vz0,x1,y1,z1,vx1,vy1,vz1,energy\n",
+            "0,0,0,0,0,0,0,3,0,-1,0,0,0,0\n",
+            "1,1,0,-1,1,0,-1,2,0,0,-1,0,1,8\n",
+            "2,3,0,-1,2,0,0,0,0,0,-2,0,0,8\n",
+        ));
+    }
+
+    #[test]
+    fn any_number_of_bodies() {
+        // a single body never moves, two bodies oscillate around each other
+        assert_eq!(part1(&lines(&["<x=1, y=2, z=3>"]), 50), 0);
+        assert_eq!(part2(&lines(&["<x=1, y=2, z=3>"])), 1);
+        let pair = lines(&["<x=0, y=0, z=0>", "<x=3, y=0, z=0>", ""]);
+        assert_eq!(System::from(&pair).bodies.len(), 2);
+        assert_eq!(part2(&pair), 8);
+
+        let five = lines(&["<x=-1, y=0, z=2>", "<x=2, y=-10, z=-7>", "<x=4, y=-8, z=8>", "<x=3, y=5, z=-1>", "<x=0, y=1, z=-2>"]);
+        let mut system = System::from(&five);
+        let period = part2(&five);
+        system.step_n(period as usize);
+        assert_eq!(system.bodies, System::from(&five).bodies);
     }
-    return None;
 }
-
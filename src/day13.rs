@@ -3,6 +3,14 @@ use std::convert::From;
 use std::collections::HashMap;
 use crate::util;
 use crate::intcode::{CPU};
+use crate::frames::{Frame, FrameRecorder};
+use crate::grid::SparseGrid;
+use clap::ArgMatches;
+use crossterm::{cursor, execute, terminal};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::time::{Duration, Instant};
 
 #[derive(PartialEq, Eq, Copy, Clone, Debug, Hash)]
 struct Pos {
@@ -29,14 +37,18 @@ impl From<i64> for TileKind {
         }
     }
 }
+#[derive(Clone)]
 struct Tile {
     pos: Pos,
     kind: TileKind,
 }
+#[derive(Clone)]
 struct Arcade {
     cpu: CPU,
     tiles: HashMap<Pos, Tile>,
     score: i64,
+    steps: usize,
+    score_history: Vec<(usize, i64)>, // (step, new score) for every time the score changed
 }
 impl Arcade {
     pub fn new(program: &Vec<i64>) -> Self {
@@ -44,36 +56,44 @@ impl Arcade {
             cpu: CPU::new(program),
             tiles: HashMap::new(),
             score: 0,
+            steps: 0,
+            score_history: Vec::new(),
         }
     }
     pub fn play_for_free(&mut self) {
         self.cpu.write_mem(0, 2);
     }
-    pub fn step_game(&mut self, input: i64) {
+    pub fn step_game(&mut self, input: i64) -> Vec<(Pos, TileKind)> {
         // give the game a single joystick input, let it run for a bit,
         // and update the game state according to any output it produces (if any).
         self.cpu.send_input(input);
         self.cpu.run();
-        self.check_output();
+        self.steps += 1;
+        self.check_output()
     }
-    pub fn check_output(&mut self) {
+    pub fn check_output(&mut self) -> Vec<(Pos, TileKind)> {
         // check for output from the CPU and update the game state accordingly
-        // output comes in pairs of threes
+        // output comes in pairs of threes. returns the tiles that were drawn, in order.
+        let mut updates = Vec::new();
         loop {
             if let Some(x) = self.cpu.consume_output() {
                 let y = self.cpu.consume_output().unwrap();
                 let id = self.cpu.consume_output().unwrap();
                 if x == -1 && y == 0 {
+                    if id != self.score {
+                        self.score_history.push((self.steps, id));
+                    }
                     self.score = id;
                 } else {
-                    self.tiles.insert(Pos { x, y },
-                                      Tile { pos: Pos { x, y },
-                                             kind: TileKind::from(id) });
+                    let kind = TileKind::from(id);
+                    self.tiles.insert(Pos { x, y }, Tile { pos: Pos { x, y }, kind });
+                    updates.push((Pos { x, y }, kind));
                 }
             } else {
                 break;
             }
         }
+        updates
     }
     pub fn get_ball(&self) -> &Tile {
         // finds the ball tile in the current game (assumes one exists)
@@ -84,6 +104,22 @@ impl Arcade {
         // finds the paddle tile in the current game (assumes one exists)
         self.tiles.values().filter(|t| t.kind == TileKind::HorizPaddle).nth(0).unwrap()
     }
+    pub fn remaining_blocks(&self) -> usize {
+        self.tiles.values().filter(|t| t.kind == TileKind::Block).count()
+    }
+    #[allow(unused)]
+    pub fn score_history(&self) -> &[(usize, i64)] {
+        &self.score_history
+    }
+    pub fn is_lost(&self) -> bool {
+        // once the ball has come down as far as the paddle's row without bouncing off of it, there's no saving it
+        let ball = self.tiles.values().find(|t| t.kind == TileKind::Ball);
+        let paddle = self.tiles.values().find(|t| t.kind == TileKind::HorizPaddle);
+        match (ball, paddle) {
+            (Some(ball), Some(paddle)) => ball.pos.y >= paddle.pos.y,
+            _                          => false,
+        }
+    }
     #[allow(unused)]
     pub fn visualize(&self) -> String {
         let mut result = String::new();
@@ -122,41 +158,329 @@ impl Arcade {
     }
 }
 
-pub fn main() {
+pub fn main(args: &ArgMatches) {
     let line: String = util::file_read_lines("input/day13.txt").into_iter().next().unwrap();
     let program: Vec<i64> = line.split(",").map(|s| s.parse().unwrap()).collect();
     part1(&program);
-    part2(&program);
+    if args.is_present("play") {
+        match play(&program) {
+            Ok(score) => println!("{}", score),
+            Err(err)  => eprintln!("terminal error: {}", err),
+        }
+        return;
+    }
+
+    let mut strategy: Box<dyn Strategy> = match args.value_of("strategy") {
+        None | Some("follow") => Box::new(FollowBall),
+        Some("predict")       => Box::new(Predict),
+        Some(other)           => panic!("unknown strategy: {} (expected follow or predict)", other),
+    };
+    let (result, replay) = part2(&program, strategy.as_mut());
+    match result {
+        Ok(score) => println!("{}", score),
+        Err(err)  => eprintln!("{}", err), // not an answer, so keep it apart from the ones on stdout
+    }
+    if args.is_present("render") || args.is_present("gif") {
+        let delay = Duration::from_millis(args.value_of("delay").map_or(30, |s| s.parse().unwrap_or_else(|_| panic!("invalid delay: {}", s))));
+        let recorder = replay.frames();
+        if args.is_present("render") {
+            recorder.play(&mut io::stdout(), delay).unwrap();
+        }
+        if let Some(filename) = args.value_of("gif") {
+            let scale = args.value_of("scale").map_or(10, |s| s.parse().unwrap_or_else(|_| panic!("invalid scale: {}", s)));
+            File::create(filename).and_then(|f| recorder.write_gif(BufWriter::new(f), scale, delay, arcade_color))
+                                  .unwrap_or_else(|err| panic!("failed to write {}: {}", filename, err));
+        }
+    }
 }
 
 fn part1(program: &Vec<i64>) {
     let mut arcade = Arcade::new(program);
     arcade.cpu.run();
     arcade.check_output();
-    println!("{}", arcade.tiles.values().filter(|t| t.kind == TileKind::Block).count());
+    println!("{}", arcade.remaining_blocks());
 }
 
-fn part2(program: &Vec<i64>) {
-    //for _ in 0..100 { println!(""); } // create some vertical space
+fn part2(program: &Vec<i64>, strategy: &mut dyn Strategy) -> (Result<i64, String>, Replay) {
+    // plays the game until all blocks are gone, and returns the final score. if the paddle misses the ball
+    // instead, it stops right there with an error. either way, the replay shows how it went.
     let mut arcade = Arcade::new(program);
     arcade.play_for_free();
 
+    let mut replay = Replay::new();
     let mut next_input = 0i64;
     loop {
-        arcade.step_game(next_input);
+        let updates = arcade.step_game(next_input);
+        replay.record(next_input, updates, arcade.score);
+        if arcade.is_lost() {
+            let ball = arcade.get_ball().pos;
+            return (Err(format!("lost the ball at ({},{}) after {} steps, with {} blocks left and a score of {}",
+                                ball.x, ball.y, arcade.steps, arcade.remaining_blocks(), arcade.score)), replay);
+        }
         if arcade.cpu.is_halted() {
             break;
         }
+        next_input = strategy.joystick(&arcade);
+    }
+    if arcade.remaining_blocks() > 0 {
+        return (Err(format!("game ended after {} steps with {} blocks left and a score of {}",
+                            arcade.steps, arcade.remaining_blocks(), arcade.score)), replay);
+    }
+    (Ok(arcade.score), replay)
+}
+
+trait Strategy {
+    // which way to push the joystick next (-1, 0 or 1), given the current state of the game
+    fn joystick(&mut self, arcade: &Arcade) -> i64;
+}
+
+struct FollowBall;
+impl Strategy for FollowBall {
+    fn joystick(&mut self, arcade: &Arcade) -> i64 {
         // find the ball and the paddle, and move the paddle according to the ball's horizontal position
         let ball = arcade.get_ball();
         let paddle = arcade.get_paddle();
-        next_input = (ball.pos.x - paddle.pos.x).signum();
+        (ball.pos.x - paddle.pos.x).signum()
+    }
+}
+
+struct Predict;
+impl Predict {
+    const MAX_LOOKAHEAD: usize = 10_000; // steps
+
+    fn predict_landing(arcade: &Arcade) -> Option<i64> {
+        // plays on in a copy of the game with the paddle standing still, to see where the ball is when it next
+        // comes down to the row just above the paddle. the paddle needs to be right below it by then: that makes
+        // it bounce straight back up, whereas catching it on the corner of the paddle sends it back the way it
+        // came. the paddle doesn't affect the ball before then, so it follows the same path in the copy.
+        let landing_row = arcade.get_paddle().pos.y - 1;
+        let ball = arcade.get_ball().pos;
+        if ball.y == landing_row {
+            return Some(ball.x); // it's bouncing right now
+        }
+        let mut game = arcade.clone();
+        for _ in 0..Self::MAX_LOOKAHEAD {
+            game.step_game(0);
+            if game.cpu.is_halted() {
+                return None;
+            }
+            let ball = game.get_ball().pos;
+            if ball.y == landing_row {
+                return Some(ball.x);
+            }
+        }
+        None
+    }
+}
+impl Strategy for Predict {
+    fn joystick(&mut self, arcade: &Arcade) -> i64 {
+        // if there's no telling where the ball is going, fall back to following it
+        let ball = arcade.get_ball();
+        let paddle = arcade.get_paddle();
+        (Self::predict_landing(arcade).unwrap_or(ball.pos.x) - paddle.pos.x).signum()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct ReplayStep {
+    input: i64,                     // joystick position given to the game
+    updates: Vec<(Pos, TileKind)>,  // tiles it drew in response
+    score: i64,                     // score afterwards
+}
+
+struct Replay {
+    steps: Vec<ReplayStep>,
+}
+impl Replay {
+    pub fn new() -> Self {
+        Self { steps: Vec::new() }
+    }
+    pub fn record(&mut self, input: i64, updates: Vec<(Pos, TileKind)>, score: i64) {
+        self.steps.push(ReplayStep { input, updates, score });
+    }
+    pub fn frames(&self) -> FrameRecorder {
+        // redraws the screen after every step, with a status line on top showing the score and which way the
+        // joystick was pushed to get there. the screen is sized to fit everything that was ever drawn.
+        let mut screen: SparseGrid<TileKind> = SparseGrid::new();
+        for step in &self.steps {
+            for &(pos, kind) in &step.updates {
+                screen.set(pos.x, pos.y, kind);
+            }
+        }
+        let bounds = match screen.bounds() {
+            Some(bounds) => bounds,
+            None         => return FrameRecorder::new(),
+        };
+
+        let mut recorder = FrameRecorder::new();
+        let mut screen: SparseGrid<TileKind> = SparseGrid::new();
+        for step in &self.steps {
+            for &(pos, kind) in &step.updates {
+                screen.set(pos.x, pos.y, kind);
+            }
+            let game = screen.render(&bounds, |kind| tile_char(kind.cloned().unwrap_or(TileKind::Empty)));
+            let mut frame = Frame::new(game.width(), game.height() + 1, ' ');
+            let joystick = match step.input { -1 => "<", 1 => ">", _ => "|" };
+            for (x, c) in format!("{} {}", joystick, step.score).chars().take(frame.width()).enumerate() {
+                frame.set(x, 0, c);
+            }
+            for y in 0..game.height() {
+                for x in 0..game.width() {
+                    frame.set(x, y + 1, game.get(x, y));
+                }
+            }
+            recorder.record(frame);
+        }
+        recorder
+    }
+}
+
+fn tile_char(kind: TileKind) -> char {
+    match kind {
+        TileKind::Empty       => ' ',
+        TileKind::Wall        => '#',
+        TileKind::Block       => 'x',
+        TileKind::HorizPaddle => '_',
+        TileKind::Ball        => 'o',
+    }
+}
 
-        //print!("{}[2J", 27 as char); // clear screen
-        //println!("{}", arcade.visualize());
-        //println!("Press any key to step the game forward.");
-        //io::stdin().read_line(&mut String::new());
+fn arcade_color(c: char) -> [u8; 3] {
+    match c {
+        ' ' => [0, 0, 0],
+        '#' => [120, 120, 120],
+        'x' => [230, 120, 30],
+        '_' => [80, 160, 255],
+        'o' => [255, 255, 255],
+        _   => [255, 220, 0], // status line
     }
-    println!("{}", arcade.score);
 }
 
+const FRAME_TIME: Duration = Duration::from_millis(120);
+
+struct RawMode;
+impl RawMode {
+    fn enable() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        execute!(io::stdout(), terminal::Clear(terminal::ClearType::All), cursor::Hide)?;
+        Ok(RawMode)
+    }
+}
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        // put the terminal back the way it was, also when bailing out with an error
+        let _ = execute!(io::stdout(), cursor::Show);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+fn play(program: &Vec<i64>) -> io::Result<i64> {
+    // lets a human play the game in the terminal. the game advances at a fixed pace; the arrow keys (or a/d) tilt
+    // the joystick for one frame, and q or escape gives up. returns the score when the game ends.
+    let mut arcade = Arcade::new(program);
+    arcade.play_for_free();
+
+    let _raw_mode = RawMode::enable()?;
+    let mut stdout = io::stdout();
+    let mut input = 0i64;
+    loop {
+        arcade.step_game(input);
+        // raw mode doesn't turn \n into a carriage return + line feed
+        execute!(stdout, cursor::MoveTo(0, 0))?;
+        write!(stdout, "{}", arcade.visualize().replace("\n", "\r\n"))?;
+        write!(stdout, "arrow keys to move, q to quit\r\n")?;
+        stdout.flush()?;
+        if arcade.cpu.is_halted() {
+            break;
+        }
+
+        // the last key pressed during this frame decides where the joystick goes
+        input = 0;
+        let frame_end = Instant::now() + FRAME_TIME;
+        loop {
+            let now = Instant::now();
+            if now >= frame_end || !event::poll(frame_end - now)? {
+                break;
+            }
+            if let Event::Key(key) = event::read()? {
+                if key.kind != KeyEventKind::Press {
+                    continue;
+                }
+                match key.code {
+                    KeyCode::Left  | KeyCode::Char('a') => input = -1,
+                    KeyCode::Right | KeyCode::Char('d') => input = 1,
+                    KeyCode::Char('q') | KeyCode::Esc   => return Ok(arcade.score),
+                    // ctrl-c doesn't raise a signal in raw mode
+                    KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Ok(arcade.score),
+                    _ => {},
+                }
+            }
+        }
+    }
+    Ok(arcade.score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn arcade_with(ball: Pos, paddle: Pos) -> Arcade {
+        let mut arcade = Arcade::new(&vec![99]);
+        arcade.tiles.insert(ball, Tile { pos: ball, kind: TileKind::Ball });
+        arcade.tiles.insert(paddle, Tile { pos: paddle, kind: TileKind::HorizPaddle });
+        arcade
+    }
+
+    #[test]
+    fn strategies() {
+        let arcade = arcade_with(Pos { x: 3, y: 5 }, Pos { x: 7, y: 10 });
+        assert_eq!(FollowBall.joystick(&arcade), -1);
+        // the game is over in the copy, so there's nothing to predict; falls back to following the ball
+        assert_eq!(Predict::predict_landing(&arcade), None);
+        assert_eq!(Predict.joystick(&arcade), -1);
+
+        // about to bounce: stay right underneath it
+        let arcade = arcade_with(Pos { x: 7, y: 9 }, Pos { x: 7, y: 10 });
+        assert_eq!(Predict::predict_landing(&arcade), Some(7));
+        assert_eq!(Predict.joystick(&arcade), 0);
+    }
+
+    #[test]
+    fn game_state() {
+        let mut arcade = arcade_with(Pos { x: 3, y: 5 }, Pos { x: 7, y: 10 });
+        arcade.tiles.insert(Pos { x: 1, y: 1 }, Tile { pos: Pos { x: 1, y: 1 }, kind: TileKind::Block });
+        assert_eq!(arcade.remaining_blocks(), 1);
+        assert!(!arcade.is_lost());
+        arcade.tiles.remove(&Pos { x: 3, y: 5 });
+        arcade.tiles.insert(Pos { x: 3, y: 10 }, Tile { pos: Pos { x: 3, y: 10 }, kind: TileKind::Ball });
+        assert!(arcade.is_lost());
+
+        // the first instruction is a harmless add that playing for free turns into an equally harmless multiply
+        let (result, replay) = part2(&vec![1101,0,0,100, 104,-1,104,0,104,10, 104,-1,104,0,104,42, 99], &mut FollowBall);
+        assert_eq!((result, replay.steps.len()), (Ok(42), 1));
+        let mut arcade = Arcade::new(&vec![1101,0,0,100, 104,-1,104,0,104,10, 104,-1,104,0,104,10, 104,-1,104,0,104,42, 99]);
+        arcade.step_game(0);
+        assert_eq!(arcade.score_history(), &[(1, 10), (1, 42)]);
+
+        let (result, _) = part2(&vec![1101,0,0,100, 104,5,104,5,104,2, 99], &mut FollowBall);
+        assert_eq!(result, Err("game ended after 1 steps with 1 blocks left and a score of 0".to_string()));
+
+        // draws the ball below the paddle and keeps asking for input
+        let (result, _) = part2(&vec![1101,0,0,100, 104,0,104,2,104,3, 104,0,104,3,104,4, 3,100, 1105,1,16], &mut FollowBall);
+        assert_eq!(result, Err("lost the ball at (0,3) after 1 steps, with 0 blocks left and a score of 0".to_string()));
+    }
+
+    #[test]
+    fn replay() {
+        // a one-tile-wide game: the ball falls onto the paddle, scoring a point, and then the game ends
+        let mut replay = Replay::new();
+        replay.record(0, vec![(Pos { x: 0, y: 0 }, TileKind::Wall), (Pos { x: 0, y: 1 }, TileKind::Ball),
+                              (Pos { x: 0, y: 3 }, TileKind::HorizPaddle)], 0);
+        replay.record(1, vec![(Pos { x: 0, y: 1 }, TileKind::Empty), (Pos { x: 0, y: 2 }, TileKind::Ball)], 1);
+
+        let recorder = replay.frames();
+        let frames: Vec<String> = recorder.frames().iter().map(|f| f.to_string()).collect();
+        assert_eq!(frames, vec!["|\n#\no\n \n_\n", ">\n#\n \no\n_\n"]);
+        assert!(Replay::new().frames().is_empty());
+    }
+}
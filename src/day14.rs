@@ -1,9 +1,11 @@
 // vim: set ai et ts=4 sts=4 sw=4:
 use crate::util;
-use std::collections::{HashMap, VecDeque};
+use clap::ArgMatches;
+use std::collections::HashMap;
 use std::convert::From;
 use std::ops::{AddAssign, Mul};
 use std::fmt;
+use std::fs;
 
 #[derive(Debug, Clone)]
 struct Resource {
@@ -18,7 +20,7 @@ impl fmt::Display for Resource {
 }
 
 #[derive(Clone, Debug)]
-struct Term {
+pub struct Term {
     resource: String,
     quantity: usize,
 }
@@ -87,8 +89,33 @@ impl fmt::Display for TermSet {
     }
 }
 
-struct Problem {
+#[derive(Debug, Clone, PartialEq)]
+pub struct Plan {
+    pub ore: usize,                        // ORE that has to be mined, on top of what was in stock
+    pub batches: HashMap<String, usize>,   // how many times each reaction has to run
+    pub leftovers: HashMap<String, usize>, // what's left afterwards, including unused stock
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Byproduct {
+    pub resource: String,
+    pub produced: usize,
+    pub wasted: usize,
+}
+impl Byproduct {
+    pub fn waste_ratio(&self) -> f64 {
+        self.wasted as f64 / self.produced as f64
+    }
+}
+impl fmt::Display for Byproduct {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {} of {} wasted ({:.1}%)", self.resource, self.wasted, self.produced, 100.0*self.waste_ratio())
+    }
+}
+
+pub struct Problem {
     resources: HashMap<String, Resource>,
+    order: Vec<String>, // every resource comes before the ones it's made from, so FUEL is first and ORE last
 }
 impl Problem {
     pub fn new(lines: &Vec<String>) -> Self {
@@ -113,9 +140,42 @@ impl Problem {
             batch_size: 1,
             batch_inputs: TermSet::new(),
         });
+        let order = Self::topological_order(&resources);
         Self {
             resources,
+            order,
+        }
+    }
+    fn topological_order(resources: &HashMap<String, Resource>) -> Vec<String> {
+        // depth-first, adding each resource after everything it's made from; reversing that puts each one before
+        // its inputs instead. the reactions can't be circular, or nothing could ever be made.
+        fn visit<'a>(name: &'a str, resources: &'a HashMap<String, Resource>, visited: &mut HashMap<&'a str, bool>,
+                     order: &mut Vec<String>)
+        {
+            match visited.get(name) {
+                Some(true)  => return,
+                Some(false) => panic!("circular reaction involving {}", name),
+                None        => {},
+            }
+            visited.insert(name, false); // in progress
+            let mut inputs: Vec<&String> = resources[name].batch_inputs.0.keys().collect();
+            inputs.sort_unstable();
+            for input in inputs {
+                visit(input, resources, visited, order);
+            }
+            visited.insert(name, true);
+            order.push(name.to_string());
+        }
+
+        let mut names: Vec<&String> = resources.keys().collect();
+        names.sort_unstable();
+        let mut visited = HashMap::new();
+        let mut order = Vec::new();
+        for name in names {
+            visit(name, resources, &mut visited, &mut order);
         }
+        order.reverse();
+        order
     }
     pub fn ore_cost(&self, needed: Term)
         -> (usize, HashMap<String, usize>) // (ore cost, waste products)
@@ -127,50 +187,118 @@ impl Problem {
                                               initial_waste: HashMap<String, usize>)
         -> (usize, HashMap<String, usize>) // (ore cost, waste products)
     {
-        let mut ore_needed = 0usize;
-        let mut waste = initial_waste.clone();
+        let plan = self.plan(&needed.resource, needed.quantity, &initial_waste).unwrap_or_else(|err| panic!("{}", err));
+        (plan.ore, plan.leftovers)
+    }
 
-        let mut to_expand = VecDeque::<Term>::new();
-        to_expand.push_front(needed);
+    pub fn plan(&self, resource: &str, quantity: usize, stockpile: &HashMap<String, usize>) -> Result<Plan, String> {
+        // works out how to make the given amount of a resource, using what's in the stockpile first (which may
+        // include ORE). going through the resources in topological order, by the time we get to one, everything
+        // that uses it has already been accounted for, so we know exactly how much of it is needed in total.
+        if !self.resources.contains_key(resource) {
+            return Err(format!("unknown resource: {}", resource));
+        }
+        let mut leftovers = stockpile.clone();
+        let mut batches = HashMap::<String, usize>::new();
+        let mut needs = HashMap::<&str, usize>::new();
+        needs.insert(resource, quantity);
 
-        while let Some(term) = to_expand.pop_front()
-        {
-            let resource = &self.resources[&term.resource];
-            let wasted: &mut usize = waste.entry(resource.name.clone())
-                                          .or_insert(0usize);
-
-            // we need $term.quantity of $resource; do we have any of that left over
-            // from a previous expansion? if so, reduce it by that amount.
-            let mut needed = term.quantity;
-            if needed <= *wasted {
-                // don't need to do anything, still have this amount "in stock"
-                // just need to update the new amount left over
-                *wasted -= needed as usize;
-                continue;
-            }
-            needed -= *wasted;
-            assert!(needed > 0); // we already checked for needed <= wasted
+        for name in &self.order {
+            let needed = match needs.get(name.as_str()) {
+                Some(&n) => n,
+                None     => continue,
+            };
 
-            if resource.name == "ORE" {
-                // can't expand ORE any further (and ORE can never be wasted, nothing produces it)
-                ore_needed += needed as usize;
+            // use up whatever is left over first, then run enough batches to make the rest. what they make in
+            // excess of that is left over in turn. ORE is just a resource that comes in batches of 1 and is made
+            // out of nothing, i.e. mined.
+            let resource = &self.resources[name];
+            let left = leftovers.entry(name.clone()).or_insert(0);
+            let from_stock = needed.min(*left);
+            let needed = needed - from_stock;
+            let num_batches = needed.div_ceil(resource.batch_size);
+            *left = *left - from_stock + num_batches*resource.batch_size - needed;
+            if num_batches == 0 {
                 continue;
             }
+            batches.insert(name.clone(), num_batches);
+            for (input, &quantity) in &resource.batch_inputs.0 {
+                *needs.entry(input).or_insert(0) += quantity*num_batches;
+            }
+        }
+        Ok(Plan {
+            ore: batches.remove("ORE").unwrap_or(0),
+            batches,
+            leftovers: leftovers.into_iter().filter(|&(_, n)| n > 0).collect(),
+        })
+    }
 
-            // given the adjusted amount of this resource that we still need to produce,
-            // how many batches of its production formula do we need to run?
-            // and how many of this resource will we have produced in excess of what's needed?
-            // that becomes the new amount wasted of this resource.
-            let num_batches = ((needed as f64)/(resource.batch_size as f64)).ceil() as usize;
-            *wasted = (num_batches * resource.batch_size) - needed;
+    pub fn max_fuel_from(&self, stockpile: &HashMap<String, usize>) -> usize {
+        // the more FUEL you make, the more it takes, so binary search for the largest amount that can be made from
+        // the stockpile alone. first keep doubling to find an amount that can't, to search up to.
+        let possible = |fuel: usize| self.plan("FUEL", fuel, stockpile).unwrap().ore == 0;
+        let mut hi = 1usize;
+        while possible(hi) {
+            hi *= 2;
+        }
+        util::binary_search_max(0, hi, possible).unwrap() // zero FUEL always takes zero ORE
+    }
 
-            let scaled_inputs = &resource.batch_inputs * num_batches;
-            for input_term in scaled_inputs.terms() {
-                to_expand.push_front(input_term); // we can do this DFS (push_front) or BFS (push_back), doesn't matter
-            }
+    pub fn byproducts(&self, resource: &str, quantity: usize) -> Result<Vec<Byproduct>, String> {
+        // everything that gets made in excess of what's needed when making the given amount of a resource, most
+        // wasteful first (relative to how much of it is made)
+        let plan = self.plan(resource, quantity, &HashMap::new())?;
+        let mut result: Vec<Byproduct> = plan.leftovers.iter().map(|(name, &wasted)| Byproduct {
+            resource: name.clone(),
+            produced: plan.batches[name] * self.resources[name].batch_size,
+            wasted,
+        }).collect();
+        result.sort_by(|a, b| b.waste_ratio().partial_cmp(&a.waste_ratio()).unwrap().then_with(|| a.resource.cmp(&b.resource)));
+        Ok(result)
+    }
+
+    pub fn critical_chain(&self) -> Vec<String> {
+        // the longest chain of reactions from ORE to FUEL, i.e. the most reactions that have to happen one after
+        // the other. ties go to the alphabetically first resource.
+        let mut depth = HashMap::<&str, usize>::new(); // reactions needed before the resource can be made
+        for name in self.order.iter().rev() {
+            let d = self.resources[name].batch_inputs.0.keys().map(|input| depth[input.as_str()] + 1).max().unwrap_or(0);
+            depth.insert(name, d);
         }
+        let mut chain = vec!["FUEL".to_string()];
+        while let Some(next) = self.resources[chain.last().unwrap()].batch_inputs.0.keys()
+                                   .min_by_key(|&input| (std::cmp::Reverse(depth[input.as_str()]), input))
+        {
+            chain.push(next.clone());
+        }
+        chain.reverse();
+        chain
+    }
 
-        (ore_needed, waste)
+    pub fn to_dot(&self) -> String {
+        // the reactions as a Graphviz graph, with an edge from each input to what it's used to make, and the
+        // critical chain highlighted. edges are labeled with how much of the input one batch takes and how much
+        // that batch makes.
+        let chain = self.critical_chain();
+        let on_chain = |from: &str, to: &str| chain.windows(2).any(|w| w[0] == from && w[1] == to);
+
+        let mut result = String::from("digraph {\n    rankdir=LR;\n");
+        for name in self.order.iter().rev() {
+            let extra = if chain.contains(name) { ", color=red, penwidth=2" } else { "" };
+            result.push_str(&format!("    {} [label=\"{}\"{}];\n", name, name, extra));
+        }
+        for name in self.order.iter().rev() {
+            let resource = &self.resources[name];
+            let mut inputs: Vec<&String> = resource.batch_inputs.0.keys().collect();
+            inputs.sort_unstable();
+            for input in inputs {
+                let extra = if on_chain(input, name) { ", color=red, penwidth=2" } else { "" };
+                result.push_str(&format!("    {} -> {} [label=\"{} for {}\"{}];\n",
+                                         input, name, resource.batch_inputs.0[input], resource.batch_size, extra));
+            }
+        }
+        result.push_str("}\n");
+        result
     }
 
 }
@@ -198,11 +326,20 @@ impl fmt::Display for Problem {
     }
 }
 
-pub fn main() {
+pub fn main(args: &ArgMatches) {
     let lines = util::file_read_lines("input/day14.txt");
     let problem = Problem::new(&lines);
     println!("{}", part1(&problem));
     println!("{}", part2(&problem));
+
+    if let Some(filename) = args.value_of("dot") {
+        fs::write(filename, problem.to_dot()).unwrap();
+    }
+    if args.is_present("analyze") {
+        for byproduct in problem.byproducts("FUEL", 1).unwrap() {
+            println!("{}", byproduct);
+        }
+    }
 }
 
 fn part1(problem: &Problem) -> usize {
@@ -210,29 +347,9 @@ fn part1(problem: &Problem) -> usize {
     ore_needed
 }
 
-fn part2(problem: &Problem) -> usize
-{
-    // feed the waste products of the last FUEL production back into the next one,
-    // for maximal reuse of wasted resources.
-    // TODO: slow, can probably speed this up by guesstimating an amount of FUEL that we can
-    // produce and search around that neighbourhood
-    let mut fuel_produced = 0usize;
-    let mut ore_remaining = 1_000_000_000_000usize;
-
-    let mut waste = HashMap::<String, usize>::new();
-    loop {
-        let (ore_cost, new_waste) = problem.ore_cost_with_initial_waste(term!["FUEL", 1], waste);
-        waste = new_waste;
-
-        if ore_cost > ore_remaining {
-            break;
-        }
-
-        fuel_produced += 1;
-        ore_remaining -= ore_cost;
-    }
-
-    fuel_produced
+fn part2(problem: &Problem) -> usize {
+    let stockpile: HashMap<String, usize> = [("ORE".to_string(), 1_000_000_000_000)].iter().cloned().collect();
+    problem.max_fuel_from(&stockpile)
 }
 
 #[allow(unused)]
@@ -314,5 +431,64 @@ mod tests {
         assert_eq!(part1(&Problem::new(&example_input(3))), 13312);
         assert_eq!(part1(&Problem::new(&example_input(4))), 180697);
         assert_eq!(part1(&Problem::new(&example_input(5))), 2210736);
+
+        assert_eq!(part2(&Problem::new(&example_input(3))), 82892753);
+        assert_eq!(part2(&Problem::new(&example_input(4))), 5586022);
+        assert_eq!(part2(&Problem::new(&example_input(5))), 460664);
+    }
+
+    fn stock(items: &[(&str, usize)]) -> HashMap<String, usize> {
+        items.iter().map(|&(name, n)| (name.to_string(), n)).collect()
+    }
+
+    #[test]
+    fn what_if() {
+        let problem = Problem::new(&example_input(1));
+        assert_eq!(problem.max_fuel_from(&stock(&[("ORE", 30)])), 0);
+        assert_eq!(problem.max_fuel_from(&stock(&[("ORE", 31)])), 1);
+        assert_eq!(problem.max_fuel_from(&stock(&[("A", 7), ("E", 1)])), 1);
+        assert_eq!(problem.max_fuel_from(&stock(&[("FUEL", 3)])), 3);
+
+        let plan = problem.plan("D", 1, &stock(&[("A", 5), ("B", 2)])).unwrap();
+        assert_eq!(plan, Plan { ore: 10, batches: stock(&[("A", 1), ("C", 1), ("D", 1)]), leftovers: stock(&[("A", 1), ("B", 1)]) });
+        assert_eq!(problem.plan("X", 1, &HashMap::new()), Err("unknown resource: X".to_string()));
+
+        let byproducts = problem.byproducts("FUEL", 1).unwrap();
+        assert_eq!(byproducts, vec![Byproduct { resource: "A".to_string(), produced: 30, wasted: 2 }]);
+        assert_eq!(byproducts[0].to_string(), "A: 2 of 30 wasted (6.7%)");
+        assert!(problem.byproducts("FUEL", 15).unwrap().is_empty());
+    }
+
+    #[test]
+    fn ore_cost() {
+        let problem = Problem::new(&example_input(1));
+        assert_eq!(problem.order.first().map(String::as_str), Some("FUEL"));
+        assert_eq!(problem.order.last().map(String::as_str), Some("ORE"));
+
+        // 28 A needed, made in batches of 10
+        let (ore, waste) = problem.ore_cost(term!["FUEL", 1]);
+        assert_eq!((ore, waste), (31, [("A".to_string(), 2)].iter().cloned().collect()));
+        let (ore, waste) = problem.ore_cost(term!["FUEL", 5]);
+        assert_eq!((ore, waste), (145, HashMap::new()));
+        // with the leftovers from the first one, the second one needs one less batch of A
+        let (ore, _) = problem.ore_cost_with_initial_waste(term!["FUEL", 1], [("A".to_string(), 2)].iter().cloned().collect());
+        assert_eq!(ore, 31);
+        let (ore, _) = problem.ore_cost_with_initial_waste(term!["FUEL", 1], [("A".to_string(), 8)].iter().cloned().collect());
+        assert_eq!(ore, 21);
+        assert_eq!(problem.ore_cost(term!["C", 2]).0, 22);
+    }
+
+    #[test]
+    fn dot() {
+        let problem = Problem::new(&example_input(1));
+        assert_eq!(problem.critical_chain(), vec!["ORE", "A", "C", "D", "E", "FUEL"]);
+
+        let dot = problem.to_dot();
+        assert!(dot.starts_with("digraph {\n"));
+        assert_eq!(dot.matches("->").count(), 10);
+        assert!(dot.contains("    A -> C [label=\"7 for 1\", color=red, penwidth=2];\n"));
+        assert!(dot.contains("    ORE -> A [label=\"10 for 10\", color=red, penwidth=2];\n"));
+        assert!(dot.contains("    ORE -> B [label=\"1 for 1\"];\n"));
+        assert_eq!(dot.matches("color=red").count(), 6 + 5);
     }
 }
@@ -3,20 +3,28 @@ use crate::util;
 use std::convert::From;
 use std::collections::{HashMap};
 use crate::intcode::{CPU};
+use crate::path;
+use crate::frames::{Frame, FrameRecorder};
+use crate::grid::SparseGrid;
+use clap::ArgMatches;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::time::Duration;
+use rayon::prelude::*;
 
 #[derive(PartialEq, Eq, Copy, Clone, Debug, Hash)]
-struct Pos {
-    pub x: i32,
-    pub y: i32,
+pub struct Pos {
+    pub x: i64,
+    pub y: i64,
 }
 impl Pos {
-    pub fn up(&self)    -> Self { Self { x: self.x, y: self.y+1 } } // positive Y axis points up
-    pub fn down(&self)  -> Self { Self { x: self.x, y: self.y-1 } }
+    pub fn up(&self)    -> Self { Self { x: self.x, y: self.y-1 } } // positive Y axis points down, like the grid
+    pub fn down(&self)  -> Self { Self { x: self.x, y: self.y+1 } }
     pub fn left(&self)  -> Self { Self { x: self.x-1, y: self.y } }
     pub fn right(&self) -> Self { Self { x: self.x+1, y: self.y } }
 }
 #[derive(PartialEq, Eq, Copy, Clone, Debug, Hash)]
-enum TileKind {
+pub enum TileKind {
     Empty,
     Wall,
     Target,
@@ -32,132 +40,295 @@ impl From<i64> for TileKind {
     }
 }
 
-fn discover_map(program: &Vec<i64>)
-    -> (HashMap<Pos, TileKind>,   // map pos -> tile_kind
-        HashMap<Pos, Vec<i64>>) // map pos -> shortest inputs to reach it
+pub struct Maze {
+    tiles: SparseGrid<TileKind>, // everything the droid found, with the starting point at 0,0
+}
+impl path::Node for Pos {}
+impl path::Map for Maze {
+    type Node = Pos;
+    type Cost = usize;
+    fn neighbours(&self, of: &Pos) -> Vec<(Pos, usize)> {
+        vec![(of.up(), 1), (of.down(), 1), (of.left(), 1), (of.right(), 1)]
+    }
+}
+impl Maze {
+    pub fn get(&self, pos: &Pos) -> Option<TileKind> {
+        self.tiles.get(pos.x, pos.y).cloned()
+    }
+    pub fn is_open(&self, pos: &Pos) -> bool {
+        matches!(self.get(pos), Some(TileKind::Empty) | Some(TileKind::Target))
+    }
+    pub fn target(&self) -> Option<Pos> {
+        self.tiles.iter().find(|&(_, &kind)| kind == TileKind::Target).map(|((x, y), _)| Pos { x, y })
+    }
+    pub fn render(&self) -> Frame {
+        match self.tiles.bounds() {
+            Some(bounds) => self.tiles.render(&bounds, tile_char),
+            None         => Frame::new(0, 0, ' '),
+        }
+    }
+    pub fn distances_from(&self, pos: &Pos) -> HashMap<Pos, usize> {
+        path::bfs(self, pos, |maze, p| maze.is_open(p)).0
+    }
+}
+
+const MOVES: [(i64, i64); 4] = [(1, 2), (2, 1), (3, 4), (4, 3)]; // (input to move in a direction, input to move back)
+
+fn step(pos: &Pos, input: i64) -> Pos {
+    match input {
+        1 => pos.up(),
+        2 => pos.down(),
+        3 => pos.left(),
+        4 => pos.right(),
+        _ => panic!("invalid movement command: {}", input),
+    }
+}
+
+fn discover_map(program: &Vec<i64>) -> Maze {
+    discover_map_with(program, |_, _| {})
+}
+
+fn discover_map_with<F>(program: &Vec<i64>, mut on_move: F) -> Maze
+    where F: FnMut(Pos, Option<(Pos, TileKind)>) // after every command: where the droid is, and what it discovered
 {
-    // walk the terrain and explore the full extent of the map
-    let starting_pos = Pos{x:0, y:0};
+    // walk the terrain depth-first to explore the full extent of the map. the stack holds the droid's way back
+    // to the start: each position on it, along with the next direction to try from there and the input that
+    // gets back to the one below it.
     let mut cpu = CPU::new(program);
-    let mut visited = HashMap::<Pos, TileKind>::new();
-    let mut shortest_paths = HashMap::<Pos, Vec<i64>>::new(); // shortest list of inputs to reach the position
-    let mut current_path: Vec<i64> = vec![];
-
-    visited.insert(starting_pos.clone(), TileKind::Empty);
-    shortest_paths.insert(starting_pos.clone(), vec![]);
-    discover_map_r(&starting_pos, &mut cpu, &mut visited, &mut shortest_paths, &mut current_path);
-    return (visited, shortest_paths);
-}
-fn discover_map_r(pos: &Pos,
-                  cpu: &mut CPU,
-                  visited: &mut HashMap<Pos, TileKind>,
-                  shortest_paths: &mut HashMap<Pos, Vec<i64>>,
-                  current_path: &mut Vec<i64>)
-{
-    // from the current position, try each direction in sequence
-    // (except squares we've already visited)
-    for (move_input, new_pos, return_input) in [(1, pos.up(), 2),
-                                                (2, pos.down(), 1),
-                                                (3, pos.left(), 4),
-                                                (4, pos.right(), 3)].iter()
-    {
-        current_path.push(*move_input);
-
-        // update the shortest path seen to travel to this position
-        let existing_shortest_path = shortest_paths.get(new_pos);
-        match existing_shortest_path {
-            None    => { shortest_paths.insert(new_pos.clone(), current_path.clone()); },
-            Some(p) => {
-                if current_path.len() < p.len() {
-                    shortest_paths.insert(new_pos.clone(), current_path.clone());
-                }
-            },
+    let mut tiles = SparseGrid::<TileKind>::new();
+    tiles.set(0, 0, TileKind::Empty);
+
+    let mut stack: Vec<(Pos, usize, Option<i64>)> = vec![(Pos{x:0, y:0}, 0, None)];
+    while let Some((pos, next_move, return_input)) = stack.last_mut() {
+        if *next_move == MOVES.len() {
+            // tried everything from here; step back to where we were before
+            if let Some(input) = *return_input {
+                cpu.send_input(input);
+                assert!(TileKind::from(cpu.run().consume_output().unwrap()) != TileKind::Wall);
+                on_move(step(pos, input), None);
+            }
+            stack.pop();
+            continue;
         }
-        //shortest_paths.insert(new_pos.clone(),
-        //                 min(*shortest_paths.get(new_pos).unwrap_or(&(steps_taken+1)), steps_taken+1));
+        let (move_input, back_input) = MOVES[*next_move];
+        *next_move += 1;
 
-        if let None = visited.get(&new_pos) {
-            cpu.send_input(*move_input);
-            let tile_kind = TileKind::from(cpu.run().consume_output().unwrap());
-            visited.insert(*new_pos, tile_kind);
+        let new_pos = step(pos, move_input);
+        if tiles.get(new_pos.x, new_pos.y).is_some() {
+            continue;
+        }
+        cpu.send_input(move_input);
+        let tile_kind = TileKind::from(cpu.run().consume_output().unwrap());
+        tiles.set(new_pos.x, new_pos.y, tile_kind);
+        on_move(if tile_kind == TileKind::Wall { *pos } else { new_pos }, Some((new_pos, tile_kind)));
 
-            // if we hit a wall, our position hasn't changed so we can just try the next direction;
-            // otherwise, continue discovering recursively from the new position
-            if tile_kind != TileKind::Wall {
-                // recursively discover further locations
-                discover_map_r(new_pos, cpu, visited, shortest_paths, current_path);
+        // if we hit a wall, our position hasn't changed so we can just try the next direction;
+        // otherwise, continue discovering from the new position
+        if tile_kind != TileKind::Wall {
+            stack.push((new_pos, 0, Some(back_input)));
+        }
+    }
+    Maze { tiles }
+}
 
-                // we need to step back to where we were before trying the next direction.
-                cpu.send_input(*return_input);
-                assert!(TileKind::from(cpu.run().consume_output().unwrap()) != TileKind::Wall);
+fn discover_map_parallel(program: &Vec<i64>) -> Maze {
+    // explores breadth-first instead, without ever walking back: every open position on the frontier has its own
+    // droid standing on it, which gets copied for each direction it tries. the positions on the frontier are all
+    // tried at the same time.
+    let mut tiles = SparseGrid::<TileKind>::new();
+    tiles.set(0, 0, TileKind::Empty);
+    let mut frontier: Vec<(Pos, CPU)> = vec![(Pos{x:0, y:0}, CPU::new(program))];
+
+    while !frontier.is_empty() {
+        let discovered: Vec<(Pos, TileKind, CPU)> = frontier.into_par_iter().flat_map_iter(|(pos, cpu)| {
+            MOVES.iter().map(move |&(move_input, _)| {
+                let mut droid = cpu.clone();
+                droid.send_input(move_input);
+                let tile_kind = TileKind::from(droid.run().consume_output().unwrap());
+                (step(&pos, move_input), tile_kind, droid)
+            }).collect::<Vec<_>>()
+        }).collect();
+
+        // several droids can end up discovering the same position; only the first one carries on from there
+        frontier = Vec::new();
+        for (pos, tile_kind, droid) in discovered {
+            if tiles.get(pos.x, pos.y).is_some() {
+                continue;
+            }
+            tiles.set(pos.x, pos.y, tile_kind);
+            if tile_kind != TileKind::Wall {
+                frontier.push((pos, droid));
             }
         }
+    }
+    Maze { tiles }
+}
+
+fn tile_char(kind: Option<&TileKind>) -> char {
+    match kind {
+        Some(TileKind::Empty)  => '.',
+        Some(TileKind::Wall)   => '#',
+        Some(TileKind::Target) => 'T',
+        None                   => ' ', // unexplored
+    }
+}
+
+fn record_exploration(program: &Vec<i64>, maze: &Maze) -> FrameRecorder {
+    // animation of the droid exploring the maze, one frame per movement command, followed by the oxygen spreading
+    // out from the oxygen system, one frame per minute
+    let bounds = match maze.tiles.bounds() {
+        Some(bounds) => bounds,
+        None         => return FrameRecorder::new(),
+    };
+    let droid_frame = |grid: &SparseGrid<TileKind>, droid: &Pos| {
+        let mut frame = grid.render(&bounds, tile_char);
+        frame.set_checked(droid.x - bounds.min_x, droid.y - bounds.min_y, 'D');
+        frame
+    };
+
+    let mut recorder = FrameRecorder::new();
+    let mut grid = SparseGrid::<TileKind>::new();
+    grid.set(0, 0, TileKind::Empty);
+    recorder.record(droid_frame(&grid, &Pos{x:0, y:0}));
+    discover_map_with(program, |droid, discovered| {
+        if let Some((pos, kind)) = discovered {
+            grid.set(pos.x, pos.y, kind);
+        }
+        recorder.record(droid_frame(&grid, &droid));
+    });
 
-        current_path.pop();
-    }
-}
-#[allow(unused)]
-fn visualize_map(map: &HashMap<Pos, TileKind>) -> String {
-    let mut result = String::new();
-    if map.len() == 0 {
-        return result;
-    }
-    let min_x = map.keys().map(|p| p.x).min().unwrap();
-    let max_x = map.keys().map(|p| p.x).max().unwrap();
-    let min_y = map.keys().map(|p| p.y).min().unwrap();
-    let max_y = map.keys().map(|p| p.y).max().unwrap();
-
-    let w = (max_x - min_x) + 1;
-    let h = (max_y - min_y) + 1;
-    for y in 0..h {
-        for x in 0..w {
-            let pos = Pos{ x: min_x + x, y: min_y + y };
-            let tile_kind = map.get(&pos).unwrap_or(&TileKind::Wall);
-            result.push_str(if pos.x == 0 && pos.y == 0 {
-                                "S "
-                            } else { match tile_kind {
-                                TileKind::Empty       => "  ",
-                                TileKind::Wall        => "# ",
-                                TileKind::Target      => "T ",
-                            }});
+    if let Some(target) = maze.target() {
+        let minutes = maze.distances_from(&target);
+        for minute in 0..=*minutes.values().max().unwrap() {
+            let mut frame = grid.render(&bounds, tile_char);
+            for (pos, _) in minutes.iter().filter(|&(_, &m)| m <= minute) {
+                frame.set_checked(pos.x - bounds.min_x, pos.y - bounds.min_y, 'O');
+            }
+            recorder.record(frame);
         }
-        result.push_str("\n");
     }
+    recorder
+}
 
-    return result;
+fn maze_color(c: char) -> [u8; 3] {
+    match c {
+        '#' => [120, 120, 120],
+        '.' => [20, 20, 20],
+        ' ' => [0, 0, 0],
+        'T' => [0, 200, 0],
+        'O' => [60, 140, 255],
+        _   => [255, 60, 60], // the droid
+    }
 }
 
-pub fn main() {
+pub fn main(args: &ArgMatches) {
     let line: String = util::file_read_lines("input/day15.txt").into_iter().next().unwrap();
     let program: Vec<i64> = line.split(",").map(|s| s.parse().unwrap()).collect();
-    solve(&program);
+    let maze = if args.is_present("parallel") { discover_map_parallel(&program) } else { discover_map(&program) };
+    if args.is_present("verbose") {
+        print!("{}", maze.render());
+    }
+    let solution = solve(&maze).unwrap_or_else(|err| panic!("{}", err));
+    println!("{}", solution.distance);
+    println!("{}", solution.fill_time);
+
+    if args.is_present("render") || args.is_present("gif") {
+        let delay = Duration::from_millis(args.value_of("delay").map_or(10, |s| s.parse().unwrap_or_else(|_| panic!("invalid delay: {}", s))));
+        let recorder = record_exploration(&program, &maze);
+        if args.is_present("render") {
+            recorder.play(&mut io::stdout(), delay).unwrap();
+        }
+        if let Some(filename) = args.value_of("gif") {
+            let scale = args.value_of("scale").map_or(10, |s| s.parse().unwrap_or_else(|_| panic!("invalid scale: {}", s)));
+            File::create(filename).and_then(|f| recorder.write_gif(BufWriter::new(f), scale, delay, maze_color))
+                                  .unwrap_or_else(|err| panic!("failed to write {}: {}", filename, err));
+        }
+    }
 }
 
-fn solve(program: &Vec<i64>) {
-    let (map, paths) = discover_map(program);
-    let target_pos = map.iter().filter(|(_, &tile_kind)| tile_kind == TileKind::Target)
-                               .map(|(p, _)| p)
-                               .nth(0).unwrap();
-    let target_path = paths.get(target_pos).unwrap();
-    //println!("{}", visualize_map(&map));
-    println!("{}", target_path.len());
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Solution {
+    pub distance: usize,  // fewest moves from the starting point to the oxygen system
+    pub fill_time: usize, // minutes until the oxygen has spread everywhere
+}
+
+pub fn solve(maze: &Maze) -> Result<Solution, String> {
+    let target = maze.target().ok_or("no oxygen system found")?;
+    let distance = *maze.distances_from(&Pos{x:0, y:0}).get(&target)
+                        .ok_or("the oxygen system can't be reached from the starting point")?;
 
     // amount of time to fill the whole map with oxygen = largest shortest distance from the target to
     // any other tile on the map.
+    let fill_time = *maze.distances_from(&target).values().max().unwrap();
+    Ok(Solution { distance, fill_time })
+}
 
-    // make a new cpu, move it to the target location, then run another scan from there.
-    let mut cpu = CPU::new(program);
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    for input in target_path {
-        cpu.send_input(*input);
-        cpu.run();
-        assert!(cpu.consume_output().unwrap() != 0); // we shouldn't be hitting a wall at any point here
+    fn parse_maze(lines: &[&str]) -> Maze {
+        // S is the starting point at 0,0 and T the oxygen system; spaces are unexplored
+        let (sx, sy) = lines.iter().enumerate()
+                            .find_map(|(y, line)| line.find('S').map(|x| (x as i64, y as i64)))
+                            .unwrap();
+        let mut tiles = SparseGrid::new();
+        for (y, line) in lines.iter().enumerate() {
+            for (x, c) in line.chars().enumerate() {
+                let kind = match c { '#' => TileKind::Wall, 'T' => TileKind::Target, ' ' => continue, _ => TileKind::Empty };
+                tiles.set(x as i64 - sx, y as i64 - sy, kind);
+            }
+        }
+        Maze { tiles }
     }
 
-    let mut visited = HashMap::<Pos, TileKind>::new(); // unused
-    let mut shortest_paths = HashMap::<Pos, Vec<i64>>::new();
-    discover_map_r(target_pos, &mut cpu, &mut visited, &mut shortest_paths, &mut vec![]);
-    println!("{}", shortest_paths.values().map(|p| p.len()).max().unwrap());
+    fn tiny_droid() -> Vec<i64> {
+        // a droid that finds walls everywhere except right above the start, where the oxygen system is. it keeps
+        // track of whether it's up there in [102].
+        vec![3,100, 1008,100,1,101, 1005,101,21, 1008,100,2,101, 1005,101,33, 104,0, 1105,1,0,
+             1005,102,16, 1101,1,0,102, 104,2, 1105,1,0,  // up
+             1006,102,16, 1101,0,0,102, 104,1, 1105,1,0]  // down
+    }
 
-}
+    #[test]
+    fn parallel() {
+        let program = tiny_droid();
+        let maze = discover_map_parallel(&program);
+        assert_eq!(maze.tiles, discover_map(&program).tiles);
+        assert_eq!(solve(&maze), Ok(Solution { distance: 1, fill_time: 1 }));
+    }
+
+    #[test]
+    fn animation() {
+        let program = tiny_droid();
+        let maze = discover_map(&program);
+        assert_eq!(maze.target(), Some(Pos { x: 0, y: -1 }));
+
+        let frames: Vec<String> = record_exploration(&program, &maze).frames().iter().map(|f| f.to_string()).collect();
+        assert_eq!(frames.len(), 1 + 8 + 2); // start, 7 moves while exploring and 1 back, 2 minutes of oxygen
+        assert_eq!(frames[0], "   \n   \n D \n   \n");
+        assert_eq!(frames[1], "   \n D \n . \n   \n"); // moved up onto the oxygen system
+        assert_eq!(frames[8], " # \n#T#\n#D#\n # \n");
+        assert_eq!(frames[10], " # \n#O#\n#O#\n # \n");
+    }
 
+    #[test]
+    fn oxygen() {
+        let maze = parse_maze(&[" ##   ",
+                          "#S.## ",
+                          "#.#..#",
+                          "#.T.# ",
+                          " ###  "]);
+        let target = maze.target().unwrap();
+        assert_eq!(target, Pos { x: 1, y: 2 });
+        let from_target = maze.distances_from(&target);
+        assert_eq!(from_target.len(), 8);
+        assert_eq!(from_target[&Pos { x: 2, y: 1 }], 2);
+        assert_eq!(solve(&maze), Ok(Solution { distance: 3, fill_time: 4 }));
+        assert_eq!(maze.render().to_string(), " ##   \n#..## \n#.#..#\n#.T.# \n ###  \n");
+
+        let walled_in = parse_maze(&["###", "#S#", "###", "#T#", "###"]);
+        assert_eq!(solve(&walled_in), Err("the oxygen system can't be reached from the starting point".to_string()));
+        assert_eq!(solve(&parse_maze(&["S"])), Err("no oxygen system found".to_string()));
+    }
+}
@@ -1,52 +1,16 @@
 // vim: set ai et ts=4 sts=4 sw=4:
 use crate::util;
-use rulinalg::matrix::Matrix;
-use rulinalg::vector::Vector;
+use clap::ArgMatches;
 use std::mem;
 
-#[inline]
-fn pattern_at(r: usize, c: usize) -> i32 {
-    // pattern value at row r, column c
-    [0, 1, 0, -1][((c+1)/(r+1)) % 4] // floored division
+fn part1(input: &[u8], num_phases: u32) -> u32 {
+    // each phase is evaluated one run of equal pattern values at a time using prefix sums (see fft_phase), so there's
+    // no need to build the full NxN pattern matrix
+    fft(input, num_phases, 1, 0).unwrap_or_else(|err| panic!("{}", err))
 }
 
-#[allow(non_snake_case)]
-fn part1(line: &String, num_phases: u32) -> u32 {
-    let input: Vec<u8> = line.chars().map(|c| c.to_string().parse().unwrap()).collect();
-    let N = input.len();
-
-    let mut data: Vec<i32> = Vec::with_capacity(N*N);
-    for r in 0..N {
-        for c in 0..N {
-            data.push(pattern_at(r,c));
-        }
-    }
-
-    let A = Matrix::new(N, N, data);
-
-    let mut result = Vector::from_fn(N, |idx| input[idx % input.len()] as i32);
-    for _ in 0..num_phases {
-        result = &A * result;
-        result.iter_mut().for_each(|v| *v = v.abs() % 10);
-    }
-
-    let data: &Vec<i32> = result.data();
-    let result = data[0]*10_000_000
-               + data[1]*1_000_000
-               + data[2]*100_000
-               + data[3]*10_000
-               + data[4]*1000
-               + data[5]*100
-               + data[6]*10
-               + data[7];
-    result as u32
-}
-
-#[allow(non_snake_case)]
-fn part2(line: &String, num_phases: u32, scale: u32) -> u32 {
+fn part2(input: &[u8], num_phases: u32, scale: u32) -> u32 {
     // scale = amount of times the input is repeated
-    let input: Vec<u8> = line.chars().map(|c| c.to_string().parse().unwrap()).collect();
-    let N = input.len() * (scale as usize);
 
     //  It's helpful to consider an FFT phase as a matrix multiplication:
     //     A x input = output
@@ -118,41 +82,47 @@ fn part2(line: &String, num_phases: u32, scale: u32) -> u32 {
     //        where N is the size of the input (and also both sizes of the matrix A).
     //        in the example above, this value is indeed ceil((32+1)/3) - 1 = 10.
     //
+    //        further down still, from row floor(N/2) onwards, the single run of 1's reaches all the way to the
+    //        end of the row (in the example above, from row 16 onwards). each output value on those rows is then
+    //        simply the sum of the input values from that position onwards.
+    //
     // the problem statement is only asking for the values of 8 output values at a specific offset.
-    // if that offset is at or beyond the first row without -1's, then we can solve the problem
-    // by computing A^num_phases first, then multiplying with the (repeated) input as described above,
-    // and finally taking the values mod 10.
-
-    let first_line_without_negone: usize = (((N+1) as f64)/3.0f64).ceil() as usize - 1;
-    let message_offset: usize = (input[0] as usize)*1_000_000
-                              + (input[1] as usize)*100_000
-                              + (input[2] as usize)*10_000
-                              + (input[3] as usize)*1000
-                              + (input[4] as usize)*100
-                              + (input[5] as usize)*10
-                              + (input[6] as usize);
-
-    if message_offset >= N {
-        panic!("invalid message offset {}; exceeds input size {}", message_offset, N);
-    }
-    if message_offset < first_line_without_negone {
-        panic!("message offset is not big enough for efficient calculation");
-    }
+    // if that offset is at or beyond the first row whose run of 1's reaches the end, then we can solve
+    // the problem with suffix sums of the (repeated) input as described above, taking the values mod 10
+    // after each phase.
 
-    let N_reduced = N - message_offset;
+    // if the offset comes before that, we do it the hard way, evaluating the full pattern on every row with
+    // fft_phase (still only from the offset onwards, thanks to the upper-triangular property).
+    let message_offset = util::from_digits(&input[..7]) as usize;
+    fft(input, num_phases, scale, message_offset).unwrap_or_else(|err| panic!("{}", err))
+}
 
-    let mut input: Vec<u32> = (message_offset..N).map(|x| input[x % input.len()] as u32).collect();
-    let mut output: Vec<u32> = Vec::with_capacity(input.len());
-    output.resize(input.len(), 0);
+pub fn fft(input: &[u8], num_phases: u32, scale: u32, offset: usize) -> Result<u32, String> {
+    // the 8 digits at the given offset in the output after the given number of phases, of the input repeated
+    // scale times
+    let n = input.len() * (scale as usize);
+    if offset + 8 > n {
+        return Err(format!("invalid message offset {}; the signal is only {} digits long", offset, n));
+    }
+    let first_line_all_ones: usize = n / 2;
+    let signal: Vec<i64> = (offset..n).map(|x| input[x % input.len()] as i64).collect();
+    let output = if offset >= first_line_all_ones {
+        suffix_sum_phases(signal, num_phases)
+    } else {
+        general_phases(signal, offset, num_phases)
+    };
+    Ok(util::from_digits(&output[..8]) as u32)
+}
 
+fn suffix_sum_phases(mut input: Vec<i64>, num_phases: u32) -> Vec<i64> {
+    let mut output: Vec<i64> = vec![0; input.len()];
     for _ in 0..num_phases {
         // we know that there are no -1's on these rows, and therefore there can only be a
         // single run of 1's on these rows.
 
-        // also, the lengths of these runs of 1's are sufficiently large at this point that
+        // also, these runs of 1's all reach the end of the row (the caller makes sure of that), so
         // the matrix is essentially a clean square matrix with all 1's in the upper diagonal
-        // and all 0's in the lower diagonal, so we can avoid duplicate addition work by adding
-        // up values from the input backwards:
+        // and all 0's in the lower diagonal, so each output value is just a suffix sum of the input:
         //
         // 1  1  1  1  1  1  1  1
         // 0  1  1  1  1  1  1  1
@@ -163,40 +133,68 @@ fn part2(line: &String, num_phases: u32, scale: u32) -> u32 {
         // 0  0  0  0  0  0  1  1
         // 0  0  0  0  0  0  0  1
 
-        let mut incr_sum: u32 = 0;
-        for k in 0..N_reduced {
-            incr_sum = (incr_sum + input[N_reduced-1-k]) % 10;
-            output[N_reduced-1-k] = incr_sum;
+        let sums = util::PrefixSums::new(&input);
+        for (k, out) in output.iter_mut().enumerate() {
+            *out = sums.suffix_sum(k) % 10;
         }
 
         mem::swap(&mut input, &mut output);
     }
+    input // actually the output from the last iteration at this point
+}
 
-    let result = input[0]*10_000_000 // note: 'input' is actually output from the last iteration at this point
-               + input[1]*1_000_000
-               + input[2]*100_000
-               + input[3]*10_000
-               + input[4]*1000
-               + input[5]*100
-               + input[6]*10
-               + input[7];
-    result
+fn general_phases(mut input: Vec<i64>, offset: usize, num_phases: u32) -> Vec<i64> {
+    let mut output: Vec<i64> = vec![0; input.len()];
+    for _ in 0..num_phases {
+        fft_phase(&input, offset, &mut output);
+        mem::swap(&mut input, &mut output);
+    }
+    input
 }
 
-pub fn main() {
-    let line: String = util::file_read_lines("input/day16.txt").into_iter().next().unwrap();
+fn fft_phase(signal: &[i64], offset: usize, output: &mut [i64]) {
+    // one phase on the tail end of a signal that starts at the given position. on the row for position k, the
+    // pattern consists of runs of k+1 equal values: a run of 1's starting at column k, then one of 0's, one of
+    // -1's and another one of 0's, and so on. with prefix sums, each run can be summed in constant time, so that
+    // the whole phase takes n/1 + n/2 + n/3 + ... = O(n log n) steps.
+    let sums = util::PrefixSums::new(signal);
+    let end = offset + signal.len();
+    let run_sum = |from: usize, to: usize| { // positions within the full signal
+        let (from, to) = (from.min(end), to.min(end));
+        sums.range_sum(from - offset, to - offset)
+    };
+    for (i, out) in output.iter_mut().enumerate() {
+        let k = offset + i;
+        let len = k + 1;
+        let mut total = 0i64;
+        let mut start = k;
+        while start < end {
+            total += run_sum(start, start + len) - run_sum(start + 2*len, start + 3*len);
+            start += 4*len;
+        }
+        *out = total.abs() % 10;
+    }
+}
 
-    let (input, num_phases, scale) = (line, 100, 10_000);
-    //let (input, num_phases, scale) = (example_input(5).clone().to_string(), 100, 10_000);
-    //let (input, num_phases, scale) = (example_input(1).clone(), 4, 1);
+pub fn main(args: &ArgMatches) {
+    let input = util::Input::for_day(16);
+    let arg = |name: &str| args.value_of(name).map(|s| s.parse::<usize>().unwrap_or_else(|_| panic!("invalid --{}: {}", name, s)));
 
-    println!("{}", part1(&input, num_phases));
-    println!("{}", part2(&input, num_phases, scale));
+    if args.is_present("phases") || args.is_present("repeat") || args.is_present("offset") {
+        // just the one message with the given parameters instead of the two answers
+        let num_phases = arg("phases").unwrap_or(100) as u32;
+        let scale = arg("repeat").unwrap_or(1) as u32;
+        let offset = arg("offset").unwrap_or(0);
+        println!("{}", fft(input.digits(), num_phases, scale, offset).unwrap_or_else(|err| panic!("{}", err)));
+        return;
+    }
+    println!("{}", part1(input.digits(), 100));
+    println!("{}", part2(input.digits(), 100, 10_000));
 }
 
 #[allow(dead_code)]
-fn example_input(n: i32) -> String {
-    match n {
+fn example_input(n: i32) -> util::Input {
+    util::Input::from_string(match n {
         1 => "12345678",
         2 => "80871224585914546619083218645595",
         3 => "19617804207202209144916044189917",
@@ -206,23 +204,92 @@ fn example_input(n: i32) -> String {
         6 => "02935109699940807407585447034323",
         7 => "03081770884921959731165446850517",
         _ => panic!(),
-    }.to_string()
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn naive_phase(signal: &[i64]) -> Vec<i64> {
+        // straight from the puzzle description, one full pattern row at a time
+        (0..signal.len()).map(|r| {
+            let sum: i64 = signal.iter().enumerate().map(|(c, &v)| v * [0, 1, 0, -1][((c+1)/(r+1)) % 4]).sum();
+            sum.abs() % 10
+        }).collect()
+    }
+
+    #[test]
+    fn fft_against_naive() {
+        // random inputs, phase counts, repetitions and offsets
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut random = |n: usize| {
+            // xorshift
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            (seed % n as u64) as usize
+        };
+        for _ in 0..50 {
+            let input: Vec<u8> = (0..1+random(40)).map(|_| random(10) as u8).collect();
+            let (num_phases, scale) = (random(8) as u32, 1 + random(5) as u32);
+            let n = input.len() * scale as usize;
+            if n < 8 {
+                assert!(fft(&input, num_phases, scale, 0).is_err());
+                continue;
+            }
+            let offset = random(n - 7);
+
+            let mut signal: Vec<i64> = input.iter().cycle().take(n).map(|&d| d as i64).collect();
+            for _ in 0..num_phases {
+                signal = naive_phase(&signal);
+            }
+            assert_eq!(fft(&input, num_phases, scale, offset), Ok(util::from_digits(&signal[offset..offset+8]) as u32));
+            assert!(fft(&input, num_phases, scale, n - 7).is_err());
+        }
+    }
+
+    #[test]
+    fn against_naive() {
+        let mut signal: Vec<i64> = example_input(4).digits().iter().cycle().take(200).map(|&d| d as i64).collect();
+        for _ in 0..10 {
+            let expected = naive_phase(&signal);
+            assert_eq!(general_phases(signal.clone(), 0, 1), expected);
+            assert_eq!(general_phases(signal[70..].to_vec(), 70, 1), &expected[70..]);
+            signal = expected;
+        }
+    }
+
     #[test]
-    #[allow(non_snake_case)]
     fn examples() {
-        assert_eq!(part1(&example_input(1), 4), 1029498);
-        assert_eq!(part1(&example_input(2), 100), 24176176);
-        assert_eq!(part1(&example_input(3), 100), 73745418);
-        assert_eq!(part1(&example_input(4), 100), 52432133);
-
-        assert_eq!(part2(&example_input(5), 100, 10_000), 84462026);
-        assert_eq!(part2(&example_input(6), 100, 10_000), 78725270);
-        assert_eq!(part2(&example_input(7), 100, 10_000), 53553731);
+        assert_eq!(part1(example_input(1).digits(), 4), 1029498);
+        assert_eq!(part1(example_input(2).digits(), 100), 24176176);
+        assert_eq!(part1(example_input(3).digits(), 100), 73745418);
+        assert_eq!(part1(example_input(4).digits(), 100), 52432133);
+
+        assert_eq!(part2(example_input(5).digits(), 100, 10_000), 84462026);
+        assert_eq!(part2(example_input(6).digits(), 100, 10_000), 78725270);
+        assert_eq!(part2(example_input(7).digits(), 100, 10_000), 53553731);
+    }
+
+    #[test]
+    fn any_offset() {
+        // the general algorithm agrees with the matrix multiplication from the start
+        assert_eq!(fft(example_input(1).digits(), 4, 1, 0), Ok(1029498));
+        assert_eq!(fft(example_input(2).digits(), 100, 1, 0), Ok(24176176));
+        let signal: Vec<i64> = example_input(3).digits().iter().map(|&d| d as i64).collect();
+        let full = general_phases(signal.clone(), 0, 100);
+        assert_eq!(&full[..8], &[7,3,7,4,5,4,1,8]);
+        assert_eq!(fft(example_input(3).digits(), 100, 1, 5), Ok(util::from_digits(&full[5..13]) as u32));
+        // ... and with the fast path where that one applies
+        assert_eq!(general_phases(signal[20..].to_vec(), 20, 100), suffix_sum_phases(signal[20..].to_vec(), 100));
+
+        // offsets well before the point where the fast path applies, on a repeated input
+        let repeated: Vec<u8> = example_input(4).digits().iter().cycle().take(32*20).cloned().collect();
+        let full = general_phases(repeated.iter().map(|&d| d as i64).collect(), 0, 10);
+        for &offset in &[0, 37, 150, 400] {
+            assert_eq!(fft(example_input(4).digits(), 10, 20, offset), Ok(util::from_digits(&full[offset..offset+8]) as u32));
+            assert_eq!(fft(&repeated, 10, 1, offset), Ok(util::from_digits(&full[offset..offset+8]) as u32));
+        }
     }
 }
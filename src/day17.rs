@@ -1,14 +1,19 @@
 // vim: set ai et ts=4 sts=4 sw=4:
-use std::collections::{HashSet};
-use std::iter::FromIterator;
-use std::io::{self, BufRead};
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::iter::once;
+use std::io::{self, BufRead, Write};
 use std::fmt;
 use std::cmp::{min, max};
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use std::time::Duration;
+use clap::ArgMatches;
 use crate::util;
-use crate::intcode::{CPU, CpuState};
+use crate::intcode::CPU;
 
-type NodeId = usize;
-type Edge = (NodeId,NodeId);
+pub type NodeId = usize;
+pub type Edge = (NodeId,NodeId);
 type Walk = Vec<NodeId>;
 
 enum CallbackResult {
@@ -17,10 +22,10 @@ enum CallbackResult {
 }
 
 #[derive(Debug)]
-struct Node {
-    x: i32, // actually unsigned, but let's use signed to avoid underflows in computations
-    y: i32,
-    id: NodeId,
+pub struct Node {
+    pub x: i32, // actually unsigned, but let's use signed to avoid underflows in computations
+    pub y: i32,
+    pub id: NodeId,
 }
 impl Node {
     pub fn new(x: i32, y: i32, id: NodeId) -> Self {
@@ -40,7 +45,7 @@ macro_rules! undirected_edge {
 }
 
 #[derive(PartialEq, Eq, Debug, Clone, Hash)]
-enum Instr {
+pub enum Instr {
     TurnLeft,
     TurnRight,
     Forward(usize),
@@ -63,7 +68,7 @@ impl fmt::Display for Instr {
 }
 impl Instr {
     #[allow(dead_code)]
-    fn decode(s: &str) -> Option<Self> {
+    pub fn decode(s: &str) -> Option<Self> {
         if let Ok(x) = s.parse::<usize>() {
             return Some(Self::Forward(x));
         }
@@ -94,9 +99,9 @@ macro_rules! instrs {
 }
 
 #[derive(Debug)]
-struct Program {
-    main_program: Vec<Instr>,     // only allowed to contain Instr::SubProgram values
-    subprograms: Vec<Vec<Instr>>, // not allowed to contain Instr::SubProgram calls
+pub struct Program {
+    pub main_program: Vec<Instr>,     // only allowed to contain Instr::SubProgram values
+    pub subprograms: Vec<Vec<Instr>>, // not allowed to contain Instr::SubProgram calls
 }
 impl fmt::Display for Program {
     #[allow(unused_must_use)]
@@ -114,19 +119,63 @@ impl fmt::Display for Program {
     }
 }
 impl Program {
-    fn from_instructions(instrs: &Vec<Instr>) -> Option<Self> {
+    pub fn from_instructions(instrs: &Vec<Instr>) -> Option<Self> {
         // the main program can only contain subprogram calls, and subprograms cannot call other subprograms
         // either, so the problem here is to find a way to fully segment the program into (up to) 3 segments
         // such that each segment is <= 20 chars in string form.
         if let Some(segmentation) = Segmentation::find_segmentation(&instrs) {
             let (segments, arrangement) = segmentation;
-            return Some(Self {
+            let program = Self {
                 main_program: arrangement.iter().map(|&idx| Instr::SubProgram(idx)).collect(),
                 subprograms:  segments.iter().map(|slice| slice.to_vec()).collect(),
-            });
+            };
+            return program.validate().ok().map(|_| program);
         }
         None
     }
+    pub fn validate(&self) -> Result<(), String> {
+        // checks the rules the robot imposes on its movement routines
+        if self.subprograms.len() > 3 {
+            return Err(format!("{} subprograms, only A, B and C are available", self.subprograms.len()));
+        }
+        for instr in &self.main_program {
+            match instr {
+                Instr::SubProgram(n) if *n < self.subprograms.len() => {},
+                Instr::SubProgram(n) => return Err(format!("main program calls undefined subprogram {}", n)),
+                _                    => return Err(format!("main program can only call subprograms, found {}", instr)),
+            }
+        }
+        for (i, sub) in self.subprograms.iter().enumerate() {
+            let name = (b'A' + i as u8) as char;
+            if sub.is_empty() {
+                return Err(format!("subprogram {} is empty", name));
+            }
+            if sub.iter().any(|instr| matches!(instr, Instr::SubProgram(_))) {
+                return Err(format!("subprogram {} calls another subprogram", name));
+            }
+        }
+        for (i, line) in self.to_input_lines().iter().enumerate() {
+            if line.len() > PROGRAM_MAX_LEN {
+                let name = if i == 0 { "main program".to_string() } else { format!("subprogram {}", (b'A' + i as u8 - 1) as char) };
+                return Err(format!("{} is {} characters long, more than the {} allowed", name, line.len(), PROGRAM_MAX_LEN));
+            }
+        }
+        Ok(())
+    }
+    pub fn to_input_lines(&self) -> Vec<String> {
+        // the main program and the three subprograms in the form the robot expects them
+        let mut lines = vec![format_program!(self.main_program)];
+        lines.extend((0..3).map(|i| format_program!(self.subprograms.get(i).unwrap_or(&vec![]))));
+        lines
+    }
+    #[allow(dead_code)]
+    pub fn expand(&self) -> Vec<Instr> {
+        // the movement instructions with the subprogram calls filled in
+        self.main_program.iter().flat_map(|instr| match instr {
+            Instr::SubProgram(n) => self.subprograms[*n].clone(),
+            _                    => vec![instr.clone()],
+        }).collect()
+    }
 }
 struct Segmentation {
 
@@ -153,8 +202,11 @@ impl Segmentation {
 
         // if we've consumed all instructions, and the total string length of the main
         // program fits in the allowed space, then we've found a solution
-        if offset == L && 2*arrangement.len() - 1 <= PROGRAM_MAX_LEN {
-            // TODO: hardcodes knowledge that each subprogram instruction takes up 1 char in size
+        // TODO: hardcodes knowledge that each subprogram instruction takes up 1 char in size
+        if !arrangement.is_empty() && 2*arrangement.len() - 1 > PROGRAM_MAX_LEN {
+            return false; // main program is already too long, no point in going on
+        }
+        if offset == L {
             return true;
         }
 
@@ -210,45 +262,40 @@ impl Segmentation {
 }
 
 #[derive(PartialEq, Eq, Copy, Clone, Hash, Debug)]
-enum Orientation {
+pub enum Orientation {
     North,
     South,
     East,
     West,
 }
 impl Orientation {
-    pub fn rotation_move_to(&self, other: &Orientation) -> Option<Instr> {
+    pub fn left(&self) -> Orientation {
         match self {
-            Orientation::North => match other {
-                                      Orientation::North => None,
-                                      Orientation::South => panic!(),
-                                      Orientation::East  => Some(Instr::TurnRight),
-                                      Orientation::West  => Some(Instr::TurnLeft),
-                                  },
-            Orientation::South => match other {
-                                      Orientation::North => panic!(),
-                                      Orientation::South => None,
-                                      Orientation::East  => Some(Instr::TurnLeft),
-                                      Orientation::West  => Some(Instr::TurnRight),
-                                  },
-            Orientation::East  => match other {
-                                      Orientation::North => Some(Instr::TurnLeft),
-                                      Orientation::South => Some(Instr::TurnRight),
-                                      Orientation::East  => None,
-                                      Orientation::West  => panic!(),
-                                  },
-            Orientation::West  => match other {
-                                      Orientation::North => Some(Instr::TurnRight),
-                                      Orientation::South => Some(Instr::TurnLeft),
-                                      Orientation::East  => panic!(),
-                                      Orientation::West  => None,
-                                  },
+            Orientation::North => Orientation::West,
+            Orientation::West  => Orientation::South,
+            Orientation::South => Orientation::East,
+            Orientation::East  => Orientation::North,
+        }
+    }
+    pub fn right(&self) -> Orientation {
+        self.left().left().left()
+    }
+    pub fn rotation_move_to(&self, other: &Orientation) -> Vec<Instr> {
+        // the turns needed to face the other way; turning around takes two of them
+        if other == self {
+            vec![]
+        } else if *other == self.left() {
+            vec![Instr::TurnLeft]
+        } else if *other == self.right() {
+            vec![Instr::TurnRight]
+        } else {
+            vec![Instr::TurnLeft, Instr::TurnLeft]
         }
     }
 }
 
 #[derive(Debug)]
-struct Graph {
+pub struct Graph {
     nodes: Vec<Node>,
     adjacency: Vec<HashSet<NodeId>>, // nodeID => set of adjacent nodeIDs
     start_node_id: NodeId,
@@ -258,8 +305,9 @@ impl Graph {
     pub fn from_lines(lines: &Vec<String>) -> Self {
         // parse a description of the playing field as an incoming set of strings
         // and construct the corresponding graph for it
+        time_scope!("day17 scaffold graph");
         let h = lines.len();
-        let w = lines[0].len();
+        let w = lines.iter().map(|l| l.len()).max().unwrap_or(0);
 
         let mut nodes = Vec::<Node>::new();
         let mut start_node_id: Option<NodeId> = None;
@@ -267,7 +315,7 @@ impl Graph {
 
         macro_rules! tile_char_at {
             ($x:expr, $y:expr) => {
-                lines[$y as usize].chars().nth($x as usize).unwrap()
+                lines[$y as usize].chars().nth($x as usize).unwrap_or('.') // lines might not all be the same length
             }
         }
         macro_rules! is_scaffold_char {
@@ -286,23 +334,21 @@ impl Graph {
 
         for y in 0..h {
             for x in 0..w {
-                // a spot on the map is a node if it doesn't have exactly two # neighbours along the same axis
-                // (i.e. either the start of a segment, a bend in a segment, or an intersection).
-                // the initial position of the robot is on a node and marked as the starting node.
+                // a spot on the map is a node unless it's part of a straight segment, i.e. it has exactly two
+                // scaffold neighbours along the same axis (so it's either the start of a segment, a bend in a segment,
+                // or an intersection). the initial position of the robot is on a node and marked as the starting node.
 
                 let this_char = tile_char_at!(x, y);
                 if !is_scaffold_char!(this_char) {
                     continue;
                 }
-                let has_two_neighbours_hor = (x > 0 && x < w-1) && is_scaffold_tile_at!(x-1, y)
-                                                                && is_scaffold_tile_at!(x+1, y);
-                let has_two_neighbours_ver = (y > 0 && y < h-1) && is_scaffold_tile_at!(x, y-1)
-                                                                && is_scaffold_tile_at!(x, y+1);
-
-                // we're on a node if either has_two_n_hor and has_two_n_v are both false (a bend or a segment start)
-                // or if both are true (an intersection)
-                if (!has_two_neighbours_hor && !has_two_neighbours_ver) ||
-                    (has_two_neighbours_hor && has_two_neighbours_ver)
+                let left  = x > 0   && is_scaffold_tile_at!(x-1, y);
+                let right = x < w-1 && is_scaffold_tile_at!(x+1, y);
+                let up    = y > 0   && is_scaffold_tile_at!(x, y-1);
+                let down  = y < h-1 && is_scaffold_tile_at!(x, y+1);
+
+                let straight = (left && right && !up && !down) || (up && down && !left && !right);
+                if !straight || ['^', '<', '>', 'v'].contains(&this_char)
                 {
                     let node = Node::new(x as i32, y as i32, nodes.len());
                     if ['^', '<', '>', 'v'].contains(&this_char) {
@@ -364,7 +410,7 @@ impl Graph {
         Self {
             nodes,
             adjacency,
-            start_node_id: start_node_id.unwrap(),
+            start_node_id: start_node_id.expect("no robot on the map"),
             start_orientation: start_orientation.unwrap(),
         }
     }
@@ -382,42 +428,48 @@ impl Graph {
     }
 }
 
-fn generate_walks<F>(g: &Graph, mut callback: F)
+fn generate_walks<F>(g: &Graph, max_uses: usize, mut callback: F)
     where F: FnMut(&Walk) -> CallbackResult
 {
+    // generates walks that take every edge at least once and at most max_uses times
     let mut walk = vec![g.start_node_id];
-    let mut remaining_edges = HashSet::from_iter(g.edges());
+    let mut uses: HashMap<Edge, usize> = g.edges().into_iter().map(|e| (e, 0)).collect();
+    let mut uncovered = uses.len();
 
-    generate_walks_r(g, &mut callback, &mut walk, &mut remaining_edges);
+    generate_walks_r(g, max_uses, &mut callback, &mut walk, &mut uses, &mut uncovered);
 }
 fn generate_walks_r<F>(g: &Graph,
+                       max_uses: usize,
                        f: &mut F,
                        walk: &mut Walk,
-                       remaining_edges: &mut HashSet<Edge>) -> CallbackResult
+                       uses: &mut HashMap<Edge, usize>,
+                       uncovered: &mut usize) -> CallbackResult
     where F: FnMut(&Walk) -> CallbackResult
 {
+    if *uncovered == 0 {
+        return f(walk); // found a walk, call the callback function
+    }
     let current_node = walk[walk.len()-1];
 
-    // recursively visit each of the current node's neighbours, assuming that edge has not yet been visited
+    // recursively visit each of the current node's neighbours, as long as we haven't used up that edge yet
     for &nb_id in &g.adjacency[current_node] {
         let edge = undirected_edge!(current_node, nb_id);
-        if remaining_edges.contains(&edge) {
-            remaining_edges.remove(&edge);
+        let n = uses[&edge];
+        if n < max_uses {
+            uses.insert(edge, n+1);
+            if n == 0 { *uncovered -= 1; }
             walk.push(nb_id);
 
-            let cb_result = generate_walks_r(g, f, walk, remaining_edges);
+            let cb_result = generate_walks_r(g, max_uses, f, walk, uses, uncovered);
             if let CallbackResult::Stop = cb_result {
                 return cb_result;
             }
 
             walk.pop();
-            remaining_edges.insert(edge);
+            if n == 0 { *uncovered += 1; }
+            uses.insert(edge, n);
         }
     }
-
-    if remaining_edges.len() == 0 {
-        return f(&walk); // found a walk, call the callback function
-    }
     CallbackResult::Continue
 }
 
@@ -445,11 +497,7 @@ fn make_instructions(g: &Graph, walk: &Walk) -> Vec<Instr> {
 
         // do we need to turn before we can take this edge in this direction?
         let edge_orientation = edge_orientation(&current_node, &next_node);
-        if edge_orientation != current_orientation {
-            if let Some(move_instr) = current_orientation.rotation_move_to(&edge_orientation) {
-                result.push(move_instr);
-            }
-        }
+        result.extend(current_orientation.rotation_move_to(&edge_orientation));
         current_orientation = edge_orientation;
 
         // we've rotated if needed; now move forward along the edge
@@ -463,79 +511,135 @@ fn make_instructions(g: &Graph, walk: &Walk) -> Vec<Instr> {
     return result;
 }
 
-pub fn main() {
+pub fn main(args: &ArgMatches) {
+    if let Some(filename) = args.value_of("camera") {
+        // work from a map in a text file instead of the one the robot's camera sees, and just show the program
+        // we'd give it
+        let lines: Vec<String> = util::file_read_lines(filename).into_iter().filter(|l| !l.trim().is_empty()).collect();
+        let g = Graph::from_lines(&lines);
+        println!("{}", part1(&g));
+        match find_program(&g) {
+            Some(p) => print!("{}", p),
+            None    => println!("no solution found :("),
+        }
+        return;
+    }
+
     let line: String = util::file_read_lines("input/day17.txt").into_iter().next().unwrap();
     let program: Vec<i64> = line.split(",").map(|s| s.parse().unwrap()).collect();
 
-    let mut cpu = CPU::new(&program);
-    cpu.run();
-    let lines: Vec<String> = cpu.consume_output_all().into_iter()
-                                .map(|n| char::from(n as u8)).collect::<String>()
-                                .trim().lines().map(String::from).collect();
+    let lines: Vec<String> = {
+        time_scope!("day17 camera");
+        let mut cpu = CPU::new(&program);
+        cpu.run();
+        cpu.consume_output_all().into_iter()
+           .map(|n| char::from(n as u8)).collect::<String>()
+           .trim().lines().map(String::from).collect()
+    };
 
     let g = Graph::from_lines(&lines);
-    part1(&g);
-    part2(&g, &program);
+    println!("{}", part1(&g));
+
+    let delay = Duration::from_millis(args.value_of("delay").map_or(30, |s| s.parse().unwrap_or_else(|_| panic!("invalid delay: {}", s))));
+    if args.is_present("interactive") {
+        interactive(&program, delay);
+    } else {
+        part2(&g, &program, args.is_present("render").then_some(delay));
+    }
 }
 
-fn part1(g: &Graph) {
-    println!("{}", g.nodes.iter().filter(|n| g.adjacency[n.id].len() > 2)
-                                 .map(|n| n.x*n.y)
-                                 .sum::<i32>());
+fn part1(g: &Graph) -> i32 {
+    g.nodes.iter().filter(|n| g.adjacency[n.id].len() == 4) // intersections, not just any junction
+                  .map(|n| n.x*n.y)
+                  .sum()
 }
 
-fn part2(g: &Graph, original_program: &Vec<i64>) {
+fn part2(g: &Graph, original_program: &Vec<i64>, video_feed: Option<Duration>) {
+    // if video_feed is given, asks for the continuous video feed and draws it to the terminal, with the given
+    // delay between frames
     let mut cpu = CPU::new(&original_program);
     cpu.write_mem(0, 2);
 
-    let interactive = false;
-    if !interactive {
-        // strap in, this is gonna take a while
-        let p = match find_program(g) {
-            Some(p) => p,
-            None    => { println!("no solution found :("); return; }
-        };
+    let p = match find_program(g) {
+        Some(p) => p,
+        None    => { println!("no solution found :("); return; }
+    };
 
-        // note: no subprogram can be empty, will be rejected
+    // note: no subprogram can be empty, will be rejected
 
-        // send main program
-        cpu.send_input_string(&format_program!(p.main_program));
-        cpu.send_input_string("\n");
+    // main program, then the subprograms, then the answer to the video feed prompt
+    let mut input = p.to_input_lines().join("\n") + "\n";
+    input += if video_feed.is_some() { "y\n" } else { "n\n" };
 
-        // send subprograms
-        for i in 0..3 {
-            cpu.send_input_string(&format_program!(p.subprograms.get(i).unwrap_or(&vec![])));
-            cpu.send_input_string("\n");
-        }
-
-        // video feed prompt?
-        cpu.send_input_string("n\n");
-        cpu.run();
-
-        println!("{}", cpu.consume_output_last().unwrap());
-    }
-    else {
-        // for interactive mode:
-        loop {
+    match video_feed {
+        None => {
+            cpu.send_input_string(&input);
             cpu.run();
-            let lines: Vec<String> = cpu.consume_output_all().into_iter()
-                                        .map(|n| char::from(n as u8)).collect::<String>()
-                                        .trim().lines().map(String::from).collect();
-            for line in lines {
-                println!("{}", line);
+            println!("{}", cpu.consume_output_last().unwrap());
+        },
+        Some(delay) => {
+            let (tx, rx) = channel();
+            for b in input.bytes() {
+                tx.send(b as i64).unwrap();
             }
-            match cpu.get_state() {
-                CpuState::Running => panic!(), // can't be running, we just returned from it running
-                CpuState::Halted  => { break; },
-                CpuState::WaitIO  => {
-                    // read a single line from stdin and feed it to the cpu
-                    let mut line = String::new();
-                    io::stdin().lock().read_line(&mut line).unwrap(); // includes \n at the end
-                    cpu.send_input_string(&line);
-                },
+            drop(tx);
+            watch(cpu, rx, delay);
+        },
+    }
+}
+
+fn interactive(original_program: &Vec<i64>, delay: Duration) {
+    // lets you type in the movement routines yourself, one line at a time (the main routine, then functions A, B
+    // and C), then asks for the continuous video feed so that you can watch the robot follow them
+    let mut cpu = CPU::new(original_program);
+    cpu.write_mem(0, 2);
+
+    let (tx, rx) = channel();
+    thread::spawn(move || {
+        let routines = io::stdin().lock().lines().take(4).map(|line| line.unwrap());
+        for line in routines.chain(once(String::from("y"))) {
+            for b in line.bytes().chain(once(b'\n')) {
+                if tx.send(b as i64).is_err() {
+                    return; // robot is done
+                }
             }
         }
+    });
+    watch(cpu, rx, delay);
+}
+
+fn watch(mut cpu: CPU, input: Receiver<i64>, delay: Duration) {
+    // runs the robot on its own thread and prints its output as it comes in. the camera images are followed by
+    // a blank line; after each one, pause for a bit and start drawing again from the top of the screen, so that
+    // the frames of the video feed draw over each other.
+    let (tx, output) = channel();
+    let robot = thread::spawn(move || { cpu.run_with_channels(&input, &tx); });
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let mut line = String::new();
+    let mut new_frame = false;
+    for value in output {
+        match u8::try_from(value) {
+            Ok(b'\n') if line.is_empty() => {
+                out.flush().unwrap();
+                thread::sleep(delay);
+                new_frame = true;
+            },
+            Ok(b'\n') => {
+                if new_frame {
+                    write!(out, "\x1b[2J\x1b[H").unwrap(); // clear screen, cursor to top left
+                    new_frame = false;
+                }
+                writeln!(out, "{}", line).unwrap();
+                line.clear();
+            },
+            Ok(b) if b.is_ascii() => line.push(b as char),
+            _ => writeln!(out, "{}", value).unwrap(), // amount of dust collected
+        }
+        out.flush().unwrap();
     }
+    robot.join().unwrap();
 }
 
 
@@ -543,35 +647,74 @@ fn find_program(g: &Graph) -> Option<Program> {
     // given a graph representing the puzzle input, find a 'program' for the robot to travel each
     // consisting of a main and 3 subroutines, all of which of string length <= 20,
     // such that the robot travels every path segment at least once.
+    //
+    // the scaffolds are laid out so that the robot can simply go straight through every intersection and only
+    // turn where the scaffold bends, so try that one first; it's a single walk, and segmenting it is quick.
+    time_scope!("day17 movement routines");
+    let walk = greedy_walk(g);
+    if walk.len() == g.edges().len() + 1 {
+        if let Some(program) = Program::from_instructions(&maximally_merge_instructions(&make_instructions(g, &walk))) {
+            return Some(program);
+        }
+    }
 
+    // no luck; fall back to trying every walk through the graph that visits each edge (not node!) exactly once.
+    // whenever one is found, run a callback function that produces the set of instructions for that walk, and
+    // then tries to see if it will fit in the size limitations of the program to be generated.
+    // not every scaffold can be walked that way (e.g. when it has more than two dead ends), so if that doesn't
+    // work out either, allow taking edges twice, turning around where needed.
+    // (strap in, this can take a while)
     let mut program: Option<Program> = None;
-
-    // generate walks through the graph that visit each edge (not node!) exactly once; whenever one is found,
-    // run a callback function that produces the set of instructions for that walk, and then tries
-    // to see if it will fit in the size limitations of the program to be generated.
-
-    generate_walks(g, |walk| {
-        let instrs = make_instructions(g, walk);
-        // the list of walk instructions might contain consecutive steps forward that can be merged
-        // into a single bigger forward move. And wouldn't you know it, a given puzzle might not have any paths
-        // that can be decomposed into A/B/C subprograms unless one or more or any of those mergers are performed
-        // (the example given in part 2 appears to be such a puzzle).
-        //
-        // The amount of variants a single program can have grows very quickly though (order of n factorial?),
-        // so checking all of them is infeasible. Instead, we'll only take the maximally-reduced version of
-        // each program and check that one.
-
-        let merged_variant = maximally_merge_instructions(&instrs);
-        program = Program::from_instructions(&merged_variant);
-        match program {
-            Some(_) => CallbackResult::Stop,
-            None    => CallbackResult::Continue,
+    for max_uses in 1..=2 {
+        generate_walks(g, max_uses, |walk| {
+            let instrs = make_instructions(g, walk);
+            // the list of walk instructions might contain consecutive steps forward that can be merged
+            // into a single bigger forward move. And wouldn't you know it, a given puzzle might not have any paths
+            // that can be decomposed into A/B/C subprograms unless one or more or any of those mergers are performed
+            // (the example given in part 2 appears to be such a puzzle).
+            //
+            // The amount of variants a single program can have grows very quickly though (order of n factorial?),
+            // so checking all of them is infeasible. Instead, we'll only take the maximally-reduced version of
+            // each program and check that one.
+
+            let merged_variant = maximally_merge_instructions(&instrs);
+            program = Program::from_instructions(&merged_variant);
+            match program {
+                Some(_) => CallbackResult::Stop,
+                None    => CallbackResult::Continue,
+            }
+        });
+        if program.is_some() {
+            break;
         }
-    });
+    }
 
     program
 }
 
+fn greedy_walk(g: &Graph) -> Walk {
+    // walk from the starting position, going straight ahead whenever possible and turning left or right
+    // otherwise, until we hit a dead end (or an edge we've already taken)
+    let mut walk = vec![g.start_node_id];
+    let mut orientation = g.start_orientation;
+    let mut taken = HashSet::<Edge>::new();
+    loop {
+        let current = walk[walk.len()-1];
+        let towards = |o: Orientation| g.adjacency[current].iter().cloned()
+                                        .find(|&nb| edge_orientation(&g.nodes[current], &g.nodes[nb]) == o);
+        let next = towards(orientation).or_else(|| towards(orientation.left()))
+                                       .or_else(|| towards(orientation.right()));
+        match next {
+            Some(nb) if taken.insert(undirected_edge!(current, nb)) => {
+                orientation = edge_orientation(&g.nodes[current], &g.nodes[nb]);
+                walk.push(nb);
+            },
+            _ => break,
+        }
+    }
+    walk
+}
+
 fn maximally_merge_instructions(instrs: &Vec<Instr>) -> Vec<Instr> {
     let mut working_copy = instrs.clone();
     let mut i = 0usize;
@@ -587,6 +730,7 @@ fn maximally_merge_instructions(instrs: &Vec<Instr>) -> Vec<Instr> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::iter::FromIterator;
 
     fn get_example_1() -> Vec<String> {
         vec![
@@ -599,7 +743,6 @@ mod tests {
            "..#####...^..",
         ].into_iter().map(|s| s.to_string()).collect()
     }
-    #[allow(dead_code)]
     fn get_example_2() -> Vec<String> {
         vec![
             "#######...#####",
@@ -712,6 +855,28 @@ mod tests {
         let reduced = reduced.unwrap();
         assert!(format_program!(&reduced.main_program).len() <= PROGRAM_MAX_LEN);
         assert!(reduced.subprograms.iter().all(|sp| format_program!(sp).len() <= PROGRAM_MAX_LEN));
+        assert_eq!(reduced.validate(), Ok(()));
+        assert_eq!(reduced.expand(), program);
+    }
+
+    #[test]
+    fn validate_program() {
+        let program = |main: &str, subs: &[&str]| Program {
+            main_program: instrs!(main),
+            subprograms:  subs.iter().map(|&sub| instrs!(sub)).collect(),
+        };
+        let p = program("A,B,A,C", &["R,8,R,8", "R,4,R,4,R,8", "L,6,L,2"]);
+        assert_eq!(p.validate(), Ok(()));
+        assert_eq!(p.to_input_lines(), vec!["A,B,A,C", "R,8,R,8", "R,4,R,4,R,8", "L,6,L,2"]);
+        assert_eq!(program("A", &["R,8"]).to_input_lines(), vec!["A", "R,8", "", ""]);
+
+        assert!(program("A,R", &["R,8"]).validate().unwrap_err().contains("only call subprograms"));
+        assert!(program("A,B", &["R,8"]).validate().unwrap_err().contains("undefined"));
+        assert!(program("A", &["R,8,B", "L,2"]).validate().unwrap_err().contains("calls another"));
+        assert_eq!(program("A", &["R,10,R,10,R,10,R,10,R,10"]).validate(),
+                   Err("subprogram A is 24 characters long, more than the 20 allowed".to_string()));
+        assert_eq!(program("A,A,A,A,A,A,A,A,A,A,A", &["R,8"]).validate(),
+                   Err("main program is 21 characters long, more than the 20 allowed".to_string()));
     }
 
     #[test]
@@ -722,6 +887,55 @@ mod tests {
         );
     }
 
+    #[test]
+    fn greedy_program() {
+        let g = Graph::from_lines(&get_example_2());
+        let walk = greedy_walk(&g);
+        assert_eq!(walk.len(), g.edges().len() + 1);
+        assert_eq!(maximally_merge_instructions(&make_instructions(&g, &walk)),
+                   instrs!("R,8,R,8,R,4,R,4,R,8,L,6,L,2,R,4,R,4,R,8,R,8,R,8,L,6,L,2"));
+
+        let p = find_program(&g).unwrap();
+        let expanded = p.expand();
+        assert_eq!(expanded, instrs!("R,8,R,8,R,4,R,4,R,8,L,6,L,2,R,4,R,4,R,8,R,8,R,8,L,6,L,2"));
+    }
+
+    #[test]
+    fn revisits() {
+        assert_eq!(Orientation::North.rotation_move_to(&Orientation::South), vec![Instr::TurnLeft, Instr::TurnLeft]);
+        assert_eq!(Orientation::East.rotation_move_to(&Orientation::North), vec![Instr::TurnLeft]);
+
+        // three dead ends, so there's no way to get through without going back over an edge; the robot also
+        // starts out facing away from the scaffold
+        let g = Graph::from_lines(&vec![".......", ".#####.", "...#...", "...v...", "......."].into_iter().map(String::from).collect());
+        let p = find_program(&g).unwrap();
+        let expanded = p.expand();
+        assert!(expanded == instrs!("L,L,2,L,2,L,L,4") || expanded == instrs!("L,L,2,R,2,L,L,4"));
+    }
+
+    #[test]
+    fn offline() {
+        assert_eq!(part1(&Graph::from_lines(&get_example_1())), 76);
+        assert_eq!(part1(&Graph::from_lines(&get_example_2())), 6*6 + 8*8 + 12*8 + 8*10);
+
+        // lines of different lengths
+        let g = Graph::from_lines(&vec!["#####", "#", "^"].into_iter().map(String::from).collect());
+        assert_eq!(find_program(&g).unwrap().expand(), instrs!("2,R,4"));
+
+        // a staircase with steps that keep getting longer; there's only one way to walk it, and it has too many
+        // different moves to fit into three subprograms
+        let mut lines = vec![String::from("v")];
+        let mut x = 0;
+        for step in 1..=12 {
+            lines.extend((0..step).map(|_| format!("{}#", " ".repeat(x))));
+            lines.push(format!("{}{}", " ".repeat(x), "#".repeat(step+1)));
+            x += step;
+        }
+        let g = Graph::from_lines(&lines);
+        assert_eq!(greedy_walk(&g).len(), g.edges().len() + 1);
+        assert!(find_program(&g).is_none());
+    }
+
     #[test]
     fn experimentation() {
         let prog = &instrs!("R,8,R,8,R,4,R,4,R,8,L,6,L,2,R,4,R,4,R,8,R,8,R,8,L,6,L,2");
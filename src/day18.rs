@@ -3,11 +3,13 @@ use crate::util;
 use std::fmt;
 use std::iter::{FromIterator, Iterator, IntoIterator, Extend};
 use std::ops::{Index, IndexMut, Add, Sub, AddAssign};
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
 use std::convert::From;
 use crate::path;
+use clap::ArgMatches;
 
-#[derive(PartialEq, Eq, Copy, Clone, Debug, Hash)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Debug, Hash)]
 struct Pos {
     pub x: i32,
     pub y: i32,
@@ -35,7 +37,7 @@ impl fmt::Display for Pos {
     }
 }
 
-#[derive(PartialEq, Eq, Debug, Hash)]
+#[derive(PartialEq, Eq, Clone, Debug, Hash)]
 enum TileKind {
     Empty,
     Wall,
@@ -77,12 +79,6 @@ impl Tile {
             kind: TileKind::from(c),
         }
     }
-    fn key_char(&self) -> Option<char> {
-        match self.kind {
-            TileKind::Key(c) => Some(c),
-            _                => None,
-        }
-    }
 }
 impl fmt::Display for Tile {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -94,7 +90,7 @@ struct Map {
     w: usize,
     h: usize,
     tiles: Vec<Vec<Tile>>,
-    starting_pos: Pos,
+    starting_positions: Vec<Pos>, // one for each robot
 }
 impl Map {
     pub fn new(lines: &Vec<String>) -> Self {
@@ -102,7 +98,7 @@ impl Map {
         let w = lines[0].len();
 
         let mut tiles = Vec::new();
-        let mut starting_pos: Option<Pos> = None;
+        let mut starting_positions = Vec::new();
 
         for (y, line) in lines.iter().enumerate() {
             let mut row_tiles = Vec::new();
@@ -111,7 +107,7 @@ impl Map {
                 let tile: Tile;
                 match c {
                     '@' => {
-                        starting_pos = Some(pos.clone());
+                        starting_positions.push(pos);
                         tile = Tile { pos, kind: TileKind::Empty };
                     },
                     _   => {
@@ -123,19 +119,54 @@ impl Map {
             tiles.push(row_tiles);
         }
 
+        if starting_positions.is_empty() {
+            panic!("no starting position on the map");
+        }
         Self {
             w,
             h,
             tiles,
-            starting_pos: starting_pos.unwrap(),
+            starting_positions,
+        }
+    }
+    pub fn split_into_vaults(&self) -> Result<Map, String> {
+        // part 2: replaces the area around the single starting position by walls, splitting the map into four
+        // vaults with a robot in each of them:
+        //
+        //    ...      @#@
+        //    .@.  =>  ###
+        //    ...      @#@
+        let center = match self.starting_positions[..] {
+            [pos] => pos,
+            _     => return Err(format!("expected a single starting position, found {}", self.starting_positions.len())),
+        };
+        let mut result = Map {
+            w: self.w,
+            h: self.h,
+            tiles: self.tiles.iter().map(|row| row.iter().map(|t| Tile { pos: t.pos, kind: t.kind.clone() }).collect()).collect(),
+            starting_positions: Vec::new(),
+        };
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                let pos = center + Pos::new(dx, dy);
+                if pos.x < 0 || pos.y < 0 || pos.x >= self.w as i32 || pos.y >= self.h as i32 || self[pos].kind != TileKind::Empty {
+                    return Err(format!("the area around the starting position {} is not open", center));
+                }
+                if dx != 0 && dy != 0 {
+                    result.starting_positions.push(pos);
+                } else {
+                    result[pos].kind = TileKind::Wall;
+                }
+            }
         }
+        Ok(result)
     }
     pub fn iter(&self) -> MapIterator {
         MapIterator { map: &self, counter: 0 }
     }
     #[allow(dead_code)]
     pub fn label_at(&self, pos: &Pos) -> String {
-        if *pos == self.starting_pos {
+        if self.starting_positions.contains(pos) {
             "@".to_owned()
         } else {
             self[*pos].kind.to_string()
@@ -143,14 +174,14 @@ impl Map {
     }
     #[allow(dead_code)]
     pub fn visualize(&self) -> String {
-        self.visualize_at(&self.starting_pos, &vec![])
+        self.visualize_at(&self.starting_positions, &[])
     }
     #[allow(dead_code)]
-    pub fn visualize_at(&self, pos: &Pos, keys_collected: &Vec<char>) -> String {
+    pub fn visualize_at(&self, robots: &[Pos], keys_collected: &[char]) -> String {
         let mut result = String::new();
         for y in 0..self.h {
             for x in 0..self.w {
-                if *pos == Pos::new(x as i32, y as i32) {
+                if robots.contains(&Pos::new(x as i32, y as i32)) {
                     result.push_str("@");
                 } else {
                     let tile = &self.tiles[y][x];
@@ -165,6 +196,28 @@ impl Map {
         }
         return result;
     }
+    pub fn visualize_route(&self, solution: &Solution) -> String {
+        // the map with the tiles the robots walk over marked with a *, the keys numbered in the order they're
+        // picked up, and the doors they pass through opened up as a /
+        let order: HashMap<char, usize> = solution.pickups.iter().enumerate().map(|(i, p)| (p.key, i+1)).collect();
+        let walked: HashSet<Pos> = solution.paths.iter().flatten().cloned().collect();
+        let mut result = String::new();
+        for y in 0..self.h {
+            for x in 0..self.w {
+                let pos = Pos::new(x as i32, y as i32);
+                let label = match self[pos].kind {
+                    _ if self.starting_positions.contains(&pos) => "@".to_string(),
+                    TileKind::Key(c) if order.contains_key(&c) => order[&c].to_string(),
+                    TileKind::Door(_) if walked.contains(&pos) => "/".to_string(),
+                    TileKind::Empty if walked.contains(&pos)   => "*".to_string(),
+                    ref kind => kind.to_string(),
+                };
+                result.push_str(&format!("{:<2}", label));
+            }
+            result.push('\n');
+        }
+        result
+    }
 }
 impl Index<Pos> for Map {
     type Output = Tile;
@@ -212,7 +265,7 @@ impl path::Map for Map {
     }
 }
 
-#[derive(Copy,Clone,Debug,Hash,Eq,PartialEq)]
+#[derive(Copy,Clone,Debug,Hash,Eq,PartialEq,Ord,PartialOrd)]
 struct KeySet(u32);
 
 #[allow(dead_code)]
@@ -354,111 +407,238 @@ impl Iterator for KeySetIterator {
     }
 }
 
+#[derive(Debug)]
+struct Route {
+    // shortest way from one spot on the map to a key, ignoring doors
+    key: char,
+    to: Pos,
+    distance: u32,
+    doors: KeySet,       // doors along the way, i.e. keys needed to take this route
+    keys_passed: KeySet, // other keys along the way
+    path: Vec<Pos>,      // tiles along the way, including both ends
+}
+
+#[derive(Debug)]
+struct Pickup {
+    robot: usize,
+    key: char,
+    cost: u32, // total amount of steps taken by all robots up to and including this pickup
+}
+
+#[derive(Debug)]
+struct Solution {
+    cost: u32,
+    pickups: Vec<Pickup>,  // in the order the keys were picked up
+    paths: Vec<Vec<Pos>>,  // tiles visited by each robot, in order
+}
+impl fmt::Display for Solution {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, pickup) in self.pickups.iter().enumerate() {
+            writeln!(f, "{:>2}. robot {} picks up {} ({} steps in total)", i+1, pickup.robot, pickup.key, pickup.cost)?;
+        }
+        Ok(())
+    }
+}
+
+type State = (Vec<Pos>, KeySet); // robot positions and the keys collected so far
+
 struct Solver<'a> {
     // contains statically-computed information about a map that we want to be able to query for
     map: &'a Map, // for ease of having the map visualize itself during debugging
     key_locations: HashMap<char, Pos>,
+    routes: HashMap<Pos, Vec<Route>>, // from each starting position and key to every other key
 }
 impl<'a> Solver<'a> {
     fn new(map: &'a Map) -> Self {
+        time_scope!("day18 key routes");
         let mut key_locations = HashMap::<char, Pos>::new();
-        let mut door_locations = HashMap::<char, Pos>::new();
         for tile in map.iter() {
-            match tile.kind {
-                TileKind::Key(c)  => { key_locations.insert(c, tile.pos.clone()); },
-                TileKind::Door(c) => { door_locations.insert(c, tile.pos.clone()); },
-                _ => {}
+            if let TileKind::Key(c) = tile.kind {
+                key_locations.insert(c, tile.pos);
             }
         }
-        Self {
+        let mut solver = Self {
             map,
             key_locations,
+            routes: HashMap::new(),
+        };
+        let sources: Vec<Pos> = map.starting_positions.iter().chain(solver.key_locations.values()).cloned().collect();
+        for source in sources {
+            let routes = solver.routes_from(&source);
+            solver.routes.insert(source, routes);
         }
+        solver
     }
-    fn minimal_collection_cost(&self) -> u32 {
-        // BFS over (pos, keys_collected) states, each one with an associated cost to reach it.
-        // a complete path is found in states where all keys have been collected; the one of those with the
-        // smallest cost is the answer. when the same state is encountered with a higher cost than previously seen,
-        // we can stop expanding that path.
-        let all_keys: HashSet<char> = self.key_locations.keys().copied().collect();
-
-        let mut states_seen = HashMap::<(Pos, KeySet), u32>::new(); // state -> cost map
-        let mut queue: VecDeque<(Pos, KeySet, u32)> = VecDeque::new();
-        queue.push_back((self.map.starting_pos.clone(), KeySet::default(), 0));
-
-        let mut result: Option<u32> = None;
-        while !queue.is_empty() {
-            let (current_pos, keys_collected, cost) = queue.pop_front().unwrap();
-
-            // is this a final state, i.e. one in which all keys have been collected? if so, record its cost
-            // and make it the new solution if it's better than any seen before.
-            let remaining_keys: HashSet<char> = all_keys.difference(&keys_collected.iter().collect()).copied().collect();
-            if remaining_keys.is_empty() {
-                if result.is_none() || cost < result.unwrap() {
-                    result = Some(cost);
-                }
+    fn routes_from(&self, source: &Pos) -> Vec<Route> {
+        // walk the map once, going through doors as if they were open, and note which doors and keys lie on the
+        // shortest way to each key. the vaults are laid out as mazes, so there's not really any choice in how to
+        // get from one key to another anyway.
+        let (dists, came_from) = path::bfs(self.map, source, |map, &pos| map[pos].kind != TileKind::Wall);
+        let mut result = Vec::new();
+        for (&key, key_location) in &self.key_locations {
+            if key_location == source {
                 continue;
             }
-
-            // have we seen this state before, and if so, did we arrive in it through a more expensive path?
-            // if so, ignore this state and don't expand on it. otherwise, record this state and
-            // discover new states, i.e. reachable keys from this position with the current set of keys, and add
-            // them to the queue for further exploration.
-            if let Some(previously_seen_cost) = states_seen.get(&(current_pos, keys_collected)) {
-                if cost > *previously_seen_cost {
-                    continue;
+            if let Some(&distance) = dists.get(key_location) {
+                let path_nodes = path::Path::<Pos,Map>::reconstruct_from(key_location, &came_from);
+                let mut route = Route { key, to: *key_location, distance: distance as u32,
+                                        doors: KeySet::default(), keys_passed: KeySet::default(), path: Vec::new() };
+                for pos in &path_nodes[1..path_nodes.len()-1] {
+                    match self.map[*pos].kind {
+                        TileKind::Door(d) => route.doors += KeySet::from(d),
+                        TileKind::Key(k)  => route.keys_passed += KeySet::from(k),
+                        _ => {},
+                    }
                 }
-            };
-            states_seen.insert((current_pos, keys_collected), cost);
-
-            // discover new states reachable from this one, and the cost associated with reaching them
-            // find shortest paths from the current position to all other keys in the map,
-            assert!(remaining_keys.len() > 0);
-            let (dists, came_from) = path::dijkstra(self.map, &current_pos,
-                                                    |map, &pos| match map[pos].kind {
-                                                        TileKind::Wall => false,
-                                                        TileKind::Door(d) => keys_collected.contains(&d),
-                                                        _ => true,
-                                                    });
-            for remaining_key in remaining_keys
-            {
-                let key_location: &Pos = &self.key_locations[&remaining_key];
-
-                if let Some(path_cost) = dists.get(key_location) {
-                    let path_nodes = path::Path::<Pos,Map>::reconstruct_from(key_location, &came_from);
-                    assert!(path_nodes[path_nodes.len()-1] == *key_location);
-                    assert!(path_nodes[0] == current_pos);
-
-                    // for simplicity, reject paths that pick up other keys along the way to $remaining_key;
-                    // i.e. we only want paths that pick up exactly one key (keys that lie behind it will be picked up
-                    // in a later iteration when evaluating the states we're adding here)
-                    if path_nodes[1..path_nodes.len()-1].iter().any(|p| match self.map[*p].key_char() {
-                        Some(k) => !keys_collected.contains(&k),
-                        None    => false,
-                    }) {
+                route.path = path_nodes;
+                result.push(route);
+            }
+        }
+        result
+    }
+    #[allow(dead_code)]
+    fn minimal_collection_cost(&self) -> u32 {
+        self.solve().cost
+    }
+    fn solve(&self) -> Solution {
+        self.search(true).0
+    }
+    fn search(&self, use_heuristic: bool) -> (Solution, usize) {
+        // A* over (robot positions, keys_collected) states, each one with an associated cost to reach it; returns
+        // the cheapest way to collect all keys and the amount of states that were expanded to find it.
+        // there's one robot per starting position; each step in the search moves one of them to a key.
+        // states are expanded in order of their cost plus an estimate of what it'll take at least to collect the
+        // remaining keys, so the first state in which all keys have been collected is the answer (without the
+        // estimate, that's just dijkstra). a state only gets (re)queued if we've found a cheaper way to reach it
+        // than any seen before; entries in the queue that have since been superseded by a cheaper one are skipped
+        // when they come up.
+        time_scope!("day18 search");
+        let all_keys: KeySet = self.key_locations.keys().copied().collect();
+        let estimate = |robots: &[Pos], keys_collected: KeySet| {
+            if use_heuristic { self.remaining_cost_estimate(robots, keys_collected) } else { 0 }
+        };
+
+        let start = (self.map.starting_positions.clone(), KeySet::default());
+        let mut best_cost = HashMap::<State, u32>::new(); // state -> cost map
+        let mut came_from = HashMap::<State, (State, usize, &Route)>::new(); // state -> (previous state, robot, route taken)
+        let mut queue = BinaryHeap::new();
+        best_cost.insert(start.clone(), 0);
+        queue.push(Reverse((estimate(&start.0, start.1), 0, start)));
+
+        let mut expanded = 0;
+        while let Some(Reverse((_, cost, state))) = queue.pop() {
+            if cost > best_cost[&state] {
+                continue;
+            }
+            if state.1 == all_keys {
+                return (self.reconstruct(&state, &came_from), expanded);
+            }
+            let (robots, keys_collected) = state.clone();
+            expanded += 1;
+
+            // discover new states reachable from this one, and the cost associated with reaching them, for each
+            // of the robots in turn. a key can be reached if we have the keys to all doors along the way.
+            for (robot, current_pos) in robots.iter().enumerate() {
+                for route in &self.routes[current_pos] {
+                    if keys_collected.contains(&route.key) || !(route.doors - keys_collected).is_empty() {
                         continue;
                     }
-
-                    let new_state = (path_nodes[path_nodes.len()-1], keys_collected + remaining_key, cost + path_cost);
-                    queue.push_back(new_state);
-                } else {
-                    continue; // key is not directly reachable from here, try the next one
+                    // for simplicity, reject routes that pick up other keys along the way; i.e. we only want
+                    // routes that pick up exactly one key (keys that lie behind it will be picked up in a later
+                    // iteration when evaluating the states we're adding here)
+                    if !(route.keys_passed - keys_collected).is_empty() {
+                        continue;
+                    }
+                    let mut new_robots = robots.clone();
+                    new_robots[robot] = route.to;
+                    let new_state = (new_robots, keys_collected + route.key);
+                    let new_cost = cost + route.distance;
+                    if best_cost.get(&new_state).is_none_or(|&c| new_cost < c) {
+                        best_cost.insert(new_state.clone(), new_cost);
+                        came_from.insert(new_state.clone(), (state.clone(), robot, route));
+                        queue.push(Reverse((new_cost + estimate(&new_state.0, new_state.1), new_cost, new_state)));
+                    }
                 }
             }
         }
-        result.unwrap()
+        panic!("not all keys can be collected");
+    }
+    fn reconstruct(&self, end: &State, came_from: &HashMap<State, (State, usize, &Route)>) -> Solution {
+        // walk back from the final state to the start to find out which robot picked up which key when
+        let mut steps = Vec::new();
+        let mut current = end;
+        while let Some((previous, robot, route)) = came_from.get(current) {
+            steps.push((*robot, *route));
+            current = previous;
+        }
+        steps.reverse();
+
+        let mut paths: Vec<Vec<Pos>> = self.map.starting_positions.iter().map(|&pos| vec![pos]).collect();
+        let mut pickups = Vec::new();
+        let mut cost = 0;
+        for (robot, route) in steps {
+            paths[robot].extend(&route.path[1..]);
+            cost += route.distance;
+            pickups.push(Pickup { robot, key: route.key, cost });
+        }
+        Solution { cost, pickups, paths }
+    }
+    fn remaining_cost_estimate(&self, robots: &[Pos], keys_collected: KeySet) -> u32 {
+        // a lower bound for the cost of collecting the remaining keys: the weight of a minimum spanning tree over
+        // the remaining keys and the robots, where the robots are connected to each other for free. the paths
+        // the robots will take from here on together span all of those (given the free connections), so they
+        // can't be any shorter than that. distances are measured through doors, which only makes them shorter.
+        let dist = |from: &Pos, key: char| self.routes[from].iter().find(|r| r.key == key).map(|r| r.distance);
+
+        // prim's algorithm, starting from the robots
+        let mut remaining: Vec<(char, Option<u32>)> = self.key_locations.keys()
+            .filter(|k| !keys_collected.contains(k))
+            .map(|&k| (k, robots.iter().filter_map(|pos| dist(pos, k)).min()))
+            .collect();
+        let mut total = 0;
+        while let Some(idx) = (0..remaining.len()).filter(|&i| remaining[i].1.is_some()).min_by_key(|&i| remaining[i].1) {
+            let (key, d) = remaining.swap_remove(idx);
+            total += d.unwrap();
+            let from = &self.key_locations[&key];
+            for (other, other_dist) in remaining.iter_mut() {
+                if let Some(d) = dist(from, *other) {
+                    if other_dist.is_none_or(|od| d < od) {
+                        *other_dist = Some(d);
+                    }
+                }
+            }
+        }
+        total
     }
 }
 
-pub fn main() {
+pub fn main(args: &ArgMatches) {
     let lines = util::file_read_lines("input/day18.txt");
     let map = Map::new(&lines);
-    part1(&map);
+    report(&map, &part1(&map), args);
+    let (vaults, solution) = part2(&map);
+    report(&vaults, &solution, args);
+}
+
+fn report(map: &Map, solution: &Solution, args: &ArgMatches) {
+    println!("{}", solution.cost);
+    if args.is_present("analyze") {
+        print!("{}", solution);
+    }
+    if args.is_present("render") {
+        print!("{}", map.visualize_route(solution));
+    }
 }
 
-fn part1(map: &Map) {
-    let solver = Solver::new(map);
-    println!("{}", solver.minimal_collection_cost());
+fn part1(map: &Map) -> Solution {
+    Solver::new(map).solve()
+}
+
+fn part2(map: &Map) -> (Map, Solution) {
+    // also returns the map split into vaults, for reporting the solution on
+    let vaults = map.split_into_vaults().unwrap_or_else(|err| panic!("{}", err));
+    let solution = Solver::new(&vaults).solve();
+    (vaults, solution)
 }
 
 #[allow(dead_code)]
@@ -497,6 +677,41 @@ fn example_map(n: i32) -> Vec<String> {
                   "###g#h#i################",
                   "########################"],
 
+        // part 2
+        6 => vec!["#######",
+                  "#a.#Cd#",
+                  "##...##",
+                  "##.@.##",
+                  "##...##",
+                  "#cB#Ab#",
+                  "#######"],
+
+        7 => vec!["###############",
+                  "#d.ABC.#.....a#",
+                  "######@#@######",
+                  "###############",
+                  "######@#@######",
+                  "#b.....#.....c#",
+                  "###############"],
+
+        8 => vec!["#############",
+                  "#DcBa.#.GhKl#",
+                  "#.###@#@#I###",
+                  "#e#d#####j#k#",
+                  "###C#@#@###J#",
+                  "#fEbA.#.FgHi#",
+                  "#############"],
+
+        9 => vec!["#############",
+                  "#g#f.D#..h#l#",
+                  "#F###e#E###.#",
+                  "#dCba@#@BcIJ#",
+                  "#############",
+                  "#nK.L@#@G...#",
+                  "#M###N#H###.#",
+                  "#o#m..#i#jk.#",
+                  "#############"],
+
         _ => panic!(),
     }.iter().map(|s| s.to_string()).collect::<Vec<_>>()
 }
@@ -513,4 +728,75 @@ mod tests {
         assert_eq!(Solver::new(&Map::new(&example_map(4))).minimal_collection_cost(), 136);
         assert_eq!(Solver::new(&Map::new(&example_map(5))).minimal_collection_cost(), 81);
     }
+
+    #[test]
+    fn four_vaults() {
+        let map = Map::new(&example_map(6));
+        let vaults = map.split_into_vaults().unwrap();
+        assert_eq!(vaults.visualize().replace(" ", ""), "#######\n#a.#Cd#\n##@#@##\n#######\n##@#@##\n#cB#Ab#\n#######\n");
+        assert_eq!(part2(&map).1.cost, 8);
+        assert!(vaults.split_into_vaults().is_err());
+        assert!(Map::new(&example_map(1)).split_into_vaults().is_err()); // walls around the start
+
+        // already split up
+        assert_eq!(Solver::new(&Map::new(&example_map(7))).minimal_collection_cost(), 24);
+        assert_eq!(Solver::new(&Map::new(&example_map(8))).minimal_collection_cost(), 32);
+        assert_eq!(Solver::new(&Map::new(&example_map(9))).minimal_collection_cost(), 72);
+    }
+
+    #[test]
+    fn route() {
+        let map = Map::new(&example_map(2));
+        let solution = part1(&map);
+        assert_eq!(solution.pickups.iter().map(|p| p.key).collect::<String>(), "abcdef");
+        assert_eq!(solution.pickups.last().unwrap().cost, 86);
+        assert_eq!(map.visualize_route(&solution).lines().nth(1).unwrap(),
+                   "# 6 * / * / * 5 * / * 2 * / * @ * 1 * / * 3 * # ");
+
+        // the robots' paths are made of single steps, only go through doors after picking up their keys, and
+        // add up to the total cost
+        for n in 1..=9 {
+            let mut map = Map::new(&example_map(n));
+            if n == 6 {
+                map = map.split_into_vaults().unwrap();
+            }
+            let solution = Solver::new(&map).solve();
+            let mut keys = KeySet::default();
+            let mut steps = vec![0; solution.paths.len()];
+            for pickup in &solution.pickups {
+                let path = &solution.paths[pickup.robot];
+                let end = path.iter().skip(steps[pickup.robot]).position(|&p| map[p].kind == TileKind::Key(pickup.key)).unwrap() + steps[pickup.robot];
+                for pair in path[steps[pickup.robot]..=end].windows(2) {
+                    assert_eq!((pair[0].x - pair[1].x).abs() + (pair[0].y - pair[1].y).abs(), 1);
+                    match map[pair[1]].kind {
+                        TileKind::Wall    => panic!("walked into a wall at {}", pair[1]),
+                        TileKind::Door(d) => assert!(keys.contains(&d), "door {} is still closed", d),
+                        _ => {},
+                    }
+                }
+                steps[pickup.robot] = end;
+                keys += KeySet::from(pickup.key);
+            }
+            assert_eq!(solution.paths.iter().map(|p| p.len() as u32 - 1).sum::<u32>(), solution.cost);
+        }
+    }
+
+    #[test]
+    fn heuristic() {
+        // same answers with and without the heuristic, with fewer states to look at
+        for n in 1..=9 {
+            let mut map = Map::new(&example_map(n));
+            if n == 6 {
+                map = map.split_into_vaults().unwrap();
+            }
+            let solver = Solver::new(&map);
+            let (solution, expanded) = solver.search(true);
+            let (dijkstra_solution, dijkstra_expanded) = solver.search(false);
+            assert_eq!(solution.cost, dijkstra_solution.cost);
+            assert!(expanded <= dijkstra_expanded);
+        }
+        let map = Map::new(&example_map(4));
+        let solver = Solver::new(&map);
+        assert!(solver.search(true).1 < solver.search(false).1);
+    }
 }
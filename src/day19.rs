@@ -1,20 +1,66 @@
 // vim: set ai et ts=4 sts=4 sw=4:
 use crate::util;
 use crate::intcode::{CPU};
-use std::collections::VecDeque;
+use crate::frames::Frame;
+use clap::ArgMatches;
 use std::ops::Range;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+
+pub struct Beam<'a> {
+    // answers whether the beam affects a given position, running the drone program on a single CPU that's reset
+    // for each probe. the searches below tend to probe the same positions repeatedly, so results are cached.
+    program: &'a Vec<i64>,
+    cpu: RefCell<CPU>,
+    cache: RefCell<HashMap<(usize, usize), bool>>,
+    probes: Cell<usize>, // number of times the program was actually run
+}
+impl<'a> Beam<'a> {
+    pub fn new(program: &'a Vec<i64>) -> Self {
+        Self {
+            program,
+            cpu: RefCell::new(CPU::new(program)),
+            cache: RefCell::new(HashMap::new()),
+            probes: Cell::new(0),
+        }
+    }
+    pub fn affects(&self, x: usize, y: usize) -> bool {
+        if let Some(&affected) = self.cache.borrow().get(&(x, y)) {
+            return affected;
+        }
+        let mut cpu = self.cpu.borrow_mut();
+        cpu.reset(self.program);
+        cpu.send_input(x as i64);
+        cpu.send_input(y as i64);
+        cpu.run();
+        let affected = match cpu.consume_output().unwrap() {
+            0 => false,
+            1 => true,
+            _ => panic!(),
+        };
+        self.probes.set(self.probes.get() + 1);
+        self.cache.borrow_mut().insert((x, y), affected);
+        affected
+    }
+    pub fn probes(&self) -> usize {
+        self.probes.get()
+    }
+}
 
 struct IncrementalBeamRange<'a> {
     // returns the range of affected X coordinates over incremental values of Y
-    program: &'a Vec<i64>,
+    beam: &'a Beam<'a>,
     next_y: usize,
     prev_left_x: usize,
     prev_width: usize,
 }
 impl<'a> IncrementalBeamRange<'a> {
-    pub fn new(program: &'a Vec<i64>) -> Self {
+    pub fn new(beam: &'a Beam<'a>) -> Self {
         Self {
-            program,
+            beam,
             next_y: 0,
             prev_left_x: 0,
             prev_width: 1,
@@ -24,7 +70,7 @@ impl<'a> IncrementalBeamRange<'a> {
 impl<'a> Iterator for IncrementalBeamRange<'a> {
     type Item = Option<Range<usize>>;
     fn next(&mut self) -> Option<Self::Item> {
-        let result: Option<Range<usize>> = beam_range_incremental(self.next_y, self.prev_left_x, self.prev_width, self.program);
+        let result: Option<Range<usize>> = beam_range_incremental(self.next_y, self.prev_left_x, self.prev_width, self.beam);
         if let Some(x_range) = &result {
             self.prev_left_x = x_range.start;
             self.prev_width = x_range.len();
@@ -34,19 +80,7 @@ impl<'a> Iterator for IncrementalBeamRange<'a> {
     }
 }
 
-fn beam_affects(x: usize, y: usize, program: &Vec<i64>) -> bool {
-    let mut cpu = CPU::new(&program);
-    cpu.send_input(x as i64);
-    cpu.send_input(y as i64);
-    cpu.run();
-    match cpu.consume_output().unwrap() {
-        0 => false,
-        1 => true,
-        _ => panic!(),
-    }
-}
-
-fn beam_range_incremental(y: usize, prev_left_x: usize, prev_width: usize, program: &Vec<i64>)
+fn beam_range_incremental(y: usize, prev_left_x: usize, prev_width: usize, beam: &Beam)
     -> Option<Range<usize>>
 {
     // note: returns an Option because at very low Y coordinates, the beam may sometimes 'disappear'
@@ -64,7 +98,7 @@ fn beam_range_incremental(y: usize, prev_left_x: usize, prev_width: usize, progr
             // assume we're in an early pathological case and exit early.
             return None;
         }
-        if beam_affects(left_x, y, program) {
+        if beam.affects(left_x, y) {
             break;
         }
         left_x += 1;
@@ -73,17 +107,17 @@ fn beam_range_incremental(y: usize, prev_left_x: usize, prev_width: usize, progr
     // now find the right edge as well; since the width of the beam barely changes with each incremental Y position,
     // jump ahead by the previous width and scan backwards or forwards to find the edge of the beam.
     let mut right_x = left_x + prev_width;
-    match beam_affects(right_x, y, program) {
+    match beam.affects(right_x, y) {
         true => {
             // scan to the right
             right_x += 1;
-            while beam_affects(right_x, y, program) {
+            while beam.affects(right_x, y) {
                 right_x += 1;
             }
         },
         false => {
             // scan to the left
-            while !beam_affects(right_x-1, y, program) {
+            while !beam.affects(right_x-1, y) {
                 right_x -= 1;
             }
         },
@@ -91,93 +125,361 @@ fn beam_range_incremental(y: usize, prev_left_x: usize, prev_width: usize, progr
     Some(left_x..right_x)
 }
 
-pub fn main() {
+pub fn main(args: &ArgMatches) {
     let line: String = util::file_read_lines("input/day19.txt").into_iter().next().unwrap();
     let program: Vec<i64> = line.split(",").map(|s| s.parse().unwrap()).collect();
 
-    println!("{}", part1(&program, 50, false));
-    println!("{}", part2(&program, 100));
+    let beam = Beam::new(&program);
+    println!("{}", part1(&beam, 50));
+    if args.is_present("verbose") {
+        print!("{}", render(&beam, 0..50, 0..50, None));
+    }
+
+    let fit = find_fit(&beam, 100, 100).unwrap_or_else(|err| panic!("{}", err));
+    println!("{}", fit.answer());
+
+    if args.is_present("analyze") {
+        let edges = Edges::estimate(&beam, fit.y + fit.height).unwrap_or_else(|err| panic!("{}", err));
+        let covered = coverage(&beam, 0..fit.x + fit.width, 0..fit.y + fit.height);
+        println!("beam edges: x = {:.4}*y to x = {:.4}*y", edges.left, edges.right);
+        println!("first fit: {}", fit);
+        println!("beam coverage up to it: {} of {} positions ({:.1}%), in {} rows",
+                 covered.affected, covered.area, 100.0 * covered.fraction(), covered.rows);
+        println!("probes: {}", beam.probes());
+    }
+
+    if let Some(filename) = args.value_of("image") {
+        // by default, everything from the origin down to a bit past the rectangle
+        let parse = |name: &str, default: usize| args.value_of(name).map_or(default, |s| s.parse().unwrap_or_else(|_| panic!("invalid {}: {}", name, s)));
+        let width = parse("width", fit.x + fit.width + 10);
+        let height = parse("height", fit.y + fit.height + 10);
+        let scale = parse("scale", 1);
+        let frame = render(&beam, 0..width, 0..height, Some(&fit));
+        write_image_file(&frame, scale, filename).unwrap_or_else(|err| panic!("failed to write {}: {}", filename, err));
+    }
 }
 
 #[allow(non_snake_case)]
-fn part1(program: &Vec<i64>, N: usize, visualize: bool) -> usize {
-    let mut num_affected = 0usize;
-
-    let mut iterator = IncrementalBeamRange::new(program);
-    for _ in 0..N {
-        match iterator.next().unwrap() {
-            None => {
-                if visualize { print!("{}\n", ". ".repeat(N)); }
-                continue; // no positions affected at this Y coordinate, skip
-            },
-            Some(x_range) => {
-                num_affected += x_range.len();
-                if visualize {
-                    print!("{}", ". ".repeat(x_range.start));
-                    print!("{}", "# ".repeat(x_range.len()));
-                    print!("{}", ". ".repeat(N-x_range.end));
-                }
-            },
-        };
-        if visualize { print!("\n"); }
+fn part1(beam: &Beam, N: usize) -> usize {
+    let mut iterator = IncrementalBeamRange::new(beam);
+    (0..N).filter_map(|_| iterator.next().unwrap())
+          .map(|x_range| x_range.start.min(N)..x_range.end.min(N))
+          .map(|x_range| x_range.len())
+          .sum()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fit {
+    // a rectangle that lies entirely within the beam, by its top left corner
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+impl Fit {
+    pub fn answer(&self) -> usize {
+        self.x*10_000 + self.y
+    }
+    pub fn corners(&self) -> [(usize, usize); 4] {
+        let (right, bottom) = (self.x + self.width - 1, self.y + self.height - 1);
+        [(self.x, self.y), (right, self.y), (self.x, bottom), (right, bottom)]
+    }
+}
+impl fmt::Display for Fit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}x{} at ({},{})", self.width, self.height, self.x, self.y)
     }
-    num_affected
 }
 
-#[allow(non_snake_case)]
-fn part2(program: &Vec<i64>, N: usize) -> usize {
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Edges {
+    // the beam's left and right edges as lines through the origin, by their slopes (X per Y)
+    pub left: f64,
+    pub right: f64,
+}
+impl Edges {
+    pub fn estimate(beam: &Beam, sample_y: usize) -> Result<Edges, String> {
+        // from the positions of the edges at a sample row; the further down, the more accurate
+        let sample = beam_at(sample_y, beam).ok_or(format!("beam not found at row {}", sample_y))?;
+        Ok(Edges {
+            left: sample.start as f64 / sample_y as f64,
+            right: (sample.end - 1) as f64 / sample_y as f64,
+        })
+    }
+    pub fn at(&self, y: usize) -> (f64, f64) {
+        (self.left * y as f64, self.right * y as f64)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Coverage {
+    pub area: usize,     // number of positions in the region
+    pub affected: usize, // number of those the beam affects
+    pub rows: usize,     // number of rows the beam shows up in at all
+}
+impl Coverage {
+    pub fn fraction(&self) -> f64 {
+        if self.area == 0 { 0.0 } else { self.affected as f64 / self.area as f64 }
+    }
+}
+
+pub fn coverage(beam: &Beam, xs: Range<usize>, ys: Range<usize>) -> Coverage {
+    let mut coverage = Coverage { area: xs.len() * ys.len(), ..Default::default() };
+    for y in ys {
+        let affected = beam_at(y, beam).map_or(0, |x_range| (x_range.start.max(xs.start)..x_range.end.min(xs.end)).len());
+        coverage.affected += affected;
+        coverage.rows += (affected > 0) as usize;
+    }
+    coverage
+}
+
+pub fn find_fit(beam: &Beam, width: usize, height: usize) -> Result<Fit, String> {
+    // the first (i.e. closest to the origin) width x height rectangle that fits entirely within the beam.
     // note the following properties about the tractor beam:
+    //   - it's a cone coming out of the origin, so its left and right edges are (roughly) straight lines through it
     //   - the X location of the first drone affected at each Y coordinate monotonically increases
     //        (i.e. first_affected_x_coord(Y) >= first_affected_x_coord(Y-1))
-    //   - the width of the beam never changes 'much' from that of the previous Y coordinate's width
-    //     (in some early pathological cases it sometimes disappears, but other than that only appears to ever grow
-    //      or shrink by tiny fractions of its width, i.e. 1 or 2 positions)
-
-    // for an NxN square to fit inside the beam with the top left coordinate at (X,Y), the following must hold:
-    //   - the width of the beam at Y must be >= N (and therefore Y >= N as well, due to the monotically increasing beam width)
-    //   - all positions (X, Y+[0..N]) must be affected by the tractor beam as well
-
-    // we can ignore all the Y coordinates where the beam width has not yet reached N for the first time;
-    // the beam width fluctuates slightly at each incremental Y position but displays an overall growth, so we can
-    // save time by finding the Y coordinate where the beam first reaches a width of at least N.
-
-    // in the general case looking for it incrementally is likely to waste a lot of time since the beam width grows slowly,
-    // so e.g. a binary search is likely to save time, but for our particular problem input it turns out to be
-    // 'quick enough' to find it incrementally.
-
-    let mut iter = IncrementalBeamRange::new(program);
-
-    // keep a window of the last N ranges seen of width >= N; if at any point they all share the same N consecutive
-    // X coordinates then we found a place for the square to fit
-    // (or equivalently, since the X coordinates are monotonically increasing: if the oldest range in the window
-    // contains both the newest range's leftmost X and leftmost X + N coordinates).
-    let mut result: Option<(usize, usize)> = None;
-    let mut window = VecDeque::<Range<usize>>::with_capacity(N);
-    while let Some(range_maybe) = iter.next() {
-        if range_maybe.is_none() || range_maybe.as_ref().unwrap().len() < N {
-            // haven't reached the required width yet or found a gap in the beam; reset the window
-            window.clear();
-            continue;
-        }
+    //
+    // a rectangle fits with its bottom left corner on the left edge of the beam at row Y if its top right
+    // corner (at row Y-height+1) is inside the beam as well. since the beam only gets wider further down, that's
+    // true for every Y from some point on, so we can (mostly, see below) binary search for the first row
+    // where it fits.
+    // to know where to look, estimate the slopes of the beam's edges from a sample row and extrapolate.
+    if width == 0 || height == 0 {
+        return Err(format!("can't fit a {}x{} rectangle", width, height));
+    }
+    let edges = Edges::estimate(beam, width.max(height).max(10))?;
+    let (a, b) = (edges.left, edges.right);
 
-        let range = range_maybe.unwrap(); // ok, found a range of the required length; add it to the window.
-        window.push_back(range.clone());
-        if window.len() < N {
-            continue; // window needs to fill up for a bit longer
+    let fits = |bottom: usize| -> Option<Fit> {
+        // the rectangle with its bottom left corner on the left edge at this row, if it fits
+        if bottom + 1 < height {
+            return None;
         }
+        let (l, r) = edges.at(bottom);
+        let x = left_edge(bottom, ((l + r)/2.0) as usize, beam)?;
+        let top = bottom + 1 - height;
+        if beam.affects(x + width - 1, top) { Some(Fit { x, y: top, width, height }) } else { None }
+    };
+
+    // at row Y, the left and right edges are at about a*Y and b*Y; the rectangle fits once
+    //   b*(Y-height+1) >= a*Y + width-1, i.e. Y >= ((width-1) + b*(height-1))/(b-a)
+    if b <= a {
+        return Err(format!("the beam is too narrow to fit a {}x{} rectangle", width, height));
+    }
+    let estimate = (((width - 1) as f64 + b * (height - 1) as f64) / (b - a)) as usize;
 
-        // as soon as we have N ranges in the window, look for N consecutive X coordinates that are present in all of them.
-        // if we didn't find any, drop the oldest range from the window to make room on the next iteration.
-        assert!(window.len() == N);
-        if window[0].contains(&range.start) && window[0].contains(&(range.start+N-1)) {
-            assert!(window.iter().all(|r| r.contains(&range.start) && r.contains(&(range.start+N-1))));
-            result = Some((range.start, iter.next_y - N));
+    // bracket the first fitting row around the estimate, then narrow it down
+    let mut hi = estimate.max(height);
+    while fits(hi).is_none() {
+        hi += hi/16 + 1;
+    }
+    let mut lo = hi;
+    while fits(lo).is_some() {
+        hi = lo;
+        if lo == 0 {
             break;
-        } else {
-            window.pop_front();
         }
+        lo = lo.saturating_sub(lo/16 + 1);
+    }
+    let hi = util::binary_search_min(lo, hi, |y| fits(y).is_some()).unwrap(); // hi fits
+
+    // the edges are rounded to whole positions, which can be off by one from the straight lines in either
+    // direction; so whether the rectangle fits isn't quite monotone, but only within the band of rows where
+    // the margin b*(Y-height+1) - a*Y - (width-1) is between -2 and 2. the binary search lands somewhere in
+    // that band, so check the rest of it for any rows further up where the rectangle fits as well.
+    let band = (4.0 / (b - a)).ceil() as usize + 1;
+    let fit = (hi.saturating_sub(band)..=hi).find_map(fits)
+                                          .ok_or_else(|| format!("lost track of the {}x{} rectangle at row {}", width, height, hi))?;
+    if !fit.corners().iter().all(|&(x, y)| beam.affects(x, y)) {
+        return Err(format!("the {}x{} rectangle at {},{} sticks out of the beam", width, height, fit.x, fit.y));
+    }
+    Ok(fit)
+}
+
+pub fn render(beam: &Beam, xs: Range<usize>, ys: Range<usize>, fit: Option<&Fit>) -> Frame {
+    // draws a region of the beam as '#', with the given rectangle (if any) as 'O' on top of it. the beam is a
+    // single run of positions in each row, so only its edges need to be probed rather than every position.
+    let mut frame = Frame::new(xs.len(), ys.len(), '.');
+    for (row, y) in ys.clone().enumerate() {
+        if let Some(x_range) = beam_at(y, beam) {
+            for x in x_range.start.max(xs.start)..x_range.end.min(xs.end) {
+                frame.set(x - xs.start, row, '#');
+            }
+        }
+    }
+    if let Some(fit) = fit {
+        for y in fit.y..fit.y+fit.height {
+            for x in fit.x..fit.x+fit.width {
+                frame.set_checked(x as i64 - xs.start as i64, y as i64 - ys.start as i64, 'O');
+            }
+        }
+    }
+    frame
+}
+
+fn beam_color(c: char) -> [u8; 3] {
+    match c {
+        '#' => [80, 160, 255],
+        'O' => [255, 200, 0],
+        _   => [0, 0, 40],
+    }
+}
+
+fn write_image_file(frame: &Frame, scale: usize, filename: &str) -> io::Result<()> {
+    // picks the format from the file extension
+    let out = BufWriter::new(File::create(filename)?);
+    if filename.ends_with(".png") {
+        frame.write_png(out, scale, beam_color)
+    } else if filename.ends_with(".ppm") {
+        frame.write_ppm(out, scale, beam_color)
+    } else {
+        Err(io::Error::new(io::ErrorKind::InvalidInput, "unsupported image format, expected .png or .ppm"))
+    }
+}
+
+fn beam_at(y: usize, beam: &Beam) -> Option<Range<usize>> {
+    // searches a row for the beam's X range, for when there's nothing to go on: first with big steps (the beam
+    // gets wider further down), then with single ones in case the beam's too narrow for that
+    let max_x = 10*y.max(1);
+    for &stride in &[(y/16).max(1), 1] {
+        if let Some(x) = (0..=max_x).step_by(stride).find(|&x| beam.affects(x, y)) {
+            let left = if x == 0 { 0 } else { edge_between(x - stride.min(x), x, y, beam) };
+            let mut outside = x + stride;
+            while beam.affects(outside, y) {
+                outside += stride;
+            }
+            let right = edge_between(outside, outside - stride, y, beam);
+            return Some(left..right+1);
+        }
+    }
+    None
+}
+
+fn edge_between(outside: usize, inside: usize, y: usize, beam: &Beam) -> usize {
+    // binary search for the last X coordinate inside the beam when going from inside towards outside
+    // (in either direction)
+    let affected = |x: usize| beam.affects(x, y);
+    if inside < outside {
+        util::binary_search_max(inside, outside, affected).unwrap()
+    } else {
+        util::binary_search_min(outside, inside, affected).unwrap()
+    }
+}
+
+fn left_edge(y: usize, guess: usize, beam: &Beam) -> Option<usize> {
+    // the beam's left edge at the given row, given a guess for an X coordinate inside the beam. the very first
+    // rows can have gaps in the beam, so fall back to searching the whole row if the guess doesn't pan out.
+    if beam.affects(0, y) {
+        return Some(0);
+    }
+    if beam.affects(guess, y) {
+        Some(edge_between(0, guess, y, beam))
+    } else {
+        beam_at(y, beam).map(|range| range.start)
     }
-    let (x,y) = result.unwrap();
-    x*10_000 + y
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn beam_program(left: i64, right: i64) -> Vec<i64> {
+        // reads X and Y, and outputs 1 if left*Y <= 10*X <= right*Y, i.e. a beam with edges at slopes of
+        // left/10 and right/10
+        vec![3,100, 3,101,
+             1002,100,10,102,     // [102] = 10*X
+             1002,101,left,103,   // [103] = left*Y
+             7,102,103,104,       // [104] = 10*X < left*Y
+             1002,101,right,105,  // [105] = right*Y
+             7,105,102,106,       // [106] = right*Y < 10*X
+             1,104,106,107,
+             1008,107,0,108,      // [108] = neither
+             4,108,
+             99]
+    }
+
+    fn brute_force(beam: &Beam, width: usize, height: usize) -> Fit {
+        for y in 0.. {
+            for x in 0..=2*y {
+                let fit = Fit { x, y, width, height };
+                if fit.corners().iter().all(|&(x, y)| beam.affects(x, y)) {
+                    return fit;
+                }
+            }
+        }
+        unreachable!()
+    }
+
+    #[test]
+    fn synthetic_beams() {
+        for &(left, right) in &[(7, 9), (3, 12), (10, 11), (1, 2)] {
+            let program = beam_program(left, right);
+            for &n in &[2, 5, 10, 30] {
+                assert_eq!(find_fit(&Beam::new(&program), n, n).map(|fit| fit.answer()), Ok(brute_force(&Beam::new(&program), n, n).answer()), "beam {}..{}, square of {}", left, right, n);
+            }
+        }
+    }
+
+    #[test]
+    fn rectangles() {
+        for &(left, right) in &[(7, 9), (3, 12), (1, 2)] {
+            let program = beam_program(left, right);
+            let beam = Beam::new(&program);
+            for &(w, h) in &[(1, 1), (2, 7), (7, 2), (1, 20), (25, 3), (12, 18)] {
+                assert_eq!(find_fit(&beam, w, h), Ok(brute_force(&beam, w, h)), "beam {}..{}, {}x{}", left, right, w, h);
+            }
+        }
+        assert!(find_fit(&Beam::new(&beam_program(3, 12)), 0, 5).is_err());
+    }
+
+    #[test]
+    fn queries() {
+        let program = beam_program(3, 12);
+        let beam = Beam::new(&program);
+        let edges = Edges::estimate(&beam, 100).unwrap();
+        assert_eq!((edges.left, edges.right), (0.3, 1.2));
+        assert_eq!(edges.at(50), (15.0, 60.0));
+
+        // in a 10x10 region, row Y covers 0.3*Y..=min(1.2*Y, 9)
+        let expected: usize = (0..10usize).map(|y| (y*12/10).min(9) + 1 - (y*3).div_ceil(10)).sum();
+        let covered = coverage(&beam, 0..10, 0..10);
+        assert_eq!(covered, Coverage { area: 100, affected: expected, rows: 10 });
+        assert_eq!(covered.fraction(), expected as f64 / 100.0);
+        assert_eq!(coverage(&beam, 20..30, 0..10).affected, 0);
+    }
+
+    #[test]
+    fn probe_cache() {
+        let program = beam_program(7, 9);
+        let beam = Beam::new(&program);
+        assert!(beam.affects(8, 10));
+        assert!(!beam.affects(10, 10));
+        assert!(beam.affects(8, 10));
+        assert_eq!(beam.probes(), 2);
+
+        // the CPU is reused across probes, so it must start from a clean program every time
+        assert_eq!((0..20).filter(|&x| beam.affects(x, 20)).count(), 5);
+        assert_eq!(beam.probes(), 22);
+    }
+
+    #[test]
+    fn rendering() {
+        let program = beam_program(7, 9);
+        let beam = Beam::new(&program);
+        let fit = find_fit(&beam, 2, 2).unwrap();
+        assert_eq!(fit, Fit { x: 7, y: 9, width: 2, height: 2 });
+
+        let frame = render(&beam, 4..12, 7..12, Some(&fit));
+        assert_eq!(frame.to_string(), ".##.....\n\
+                                       ..##....\n\
+                                       ...OO...\n\
+                                       ...OO#..\n\
+                                       ....##..\n");
+        for (row, y) in (7..12).enumerate() {
+            for (col, x) in (4..12).enumerate() {
+                assert_eq!(frame.get(col, row) != '.', beam.affects(x, y));
+            }
+        }
+    }
+}
@@ -1,56 +1,103 @@
 // vim: set ai et ts=4 sts=4 sw=4:
 use crate::util;
+use crate::intcode::CPU;
+use clap::ArgMatches;
+use rayon::prelude::*;
+use std::ops::Range;
 
-pub fn main() {
+pub fn main(args: &ArgMatches) {
     let line: &String = &util::file_read_lines("input/day2.txt")[0];
     let data: Vec<i64> = line.split(",").map(|s| s.parse().unwrap()).collect();
-    part1(&data);
-    part2(&data);
+
+    let mut params = SearchParams::default();
+    if let Some(target) = args.value_of("target") {
+        params.target = target.parse().unwrap_or_else(|_| panic!("invalid target value: {}", target));
+    }
+    if let Some(nouns) = args.value_of("nouns") {
+        params.nouns = util::parse_range(nouns).unwrap();
+    }
+    if let Some(verbs) = args.value_of("verbs") {
+        params.verbs = util::parse_range(verbs).unwrap();
+    }
+
+    println!("{}", part1(&data));
+    match part2(&data, &params) {
+        Some(answer) => println!("{}", answer),
+        None         => println!("no noun in {:?} and verb in {:?} produce {}", params.nouns, params.verbs, params.target),
+    }
 }
 
-fn part1(data: &Vec<i64>) {
-    let mut data = data.clone();
-    data[1] = 12;
-    data[2] = 2;
-    run_intcode(&mut data);
-    println!("{}", data[0]);
+pub struct SearchParams {
+    pub target: i64,
+    pub nouns: Range<i64>,
+    pub verbs: Range<i64>,
 }
-fn part2(data: &Vec<i64>) {
-    for noun in 0..100 {
-        for verb in 0..100 {
-            let mut memory = data.clone();
-            memory[1] = noun;
-            memory[2] = verb;
-            run_intcode(&mut memory);
-            if memory[0] == 19690720 {
-                let answer = 100*noun + verb;
-                println!("{}", answer);
-                break;
-            }
-        }
+impl Default for SearchParams {
+    fn default() -> Self {
+        Self { target: 19690720, nouns: 0..100, verbs: 0..100 }
     }
 }
 
-fn run_intcode(data: &mut Vec<i64>){
-    let mut pc = 0usize;
-    loop {
-        let opcode = data[pc];
-        match opcode {
-            1|2 => {
-                let arg1 = data[pc+1] as usize;
-                let arg2 = data[pc+2] as usize;
-                let arg3 = data[pc+3] as usize;
-                data[arg3] = match opcode {
-                    1 => data[arg1] + data[arg2],
-                    2 => data[arg1] * data[arg2],
-                    _ => panic!("bad opcode: {}", opcode),
-                }
-            },
-            99 => break,
-            _ => { panic!("bad opcode: {}", opcode); }
+fn part1(data: &Vec<i64>) -> i64 {
+    run_with_inputs(data, 12, 2)
+}
+fn part2(data: &Vec<i64>, params: &SearchParams) -> Option<i64> {
+    find_noun_verb(data, params).map(|(noun, verb)| 100*noun + verb)
+}
+
+fn run_with_inputs(program: &Vec<i64>, noun: i64, verb: i64) -> i64 {
+    // runs the program with the given noun and verb in addresses 1 and 2, and returns the value at address 0
+    let mut cpu = CPU::new(program);
+    cpu.write_mem(1, noun)
+       .write_mem(2, verb)
+       .run();
+    cpu.read_mem(0)
+}
+
+fn find_noun_verb(program: &Vec<i64>, params: &SearchParams) -> Option<(i64, i64)> {
+    // finds the first (noun, verb) pair in the search ranges (ordered by noun, then verb) for which the program
+    // produces the target value
+    solve_affine(program, params).or_else(|| solve_brute_force(program, params))
+}
+
+fn solve_affine(program: &Vec<i64>, params: &SearchParams) -> Option<(i64, i64)> {
+    // the programs for this puzzle compute an output of the form a + b*noun + c*verb, so three runs are enough to
+    // find the coefficients, after which we can solve for noun and verb directly. a fourth run checks that the fit
+    // also predicts the output for noun and verb both 1, and the candidate that comes out of it is verified with an
+    // actual run; if either check fails, we give up and leave it to the brute force approach. that catches most
+    // programs that aren't affine, but not all of them: one that happens to agree with the fit on those four runs
+    // could still get a verified pair that isn't the first one in search order.
+    let a = run_with_inputs(program, 0, 0);
+    let b = run_with_inputs(program, 1, 0) - a;
+    let c = run_with_inputs(program, 0, 1) - a;
+    if run_with_inputs(program, 1, 1) != a + b + c {
+        return None;
+    }
+
+    let target = params.target;
+    for noun in params.nouns.clone() {
+        // need c*verb = remaining, for some verb in the search range
+        let remaining = target - a - b*noun;
+        let verb = if c == 0 {
+            if remaining != 0 || params.verbs.is_empty() { continue; }
+            params.verbs.start // any verb will do; take the first one
+        } else {
+            if remaining % c != 0 || !params.verbs.contains(&(remaining / c)) { continue; }
+            remaining / c
+        };
+        if run_with_inputs(program, noun, verb) == target {
+            return Some((noun, verb));
         }
-        pc += 4;
+        return None;
     }
+    None
+}
+
+fn solve_brute_force(program: &Vec<i64>, params: &SearchParams) -> Option<(i64, i64)> {
+    let verbs = params.verbs.clone();
+    params.nouns.clone().into_par_iter()
+                .flat_map(|noun| verbs.clone().into_par_iter().map(move |verb| (noun, verb)))
+                .find_first(|&(noun, verb)| run_with_inputs(program, noun, verb) == params.target)
 }
 
 #[cfg(test)]
@@ -59,9 +106,10 @@ mod tests {
 
     macro_rules! final_state_of {
         ($prog:expr) => {{
-            let mut data = $prog;
-            run_intcode(&mut data);
-            data
+            let prog: Vec<i64> = $prog;
+            let mut cpu = CPU::new(&prog);
+            cpu.run();
+            (0..prog.len() as i64).map(|addr| cpu.read_mem(addr)).collect::<Vec<i64>>()
         }}
     }
 
@@ -72,4 +120,36 @@ mod tests {
         assert_eq!(final_state_of!(vec![2,4,4,5,99,0]), vec![2,4,4,5,99,9801]);
         assert_eq!(final_state_of!(vec![1,1,1,4,99,5,6,0,99]), vec![30,1,1,4,2,5,6,0,99]);
     }
+
+    fn params(target: i64, nouns: Range<i64>, verbs: Range<i64>) -> SearchParams {
+        SearchParams { target, nouns, verbs }
+    }
+
+    #[test]
+    fn noun_verb_search() {
+        // computes 3*noun + verb
+        let affine = vec![1101,0,0,13, 1002,1,2,14, 1,13,14,0, 99,0,0];
+        for &target in &[0, 26, 99, 150, 396] {
+            let p = params(target, 0..100, 0..100);
+            assert_eq!(solve_affine(&affine, &p), solve_brute_force(&affine, &p));
+        }
+        assert_eq!(solve_affine(&affine, &params(150, 0..100, 0..100)), Some((17, 99)));
+        assert_eq!(find_noun_verb(&affine, &params(397, 0..100, 0..100)), None);
+        assert_eq!(part2(&affine, &params(397, 0..200, 0..10)), Some(100*130 + 7));
+        assert_eq!(find_noun_verb(&affine, &params(150, 40..60, 0..100)), Some((40, 30)));
+        assert_eq!(find_noun_verb(&affine, &params(151, 0..100, 0..1)), None);
+
+        // computes noun*verb; not affine, so should fall back to the brute force approach
+        let product = vec![1102,0,0,0,99];
+        assert_eq!(solve_affine(&product, &params(12, 0..100, 0..100)), None);
+        assert_eq!(find_noun_verb(&product, &params(12, 0..100, 0..100)), Some((1, 12)));
+        assert_eq!(find_noun_verb(&product, &params(12, 0..100, 5..100)), Some((1, 12)));
+        assert_eq!(find_noun_verb(&product, &params(12, 2..100, 0..100)), Some((2, 6)));
+
+        // computes noun*verb + noun, which looks like just noun from the first three runs. (12, 0) would pass the
+        // check with an actual run, but (1, 11) comes first
+        let skewed = vec![1102,0,0,9, 1,9,1,0, 99,0];
+        assert_eq!(solve_affine(&skewed, &params(12, 0..100, 0..100)), None);
+        assert_eq!(find_noun_verb(&skewed, &params(12, 0..100, 0..100)), Some((1, 11)));
+    }
 }
@@ -4,6 +4,7 @@ use std::ops::{Add, Index, IndexMut};
 use std::collections::{HashMap};
 use crate::util;
 use crate::path;
+use clap::ArgMatches;
 
 #[derive(PartialEq, Eq, Copy, Clone, Debug, Hash)]
 struct Pos {
@@ -43,6 +44,9 @@ struct PortalInfo {
     label: String,
     attached_passage: Pos,
     on_outer_edge: bool,
+    other_end: Pos,    // the portal tile at the other end
+    warp_to: Pos,      // the passage attached to the other end, where taking this portal comes out
+    level_change: i32, // in recursive mode; -1 for outer edge portals, 1 for inner ones
 }
 
 #[derive(PartialEq, Eq, Debug, Hash)]
@@ -75,13 +79,20 @@ impl Tile {
 macro_rules! tile_index {
     ($pos:ident, $map_width:expr) => { ($pos.y as usize) * $map_width + $pos.x as usize };
 }
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+struct Link {
+    to: Pos,           // at level 0
+    level_change: i32, // non-zero for portals
+}
+
 struct Map {
     w: usize,
     h: usize,
     tiles: Vec<Tile>,
     starting_pos: Pos,
     target_pos: Pos,
-    portal_pairs: HashMap<Pos, Pos>, // for every portal position, records the other end of the portal
+    links: Vec<Vec<Link>>, // for every tile, the passages that can be reached from it in one step, at level 0
     recursive_portals: bool,
 }
 #[allow(dead_code)]
@@ -136,7 +147,11 @@ impl Map {
                                         kind: TileKind::Portal(PortalInfo {
                                             label: $label,
                                             attached_passage: $attached_passage_pos,
-                                            on_outer_edge: false, // to be revised later
+                                            // to be revised later, once the other end is known
+                                            on_outer_edge: false,
+                                            other_end: $pos,
+                                            warp_to: $attached_passage_pos,
+                                            level_change: 0,
                                         }),
                                     };
                                 }
@@ -194,36 +209,54 @@ impl Map {
             }
         }
 
-        // for each portal location, record the location of the other portal, and determine
-        // whether this portal is on the outer or on the inner edge of the map.
-        let mut portal_pairs = HashMap::<Pos, Pos>::new();
-
-        #[allow(unused_parens)]
-        for (_, locations) in portal_locations {
-            assert!(locations.len() == 2);
-            let (pos1, pos2) = (locations[0], locations[1]);
-
-            portal_pairs.insert(pos1.clone(), pos2.clone());
-            portal_pairs.insert(pos2,         pos1);
-
-            // a portal is located on the outer edge iff either of the following is true:
-            //   - there are no walls or passages with the same x coordinate (left or right outer edge)
-            //   - there are no walls or passages with the same y coordinate (top or bottom outer edge)
-            let p1_lonely_x = !tiles.iter().any(|t| (t.is_wall() || t.is_passage()) && t.pos.x == pos1.x);
-            let p1_lonely_y = !tiles.iter().any(|t| (t.is_wall() || t.is_passage()) && t.pos.y == pos1.y);
-            let p2_lonely_x = !tiles.iter().any(|t| (t.is_wall() || t.is_passage()) && t.pos.x == pos2.x);
-            let p2_lonely_y = !tiles.iter().any(|t| (t.is_wall() || t.is_passage()) && t.pos.y == pos2.y);
-
-            let tile1 = &mut tiles[tile_index!(pos1, w)];
-            match tile1.kind {
-                TileKind::Portal(ref mut info) => { info.on_outer_edge = (p1_lonely_x || p1_lonely_y); },
-                _ => panic!(),
-            };
-            let tile2 = &mut tiles[tile_index!(pos2, w)];
-            match tile2.kind {
-                TileKind::Portal(ref mut info) => { info.on_outer_edge = (p2_lonely_x || p2_lonely_y); },
-                _ => panic!(),
+        // for each portal location, record the location of the other portal and where it comes out, and determine
+        // whether this portal is on the outer or on the inner edge of the map. a portal is located on the outer edge
+        // iff it lies outside of the bounding box of the walls and passages.
+        let (mut min_x, mut min_y, mut max_x, mut max_y) = (i32::MAX, i32::MAX, i32::MIN, i32::MIN);
+        for t in tiles.iter().filter(|t| t.is_wall() || t.is_passage()) {
+            min_x = min_x.min(t.pos.x);
+            min_y = min_y.min(t.pos.y);
+            max_x = max_x.max(t.pos.x);
+            max_y = max_y.max(t.pos.y);
+        }
+        let on_outer_edge = |pos: &Pos| pos.x < min_x || pos.x > max_x || pos.y < min_y || pos.y > max_y;
+
+        for (label, locations) in portal_locations {
+            assert!(locations.len() == 2, "portal {} doesn't have exactly two ends", label);
+            for &(pos, other_end) in &[(locations[0], locations[1]), (locations[1], locations[0])] {
+                let warp_to = tiles[tile_index!(other_end, w)].portal_info().attached_passage;
+                match tiles[tile_index!(pos, w)].kind {
+                    TileKind::Portal(ref mut info) => {
+                        info.on_outer_edge = on_outer_edge(&pos);
+                        info.other_end = other_end;
+                        info.warp_to = warp_to;
+                        info.level_change = if info.on_outer_edge { -1 } else { 1 };
+                    },
+                    _ => panic!(),
+                };
+            }
+        }
+
+        // from each passage, a step can be taken to any neighbouring passage, or through any neighbouring portal
+        let mut links = Vec::<Vec<Link>>::with_capacity(w * h);
+        for tile in &tiles {
+            let mut tile_links = Vec::new();
+            if tile.is_passage() {
+                let pos = tile.pos;
+                let mut nbs = Vec::new();
+                if pos.x > 0 { nbs.push(pos + Pos::x_neg_one()); }
+                if pos.y > 0 { nbs.push(pos + Pos::y_neg_one()); }
+                if pos.x < (w-1) as i32 { nbs.push(pos + Pos::x_one()); }
+                if pos.y < (h-1) as i32 { nbs.push(pos + Pos::y_one()); }
+                for nb in nbs {
+                    match tiles[tile_index!(nb, w)].kind {
+                        TileKind::Passage => tile_links.push(Link { to: nb, level_change: 0 }),
+                        TileKind::Portal(ref info) => tile_links.push(Link { to: info.warp_to, level_change: info.level_change }),
+                        _ => {},
+                    }
+                }
             }
+            links.push(tile_links);
         }
 
         Self {
@@ -232,7 +265,7 @@ impl Map {
             tiles,
             starting_pos: starting_pos.unwrap(),
             target_pos: target_pos.unwrap(),
-            portal_pairs,
+            links,
             recursive_portals,
         }
     }
@@ -241,17 +274,7 @@ impl Map {
     }
     pub fn paired_portal_location(&self, portal_pos: &Pos) -> &Pos {
         // given an input position of a portal, returns the location of the other end of the portal
-        // TODO: record this info inside PortalInfo instead?
-        match self[portal_pos].kind {
-            TileKind::Portal(_) => {
-                // note: need to perform the lookup into the portal_pairs map using a Pos with level 0,
-                // since that's how these are recorded at Map construction time
-                let other_end: &Pos = &self.portal_pairs[&portal_pos.at_level(0)];
-                assert!(self[other_end].is_portal());
-                return other_end;
-            },
-            _ => panic!("tile at position {} is not a portal", portal_pos),
-        }
+        &self[portal_pos].portal_info().other_end
     }
     pub fn visualize(&self) -> String {
         // run in two passes; in the first, just emit the tiles without any portals;
@@ -326,6 +349,29 @@ impl Map {
         let result = lines.join("\n");
         return result;
     }
+    pub fn visualize_route(&self, route: &Route) -> String {
+        // the map with every tile along the route marked with the level it was walked on (in base 36), and
+        // the passages where a portal was taken marked with a '*' next to that.
+        let mut lines: Vec<String> = self.visualize().lines().map(|l| l.to_owned()).collect();
+        let level_char = |level: i32| std::char::from_digit(level as u32, 36).unwrap_or('+');
+        for segment in &route.segments {
+            match segment {
+                Segment::Walk { tiles, .. } => {
+                    for pos in tiles.iter().filter(|&p| p.at_level(0) != self.target_pos) {
+                        let x = pos.x as usize;
+                        lines[pos.y as usize].replace_range(x*2..x*2+1, &level_char(pos.level).to_string());
+                    }
+                },
+                Segment::Jump { from, to, .. } => {
+                    for pos in &[from, to] {
+                        let x = pos.x as usize;
+                        lines[pos.y as usize].replace_range(x*2..x*2+2, &format!("{}*", level_char(pos.level)));
+                    }
+                },
+            }
+        }
+        lines.join("\n")
+    }
 }
 impl Index<&Pos> for Map {
     type Output = Tile;
@@ -363,98 +409,253 @@ impl path::Map for Map {
 
     fn neighbours(&self, pos: &Pos) -> Vec<(Pos, Self::Cost)>
     {
-        macro_rules! add_neighbour {
-            ($tile_pos:ident, $neighbours:ident) => {{
-                match self[&$tile_pos].kind {
-                    TileKind::Passage     => {
-                        $neighbours.push(($tile_pos, 1));
-                    },
-                    TileKind::Portal(ref portal_info) => {
-                        let on_outer_edge = portal_info.on_outer_edge;
-                        let paired_portal: &Pos = self.paired_portal_location(&$tile_pos);
-
-                        let mut warp_location = self[paired_portal].portal_info().attached_passage.clone(); // note: always at level 0 (since that's how it was originally recorded)!
-
-                        if self.recursive_portals {
-                            // in recursive mode, portals on the outer edge are only accessible if we're at
-                            // level > 0, and the depth of the warped-to position is either incremented
-                            // or decremented depending on whether we're taking an outer or inner portal.
-                            let accessible = ($tile_pos.level > 0 || !on_outer_edge);
-                            if accessible {
-                                warp_location.level = $tile_pos.level + if on_outer_edge { -1 }  else { 1 };
-                                assert!(warp_location.level >= 0);
-                                $neighbours.push((warp_location, 1));
-                            }
-                        } else {
-                            // in non-recursive mode, portals can always be taken
-                            $neighbours.push((warp_location, 1));
-                        }
-                    },
-                    _ => {},
-                }
-            }}
+        // the links are all recorded at level 0, so move them to the level we're on. portals are taken transparently,
+        // i.e. the neighbour is the passage at the other end rather than the portal tile itself.
+        let mut result = Vec::new();
+        for link in &self.links[tile_index!(pos, self.w)] {
+            if link.level_change == 0 || !self.recursive_portals {
+                // in non-recursive mode, portals can always be taken
+                result.push((link.to.at_level(pos.level), 1));
+                continue;
+            }
+            // in recursive mode, portals on the outer edge are only accessible if we're at level > 0, and
+            // the depth of the warped-to position is either incremented or decremented depending on whether
+            // we're taking an outer or inner portal.
+            let level = pos.level + link.level_change;
+            if level >= 0 {
+                result.push((link.to.at_level(level), 1));
+            }
         }
+        result
+    }
+}
+
+struct Corridors<'a> {
+    // the maze as plain passages, without taking any portals
+    map: &'a Map,
+}
+impl<'a> path::Map for Corridors<'a> {
+    type Node = Pos;
+    type Cost = u32;
 
+    fn neighbours(&self, pos: &Pos) -> Vec<(Pos, Self::Cost)> {
+        let map = self.map;
         let mut result = Vec::new();
-        if pos.x > 0 {
-            let left = *pos + Pos::x_neg_one();
-            add_neighbour!(left, result);
+        if pos.x > 0 { result.push((*pos + Pos::x_neg_one(), 1)); }
+        if pos.y > 0 { result.push((*pos + Pos::y_neg_one(), 1)); }
+        if pos.x < (map.w-1) as i32 { result.push((*pos + Pos::x_one(), 1)); }
+        if pos.y < (map.h-1) as i32 { result.push((*pos + Pos::y_one(), 1)); }
+        result.retain(|(nb, _)| map[nb].is_passage());
+        result
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+struct Edge {
+    to: Pos,          // always at level 0
+    distance: u32,
+    level_change: i32,
+}
+
+struct PortalGraph {
+    // the maze condensed down to the passages next to the portals plus the start and target positions, with
+    // the corridor distances between them and the portal jumps. every level of the recursive maze has the same
+    // layout, so the edges are only recorded for level 0 and moved to whatever level they're taken from.
+    edges: HashMap<Pos, Vec<Edge>>,
+    starting_pos: Pos,
+    target_pos: Pos,
+    recursive_portals: bool,
+    max_level: i32, // deepest level the search may go to, in recursive mode
+}
+impl PortalGraph {
+    pub fn new(map: &Map) -> Self {
+        let portals: Vec<&Tile> = map.iter().filter(|t| t.is_portal()).collect();
+        let mut endpoints: Vec<Pos> = portals.iter().map(|t| t.portal_info().attached_passage).collect();
+        endpoints.push(map.starting_pos);
+        endpoints.push(map.target_pos);
+        endpoints.sort_by_key(|p| (p.y, p.x));
+        endpoints.dedup(); // a passage can be next to more than one portal
+
+        let mut edges = HashMap::<Pos, Vec<Edge>>::new();
+        for source in &endpoints {
+            let (dists, _) = path::bfs(&Corridors { map }, source, |_, _| true);
+            let reachable = endpoints.iter().filter(|&p| p != source)
+                                            .filter_map(|p| dists.get(p).map(|&d| Edge { to: *p, distance: d as u32, level_change: 0 }));
+            edges.insert(*source, reachable.collect());
         }
-        if pos.y > 0 {
-            let up = *pos + Pos::y_neg_one();
-            add_neighbour!(up, result);
+        for tile in &portals {
+            let info = tile.portal_info();
+            edges.get_mut(&info.attached_passage).unwrap().push(Edge { to: info.warp_to, distance: 1, level_change: info.level_change });
         }
-        if pos.x < (self.w-1) as i32 {
-            let right = *pos + Pos::x_one();
-            add_neighbour!(right, result);
+
+        Self {
+            edges,
+            starting_pos: map.starting_pos,
+            target_pos: map.target_pos,
+            recursive_portals: map.recursive_portals,
+            max_level: Self::default_max_level(portals.len() / 2),
         }
-        if pos.y < (self.h-1) as i32 {
-            let down = *pos + Pos::y_one();
-            add_neighbour!(down, result);
+    }
+    fn default_max_level(num_portal_pairs: usize) -> i32 {
+        // every level that's gone down into has to be climbed back out of again to reach the exit, and a route
+        // that keeps going deeper is just going around in circles. how deep a shortest route can go isn't bounded
+        // by anything we know of though; going no deeper than there are portals is just a heuristic that works
+        // for the puzzle inputs, and --max-level overrides it.
+        num_portal_pairs.max(1) as i32
+    }
+}
+impl path::Map for PortalGraph {
+    type Node = Pos;
+    type Cost = u32;
+
+    fn neighbours(&self, pos: &Pos) -> Vec<(Pos, Self::Cost)> {
+        let mut result = Vec::new();
+        for edge in &self.edges[&pos.at_level(0)] {
+            if !self.recursive_portals {
+                result.push((edge.to, edge.distance));
+                continue;
+            }
+            // same rules as for the tiles: outer edge portals can't be taken from level 0
+            let level = pos.level + edge.level_change;
+            if level >= 0 && level <= self.max_level {
+                result.push((edge.to.at_level(level), edge.distance));
+            }
         }
         result
     }
 }
 
-pub fn main() {
-    let lines = util::file_read_lines("input/day20.txt");
-    println!("{}", part1(&lines));
-    println!("{}", part2(&lines));
+#[derive(PartialEq, Eq, Debug, Clone)]
+enum Segment {
+    Walk { from: String, to: String, tiles: Vec<Pos> }, // along the corridors on one level; the tiles exclude the first one
+    Jump { portal: String, from: Pos, to: Pos },        // through a portal, possibly to another level
 }
 
-fn part1(lines: &Vec<String>) -> u32 {
-    let map = Map::new(&lines, false);
+#[derive(PartialEq, Eq, Debug, Clone)]
+struct Route {
+    cost: u32,
+    segments: Vec<Segment>,
+}
+impl Route {
+    pub fn expand(map: &Map, graph: &PortalGraph, nodes: &[Pos]) -> Self {
+        // fills in the tiles walked between the graph nodes of a shortest path
+        let mut labels = HashMap::<Pos, String>::new();
+        for tile in map.iter().filter(|t| t.is_portal()) {
+            labels.insert(tile.portal_info().attached_passage, tile.portal_info().label.clone());
+        }
+        labels.insert(map.starting_pos, "AA".to_string());
+        labels.insert(map.target_pos, "ZZ".to_string());
+
+        let mut segments = Vec::new();
+        for pair in nodes.windows(2) {
+            let (from, to) = (pair[0], pair[1]);
+            // if a corridor and a portal both lead to the same place, the path went whichever way was shorter
+            // (a corridor in case of a tie); in recursive mode, the level makes the difference as well.
+            let edge = graph.edges[&from.at_level(0)].iter()
+                                .filter(|e| e.to == to.at_level(0))
+                                .filter(|e| !graph.recursive_portals || from.level + e.level_change == to.level)
+                                .min_by_key(|e| (e.distance, e.level_change != 0))
+                                .unwrap();
+            if edge.level_change != 0 {
+                segments.push(Segment::Jump { portal: labels[&from.at_level(0)].clone(), from, to });
+            } else {
+                let (_, came_from) = path::bfs(&Corridors { map }, &from.at_level(0), |_, _| true);
+                let tiles = path::Path::<Pos,Corridors>::reconstruct_from(&to.at_level(0), &came_from);
+                segments.push(Segment::Walk {
+                    from: labels[&from.at_level(0)].clone(),
+                    to: labels[&to.at_level(0)].clone(),
+                    tiles: tiles[1..].iter().map(|p| p.at_level(from.level)).collect(),
+                });
+            }
+        }
+        let cost = segments.iter().map(|s| match s {
+            Segment::Walk { tiles, .. } => tiles.len() as u32,
+            Segment::Jump { .. }        => 1,
+        }).sum();
+        Route { cost, segments }
+    }
+}
+impl fmt::Display for Route {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for segment in &self.segments {
+            match segment {
+                Segment::Walk { from, to, tiles } => {
+                    writeln!(f, "walk from {} to {} on level {} ({} steps)", from, to, tiles[0].level, tiles.len())?;
+                },
+                Segment::Jump { portal, from, to } if from.level != to.level => {
+                    writeln!(f, "take {} {} to level {}", portal, if to.level > from.level { "down" } else { "up" }, to.level)?;
+                },
+                Segment::Jump { portal, .. } => {
+                    writeln!(f, "take {}", portal)?;
+                },
+            }
+        }
+        writeln!(f, "{} steps in total", self.cost)
+    }
+}
 
-    // we can't use A* because taking a portal would cause the heuristic to change drastically
-    // midway during the operation, which is likely to render it inadmissible, so we'll use dijkstra instead.
-    // note that the pathfinder should never encounter nodes of type Portal during operation, as the .neighbours()
-    // call implementation transparently replaces them with the passageways attached to their other end.
-    let path_maybe = path::dijkstra_to_target(&map, &map.starting_pos, &map.target_pos,
-        |map,pos| match map[pos].kind {
-                      TileKind::Passage => true,
-                      TileKind::Portal(_) => panic!("encountered portal node during pathfinding"), // should be transp.
-                      _ => false,
-                  }
-        );
+pub fn main(args: &ArgMatches) {
+    let lines = util::file_read_lines("input/day20.txt");
+    let max_level = args.value_of("max-level").map(|s| s.parse().unwrap_or_else(|_| panic!("invalid max level: {}", s)));
+    report(&Map::new(&lines, false), &part1(&lines).unwrap_or_else(|err| panic!("{}", err)), args);
+    report(&Map::new(&lines, true), &part2(&lines, max_level).unwrap_or_else(|err| panic!("{}", err)), args);
+}
 
-    if let Some(path) = path_maybe {
-        assert!(path.nodes.iter().all(|p| p.level == 0)); // in part 1, we should stay entirely within the same level
-        return path.cost;
-    } else {
-        panic!("no path found between {} and {}", map.starting_pos, map.target_pos);
+fn report(map: &Map, route: &Route, args: &ArgMatches) {
+    println!("{}", route.cost);
+    if args.is_present("analyze") {
+        print!("{}", route);
+    }
+    if args.is_present("render") {
+        println!("{}", map.visualize_route(route));
     }
 }
 
-fn part2(lines: &Vec<String>) -> u32 {
-    let map = Map::new(&lines, true);
+fn part1(lines: &Vec<String>) -> Result<Route, String> {
+    let map = Map::new(&lines, false);
+    shortest_path(&map, &PortalGraph::new(&map))
+}
 
+fn part2(lines: &Vec<String>, max_level: Option<i32>) -> Result<Route, String> {
     // same thing as before, but now points contain an active third coordinate, i.e. the recursion depth.
     // note: in this variant it's possible to infinitely descend into recursively nested maps, and also
-    // for the exit to not be reachable, so there's a real risk of the pathfinding never terminating.
+    // for the exit to not be reachable (as in example map 2), so the search is limited to a maximum level
+    // to make sure it terminates.
+    let map = Map::new(lines, true);
+    let mut graph = PortalGraph::new(&map);
+    if let Some(max_level) = max_level {
+        graph.max_level = max_level;
+    }
+    shortest_path(&map, &graph)
+}
+
+fn shortest_path(map: &Map, graph: &PortalGraph) -> Result<Route, String> {
+    // we can't use A* because taking a portal would cause the heuristic to change drastically
+    // midway during the operation, which is likely to render it inadmissible, so we'll use dijkstra instead.
+    // the search runs over the condensed graph rather than tile by tile, so it only has to make a choice
+    // at the portals instead of at every step along the corridors between them.
+    let path = path::dijkstra_to_target(graph, &graph.starting_pos, &graph.target_pos, |_, _| true);
+    match path {
+        Some(path) => {
+            if !graph.recursive_portals {
+                assert!(path.nodes.iter().all(|p| p.level == 0)); // in part 1, we should stay entirely within the same level
+            }
+            let route = Route::expand(map, graph, &path.nodes);
+            assert_eq!(route.cost, path.cost);
+            Ok(route)
+        },
+        None if graph.recursive_portals => Err(format!("no path found between {} and {} within depth {}; try raising --max-level",
+                                                       graph.starting_pos, graph.target_pos, graph.max_level)),
+        None => Err(format!("no path found between {} and {}", graph.starting_pos, graph.target_pos)),
+    }
+}
 
-    // indeed, as stated in the problem description, running this on example map 2 will never terminate,
-    // so don't do that :o)
-    let path_maybe = path::dijkstra_to_target(&map, &map.starting_pos, &map.target_pos,
+#[cfg(test)]
+fn tile_by_tile(map: &Map) -> u32 {
+    // the shortest path found by walking the maze one tile at a time
+    // note that the pathfinder should never encounter nodes of type Portal during operation, as the .neighbours()
+    // call implementation transparently replaces them with the passageways attached to their other end.
+    let path_maybe = path::dijkstra_to_target(map, &map.starting_pos, &map.target_pos,
         |map,pos| match map[pos].kind {
                       TileKind::Passage => true,
                       TileKind::Portal(_) => panic!("encountered portal node during pathfinding"), // should be transp.
@@ -596,6 +797,17 @@ mod tests {
         assert!(map.neighbours(&pos![2,15]).contains(&(pos![11,12], 1)));
     }
 
+    #[test]
+    fn portal_pairing() {
+        // the inner end of portal "BC" is labeled below the passage at (9,6), the outer one left of (2,8)
+        let map = Map::new(&example_map(1), true);
+        let inner = map[&pos![9,7]].portal_info();
+        assert_eq!((inner.other_end, inner.warp_to, inner.on_outer_edge, inner.level_change), (pos![1,8], pos![2,8], false, 1));
+        let outer = map[&pos![1,8]].portal_info();
+        assert_eq!((outer.other_end, outer.warp_to, outer.on_outer_edge, outer.level_change), (pos![9,7], pos![9,6], true, -1));
+        assert_eq!(map.paired_portal_location(&pos![9,7]), &pos![1,8]);
+    }
+
     #[test]
     fn recursive_portal_neighbours() {
         // same as the portal_neighbours test, but now additionally check for proper incrementing/decrementing
@@ -611,9 +823,66 @@ mod tests {
 
     #[test]
     fn example_solutions() {
-        assert_eq!(part1(&example_map(1)), 23);
-        assert_eq!(part1(&example_map(2)), 58);
-        assert_eq!(part2(&example_map(1)), 26);
-        assert_eq!(part2(&example_map(3)), 396);
+        assert_eq!(part1(&example_map(1)).map(|r| r.cost), Ok(23));
+        assert_eq!(part1(&example_map(2)).map(|r| r.cost), Ok(58));
+        assert_eq!(part2(&example_map(1), None).map(|r| r.cost), Ok(26));
+        assert_eq!(part2(&example_map(3), None).map(|r| r.cost), Ok(396));
+    }
+
+    #[test]
+    fn depth_limit() {
+        // example map 2 has no way out in recursive mode at all
+        assert_eq!(part2(&example_map(2), None).map(|r| r.cost), Err("no path found between (x=19,y=2,d=0) and (x=2,y=17,d=0) within depth 10; try raising --max-level".to_string()));
+        // the route in example map 3 goes down to level 10
+        assert_eq!(part2(&example_map(3), Some(10)).map(|r| r.cost), Ok(396));
+        assert!(part2(&example_map(3), Some(9)).is_err());
+    }
+
+    #[test]
+    fn portal_graph() {
+        let map = Map::new(&example_map(1), false);
+        let graph = PortalGraph::new(&map);
+        // from AA, straight down to the passage next to BC, or all the way around to ZZ and FG
+        let mut from_start = graph.edges[&map.starting_pos].clone();
+        from_start.sort_by_key(|e| e.distance);
+        assert_eq!(from_start, vec![Edge { to: pos![9,6], distance: 4, level_change: 0 },
+                                    Edge { to: map.target_pos, distance: 26, level_change: 0 },
+                                    Edge { to: pos![11,12], distance: 30, level_change: 0 }]);
+        // BC is an inner portal at (9,7) leading to the outer one at (1,8)
+        assert!(graph.edges[&pos![9,6]].contains(&Edge { to: pos![2,8], distance: 1, level_change: 1 }));
+        assert!(graph.edges[&pos![2,8]].contains(&Edge { to: pos![9,6], distance: 1, level_change: -1 }));
+
+        // same answers as walking the maze tile by tile
+        for &(n, recursive) in &[(1, false), (2, false), (3, false), (1, true), (3, true)] {
+            let map = Map::new(&example_map(n), recursive);
+            assert_eq!(shortest_path(&map, &PortalGraph::new(&map)).map(|r| r.cost), Ok(tile_by_tile(&map)), "map {}, recursive: {}", n, recursive);
+        }
+    }
+
+    #[test]
+    fn route() {
+        let route = part1(&example_map(1)).unwrap();
+        assert_eq!(route.to_string(), "walk from AA to BC on level 0 (4 steps)\n\
+                                       take BC\n\
+                                       walk from BC to DE on level 0 (6 steps)\n\
+                                       take DE\n\
+                                       walk from DE to FG on level 0 (4 steps)\n\
+                                       take FG\n\
+                                       walk from FG to ZZ on level 0 (6 steps)\n\
+                                       23 steps in total\n");
+        let rendered = Map::new(&example_map(1), false).visualize_route(&route);
+        let lines: Vec<&str> = rendered.lines().collect();
+        assert_eq!(lines[8],  "B C 0*0 0 # #     C         # # # . #     ");
+        assert_eq!(lines[10], "    # # 0 0 0*D E     F     # # # . #     ");
+        assert_eq!(lines[16], "    # # # # # # # # # # # $ # # # # #     ");
+
+        // in recursive mode, the levels go up and down with the portals
+        let route = part2(&example_map(3), None).unwrap();
+        let text = route.to_string();
+        assert!(text.starts_with("walk from AA to XF on level 0 (16 steps)\ntake XF down to level 1\n"));
+        assert!(text.ends_with("take FD up to level 0\nwalk from FD to ZZ on level 0 (18 steps)\n396 steps in total\n"));
+        let rendered = Map::new(&example_map(3), true).visualize_route(&route);
+        assert_eq!(rendered.lines().nth(13).unwrap(),
+                   "    # . # . . 4 4*O A                                               W B 8*8 # . # 8 8*Z H ");
     }
 }
@@ -1,21 +1,500 @@
 // vim: set ai et ts=4 sts=4 sw=4:
 use crate::util;
 use crate::intcode::{CPU, CpuState};
+use clap::ArgMatches;
+use std::fmt;
+use std::ops;
+use std::collections::HashSet;
 
-pub fn main() {
-    let line: String = util::file_read_lines("input/day21.txt").into_iter().next().unwrap();
-    let program: Vec<i64> = line.split(",").map(|s| s.parse().unwrap()).collect();
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Walk, // sensors A to D
+    Run,  // sensors A to I
+}
+impl Mode {
+    pub fn num_sensors(&self) -> usize {
+        match self { Mode::Walk => 4, Mode::Run => 9 }
+    }
+    pub fn command(&self) -> &'static str {
+        match self { Mode::Walk => "WALK", Mode::Run => "RUN" }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Reg {
+    Sensor(u8), // 0 is A, 1 is B, ...
+    T,
+    J,
+}
+impl fmt::Display for Reg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Reg::Sensor(n) => write!(f, "{}", (b'A' + n) as char),
+            Reg::T         => write!(f, "T"),
+            Reg::J         => write!(f, "J"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Op { And, Or, Not }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Instr {
+    pub op: Op,
+    pub src: Reg,
+    pub dst: Reg, // T or J
+}
+impl fmt::Display for Instr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let op = match self.op { Op::And => "AND", Op::Or => "OR", Op::Not => "NOT" };
+        write!(f, "{} {} {}", op, self.src, self.dst)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Script {
+    pub mode: Mode,
+    pub instrs: Vec<Instr>,
+}
+impl Script {
+    pub fn check(&self) -> Result<(), String> {
+        // the limits the droid puts on the scripts it accepts
+        if self.instrs.len() > MAX_INSTRUCTIONS {
+            return Err(format!("the droid only takes up to {} instructions, got {}", MAX_INSTRUCTIONS, self.instrs.len()));
+        }
+        for instr in &self.instrs {
+            match (instr.src, instr.dst) {
+                (_, Reg::Sensor(_)) => return Err(format!("can't write to a sensor: {}", instr)),
+                (Reg::Sensor(s), _) if s as usize >= self.mode.num_sensors() =>
+                    return Err(format!("sensor {} isn't available in {} mode: {}", instr.src, self.mode.command(), instr)),
+                _ => {},
+            }
+        }
+        Ok(())
+    }
+    pub fn jumps(&self, window: u16) -> bool {
+        // runs the script for the given sensor readings (A in the lowest bit), and returns whether the droid jumps
+        let (mut t, mut j) = (false, false);
+        for instr in &self.instrs {
+            let src = match instr.src { Reg::Sensor(s) => window & (1 << s) != 0, Reg::T => t, Reg::J => j };
+            let dst = if instr.dst == Reg::T { &mut t } else { &mut j };
+            *dst = match instr.op { Op::And => src && *dst, Op::Or => src || *dst, Op::Not => !src };
+        }
+        j
+    }
+}
+impl fmt::Display for Script {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // in the format the springdroid expects it
+        for instr in &self.instrs {
+            writeln!(f, "{}", instr)?;
+        }
+        writeln!(f, "{}", self.mode.command())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    // a boolean expression over the sensors, built up with the !, & and | operators
+    Sensor(u8),
+    Not(Box<Expr>),
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+}
+pub mod sensors {
+    use super::Expr;
+    pub const A: Expr = Expr::Sensor(0);
+    pub const B: Expr = Expr::Sensor(1);
+    pub const C: Expr = Expr::Sensor(2);
+    pub const D: Expr = Expr::Sensor(3);
+    pub const E: Expr = Expr::Sensor(4);
+    pub const F: Expr = Expr::Sensor(5);
+    #[allow(dead_code)]
+    pub const G: Expr = Expr::Sensor(6);
+    pub const H: Expr = Expr::Sensor(7);
+    pub const I: Expr = Expr::Sensor(8);
+}
 
-    println!("{}", part1(&program));
-    println!("{}", part2(&program));
+// compiles a springscript expression into the script that computes it into J, e.g.
+//   spring!{ J = !(A & B & C) & D }
+// runs if it looks any further ahead than D, walks otherwise.
+macro_rules! spring {
+    (J = $($expr:tt)+) => {{
+        use $crate::day21::sensors::*;
+        ($($expr)+).script()
+    }};
 }
 
-fn part1(program: &Vec<i64>) -> usize {
+impl ops::Not for Expr {
+    type Output = Expr;
+    fn not(self) -> Expr {
+        Expr::Not(Box::new(self))
+    }
+}
+impl ops::BitAnd for Expr {
+    type Output = Expr;
+    fn bitand(self, rhs: Expr) -> Expr {
+        match self {
+            Expr::And(mut operands) => { operands.push(rhs); Expr::And(operands) },
+            lhs => Expr::And(vec![lhs, rhs]),
+        }
+    }
+}
+impl ops::BitOr for Expr {
+    type Output = Expr;
+    fn bitor(self, rhs: Expr) -> Expr {
+        match self {
+            Expr::Or(mut operands) => { operands.push(rhs); Expr::Or(operands) },
+            lhs => Expr::Or(vec![lhs, rhs]),
+        }
+    }
+}
+
+impl Expr {
+    #[allow(dead_code)]
+    pub fn holds(&self, window: u16) -> bool {
+        // evaluates the expression for the given sensor readings (A in the lowest bit)
+        match self {
+            Expr::Sensor(s)       => window & (1 << s) != 0,
+            Expr::Not(e)          => !e.holds(window),
+            Expr::And(operands)   => operands.iter().all(|e| e.holds(window)),
+            Expr::Or(operands)    => operands.iter().any(|e| e.holds(window)),
+        }
+    }
+    fn sensors(&self) -> u16 {
+        match self {
+            Expr::Sensor(s)       => 1 << s,
+            Expr::Not(e)          => e.sensors(),
+            Expr::And(operands) |
+            Expr::Or(operands)    => operands.iter().fold(0, |acc, e| acc | e.sensors()),
+        }
+    }
+    fn is_sensor(&self) -> bool {
+        matches!(self, Expr::Sensor(_))
+    }
+    fn first_operand(operands: &[Expr]) -> usize {
+        // the operand that's computed straight into the destination register; all others are folded into it one by
+        // one, and the ones that aren't plain sensor readings need the other register for that. so start with the
+        // one that needs the most registers, preferably not a plain sensor reading (those can be folded in for free).
+        (0..operands.len()).rev().max_by_key(|&i| (!operands[i].is_sensor(), operands[i].registers())).unwrap()
+    }
+    fn registers(&self) -> usize {
+        // the number of registers needed to compute the expression
+        match self {
+            Expr::Sensor(_)       => 1,
+            Expr::Not(e)          => e.registers(),
+            Expr::And(operands) |
+            Expr::Or(operands)    => {
+                let first = Expr::first_operand(operands);
+                operands.iter().enumerate().map(|(i, e)| match e {
+                    _ if i == first      => e.registers(),
+                    Expr::Sensor(_)      => 1,
+                    _                    => 1 + e.registers(),
+                }).max().unwrap()
+            },
+        }
+    }
+    fn compile(&self, dst: Reg, alloc: &mut Allocator) {
+        let other = if dst == Reg::T { Reg::J } else { Reg::T };
+        match self {
+            &Expr::Sensor(s) => {
+                if alloc.is_clean(dst) {
+                    alloc.push(Op::Or, Reg::Sensor(s), dst);
+                } else {
+                    alloc.push(Op::Not, Reg::Sensor(s), dst);
+                    alloc.push(Op::Not, dst, dst);
+                }
+            },
+            Expr::Not(e) => match **e {
+                Expr::Sensor(s) => alloc.push(Op::Not, Reg::Sensor(s), dst),
+                _ => {
+                    e.compile(dst, alloc);
+                    alloc.push(Op::Not, dst, dst);
+                },
+            },
+            Expr::And(operands) |
+            Expr::Or(operands) => {
+                let op = if let Expr::And(_) = self { Op::And } else { Op::Or };
+                let first = Expr::first_operand(operands);
+                operands[first].compile(dst, alloc);
+                let rest = || operands.iter().enumerate().filter(|&(i, _)| i != first).map(|(_, e)| e);
+                for e in rest() {
+                    if let &Expr::Sensor(s) = e {
+                        alloc.push(op, Reg::Sensor(s), dst);
+                    }
+                }
+                for e in rest().filter(|e| !e.is_sensor()) {
+                    e.compile(other, alloc);
+                    alloc.push(op, other, dst);
+                }
+            },
+        }
+    }
+    pub fn script(&self) -> Result<Script, String> {
+        // the script that jumps iff the expression holds
+        if self.registers() > 2 {
+            return Err(format!("{} needs more registers than just T and J", self));
+        }
+        let mut alloc = Allocator { instrs: Vec::new(), clean: [true, true] };
+        self.compile(Reg::J, &mut alloc);
+        let mode = if self.sensors() >> Mode::Walk.num_sensors() != 0 { Mode::Run } else { Mode::Walk };
+        Ok(Script { mode, instrs: alloc.instrs })
+    }
+}
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let operand = |e: &Expr| match e {
+            Expr::And(_) | Expr::Or(_) => format!("({})", e),
+            _ => e.to_string(),
+        };
+        match self {
+            &Expr::Sensor(s)      => write!(f, "{}", Reg::Sensor(s)),
+            Expr::Not(e)          => write!(f, "!{}", operand(e)),
+            Expr::And(operands)   => write!(f, "{}", operands.iter().map(operand).collect::<Vec<_>>().join(" & ")),
+            Expr::Or(operands)    => write!(f, "{}", operands.iter().map(operand).collect::<Vec<_>>().join(" | ")),
+        }
+    }
+}
+
+struct Allocator {
+    instrs: Vec<Instr>,
+    clean: [bool; 2], // whether T and J still hold their initial false value
+}
+impl Allocator {
+    fn is_clean(&self, reg: Reg) -> bool {
+        self.clean[if reg == Reg::T { 0 } else { 1 }]
+    }
+    fn push(&mut self, op: Op, src: Reg, dst: Reg) {
+        self.instrs.push(Instr { op, src, dst });
+        self.clean[if dst == Reg::T { 0 } else { 1 }] = false;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Survived(i64), // the amount of hull damage reported
+    Fell(Fall),
+}
+
+pub fn run_script(program: &Vec<i64>, script: &Script) -> Result<Outcome, String> {
     let mut cpu = CPU::new(program);
     cpu.run();
     cpu.consume_output_all(); // skip the input prompt
     assert!(cpu.get_state() == CpuState::WaitIO);
 
+    cpu.send_input_string(&script.to_string());
+    cpu.run();
+    assert!(cpu.is_halted());
+    let output = cpu.consume_output_all();
+    match output.last() {
+        Some(&damage) if damage > 127 => Ok(Outcome::Survived(damage)),
+        _ => {
+            let output: String = output.iter().map(|&c| c as u8 as char).collect();
+            Fall::parse(&output, script.mode).map(Outcome::Fell)
+        },
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fall {
+    // the droid's last moments, as drawn by the program when it doesn't make it across
+    pub mode: Mode,
+    pub hull: Vec<bool>,
+    pub droid: Vec<(usize, usize)>, // the droid's position in each frame, as (x, height above the hull)
+}
+impl Fall {
+    pub fn parse(output: &str, mode: Mode) -> Result<Fall, String> {
+        // each frame is drawn as three rows of air above a row of hull, with the droid somewhere in there;
+        // in the last one, it's drawn in the hole it fell into.
+        let lines: Vec<&str> = output.lines().skip_while(|line| !line.starts_with("Didn't make it across")).skip(1).collect();
+        let mut hull = None;
+        let mut droid = Vec::new();
+        for frame in lines.split(|line| line.is_empty()).filter(|frame| !frame.is_empty()) {
+            if frame.len() != 4 {
+                return Err(format!("unexpected frame of {} rows in the droid's output:\n{}", frame.len(), frame.join("\n")));
+            }
+            let (y, x) = frame.iter().enumerate().find_map(|(y, row)| row.find('@').map(|x| (y, x)))
+                              .ok_or(format!("no droid in frame:\n{}", frame.join("\n")))?;
+            droid.push((x, 3 - y));
+            hull.get_or_insert_with(|| frame[3].chars().map(|c| c == '#').collect::<Vec<bool>>());
+        }
+        match hull {
+            Some(hull) => Ok(Fall { mode, hull, droid }),
+            None       => Err(format!("can't find the droid's last moments in its output:\n{}", output)),
+        }
+    }
+    pub fn last_decision(&self) -> Option<(usize, bool)> {
+        // where the droid last stood on the hull before falling, and whether it jumped from there
+        let standing = (0..self.droid.len() - 1).rev().find(|&i| self.droid[i].1 == 1 && self.hull[self.droid[i].0])?;
+        let jumped = self.droid[standing + 1].1 > 1;
+        Some((self.droid[standing].0, jumped))
+    }
+}
+impl fmt::Display for Fall {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // the last decision the droid made, with what it saw at the time, followed by where it fell
+        let tile = |x: usize| if *self.hull.get(x).unwrap_or(&true) { '#' } else { '.' };
+        let hull: String = (0..self.hull.len()).map(tile).collect();
+        let &(fell_at, _) = self.droid.last().unwrap();
+        match self.last_decision() {
+            Some((x, jumped)) => {
+                let sensors: Vec<String> = (0..self.mode.num_sensors()).map(|s| format!("{}={}", (b'A' + s as u8) as char, tile(x + 1 + s))).collect();
+                writeln!(f, "{}@", " ".repeat(x))?;
+                writeln!(f, "{}", hull)?;
+                writeln!(f, "{}{}", " ".repeat(x + 1), &"ABCDEFGHI"[..self.mode.num_sensors()])?;
+                writeln!(f, "{} at x={} seeing {}, and fell into the hole at x={}",
+                         if jumped { "jumped" } else { "walked on" }, x, sensors.join(" "), fell_at)
+            },
+            None => {
+                writeln!(f, "{}", hull)?;
+                writeln!(f, "fell into the hole at x={}", fell_at)
+            },
+        }
+    }
+}
+
+pub const JUMP_DISTANCE: usize = 4;
+
+pub fn parse_hull(text: &str) -> Result<Vec<bool>, String> {
+    // a hull as drawn by the droid, e.g. "#####.###########", starting where the droid stands
+    let hull = text.chars().map(|c| match c {
+        '#' => Ok(true),
+        '.' => Ok(false),
+        _   => Err(format!("invalid hull tile '{}' in {}", c, text)),
+    }).collect::<Result<Vec<bool>, String>>()?;
+    match hull.first() {
+        Some(true) => Ok(hull),
+        _          => Err(format!("the droid needs ground to start on: {}", text)),
+    }
+}
+
+pub fn simulate(script: &Script, hull: &[bool]) -> Option<Fall> {
+    // runs the script on a hull the way the droid would, without involving the intcode program, and returns
+    // how it fell if it doesn't make it across. in walk mode the droid only sees the first four tiles ahead.
+    let visible = (1 << script.mode.num_sensors()) - 1;
+    let mut droid = vec![(0, 1)];
+    let mut pos = 0;
+    while pos < hull.len() {
+        let jumped = script.jumps(sensor_window(hull, pos) & visible);
+        if jumped {
+            // up and down again in an arc, ending up right above the tile it was aiming for
+            droid.extend((1..JUMP_DISTANCE).map(|i| (pos + i, 1 + i.min(JUMP_DISTANCE - i))));
+            pos += JUMP_DISTANCE;
+        } else {
+            pos += 1;
+        }
+        let on_ground = pos >= hull.len() || hull[pos];
+        if jumped || on_ground {
+            droid.push((pos, 1));
+        }
+        if !on_ground {
+            droid.push((pos, 0));
+            return Some(Fall { mode: script.mode, hull: hull.to_vec(), droid });
+        }
+    }
+    None
+}
+
+pub fn validate(script: &Script, hulls: &[Vec<bool>]) -> Result<(), String> {
+    // checks that the droid would accept the script, and that it gets across each of the hulls
+    script.check()?;
+    for hull in hulls {
+        if let Some(fall) = simulate(script, hull) {
+            return Err(format!("the script doesn't make it across:\n{}", fall));
+        }
+    }
+    Ok(())
+}
+
+fn crossable(hull: &[bool]) -> bool {
+    // whether there's any way across at all
+    let mut reachable = vec![false; hull.len() + JUMP_DISTANCE];
+    reachable[0] = true;
+    for pos in 0..hull.len() {
+        if reachable[pos] && hull[pos] {
+            reachable[pos + 1] = true;
+            reachable[pos + JUMP_DISTANCE] = true;
+        }
+    }
+    reachable[hull.len()..].iter().any(|&r| r)
+}
+
+pub fn minimize(script: &Script, hull: &[bool]) -> Option<Vec<bool>> {
+    // shrinks a hull that the script falls through to a smaller one that it still falls through (but that can
+    // still be crossed), by leaving out tiles and filling in holes for as long as that keeps working.
+    // returns None if the script makes it across the hull to begin with.
+    let falls = |hull: &[bool]| simulate(script, hull).is_some() && crossable(hull);
+    if !falls(hull) {
+        return None;
+    }
+    let mut hull = hull.to_vec();
+    'shrink: loop {
+        for pos in (1..hull.len()).rev() {
+            let mut shorter = hull.clone();
+            shorter.remove(pos);
+            if falls(&shorter) {
+                hull = shorter;
+                continue 'shrink;
+            }
+        }
+        for pos in 1..hull.len() {
+            let mut filled = hull.clone();
+            if !filled[pos] {
+                filled[pos] = true;
+                if falls(&filled) {
+                    hull = filled;
+                    continue 'shrink;
+                }
+            }
+        }
+        return Some(hull);
+    }
+}
+
+pub fn main(args: &ArgMatches) {
+    if let Some(text) = args.value_of("hull") {
+        let hull = parse_hull(text).unwrap_or_else(|err| panic!("{}", err));
+        for script in &[part1_script(), part2_script()] {
+            match simulate(script, &hull) {
+                None       => println!("{}: makes it across", script.mode.command()),
+                Some(fall) => print!("{}: doesn't make it across:\n{}", script.mode.command(), fall),
+            }
+        }
+        return;
+    }
+    let line: String = util::file_read_lines("input/day21.txt").into_iter().next().unwrap();
+    let program: Vec<i64> = line.split(",").map(|s| s.parse().unwrap()).collect();
+
+    for hand_written in &[part1_script(), part2_script()] {
+        // try the hand-written scripts first; if they don't work for this input, search for one that does
+        let mode = hand_written.mode;
+        let run = |script: &Script| run_script(&program, script).unwrap_or_else(|err| panic!("{}", err));
+        let (script, outcome) = match run(hand_written) {
+            Outcome::Survived(damage) if !args.is_present("synthesize") => (hand_written.clone(), Outcome::Survived(damage)),
+            outcome => {
+                if let (Outcome::Fell(fall), true) = (&outcome, args.is_present("analyze")) {
+                    print!("the hand-written script doesn't make it across:\n{}", fall);
+                    if let Some(hull) = minimize(hand_written, &fall.hull) {
+                        print!("which boils down to:\n{}", simulate(hand_written, &hull).unwrap());
+                    }
+                }
+                let script = synthesize(&program, mode).unwrap_or_else(|err| panic!("{}", err));
+                let outcome = run(&script);
+                (script, outcome)
+            },
+        };
+        match outcome {
+            Outcome::Survived(damage) => println!("{}", damage),
+            Outcome::Fell(fall) => panic!("the droid didn't make it across:\n{}", fall),
+        }
+        if args.is_present("analyze") {
+            print!("{}", script);
+        }
+    }
+}
+
+fn part1_script() -> Script {
     // note: I found this a bit unclear in the problem statement, so to clarify from what I've observed:
     // This program is run by the droid whenever it is located on a floor tile, to decide its next action.
     // If the register J is TRUE at the end of the program, a jump is performed no matter what (even
@@ -27,25 +506,10 @@ fn part1(program: &Vec<i64>) -> usize {
     //
     //    D ^ (-A v -B v -C)
     // == D ^ -(A ^ B ^ C)
-    cpu.send_input_string(concat!(
-        "OR A J\n",     // J = A
-        "AND B J\n",    // J = A ^ B
-        "AND C J\n",    // J = A ^ B ^ C
-        "NOT J J\n",    // J = -(A ^ B ^ C)
-        "AND D J\n",    // J = -(A ^ B ^ C) ^ D
-        "WALK\n"
-    ));
-    cpu.run();
-    assert!(cpu.is_halted());
-    return cpu.consume_output_last().unwrap() as usize;
+    spring!{ J = !(A & B & C) & D }.unwrap()
 }
 
-fn part2(program: &Vec<i64>) -> usize {
-    let mut cpu = CPU::new(program);
-    cpu.run();
-    cpu.consume_output_all(); // skip the input prompt
-    assert!(cpu.get_state() == CpuState::WaitIO);
-
+fn part2_script() -> Script {
     // same principle as before, but with some additional constraints to ensure that we can "get away" after
     // having made the jump A -> D:
     //
@@ -63,22 +527,347 @@ fn part2(program: &Vec<i64>) -> usize {
     // i.e.: jump if:
     //    D ^ (-A v -B v -C) ^ (H v (E ^ I) v (E ^ F))
     // == D ^ -(A ^ B ^ C) ^ (H v (E ^ (I v F)))
+    spring!{ J = !(A & B & C) & D & (H | E & (I | F)) }.unwrap()
+}
 
-    cpu.send_input_string(concat!(
-        "OR A J\n",     // J = A
-        "AND B J\n",    // J = A ^ B
-        "AND C J\n",    // J = A ^ B ^ C
-        "NOT J J\n",    // J = -(A ^ B ^ C)
-        "AND D J\n",    // J = -(A ^ B ^ C) ^ D
-        "OR I T\n",     // T = I
-        "OR F T\n",     // T = I v F
-        "AND E T\n",    // T = (I v F) ^ E
-        "OR H T\n",     // T = ((I v F) ^ E) v H
-        "AND T J\n",    // J = -(A ^ B ^ C) ^ D ^ ((I v F) ^ E) v H
-        "RUN\n"
-    ));
-    cpu.run();
-    assert!(cpu.is_halted());
-    return cpu.consume_output_last().unwrap() as usize;
+pub const MAX_INSTRUCTIONS: usize = 15;
+
+pub fn synthesize(program: &Vec<i64>, mode: Mode) -> Result<Script, String> {
+    // searches for a script that gets the droid across, by trying the cheapest script that gets it across all
+    // of the hulls it's fallen through so far, and adding the hull it falls through next if it doesn't work.
+    let mut hulls: Vec<Vec<bool>> = Vec::new();
+    loop {
+        let script = cheapest_script(&hulls, mode)?;
+        validate(&script, &hulls)?;
+        match run_script(program, &script)? {
+            Outcome::Survived(_) => return Ok(script),
+            Outcome::Fell(Fall { hull, .. }) => {
+                if hulls.contains(&hull) {
+                    return Err(format!("the droid fell through the same hull again: {:?}", hull));
+                }
+                hulls.push(hull);
+            },
+        }
+    }
 }
 
+fn sensor_window(hull: &[bool], pos: usize) -> u16 {
+    // the sensor readings from this position as bits, A in the lowest one; the hull continues as ground
+    // past the end of what's known of it
+    (0..9).filter(|i| *hull.get(pos + 1 + i).unwrap_or(&true)).fold(0, |acc, i| acc | 1 << i)
+}
+
+fn survives<F>(hull: &[bool], jump: F) -> bool
+    where F: Fn(usize) -> bool // whether to jump from a given position
+{
+    let mut pos = 0;
+    while pos < hull.len() {
+        pos += if jump(pos) { JUMP_DISTANCE } else { 1 };
+        if pos < hull.len() && !hull[pos] {
+            return false;
+        }
+    }
+    true
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Clause {
+    // an OR of sensor readings, as bitmasks of sensors (A in the lowest bit)
+    ground: u16, // sensors for which seeing ground satisfies the clause
+    hole: u16,   // sensors for which seeing a hole does
+}
+impl Clause {
+    pub fn holds(&self, window: u16) -> bool {
+        window & self.ground != 0 || !window & self.hole != 0
+    }
+    pub fn compile(&self, first: bool) -> Vec<Instr> {
+        // as the first clause, it's computed straight into J (which starts out false); after that, it's computed in
+        // T and ANDed into J. springscript can't OR in a negated reading, so the clause is computed as
+        //   -(x1 ^ x2 ^ ...) v y1 v y2 v ...
+        // for the sensors x that should see a hole and y that should see ground.
+        let sensors = |mask: u16| (0..9u8).filter(move |s| mask & (1 << s) != 0).map(Reg::Sensor);
+        let instr = |op, src, dst| Instr { op, src, dst };
+        let (holes, grounds): (Vec<Reg>, Vec<Reg>) = (sensors(self.hole).collect(), sensors(self.ground).collect());
+        let mut result = Vec::new();
+        let reg = if first { Reg::J } else { Reg::T };
+        match holes.len() {
+            0 if first => {},
+            0 if grounds.len() == 1 => return vec![instr(Op::And, grounds[0], Reg::J)],
+            0 => result.extend(vec![instr(Op::Not, grounds[0], reg), instr(Op::Not, reg, reg)]),
+            1 => result.push(instr(Op::Not, holes[0], reg)),
+            _ => {
+                if first {
+                    result.push(instr(Op::Or, holes[0], reg));
+                } else {
+                    result.extend(vec![instr(Op::Not, holes[0], reg), instr(Op::Not, reg, reg)]);
+                }
+                result.extend(holes[1..].iter().map(|&s| instr(Op::And, s, reg)));
+                result.push(instr(Op::Not, reg, reg));
+            },
+        }
+        let skip = if holes.is_empty() && !first { 1 } else { 0 }; // already in there
+        result.extend(grounds[skip..].iter().map(|&s| instr(Op::Or, s, reg)));
+        if !first {
+            result.push(instr(Op::And, Reg::T, Reg::J));
+        }
+        result
+    }
+}
+
+fn cnf_script(clauses: &[Clause], mode: Mode) -> Script {
+    // the script that jumps iff all of the clauses hold, starting with whichever clause saves the most
+    // instructions by going first
+    if clauses.is_empty() {
+        return Script { mode, instrs: vec![Instr { op: Op::Not, src: Reg::J, dst: Reg::J }] };
+    }
+    let first = (0..clauses.len()).max_by_key(|&i| clauses[i].compile(false).len() - clauses[i].compile(true).len()).unwrap();
+    let mut instrs = clauses[first].compile(true);
+    for (i, clause) in clauses.iter().enumerate() {
+        if i != first {
+            instrs.extend(clause.compile(false));
+        }
+    }
+    Script { mode, instrs }
+}
+
+struct Hull {
+    tiles: Vec<bool>,
+    windows: Vec<u16>,         // the sensor readings at each position
+    crossings: Vec<Vec<u16>>,  // for every way across, the readings at the positions it jumps from
+}
+impl Hull {
+    pub fn new(tiles: &[bool]) -> Self {
+        let windows: Vec<u16> = (0..tiles.len()).map(|pos| sensor_window(tiles, pos)).collect();
+        let mut crossings = Vec::new();
+        let mut stack = vec![(0, Vec::new())];
+        while let Some((pos, jumps)) = stack.pop() {
+            if pos >= tiles.len() {
+                crossings.push(jumps);
+                continue;
+            }
+            if !tiles[pos] {
+                continue;
+            }
+            let mut jumped = jumps.clone();
+            jumped.push(windows[pos]);
+            stack.push((pos + JUMP_DISTANCE, jumped));
+            stack.push((pos + 1, jumps));
+        }
+        for crossing in &mut crossings {
+            crossing.sort_unstable();
+            crossing.dedup();
+        }
+        crossings.sort();
+        crossings.dedup();
+        Self { tiles: tiles.to_vec(), windows, crossings }
+    }
+    pub fn jumps_before_falling(&self, clauses: &[Clause]) -> Option<Vec<u16>> {
+        // follows the script that jumps iff all the clauses hold; if it falls, returns the readings at each
+        // position it jumped from along the way
+        let jump = |pos: usize| clauses.iter().all(|c| c.holds(self.windows[pos]));
+        if survives(&self.tiles, jump) {
+            return None;
+        }
+        let mut jumps = Vec::new();
+        let mut pos = 0;
+        while pos < self.tiles.len() && self.tiles[pos] {
+            if jump(pos) {
+                jumps.push(self.windows[pos]);
+                pos += JUMP_DISTANCE;
+            } else {
+                pos += 1;
+            }
+        }
+        Some(jumps)
+    }
+}
+
+fn cheapest_script(hulls: &[Vec<bool>], mode: Mode) -> Result<Script, String> {
+    // searches the scripts that jump iff a number of clauses all hold (i.e. a formula in conjunctive normal form),
+    // cheapest first, for one that gets the droid across all of the given hulls. without any clauses, the droid
+    // always jumps; adding clauses can only take jumps away. so when the droid falls through a hull, the only way
+    // to fix it is to add a clause that doesn't hold at one of the positions it jumped from before falling.
+    let hulls: Vec<Hull> = hulls.iter().map(|tiles| Hull::new(tiles)).collect();
+    let n = mode.num_sensors();
+    let mut clauses = Vec::new();
+    for ground in 0u16..1 << n {
+        for hole in 0u16..1 << n {
+            if ground & hole == 0 && (1..=4).contains(&(ground | hole).count_ones()) {
+                clauses.push(Clause { ground, hole });
+            }
+        }
+    }
+    // if a clause rules out every way across some hull, it's of no use
+    let possible = |chosen: &[Clause]| hulls.iter().all(|hull| {
+        hull.crossings.iter().any(|jumps| jumps.iter().all(|&w| chosen.iter().all(|c| c.holds(w))))
+    });
+    clauses.retain(|c| possible(&[*c]));
+    let cost = |chosen: &[Clause]| cnf_script(chosen, mode).instrs.len();
+
+    fn search<C, P>(chosen: &mut Vec<Clause>, budget: usize, clauses: &[Clause], hulls: &[Hull], cost: &C, possible: &P,
+                    seen: &mut HashSet<Vec<Clause>>) -> bool
+        where C: Fn(&[Clause]) -> usize,
+              P: Fn(&[Clause]) -> bool
+    {
+        let jumps = match hulls.iter().find_map(|hull| hull.jumps_before_falling(chosen)) {
+            None => return true,
+            Some(jumps) => jumps,
+        };
+        for &w in &jumps {
+            for clause in clauses.iter().filter(|c| !c.holds(w)) {
+                chosen.push(*clause);
+                let mut key = chosen.clone();
+                key.sort_by_key(|c| (c.ground, c.hole));
+                if cost(chosen) <= budget && seen.insert(key) && possible(chosen)
+                    && search(chosen, budget, clauses, hulls, cost, possible, seen) {
+                    return true;
+                }
+                chosen.pop();
+            }
+        }
+        false
+    }
+
+    for budget in 1..=MAX_INSTRUCTIONS {
+        let mut chosen = Vec::new();
+        if search(&mut chosen, budget, &clauses, &hulls, &cost, &possible, &mut HashSet::new()) {
+            return Ok(cnf_script(&chosen, mode));
+        }
+    }
+    Err(format!("no script of at most {} instructions gets across all of these hulls: {:?}", MAX_INSTRUCTIONS,
+                hulls.iter().map(|hull| hull.tiles.iter().map(|&t| if t { '#' } else { '.' }).collect::<String>()).collect::<Vec<_>>()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hull(s: &str) -> Vec<bool> {
+        parse_hull(s).unwrap()
+    }
+
+    #[test]
+    fn scripts() {
+        let script = part1_script();
+        assert_eq!(script.to_string(), "OR A J\nAND B J\nAND C J\nNOT J J\nAND D J\nWALK\n");
+        // jump if D is ground and any of A, B or C is a hole
+        assert!(script.jumps(0b1010));
+        assert!(!script.jumps(0b0010));
+        assert!(!script.jumps(0b1111));
+        assert_eq!(part2_script().to_string(), "OR A J\nAND B J\nAND C J\nNOT J J\nAND D J\n\
+                                                OR I T\nOR F T\nAND E T\nOR H T\nAND T J\nRUN\n");
+    }
+
+    #[test]
+    fn expressions() {
+        use super::sensors::*;
+        // the generated scripts compute the expression for every possible reading
+        let exprs = vec![
+            A,
+            !A,
+            !(A | B),
+            !A & !B & !C,
+            A | !B & C,
+            !(A & B & C) & D & (H | E & (I | F)),
+            (A | B) & (C | D) | !(E & F) & G,
+            !(!(A | !B) & C),
+        ];
+        for expr in &exprs {
+            let script = expr.script().unwrap();
+            for w in 0..1 << 9 {
+                assert_eq!(script.jumps(w), expr.holds(w), "{} at {:09b}:\n{}", expr, w, script);
+            }
+        }
+        assert_eq!(spring!{ J = A }.unwrap().to_string(), "OR A J\nWALK\n");
+        assert_eq!(spring!{ J = !A & !B }.unwrap().to_string(), "NOT A J\nNOT B T\nAND T J\nWALK\n");
+        assert_eq!(spring!{ J = E | A }.unwrap().mode, Mode::Run);
+        assert_eq!((!(A & B) & D).to_string(), "!(A & B) & D");
+
+        // each side needs both registers, so there's nowhere to keep the other side in the meantime
+        let expr = (A & B | C & D) & (E & F | G & H);
+        assert_eq!(expr.script(), Err("((A & B) | (C & D)) & ((E & F) | (G & H)) needs more registers than just T and J".to_string()));
+    }
+
+    #[test]
+    fn falls() {
+        let output = "Input instructions:\n\nWalking...\n\n\nDidn't make it across:\n\n\
+                      .................\n.................\n...@.............\n#####..#.########\n\n\
+                      .................\n.................\n....@............\n#####..#.########\n\n\
+                      .................\n.....@...........\n.................\n#####..#.########\n\n\
+                      ......@..........\n.................\n.................\n#####..#.########\n\n\
+                      .................\n.......@.........\n.................\n#####..#.########\n\n\
+                      .................\n.................\n........@........\n#####..#.########\n\n\
+                      .................\n.................\n.................\n#####..#@########\n\n";
+        let fall = Fall::parse(output, Mode::Walk).unwrap();
+        assert_eq!(fall.hull, hull("#####..#.########"));
+        assert_eq!(fall.droid, vec![(3, 1), (4, 1), (5, 2), (6, 3), (7, 2), (8, 1), (8, 0)]);
+        assert_eq!(fall.last_decision(), Some((4, true)));
+        assert_eq!(fall.to_string(), "    @\n\
+                                      #####..#.########\n     ABCD\n\
+                                      jumped at x=4 seeing A=. B=. C=# D=., and fell into the hole at x=8\n");
+        assert!(Fall::parse("Walking...\n", Mode::Walk).is_err());
+        assert_eq!(sensor_window(&hull("#####..#.########"), 4), 0b111110100);
+    }
+
+    #[test]
+    fn simulation() {
+        // the simulated droid moves like the real one
+        let script = Script { mode: Mode::Walk, instrs: vec![Instr { op: Op::Not, src: Reg::Sensor(0), dst: Reg::J }] };
+        let fall = simulate(&script, &hull("#####..#.########")).unwrap();
+        assert!(fall.droid.ends_with(&[(3, 1), (4, 1), (5, 2), (6, 3), (7, 2), (8, 1), (8, 0)]));
+        assert_eq!(fall.last_decision(), Some((4, true)));
+        let never = Script { mode: Mode::Walk, instrs: vec![] };
+        assert_eq!(simulate(&never, &hull("##.#")).unwrap().droid, vec![(0, 1), (1, 1), (2, 0)]);
+        assert_eq!(simulate(&part1_script(), &hull("#####.###########")), None);
+
+        // walking, the droid can't see as far ahead
+        let far = Script { mode: Mode::Walk, instrs: vec![Instr { op: Op::Not, src: Reg::Sensor(4), dst: Reg::J }] };
+        assert_eq!(simulate(&far, &hull("#####.##")), None);
+        assert!(simulate(&Script { mode: Mode::Run, ..far.clone() }, &hull("#####.##")).is_some());
+
+        let hulls = vec![hull("#####.###########"), hull("#####..#.########"), hull("#####...#########")];
+        assert_eq!(validate(&part1_script(), &hulls), Ok(()));
+        assert!(validate(&part1_script(), &[hull("#####.#.##.#####")]).is_err());
+        assert_eq!(validate(&far, &hulls), Err("sensor E isn't available in WALK mode: NOT E J".to_string()));
+        let long = Script { mode: Mode::Walk, instrs: vec![script.instrs[0]; MAX_INSTRUCTIONS + 1] };
+        assert_eq!(validate(&long, &hulls), Err("the droid only takes up to 15 instructions, got 16".to_string()));
+
+        // the smallest hull that the part 1 script falls through, that could be crossed otherwise
+        assert_eq!(minimize(&part1_script(), &hull("#####.#.##.#####")), Some(hull("###.#.##.")));
+        assert_eq!(minimize(&part1_script(), &hull("#####.###########")), None);
+        assert!(parse_hull(".####").is_err());
+        assert!(parse_hull("##@##").is_err());
+    }
+
+    #[test]
+    fn clauses() {
+        // every kind of clause compiles to a script that computes it, whether it goes first or not
+        for &(ground, hole) in &[(0b1000, 0), (0, 0b1), (0b1010, 0), (0b100, 0b1), (0, 0b111), (0b11000, 0b110)] {
+            let clause = Clause { ground, hole };
+            let other = Clause { ground: 0b100000000, hole: 0 };
+            for &clauses in &[&[clause][..], &[other, clause][..]] {
+                let script = cnf_script(clauses, Mode::Run);
+                for w in 0..1 << 9 {
+                    assert_eq!(script.jumps(w), clauses.iter().all(|c| c.holds(w)), "{:?} at {:09b}:\n{}", clauses, w, script);
+                }
+            }
+        }
+        assert_eq!(cnf_script(&[Clause { ground: 0b1000, hole: 0 }, Clause { ground: 0, hole: 0b111 }], Mode::Walk),
+                   part1_script());
+    }
+
+    #[test]
+    fn synthesis() {
+        // the cheapest scripts for some hulls, without involving the springdroid itself
+        assert_eq!(cheapest_script(&[], Mode::Walk).unwrap().to_string(), "NOT J J\nWALK\n");
+        assert_eq!(cheapest_script(&[hull("#####.###########")], Mode::Walk).unwrap().to_string(), "NOT J J\nWALK\n");
+        assert_eq!(cheapest_script(&[hull("####.############")], Mode::Walk).unwrap().to_string(), "NOT A J\nWALK\n");
+        let hulls = [hull("#####.###########"), hull("#####..#.########"), hull("#####...#########")];
+        let script = cheapest_script(&hulls, Mode::Walk).unwrap();
+        assert!(hulls.iter().all(|hull| survives(hull, |pos| script.jumps(sensor_window(hull, pos)))));
+        assert_eq!(script.to_string(), "OR A J\nAND B J\nNOT J J\nAND D J\nWALK\n");
+
+        // no script can get across a gap that's wider than a jump
+        assert!(cheapest_script(&[hull("#####....########")], Mode::Run).is_err());
+    }
+}
@@ -1,11 +1,11 @@
 // vim: set ai et ts=4 sts=4 sw=4:
 #![allow(non_snake_case)]
 use crate::util;
-use num;
+use clap::ArgMatches;
 use std::fmt::{self, Debug};
 use std::convert::TryFrom;
 
-#[derive(Clone,Debug)]
+#[derive(Clone,Debug,PartialEq)]
 enum Instr {
     DealNewStack,
     DealIncrement(i128),
@@ -39,224 +39,228 @@ impl fmt::Display for Instr {
     }
 }
 
-struct Deck {
-    // Represents a sequence of cards as a pair of (offset, stride) plus a modulus N.
-    // In this form, the value of the card at a given position X in the deck is given by:
-    //     deck[X] = (offset + X*stride) mod N
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Shuffle {
+    // A shuffle of a deck of m cards, as the affine map that takes the position of each card before the
+    // shuffle to its position after:
+    //     x -> (a*x + b) mod m
     //
-    // A factory order deck corresponds to (offset=0, stride=1).
-    // Each shuffling operation on the deck can be translated into a modification of the offset
-    // and stride values, in such a way that the resulting values match the shuffled order of the deck.
+    // Each of the shuffling techniques is one of these:
+    //     DealNewStack:       x -> -x - 1         (a=-1, b=-1)
+    //     DealIncrement(n):   x -> n*x            (a=n,  b=0)
+    //     Cut(n):             x -> x - n          (a=1,  b=-n)
     //
-    // Note that this requires calculating the modular multiplicative inverse of the increment, which
-    // can be done efficiently using the extended euclidean gcd algorithm:
+    // and since composing two affine maps gives another one, so is any sequence of them. Undoing a shuffle
+    // requires calculating the modular multiplicative inverse of a, which can be done efficiently using the
+    // extended euclidean gcd algorithm:
     //    https://en.wikipedia.org/wiki/Extended_Euclidean_algorithm
     //
-    N: i128,
-    offset: i128,
-    stride: i128,
-    stride_inv: i128, // modular multiplicative inverse of stride,
+    a: i128,
+    b: i128,
+    m: i128,
 }
-impl Deck {
-    fn new(N: u64) -> Self {
+impl Shuffle {
+    fn new(a: i128, b: i128, m: i128) -> Self {
         Self {
-            N: i128::try_from(N).unwrap(),
-            offset: 0,
-            stride: 1,
-            stride_inv: 1, // modular mult. inverse of 1 is always 1
+            a: a.rem_euclid(m),
+            b: b.rem_euclid(m),
+            m,
         }
     }
-    fn shuffle(&mut self, instrs: &Vec<Instr>) -> &mut Self {
-        // shuffles this deck according to the given sequence of operations.
-        //
-        // deck[X] = (offset + X*stride) mod N
-        //
-        // DealNewStack:       (offset= offset+((N-1)*stride),  stride= -stride)       (mod N)
-        // DealIncrement(n):   (offset= offset,                 stride= stride*(n^-1)) (mod N)
-        // Cut(n):             (offset= offset+n*stride,        stride= stride)        (mod N)
-        for inx in instrs.iter() {
-            match inx {
-                Instr::DealNewStack => {
-                    self.offset = self.N.checked_sub(1).unwrap()
-                                        .checked_mul(self.stride).unwrap()
-                                        .checked_add(self.offset).unwrap()
-                                        .checked_rem_euclid(self.N).unwrap();
-                    self.stride = self.stride.checked_neg().unwrap()
-                                             .checked_rem_euclid(self.N).unwrap();
-                    self.stride_inv = util::mod_mult_inverse(self.stride, self.N);
-                },
-                Instr::DealIncrement(ref n) => {
-                    let n_inv = util::mod_mult_inverse(*n, self.N);
-                    self.stride = self.stride.checked_mul(n_inv).unwrap()
-                                             .checked_rem_euclid(self.N).unwrap();
-                    self.stride_inv = util::mod_mult_inverse(self.stride, self.N);
-                },
-                Instr::Cut(ref n) => {
-                    self.offset = self.stride.checked_mul(*n).unwrap()
-                                             .checked_add(self.offset).unwrap()
-                                             .checked_rem_euclid(self.N).unwrap();
-                }
-            };
+    fn identity(m: i128) -> Self {
+        Self::new(1, 0, m)
+    }
+    fn from_instr(instr: &Instr, m: i128) -> Self {
+        match instr {
+            Instr::DealNewStack         => Self::new(-1, -1, m),
+            Instr::DealIncrement(ref n) => Self::new(*n, 0, m),
+            Instr::Cut(ref n)           => Self::new(1, n.checked_neg().unwrap(), m),
         }
-        self
     }
-    fn shuffle_n(&mut self, instrs: &Vec<Instr>, k: u64) -> &mut Self
-    {
-        // shuffles this deck according to the given sequence of operations, n times.
-        //
-        // Repeatedly applying this deck's shuffle sequence generates:
-        //
-        //     x1: deck[x] = input[o + x*s]                        (mod N)    -> i.e. Deck(o,s)
-        //     x2: deck[x] = input[o + (o + x*s)*s]                (mod N)
-        //                 = input[(o + s*o) + x*(s*s)]            (mod N)    -> i.e. Deck(o + s*o, s*s)
-        //     x3: deck[x] = input[o + (o + (o + x*s)*s)*s]        (mod N)
-        //                 = input[(o + s*o + s*s*o) + x*(s*s*s)]  (mod N)    -> i.e. Deck(o + s*o + s*s*o, s*s*s)
-        //     ...
-        //     k times: -> Deck(o*(1 + s + s^2 + ... + s^(k-1)), s^k)
-        //               = Deck(o*(1-s^k)/(1-s), s^k)                  (for s != 1, because Geometric Series)
-        //               = Deck(o*(s^k-1)/(s-1), s^k)                  (for s != 1)
-        //
-
-        use num::bigint::{BigInt};
-        use num::cast::ToPrimitive;
-
-        assert_ne!(k,0);
-        macro_rules! big {
-            ($num:ident) => { BigInt::from($num) }
+    fn from_instrs(instrs: &[Instr], m: i128) -> Self {
+        instrs.iter().fold(Self::identity(m), |shuffle, instr| shuffle.then(&Self::from_instr(instr, m)))
+    }
+    fn then(&self, next: &Shuffle) -> Self {
+        // this shuffle followed by the next one:
+        //     x -> a2*(a1*x + b1) + b2
+        //        = (a2*a1)*x + (a2*b1 + b2)
+        assert_eq!(self.m, next.m);
+        Self::new(util::mod_mul(next.a, self.a, self.m),
+                  util::mod_add(util::mod_mul(next.a, self.b, self.m), next.b, self.m),
+                  self.m)
+    }
+    fn invert(&self) -> Self {
+        // the shuffle that puts the cards back where they were:
+        //     y = a*x + b
+        // <=> x = a^(-1)*y - a^(-1)*b
+        let a_inv = util::mod_mult_inverse(self.a, self.m);
+        Self::new(a_inv, -util::mod_mul(a_inv, self.b, self.m), self.m)
+    }
+    fn pow(&self, k: u64) -> Self {
+        // this shuffle repeated k times, by repeated squaring
+        let mut result = Self::identity(self.m);
+        let mut square = *self;
+        let mut k = k;
+        while k > 0 {
+            if k & 1 == 1 {
+                result = result.then(&square);
+            }
+            square = square.then(&square);
+            k >>= 1;
+        }
+        result
+    }
+    fn instrs(&self) -> Vec<Instr> {
+        // an equivalent sequence of as few shuffling techniques as possible. any shuffle can be done in two: first
+        // deal with increment a to get x -> a*x, then cut -b to add b. (a is always coprime with m, since every
+        // technique can be undone.) cuts are written with whichever of the two equivalent counts is closest to 0.
+        let cut = |b: i128| Instr::Cut(if b > self.m/2 { self.m - b } else { -b });
+        match (self.a, self.b) {
+            (1, 0)                                         => vec![],
+            (1, b)                                         => vec![cut(b)],
+            (a, 0)                                         => vec![Instr::DealIncrement(a)],
+            (a, b) if a == self.m - 1 && b == self.m - 1   => vec![Instr::DealNewStack],
+            (a, b)                                         => vec![Instr::DealIncrement(a), cut(b)],
         }
+    }
+    fn apply(&self, x: i128) -> i128 {
+        util::mod_add(util::mod_mul(self.a, x, self.m), self.b, self.m)
+    }
+}
+impl fmt::Display for Shuffle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "x -> {}*x + {} (mod {})", self.a, self.b, self.m)
+    }
+}
 
-        // shuffle the deck once to determine the values of the 'o' and 's' parameters,
-        // then scale those up by k as described.
-        self.shuffle(instrs);
-        let (N,o,s) = (self.N, self.offset, self.stride);
+fn explain(instrs: &[Instr], m: i128) -> String {
+    // lists the shuffle one technique at a time, alongside what it adds up to so far
+    let width = instrs.iter().map(|instr| instr.to_string().len()).max().unwrap_or(0);
+    let mut result = format!("{:width$}  {}\n", "", Shuffle::identity(m), width=width);
+    let mut shuffle = Shuffle::identity(m);
+    for instr in instrs {
+        shuffle = shuffle.then(&Shuffle::from_instr(instr, m));
+        result += &format!("{:width$}  {}\n", instr.to_string(), shuffle, width=width);
+    }
+    result
+}
 
-        if s != 1 {
-            let s_pow_k: i128 = big![s].modpow(&big![k], &big![N]).to_i128().unwrap();
-            let o2: i128 = o.checked_mul(s_pow_k.checked_sub(1).unwrap()).unwrap() // o*(s^k-1) ...
-                            .checked_rem_euclid(N).unwrap() // keep the numbers out of overflow range
-                            .checked_mul(util::mod_mult_inverse(s.checked_sub(1).unwrap(), N)).unwrap() // .../(s-1)
-                            .checked_rem_euclid(N).unwrap();
+const MAX_CARDS: i128 = 1 << 20;
+const MAX_CARDS_SHOWN: i128 = 100;
 
-            self.offset = o2;
-            self.stride = s_pow_k;
-        } else {
-            self.offset = o*(k as i128); // o*(1 + 1 + 1^2 + ... + 1^(k-1)) = o*k
-            self.stride = 1;             // 1^k = 1 for any k
+struct Deck {
+    // A deck of N cards in factory order, along with the shuffle it's been through since.
+    shuffle: Shuffle,
+}
+impl Deck {
+    fn new(N: u64) -> Self {
+        Self {
+            shuffle: Shuffle::identity(i128::from(N)),
         }
+    }
+    fn shuffle(&mut self, instrs: &[Instr]) -> &mut Self {
+        // shuffles this deck according to the given sequence of operations.
+        self.shuffle_n(instrs, 1)
+    }
+    fn shuffle_n(&mut self, instrs: &[Instr], k: u64) -> &mut Self {
+        // shuffles this deck according to the given sequence of operations, k times.
+        let once = Shuffle::from_instrs(instrs, self.shuffle.m);
+        self.shuffle = self.shuffle.then(&once.pow(k));
         self
     }
-    fn index_original_to_shuffled(&self, i: u64) -> u64 {
-        // given an index into the factory-ordered deck, returns the corresponding index of that value in this shuffled deck.
-        //
-        // the contents of this (shuffled) deck are given by:
-        //     shuffled[X] = factory_order[offset + X*stride]          (mod N)
-        // <=> shuffled[X - offset] = factory_order[X*stride]          (mod N)
-        // <=> shuffled[(X - offset)*stride^-1] = factory_order[X] = X (mod N)
-        //
-        // so the answer is given by:
-        //     (X-o) * s^(-1) mod N
-        //
-        let ii = i128::try_from(i).unwrap();
-        u64::try_from(
-            ii.checked_sub(self.offset).unwrap()
-              .checked_mul(self.stride_inv).unwrap()
-              .checked_rem_euclid(self.N).unwrap() // rem_euclid is guaranteed to output a non-negative int
-        ).unwrap()
+    fn position_of(&self, card: u64) -> u64 {
+        // where the given card ends up in this shuffled deck (i.e. where the card that was at that position in
+        // the factory-ordered deck ends up)
+        u64::try_from(self.shuffle.apply(i128::from(card))).unwrap()
+    }
+    fn card_at(&self, pos: u64) -> u64 {
+        // which card ends up at the given position in this shuffled deck (i.e. the position in the factory-ordered
+        // deck that it came from)
+        u64::try_from(self.shuffle.invert().apply(i128::from(pos))).unwrap()
     }
-    fn index_shuffled_to_original(&self, i: u64) -> u64 {
-        // given an index into the shuffled deck, returns the corresponding index of that value in the factory deck.
-        //
-        // the contents of this (shuffled) deck are given by:
-        //     shuffled[X] = factory_order[offset + X*stride]          (mod N)
-        //
-        // so the answer is given by:
-        //     (o + X*s) mod N
-        //
-        let ii = i128::try_from(i).unwrap();
-        u64::try_from(
-            ii.checked_mul(self.stride).unwrap()
-              .checked_add(self.offset).unwrap()
-              .checked_rem_euclid(self.N).unwrap() // rem_euclid is guaranteed to output a non-negative int
-        ).unwrap()
+    fn arrange<T: Clone>(&self, cards: &[T]) -> Vec<T> {
+        // deals out the given cards in the order this deck's shuffle leaves them in, i.e. the card at position
+        // X before the shuffle goes to position_of(X) after.
+        assert_eq!(cards.len() as i128, self.shuffle.m);
+        let inverse = self.shuffle.invert();
+        (0..self.shuffle.m).map(|pos| cards[inverse.apply(pos) as usize].clone()).collect()
+    }
+    #[cfg(test)]
+    fn restore<T: Clone>(&self, cards: &[T]) -> Vec<T> {
+        // the opposite of arrange: puts the cards of a shuffled deck back in the order they had before the shuffle
+        assert_eq!(cards.len() as i128, self.shuffle.m);
+        (0..self.shuffle.m).map(|pos| cards[self.shuffle.apply(pos) as usize].clone()).collect()
+    }
+    fn cards(&self) -> Vec<u64> {
+        // the cards of this deck in their shuffled order; only meant for small decks.
+        assert!(self.shuffle.m <= MAX_CARDS, "too many cards to lay out: {}", self.shuffle.m);
+        self.arrange(&(0..self.shuffle.m as u64).collect::<Vec<u64>>())
     }
 }
 impl fmt::Display for Deck {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Deck(N={}, offset={:20} incr={:20}", self.N,
-                                                        format!("{},", self.offset),
-                                                        format!("{})", self.stride))
+        // small decks are shown card by card, like the examples in the puzzle
+        if self.shuffle.m <= MAX_CARDS_SHOWN {
+            let cards: Vec<String> = self.cards().iter().map(|card| card.to_string()).collect();
+            write!(f, "{}", cards.join(" "))
+        } else {
+            write!(f, "Deck(N={}, {})", self.shuffle.m, self.shuffle)
+        }
     }
 }
 
-pub fn main() {
-    let lines: Vec<String> = util::file_read_lines("input/day22.txt");
-    let instrs: Vec<Instr> = lines.iter().map(|line| Instr::from(&line[..])).collect();
+pub fn main(args: &ArgMatches) {
+    let input = util::Input::for_day(22);
+    let instrs: Vec<Instr> = input.lines().iter().map(|line| Instr::from(&line[..])).collect();
     println!("{}", part1(&instrs));
     println!("{}", part2(&instrs));
+
+    if args.is_present("analyze") {
+        // the input boils down to just two techniques, for either size of deck
+        for &m in &[10_007, 119_315_717_514_047] {
+            print!("{}", explain(&instrs, m));
+            let canonical = Shuffle::from_instrs(&instrs, m).instrs();
+            println!("which for a deck of {} cards is the same as:\n{}\n", m,
+                     canonical.iter().map(|instr| instr.to_string()).collect::<Vec<_>>().join("\n"));
+        }
+    }
 }
 
-fn part1(instrs: &Vec<Instr>) -> u64 {
+fn part1(instrs: &[Instr]) -> u64 {
     let mut deck = Deck::new(10_007);
     deck.shuffle(instrs);
-    deck.index_original_to_shuffled(2019)
+    deck.position_of(2019)
 }
-fn part2(instrs: &Vec<Instr>) -> u64 {
+fn part2(instrs: &[Instr]) -> u64 {
     let mut deck = Deck::new(119_315_717_514_047);
     deck.shuffle_n(instrs, 101_741_582_076_661);
-    deck.index_shuffled_to_original(2020)
+    deck.card_at(2020)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    fn remap<T>(input: &Vec<T>, mapped_indices: &Vec<usize>) -> Vec<T>
-        where T: Copy + Default + PartialEq + Debug
-    {
-        // given an input vec of values and a mapping to new indices, returns a new vector
-        // with the elements in their new positions.
-        //
-        // example:
-        //          input: 0 1 2 3 4 5 6 7 8 9  (implicit indices)
-        //                          |
-        //                          v
-        // mapped_indices: a b c ...
-        //
-        // means:
-        //    the value that's at input[0] should go into output[a]
-        //    the value that's at input[1] should go into output[b]
-        //    the value that's at input[2] should go into output[c]
-        //    etc.
-
-        let mut result = Vec::<T>::new();
-        result.resize(input.len(), T::default());
-
-        for (pos, value) in input.iter().enumerate() {
-            let new_pos = mapped_indices[pos];
-            result[new_pos] = *value;
-        }
-        result
-    }
-
     fn expected_result<T>(input: &Vec<T>,
-                               instrs: &Vec<Instr>,
+                               instrs: &[Instr],
                                expected_output: &Vec<T>)
-        where T: Copy + Default + PartialEq + Debug
+        where T: Clone + PartialEq + Debug
     {
         let N = input.len() as u64;
         let mut deck = Deck::new(N);
         deck.shuffle(instrs);
 
-        // compute the new index of each card after the shuffle, move the values around accordingly,
-        // and check that it matches the expected result
-        let forwarded_indices: Vec<usize> = (0..N).map(|idx| deck.index_original_to_shuffled(idx) as usize).collect();
-        let forwarded_values = remap(input, &forwarded_indices);
-        assert_eq!(forwarded_values, *expected_output);
+        // deal the cards in their shuffled order, check that it matches the expected result, and that each card
+        // can be found where it ended up
+        let shuffled = deck.arrange(input);
+        assert_eq!(shuffled, *expected_output);
+        for (pos, card) in input.iter().enumerate() {
+            assert_eq!(shuffled[deck.position_of(pos as u64) as usize], *card);
+            assert_eq!(input[deck.card_at(pos as u64) as usize], shuffled[pos]);
+        }
 
-        // now apply the reverse operation and check that it matches the input again
-        let reversed_indices: Vec<usize> = (0..N).map(|idx| deck.index_shuffled_to_original(idx) as usize).collect();
-        let reversed_values = remap(&forwarded_values, &reversed_indices);
-        assert_eq!(reversed_values, *input);
+        // now put them back and check that it matches the input again
+        assert_eq!(deck.restore(&shuffled), *input);
     }
 
     #[test]
@@ -266,11 +270,11 @@ mod tests {
         //                           0   1   2   3   4   5   6   7   8   9
         let cards: Vec<char> = vec!['A','B','C','D','E','F','G','H','I','J'];
 
-        expected_result(&cards,  &vec![Instr::DealNewStack],     &vec!['J','I','H','G','F','E','D','C','B','A']);
-        expected_result(&cards,  &vec![Instr::Cut(3)],           &vec!['D','E','F','G','H','I','J','A','B','C']);
-        expected_result(&cards,  &vec![Instr::Cut(0)],           &cards);
-        expected_result(&cards,  &vec![Instr::Cut(-4)],          &vec!['G','H','I','J','A','B','C','D','E','F']);
-        expected_result(&cards,  &vec![Instr::DealIncrement(3)], &vec!['A','H','E','B','I','F','C','J','G','D']);
+        expected_result(&cards,  &[Instr::DealNewStack],     &vec!['J','I','H','G','F','E','D','C','B','A']);
+        expected_result(&cards,  &[Instr::Cut(3)],           &vec!['D','E','F','G','H','I','J','A','B','C']);
+        expected_result(&cards,  &[Instr::Cut(0)],           &cards);
+        expected_result(&cards,  &[Instr::Cut(-4)],          &vec!['G','H','I','J','A','B','C','D','E','F']);
+        expected_result(&cards,  &[Instr::DealIncrement(3)], &vec!['A','H','E','B','I','F','C','J','G','D']);
     }
 
     #[test]
@@ -278,7 +282,7 @@ mod tests {
         let factory_order = (0..10).collect();
         expected_result(
             &factory_order,
-            &vec![
+            &[
                 Instr::DealIncrement(7),
                 Instr::DealNewStack,
                 Instr::DealNewStack,
@@ -287,7 +291,7 @@ mod tests {
         );
         expected_result(
             &factory_order,
-            &vec![
+            &[
                 Instr::Cut(6),
                 Instr::DealIncrement(7),
                 Instr::DealNewStack,
@@ -296,7 +300,7 @@ mod tests {
         );
         expected_result(
             &factory_order,
-            &vec![
+            &[
                 Instr::DealIncrement(7),
                 Instr::DealIncrement(9),
                 Instr::Cut(-2),
@@ -305,7 +309,7 @@ mod tests {
         );
         expected_result(
             &factory_order,
-            &vec![
+            &[
                 Instr::DealNewStack,
                 Instr::Cut(-2),
                 Instr::DealIncrement(7),
@@ -320,4 +324,92 @@ mod tests {
             &vec![9,2,5,8,1,4,7,0,3,6],
         );
     }
+
+    #[test]
+    fn canonical_forms() {
+        let instrs = vec![
+            Instr::DealNewStack,
+            Instr::Cut(-2),
+            Instr::DealIncrement(7),
+            Instr::Cut(8),
+            Instr::Cut(-4),
+            Instr::DealIncrement(7),
+            Instr::Cut(3),
+            Instr::DealIncrement(9),
+            Instr::DealIncrement(3),
+            Instr::Cut(-1),
+        ];
+        let shuffle = Shuffle::from_instrs(&instrs, 10);
+        let canonical = shuffle.instrs();
+        assert_eq!(canonical, vec![Instr::DealIncrement(7), Instr::Cut(3)]);
+        assert_eq!(Shuffle::from_instrs(&canonical, 10), shuffle);
+
+        // a single technique stays what it was, and shuffles that cancel out disappear altogether
+        for instr in &[Instr::DealNewStack, Instr::DealIncrement(3), Instr::Cut(3), Instr::Cut(-4)] {
+            assert_eq!(Shuffle::from_instr(instr, 10).instrs(), vec![instr.clone()]);
+        }
+        assert_eq!(Shuffle::from_instrs(&[Instr::DealNewStack, Instr::DealNewStack], 10).instrs(), vec![]);
+        assert_eq!(Shuffle::from_instrs(&[Instr::Cut(3), Instr::DealIncrement(3), Instr::Cut(-9)], 10).instrs(), vec![Instr::DealIncrement(3)]);
+
+        assert_eq!(explain(&instrs[..3], 10), concat!(
+            "                       x -> 1*x + 0 (mod 10)\n",
+            "deal into new stack    x -> 9*x + 9 (mod 10)\n",
+            "cut -2                 x -> 9*x + 1 (mod 10)\n",
+            "deal with increment 7  x -> 3*x + 7 (mod 10)\n",
+        ));
+    }
+
+    #[test]
+    fn small_decks() {
+        let mut deck = Deck::new(10);
+        assert_eq!(deck.to_string(), "0 1 2 3 4 5 6 7 8 9");
+        deck.shuffle(&[Instr::Cut(6), Instr::DealIncrement(7), Instr::DealNewStack]);
+        assert_eq!(deck.cards(), vec![3,0,7,4,1,8,5,2,9,6]);
+        assert_eq!(deck.to_string(), "3 0 7 4 1 8 5 2 9 6");
+        assert_eq!(deck.position_of(7), 2);
+        assert_eq!(deck.card_at(2), 7);
+
+        let mut big = Deck::new(10_007);
+        big.shuffle(&[Instr::DealIncrement(3)]);
+        assert_eq!(big.to_string(), "Deck(N=10007, x -> 3*x + 0 (mod 10007))");
+        assert_eq!(big.cards()[1], 3336); // 3*3336 = 10008
+    }
+
+    #[test]
+    fn shuffles() {
+        let instrs = vec![
+            Instr::DealNewStack,
+            Instr::Cut(-2),
+            Instr::DealIncrement(7),
+            Instr::Cut(8),
+            Instr::DealIncrement(3),
+        ];
+        let once = Shuffle::from_instrs(&instrs, 11);
+        let stepwise = instrs.iter().fold(Shuffle::identity(11), |acc, instr| acc.then(&Shuffle::from_instr(instr, 11)));
+        assert_eq!(once, stepwise);
+        assert_eq!(once.then(&once.invert()), Shuffle::identity(11));
+        assert_eq!(once.invert().then(&once), Shuffle::identity(11));
+        assert_eq!(once.pow(0), Shuffle::identity(11));
+
+        // repeated squaring agrees with shuffling over and over, for any number of times
+        let mut repeated = Shuffle::identity(11);
+        for k in 0..30 {
+            assert_eq!(once.pow(k), repeated);
+            assert_eq!(once.pow(k).invert(), repeated.invert());
+            assert_eq!(once.pow(k).a, util::mod_pow(once.a, k, 11));
+            repeated = repeated.then(&once);
+        }
+        for x in 0..11 {
+            assert_eq!(once.invert().apply(once.apply(x)), x);
+        }
+        assert_eq!(Shuffle::from_instr(&Instr::Cut(-4), 10).to_string(), "x -> 1*x + 4 (mod 10)");
+
+        // products of positions in a deck this size (2^127-1, a prime) don't fit in an i128
+        let m = i128::MAX;
+        let shuffle = Shuffle::from_instrs(&instrs, m);
+        let k = 101_741_582_076_661;
+        assert_eq!(shuffle.pow(k).then(&shuffle.pow(k).invert()), Shuffle::identity(m));
+        assert_eq!(shuffle.pow(k).then(&shuffle), shuffle.pow(k + 1));
+        assert_eq!(shuffle.pow(k).a, util::mod_pow(shuffle.a, k, m));
+    }
 }
@@ -1,161 +1,419 @@
 // vim: set ai et ts=4 sts=4 sw=4:
 use crate::util;
 use crate::intcode::{CPU, CpuState};
+use clap::ArgMatches;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
 
-pub fn main() {
+pub fn main(args: &ArgMatches) {
     let line: String = util::file_read_lines("input/day23.txt").into_iter().next().unwrap();
     let program: Vec<i64> = line.split(",").map(|s| s.parse().unwrap()).collect();
 
-    println!("{}", part1(&program));
-    println!("{}", part2(&program));
+    if args.is_present("parallel") {
+        println!("{}", part1_parallel(&program, NUM_NICS));
+        println!("{}", part2_parallel(&program, NUM_NICS));
+        return;
+    }
+    println!("{}", part1(&mut Network::new(&program, NUM_NICS)));
+
+    // part 2 carries on for longer than part 1 but starts out the same, so that's the one to keep an eye on
+    let mut network = Network::new(&program, NUM_NICS);
+    if args.is_present("analyze") || args.is_present("json") || args.is_present("csv") {
+        network.monitor = Some(Monitor::default());
+    }
+    println!("{}", part2(&mut network));
+
+    if let Some(monitor) = &network.monitor {
+        if args.is_present("analyze") {
+            print!("{}", monitor);
+        }
+        if args.is_present("json") {
+            println!("{}", monitor.to_json());
+        }
+        if let Some(filename) = args.value_of("csv") {
+            File::create(filename).and_then(|f| monitor.write_csv(BufWriter::new(f)))
+                                  .unwrap_or_else(|err| panic!("failed to write {}: {}", filename, err));
+        }
+    }
 }
 
+const NUM_NICS: usize = 50;
+const NAT_ADDRESS: usize = 255;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 struct Packet {
+    src_id: usize,
     dest_id: usize,
     x: i64,
     y: i64,
 }
-fn part1(program: &Vec<i64>) -> i64
-{
-    #[allow(non_snake_case)]
-    let N = 50usize;
-
-    let mut nics: Vec<CPU> = (0..N).map(|id| {
-        let mut nic = CPU::new(program);
-        nic.run();                                    // kick off the CPU and get it in the running state
-        assert!(nic.get_state() == CpuState::WaitIO); // should block try to read its input ID first
-        nic.send_input(id as i64);
-        nic.step();                                   // consume the ID value
-        nic
-    }).collect();
-
-    // let all CPUs process further instructions in lockstep, and forward any packets in their output queue
-    // to their destination NIC's input queue. whenever one stalls on needing input,
-    // feed -1 to its input queue and make it re-process the last instruction (which must be an input,
-    // because output is already non-blocking).
-    loop
-    {
-        for nic in &mut nics {
-            nic.step();
-            if nic.get_state() == CpuState::WaitIO {
+
+struct Network {
+    nics: Vec<CPU>,
+    idle_reads: Vec<usize>, // for each NIC, how many -1s in a row it's read since it last sent or received a packet
+    tick: usize,            // the number of rounds each NIC has had a turn in so far
+    monitor: Option<Monitor>,
+}
+impl Network {
+    fn new(program: &Vec<i64>, n: usize) -> Self {
+        // each NIC starts out by reading its own network address
+        let nics = (0..n).map(|id| {
+            let mut nic = CPU::new(program);
+            nic.send_input(id as i64);
+            nic
+        }).collect();
+        Self { nics, idle_reads: vec![0; n], tick: 0, monitor: None }
+    }
+    fn run(&mut self) -> Vec<Packet> {
+        // gives every NIC a turn, in which it runs until it either blocks on input or has sent a full packet,
+        // and returns the packets that were sent. a NIC that's blocked without anything left in its input queue
+        // gets fed a -1 to say that there's no packet for it.
+        let mut packets = Vec::<Packet>::new();
+        self.tick += 1;
+        for (id, (nic, idle_reads)) in self.nics.iter_mut().zip(self.idle_reads.iter_mut()).enumerate() {
+            if nic.get_state() == CpuState::WaitIO && nic.peek_input_first().is_none() {
                 nic.send_input(-1);
-                nic.step(); // repeat the same input instruction
-                assert!(nic.get_state() != CpuState::WaitIO);
+                *idle_reads += 1;
             }
-        }
-
-        // collect any packets that the NICs have produced, and forward them onto their destination NIC's input queue
-        // to be consumed on the next iteration (need to do this in two stages due to ref/mut ref exclusion rules)
-        let mut packets = Vec::<Packet>::with_capacity(N);
-        for nic in &mut nics {
+            nic.run_until_output(3);
             if let Some(bytes) = nic.consume_output_n(3) {
-                let packet = Packet {
+                *idle_reads = 0;
+                packets.push(Packet {
+                    src_id: id,
                     dest_id: bytes[0] as usize,
                     x: bytes[1],
                     y: bytes[2],
-                };
-                if packet.dest_id == 255 {
-                    return packet.y; // termination condition
-                }
-                packets.push(packet);
+                });
             }
         }
-        for packet in packets {
-            nics[packet.dest_id].send_input(packet.x);
-            nics[packet.dest_id].send_input(packet.y);
+        if let Some(monitor) = &mut self.monitor {
+            let tick = self.tick;
+            monitor.packets.extend(packets.iter().map(|&packet| (tick, packet)));
+        }
+        packets
+    }
+    fn deliver(&mut self, packet: &Packet) {
+        self.nics[packet.dest_id].send_input(packet.x);
+        self.nics[packet.dest_id].send_input(packet.y);
+        self.idle_reads[packet.dest_id] = 0;
+    }
+    fn wake_up(&mut self, nat_packet: &Packet) {
+        // the NAT passes on the last packet it received to NIC 0
+        let packet = Packet { src_id: NAT_ADDRESS, dest_id: 0, ..*nat_packet };
+        if let Some(monitor) = &mut self.monitor {
+            monitor.packets.push((self.tick, packet));
         }
+        self.deliver(&packet);
+    }
+    fn is_idle(&self) -> bool {
+        // the network is idle once every NIC is waiting for a packet that isn't coming: it's blocked on input
+        // with nothing in its queue, and has been told that there's no packet for it at least twice in a row
+        // since it last sent or received one. once is not enough, since it may only have gotten around to
+        // sending something after seeing the first -1; but a NIC that reads another -1 without sending anything
+        // in between is just polling, and will keep doing so until a packet comes in.
+        self.nics.iter().zip(&self.idle_reads).all(|(nic, &idle_reads)| {
+            nic.get_state() == CpuState::WaitIO && nic.peek_input_first().is_none() && idle_reads >= 2
+        })
     }
 }
 
-fn part2(program: &Vec<i64>) -> i64
+fn part1(network: &mut Network) -> i64
+{
+    // let the NICs take turns, and forward any packets they send to their destination NIC's input queue
+    // to be consumed on their next turn.
+    loop {
+        for packet in network.run() {
+            if packet.dest_id == NAT_ADDRESS {
+                return packet.y; // termination condition
+            }
+            network.deliver(&packet);
+        }
+    }
+}
+
+fn part2(network: &mut Network) -> i64
 {
     // same as before, but now with an additional NAT packet that gets recorded whenever any NIC
-    // sends a packet to address 255, plus a check on every iteration to make the NAT kick in if
-    // all NICs are idle (i.e. in the WaitIO state with an empty output buffer).
+    // sends a packet to address 255, plus a check after every round to make the NAT kick in if
+    // all NICs are idle.
     //
-    // Note: deciding whether a NIC is idle is not as straightforward as just looking at how when
-    // all NICs are in WaitIO because we're feeding them -1 every time they stall, which means
+    // Note: deciding whether a NIC is idle is not as straightforward as just looking at whether
+    // all NICs are waiting for input, because we're feeding them -1 every time they stall, which means
     // that we can expect an idle state look more like a loop of:
     //     stall, get fed -1, some logic to handle value -1, decide to try and read another packet,
     //     stall again, repeat.
     //
-    // instead, we'll look at the input and output queues of each NIC; if all the output queues
-    // have been idle for an extended period of time (and by extension all input queues as well,
-    // since packets are transferred from output to input queues), then necessarily no more packets
-    // are going in or out of any NICs and the network can be considered idle.
-    #[allow(non_snake_case)]
-    let N = 50usize;
-
-    let mut nics: Vec<CPU> = (0..N).map(|id| {
-        let mut nic = CPU::new(program);
-        nic.run();
-        assert!(nic.get_state() == CpuState::WaitIO);
-        nic.send_input(id as i64);
-        nic.step();
-        nic
-    }).collect();
-
-    let mut idle_counter = 0usize;
-    let idle_threshold = 650usize; // trial and error, we don't know how long it takes to produce packets, or how much time elapses before a NIC decides to ping the NAT ...
+    // see Network::is_idle for how to tell when they've settled into that loop.
+
     let mut nat_packet: Option<Packet> = None; // current packet in the NAT buffer
     let mut nat_last_delivered_packet: Option<Packet> = None; // last packet delivered by the NAT to NIC 0
 
     loop {
-        for nic in &mut nics {
-            nic.step();
-            if nic.get_state() == CpuState::WaitIO {
-                nic.send_input(-1);
-                nic.step(); // repeat the same input instruction
-                assert!(nic.get_state() != CpuState::WaitIO);
+        for packet in network.run() {
+            if packet.dest_id == NAT_ADDRESS {
+                nat_packet = Some(packet);
+            } else {
+                network.deliver(&packet);
             }
         }
 
-        // collect any packets that the NICs have produced, and forward them onto their destination NIC's input queue
-        // to be consumed on the next iteration (need to do this in two stages due to ref/mut ref exclusion rules)
-        let mut packets_produced = Vec::<Packet>::with_capacity(N);
-        for nic in &mut nics {
-            if let Some(bytes) = nic.consume_output_n(3) {
-                let packet = Packet {
-                    dest_id: bytes[0] as usize,
-                    x: bytes[1],
-                    y: bytes[2],
-                };
-                if packet.dest_id == 255 {
-                    nat_packet = Some(packet);
-                } else {
-                    packets_produced.push(packet);
+        if network.is_idle() {
+            let packet = nat_packet.take().expect("network is idle but no packet was sent to the NAT yet");
+            network.wake_up(&packet);
+
+            // are we delivering the same Y value as the last time?
+            if let Some(ldp) = nat_last_delivered_packet {
+                if packet.y == ldp.y {
+                    return packet.y;
                 }
             }
+            nat_last_delivered_packet = Some(packet);
         }
-        for packet in &packets_produced {
-            nics[packet.dest_id].send_input(packet.x);
-            nics[packet.dest_id].send_input(packet.y);
+    }
+}
+
+struct Switchboard {
+    // what the NIC threads share between them: where to send packets to, and enough bookkeeping to tell when
+    // the network has gone quiet
+    nics: Vec<Sender<Packet>>,
+    nat: Sender<Packet>,
+    idle_reads: Vec<AtomicUsize>, // as in Network
+    in_flight: AtomicUsize,       // the number of packets sent but not yet picked up at the other end
+    shutdown: AtomicBool,
+}
+impl Switchboard {
+    fn send(&self, packet: Packet) {
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        let target = if packet.dest_id == NAT_ADDRESS { &self.nat } else { &self.nics[packet.dest_id] };
+        target.send(packet).ok(); // the receiving end may already have shut down
+    }
+    fn picked_up(&self) {
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+    fn is_idle(&self) -> bool {
+        // same as Network::is_idle, with nothing in flight standing in for empty input queues. this holds up
+        // with the NICs carrying on in the meantime, since they reset their idle count before a packet they
+        // send is counted as in flight, and before one they receive stops being counted.
+        self.in_flight.load(Ordering::SeqCst) == 0 &&
+            self.idle_reads.iter().all(|idle_reads| idle_reads.load(Ordering::SeqCst) >= 2)
+    }
+}
+
+fn run_nic(program: &Vec<i64>, id: usize, input: Receiver<Packet>, switchboard: &Switchboard) {
+    // runs a single NIC on its own thread, until it halts or the network shuts down
+    let mut nic = CPU::new(program);
+    nic.send_input(id as i64);
+    while !switchboard.shutdown.load(Ordering::SeqCst) {
+        nic.run_until_output(3);
+        if let Some(bytes) = nic.consume_output_n(3) {
+            switchboard.idle_reads[id].store(0, Ordering::SeqCst);
+            switchboard.send(Packet { src_id: id, dest_id: bytes[0] as usize, x: bytes[1], y: bytes[2] });
+        } else if nic.is_halted() {
+            break;
+        } else if nic.get_state() == CpuState::WaitIO && nic.peek_input_first().is_none() {
+            match input.try_recv() {
+                Ok(packet) => {
+                    switchboard.idle_reads[id].store(0, Ordering::SeqCst);
+                    switchboard.picked_up();
+                    nic.send_input(packet.x);
+                    nic.send_input(packet.y);
+                },
+                Err(_) => {
+                    switchboard.idle_reads[id].fetch_add(1, Ordering::SeqCst);
+                    nic.send_input(-1);
+                    thread::yield_now();
+                },
+            }
         }
+    }
+}
 
-        if packets_produced.len() == 0 && nics.iter().all(|nic| nic.peek_input_first().is_none()) {
-            idle_counter += 1;
-        } else {
-            idle_counter = 0;
+fn run_parallel<F>(program: &Vec<i64>, n: usize, nat: F) -> i64
+    where F: FnOnce(&Switchboard, Receiver<Packet>) -> i64 + Send
+{
+    // runs each NIC on its own thread, connected to each other by channels, and the NAT on yet another one;
+    // once the NAT comes up with an answer, the network is shut down.
+    let (senders, receivers): (Vec<Sender<Packet>>, Vec<Receiver<Packet>>) = (0..n).map(|_| channel()).unzip();
+    let (nat_tx, nat_rx) = channel();
+    let switchboard = Switchboard {
+        nics: senders,
+        nat: nat_tx,
+        idle_reads: (0..n).map(|_| AtomicUsize::new(0)).collect(),
+        in_flight: AtomicUsize::new(0),
+        shutdown: AtomicBool::new(false),
+    };
+    thread::scope(|scope| {
+        for (id, input) in receivers.into_iter().enumerate() {
+            let switchboard = &switchboard;
+            scope.spawn(move || run_nic(program, id, input, switchboard));
         }
+        let result = scope.spawn(|| nat(&switchboard, nat_rx)).join().unwrap();
+        switchboard.shutdown.store(true, Ordering::SeqCst);
+        result
+    })
+}
 
-        if idle_counter >= idle_threshold {
-            if let Some(packet) = nat_packet {
-                nics[0].send_input(packet.x);
-                nics[0].send_input(packet.y);
+fn part1_parallel(program: &Vec<i64>, n: usize) -> i64 {
+    run_parallel(program, n, |_, packets| packets.recv().unwrap().y)
+}
 
-                // are we delivering the same Y value as the last time?
-                if let Some(ldp) = nat_last_delivered_packet {
-                    if packet.y == ldp.y {
-                        return packet.y;
-                    }
+fn part2_parallel(program: &Vec<i64>, n: usize) -> i64 {
+    // same as part2, except that the NAT now needs to keep an eye out for packets coming in itself
+    run_parallel(program, n, |switchboard, packets| {
+        let mut nat_packet: Option<Packet> = None;
+        let mut nat_last_delivered_packet: Option<Packet> = None;
+        loop {
+            for packet in packets.try_iter() {
+                switchboard.picked_up();
+                nat_packet = Some(packet);
+            }
+            if !switchboard.is_idle() {
+                thread::yield_now();
+                continue;
+            }
+            let packet = nat_packet.take().expect("network is idle but no packet was sent to the NAT yet");
+            switchboard.send(Packet { src_id: NAT_ADDRESS, dest_id: 0, ..packet });
+            if let Some(ldp) = nat_last_delivered_packet {
+                if packet.y == ldp.y {
+                    return packet.y;
                 }
-                nat_last_delivered_packet = Some(packet);
-            } else {
-                panic!("network has been idle for {} cycles but no packet was sent to the NAT yet", idle_counter);
             }
-            idle_counter = 0;
-            nat_packet = None; // clear NAT buffer
+            nat_last_delivered_packet = Some(packet);
         }
+    })
+}
+
+#[derive(Debug, Default)]
+struct Monitor {
+    // every packet that went over the network, along with the round it was sent in
+    packets: Vec<(usize, Packet)>,
+}
+impl Monitor {
+    fn counts(&self, id: usize) -> (usize, usize) {
+        // the number of packets sent and received by the NIC (or NAT) with the given address
+        let sent = self.packets.iter().filter(|(_, p)| p.src_id == id).count();
+        let received = self.packets.iter().filter(|(_, p)| p.dest_id == id).count();
+        (sent, received)
+    }
+    fn nics(&self) -> usize {
+        self.packets.iter().map(|(_, p)| p.src_id.max(p.dest_id)).filter(|&id| id != NAT_ADDRESS).max().map_or(0, |id| id + 1)
+    }
+    fn write_csv<W: Write>(&self, mut out: W) -> io::Result<()> {
+        writeln!(out, "tick,source,dest,x,y")?;
+        for (tick, p) in &self.packets {
+            writeln!(out, "{},{},{},{},{}", tick, p.src_id, p.dest_id, p.x, p.y)?;
+        }
+        out.flush()
+    }
+    fn to_json(&self) -> String {
+        let packets: Vec<String> = self.packets.iter().map(|(tick, p)| {
+            format!("{{\"tick\":{},\"source\":{},\"dest\":{},\"x\":{},\"y\":{}}}", tick, p.src_id, p.dest_id, p.x, p.y)
+        }).collect();
+        let nics: Vec<String> = (0..self.nics()).map(|id| {
+            let (sent, received) = self.counts(id);
+            format!("{{\"id\":{},\"sent\":{},\"received\":{}}}", id, sent, received)
+        }).collect();
+        let (delivered, received) = self.counts(NAT_ADDRESS);
+        format!("{{\"packets\":[{}],\"nics\":[{}],\"nat\":{{\"received\":{},\"delivered\":{}}}}}",
+                packets.join(","), nics.join(","), received, delivered)
     }
 }
+impl fmt::Display for Monitor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ticks = self.packets.last().map_or(0, |&(tick, _)| tick);
+        writeln!(f, "{} packets sent over {} rounds", self.packets.len(), ticks)?;
+        writeln!(f, "  nic  sent  received")?;
+        for id in 0..self.nics() {
+            let (sent, received) = self.counts(id);
+            writeln!(f, "  {:>3}  {:>4}  {:>8}", id, sent, received)?;
+        }
+        let (delivered, received) = self.counts(NAT_ADDRESS);
+        writeln!(f, "  NAT received {} packets, and woke up the network {} times:", received, delivered)?;
+        for (tick, p) in self.packets.iter().filter(|(_, p)| p.src_id == NAT_ADDRESS) {
+            writeln!(f, "    round {:>4}: x={} y={}", tick, p.x, p.y)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
+    fn relay(n: i64) -> Vec<i64> {
+        // a NIC that passes on any packet it gets to the next address up (or the NAT, from the last one) with
+        // x incremented; NIC 0 gets things going by sending (0, 5) to NIC 1.
+        vec![3,100,                 // read own address into [100]
+             1008,100,0,101,        // [101] = address == 0
+             1006,101,18,           // if not, go wait for packets
+             104,1,104,0,104,5,     // send (0, 5) to NIC 1
+             1105,1,18,
+             3,102,                 // 18: read x into [102]
+             1008,102,-1,103,
+             1005,103,18,           // no packet, try again
+             3,104,                 // read y into [104]
+             1001,100,1,105,        // [105] = address + 1
+             1008,105,n,106,
+             1006,106,44,
+             1101,255,0,105,        // past the last NIC, send it to the NAT instead
+             1001,102,1,102,        // 44: x += 1
+             4,105,4,102,4,104,
+             1105,1,18]
+    }
+
+    #[test]
+    fn networks() {
+        // the packet makes it around the network once before reaching the NAT, after which the NAT sends it
+        // around again; the second time around it has the same y, and the NAT stops.
+        let program = relay(NUM_NICS as i64);
+        assert_eq!(part1(&mut Network::new(&program, NUM_NICS)), 5);
+        let mut network = Network::new(&program, NUM_NICS);
+        network.monitor = Some(Monitor::default());
+        assert_eq!(part2(&mut network), 5);
+        let monitor = network.monitor.unwrap();
+        assert_eq!(monitor.counts(NAT_ADDRESS), (2, 2));
+        assert_eq!(monitor.counts(NUM_NICS - 1), (2, 2));
+        assert_eq!(monitor.packets.iter().filter(|(_, p)| p.dest_id == NAT_ADDRESS).map(|(_, p)| p.x).collect::<Vec<_>>(),
+                   vec![NUM_NICS as i64 - 1, 2*NUM_NICS as i64 - 1]);
+
+        // same thing with the NICs on their own threads, a few times over to shake out any races
+        for _ in 0..10 {
+            assert_eq!(part1_parallel(&program, NUM_NICS), 5);
+            assert_eq!(part2_parallel(&program, NUM_NICS), 5);
+        }
+    }
+
+    #[test]
+    fn monitor() {
+        let packet = |src_id, dest_id, x, y| Packet { src_id, dest_id, x, y };
+        let monitor = Monitor { packets: vec![
+            (1, packet(0, 1, 10, 20)),
+            (1, packet(1, NAT_ADDRESS, 11, 21)),
+            (3, packet(NAT_ADDRESS, 0, 11, 21)),
+        ]};
+        assert_eq!(monitor.counts(0), (1, 1));
+        assert_eq!(monitor.counts(1), (1, 1));
+        assert_eq!(monitor.counts(NAT_ADDRESS), (1, 1));
+
+        let mut out = Vec::new();
+        monitor.write_csv(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "tick,source,dest,x,y\n1,0,1,10,20\n1,1,255,11,21\n3,255,0,11,21\n");
+        assert_eq!(monitor.to_json(), concat!(
+            r#"{"packets":[{"tick":1,"source":0,"dest":1,"x":10,"y":20},{"tick":1,"source":1,"dest":255,"x":11,"y":21},"#,
+            r#"{"tick":3,"source":255,"dest":0,"x":11,"y":21}],"#,
+            r#""nics":[{"id":0,"sent":1,"received":1},{"id":1,"sent":1,"received":1}],"nat":{"received":1,"delivered":1}}"#,
+        ));
+        assert_eq!(monitor.to_string(), concat!(
+            "3 packets sent over 3 rounds\n",
+            "  nic  sent  received\n",
+            "    0     1         1\n",
+            "    1     1         1\n",
+            "  NAT received 1 packets, and woke up the network 1 times:\n",
+            "    round    3: x=11 y=21\n",
+        ));
+    }
+}
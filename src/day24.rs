@@ -1,67 +1,171 @@
 // vim: set ai et ts=4 sts=4 sw=4:
 #![allow(unused)]
 use std::convert::From;
-use std::collections::{HashSet, HashMap};
+use std::collections::{HashSet, VecDeque};
 use std::fmt;
 use crate::util;
 use crate::dprint::*;
+use crate::frames::{Frame, FrameRecorder};
+use clap::ArgMatches;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::ops::RangeInclusive;
+use std::time::Duration;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct Rules {
+    // which numbers of neighbouring bugs make a bug appear in an empty space, and which let a bug survive,
+    // as bitmasks over the count (bit n is set if n neighbours will do). written in the B/S notation of
+    // Conway's Game of Life, the puzzle's rules are B12/S1.
+    birth: u16,
+    survival: u16,
+}
+impl Rules {
+    pub fn parse(text: &str) -> Result<Rules, String> {
+        let counts = |part: &str, prefix: char| -> Result<u16, String> {
+            if !part.starts_with(prefix) {
+                return Err(format!("invalid rules '{}', expected e.g. B12/S1", text));
+            }
+            part[1..].chars().map(|c| match c.to_digit(10) {
+                Some(n) if n <= 8 => Ok(1 << n), // a position has no more than 8 neighbours, even in a recursive biome
+                _ => Err(format!("invalid neighbour count '{}' in rules '{}'", c, text)),
+            }).try_fold(0, |acc, bit| bit.map(|b| acc | b))
+        };
+        match text.split('/').collect::<Vec<&str>>()[..] {
+            [b, s] => Ok(Rules { birth: counts(b, 'B')?, survival: counts(s, 'S')? }),
+            _      => Err(format!("invalid rules '{}', expected e.g. B12/S1", text)),
+        }
+    }
+    pub fn check_recursive(&self) -> Result<(), String> {
+        // an empty level with no bugs around it stays empty unless bugs appear out of nowhere, which
+        // would have to happen on every one of the infinitely many levels of a recursive biome at once
+        if self.birth & 1 != 0 {
+            return Err(format!("rules {} can't be used in a recursive biome: bugs would appear on every one of the infinitely many levels", self));
+        }
+        Ok(())
+    }
+    pub fn next(&self, has_bug: bool, num_neighbouring_bugs: usize) -> bool {
+        // whether there'll be a bug in a position in the next minute
+        let counts = if has_bug { self.survival } else { self.birth };
+        counts & (1 << num_neighbouring_bugs) != 0
+    }
+}
+impl Default for Rules {
+    fn default() -> Rules {
+        // a bug dies unless there's exactly one bug adjacent to it; an empty space becomes infested if exactly one
+        // or two bugs are adjacent to it.
+        Rules { birth: 0b110, survival: 0b10 }
+    }
+}
+impl fmt::Display for Rules {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let counts = |mask: u16| (0..=8).filter(|n| mask & (1 << n) != 0).map(|n: u16| n.to_string()).collect::<String>();
+        write!(f, "B{}/S{}", counts(self.birth), counts(self.survival))
+    }
+}
 
 #[derive(Clone, PartialEq, Eq, Debug, Hash)]
-struct Biome(u32); // biome is 5x5, so can be encoded in bits
+struct Biome {
+    // a grid of any size, encoded as bits in row-major order (64 positions per word). grids of up to 64
+    // positions (like the puzzle's 5x5 one) fit in a single word, and get advanced all at once with bitwise ops.
+    width: usize,
+    height: usize,
+    words: Vec<u64>,
+}
 impl Biome {
-    fn bit(n: usize) -> u32 {
-        1 << n
+    pub fn new(width: usize, height: usize) -> Self {
+        Self { width, height, words: vec![0; (width*height).div_ceil(64)] }
+    }
+    fn bit(n: usize) -> u64 {
+        1 << (n % 64)
+    }
+    pub fn size(&self) -> usize {
+        self.width * self.height
     }
     pub fn is_empty(&self) -> bool {
-        self.0 == 0
+        self.words.iter().all(|&w| w == 0)
     }
-    pub fn biodiversity_rating(&self) -> u32 {
-        self.0
+    pub fn biodiversity_rating(&self) -> u64 {
+        // each position n with a bug in it is worth 2^n, which is just the encoded grid
+        assert!(self.size() <= 64, "biodiversity rating of a {}x{} biome doesn't fit in 64 bits", self.width, self.height);
+        self.words[0]
     }
     pub fn num_bugs(&self) -> u32 {
-        self.0.count_ones() as u32
+        self.words.iter().map(|w| w.count_ones()).sum()
     }
     pub fn has_bug_at(&self, pos: usize) -> bool {
-        let bit = Self::bit(pos);
-        self.0 & bit == bit
+        self.words[pos/64] & Self::bit(pos) != 0
+    }
+    pub fn set_bug_at(&mut self, pos: usize) {
+        self.words[pos/64] |= Self::bit(pos);
     }
-    pub fn advance_by(&self, n: usize) -> Biome {
+    pub fn advance_by(&self, n: usize, rules: &Rules) -> Biome {
         let mut current = self.clone();
         for _ in 0..n {
-            current = current.advance();
+            current = current.advance(rules);
         }
         current
     }
-    pub fn advance(&self) -> Biome {
-        let mut new_encoded = 0u32;
-        for n in 0usize..25 {
-            let num_neighbouring_bugs =   (n >= 5   && self.has_bug_at(n-5)) as usize  // upper edge
-                                        + (n%5 != 0 && self.has_bug_at(n-1)) as usize  // left edge
-                                        + (n%5 != 4 && self.has_bug_at(n+1)) as usize  // right edge
-                                        + (n < 20   && self.has_bug_at(n+5)) as usize; // bottom edge
-
-            if self.has_bug_at(n) {
-                if num_neighbouring_bugs == 1 {
-                    new_encoded |= Self::bit(n);
-                }
-            } else {
-                if num_neighbouring_bugs == 1 || num_neighbouring_bugs == 2 {
-                    new_encoded |= Self::bit(n);
-                }
+    pub fn advance(&self, rules: &Rules) -> Biome {
+        if self.size() <= 64 {
+            self.advance_word(rules)
+        } else {
+            self.advance_cellwise(rules)
+        }
+    }
+    fn advance_cellwise(&self, rules: &Rules) -> Biome {
+        let (w, h) = (self.width, self.height);
+        let mut result = Biome::new(w, h);
+        for n in 0..self.size() {
+            let num_neighbouring_bugs =   (n >= w         && self.has_bug_at(n-w)) as usize  // upper edge
+                                        + (n%w != 0       && self.has_bug_at(n-1)) as usize  // left edge
+                                        + (n%w != w-1     && self.has_bug_at(n+1)) as usize  // right edge
+                                        + (n < (h-1)*w    && self.has_bug_at(n+w)) as usize; // bottom edge
+            if rules.next(self.has_bug_at(n), num_neighbouring_bugs) {
+                result.set_bug_at(n);
             }
         }
-        Biome(new_encoded)
+        result
+    }
+    fn advance_word(&self, rules: &Rules) -> Biome {
+        // same as advance_cellwise, but for all positions at once: shifting the grid by a row or column lines up
+        // each position with one of its neighbours, and adding up those four neighbour grids bit by bit tells
+        // which positions have how many neighbouring bugs.
+        let (w, size) = (self.width, self.size());
+        let all = if size == 64 { !0 } else { (1u64 << size) - 1 };
+        let first_col = (0..size).step_by(w).fold(0, |acc, n| acc | Self::bit(n));
+        let last_col = first_col << (w-1);
+
+        let bugs = self.words[0];
+        let (a, b, c, d) = (bugs.checked_shl(w as u32).unwrap_or(0) & all,   // upper neighbour (none in a single row)
+                            bugs.checked_shr(w as u32).unwrap_or(0),         // lower neighbour
+                            (bugs << 1) & !first_col,                        // left neighbour
+                            (bugs >> 1) & !last_col);                        // right neighbour
+        let odd = a ^ b ^ c ^ d;
+        let one_or_more = a | b | c | d;
+        let two_or_more = (a & b) | (a & c) | (a & d) | (b & c) | (b & d) | (c & d);
+        let three_or_more = (a & b & c) | (a & b & d) | (a & c & d) | (b & c & d);
+        let four = a & b & c & d;
+        let exactly = [!one_or_more,
+                       odd & !two_or_more,
+                       two_or_more & !odd & !three_or_more,
+                       odd & three_or_more,
+                       four];
+        let matching = |counts: u16| (0..exactly.len()).filter(|n| counts & (1 << n) != 0).fold(0, |acc, n| acc | exactly[n]);
+
+        let mut result = Biome::new(self.width, self.height);
+        result.words[0] = ((bugs & matching(rules.survival)) | (!bugs & matching(rules.birth))) & all;
+        result
     }
     pub fn visualize(&self) -> String {
         let mut result = String::new();
-        for n in 0..25 {
-            let mask = Self::bit(n);
-            if self.0 & mask == mask {
+        for n in 0..self.size() {
+            if self.has_bug_at(n) {
                 result.push_str("# ");
             } else {
                 result.push_str(". ");
             }
-            if (n+1) % 5 == 0 {
+            if (n+1) % self.width == 0 {
                 result.push('\n');
             }
         }
@@ -71,18 +175,21 @@ impl Biome {
 }
 impl Default for Biome {
     fn default() -> Biome {
-        Biome(0)
+        Biome::new(5, 5)
     }
 }
 impl From<&Vec<&str>> for Biome {
     fn from(lines: &Vec<&str>) -> Self {
-        let mut encoded = 0u32;
-        for n in 0..25 {
-            if lines[n/5].chars().nth(n%5).unwrap() == '#' {
-                encoded |= Self::bit(n);
+        let mut biome = Biome::new(lines[0].len(), lines.len());
+        for (y, line) in lines.iter().enumerate() {
+            assert_eq!(line.len(), biome.width, "biome rows must all be the same width");
+            for (x, c) in line.chars().enumerate() {
+                if c == '#' {
+                    biome.set_bug_at(y*biome.width + x);
+                }
             }
         }
-        Self(encoded)
+        biome
     }
 }
 impl fmt::Display for Biome {
@@ -93,7 +200,15 @@ impl fmt::Display for Biome {
 
 #[derive(Clone)]
 struct RecursiveBiome {
-    levels: HashMap<i32, Biome>,
+    levels: VecDeque<Biome>, // consecutive levels, from the outermost one down to the innermost one
+    outermost: i32,          // level number of the first entry in levels
+    neighbours: [NeighbourMasks; 25],
+}
+#[derive(Clone, Copy, Default)]
+struct NeighbourMasks { // for a position, which positions on the outer, current and inner level are its neighbours
+    outer: u64,
+    same: u64,
+    inner: u64,
 }
 struct RecLocation { // identifies a position in the recursive biome
     level: i32,
@@ -105,9 +220,21 @@ macro_rules! recpos {
 
 impl RecursiveBiome {
     pub fn new(initial_biome: &Biome) -> Self {
-        let mut levels = HashMap::<i32, Biome>::new();
-        levels.insert(0, initial_biome.clone());
-        Self { levels }
+        assert!(initial_biome.width == 5 && initial_biome.height == 5, "recursive biomes must be 5x5");
+        // neighbours are in the same places relative to a position regardless of its level, so they can be
+        // worked out once up front for a position on level 0
+        let mut neighbours = [NeighbourMasks::default(); 25];
+        for (n, masks) in neighbours.iter_mut().enumerate() {
+            if n == 12 { continue; } // the center position holds the inner level instead
+            for p in Self::neighbours_of(&recpos![0, n]) {
+                match p.level {
+                    -1 => masks.outer |= 1 << p.index,
+                     0 => masks.same  |= 1 << p.index,
+                     _ => masks.inner |= 1 << p.index,
+                }
+            }
+        }
+        Self { levels: VecDeque::from(vec![initial_biome.clone()]), outermost: 0, neighbours }
     }
     pub fn neighbours_of(pos: &RecLocation) -> Vec<RecLocation> {
         // given a position within the recursive biome, determines its neighbour positions.
@@ -182,109 +309,73 @@ impl RecursiveBiome {
     pub fn has_bug_at(&self, pos: &RecLocation) -> bool {
         // look up the requested level in the stack; if that level doesn't exist in the stack,
         // then that means it's empty and the result is therefore necessarily false
-        if let Some(biome) = self.levels.get(&pos.level) {
-            biome.has_bug_at(pos.index)
-        } else {
-            false
+        match self.levels.get((pos.level - self.outermost) as usize) {
+            Some(biome) if pos.level >= self.outermost => biome.has_bug_at(pos.index),
+            _ => false,
         }
     }
     pub fn num_bugs(&self) -> u32 {
-        self.levels.values().map(|biome| biome.num_bugs()).sum()
+        self.levels.iter().map(|biome| biome.num_bugs()).sum()
     }
-    pub fn advance_by(&self, n: usize) -> RecursiveBiome {
-        let mut current = self.clone();
+    pub fn advance_by(&self, n: usize, rules: &Rules) -> RecursiveBiome {
+        let mut result = self.clone();
         for _ in 0..n {
-            current = current.advance();
+            result.step(rules);
         }
-        current
+        result
     }
-    pub fn advance(&self) -> RecursiveBiome {
-        let mut result = self.clone();
+    pub fn advance(&self, rules: &Rules) -> RecursiveBiome {
+        self.advance_by(1, rules)
+    }
+    fn step(&mut self, rules: &Rules) {
+        // advances all levels by one minute, in place. the rules must pass check_recursive.
+        debug_assert!(rules.check_recursive().is_ok());
 
-        // record the new state of all the bugs at the currently-recorded biome levels,
-        // (but leave out their center position at each biome level since those contain deeper recursion
-        //  levels and shouldn't be regarded as containing bugs)
-        for (&level, biome) in &self.levels {
-            let mut new_encoded = 0u32;
-            for n in 0..25 {
-                if n == 12 { continue; } // skip center position
-                let pos = recpos![level, n];
-                let num_neighbouring_bugs = Self::neighbours_of(&pos)
-                                                .iter()
-                                                .filter(|p| self.has_bug_at(p))
-                                                .count();
-
-                // TODO: copy/paste from Biome::advance
-                if self.has_bug_at(&pos) {
-                    if num_neighbouring_bugs == 1 {
-                        new_encoded |= Biome::bit(n);
-                    }
-                } else {
-                    if num_neighbouring_bugs == 1 || num_neighbouring_bugs == 2 {
-                        new_encoded |= Biome::bit(n);
-                    }
-                }
-            }
-            result.levels.insert(level, Biome(new_encoded));
-        }
+        // bugs can spread into the levels just outside of and inside of the ones we have so far,
+        // so start off those as empty ones
+        self.levels.push_front(Biome::default());
+        self.levels.push_back(Biome::default());
+        self.outermost -= 1;
 
-        let max_level: i32 = *self.levels.keys().max().unwrap();
-        let min_level: i32 = *self.levels.keys().min().unwrap();
-
-        // additionally, spawn a new empty outermost and innermost biome, and see if any of the bugs
-        // along their rim to the previous level have been affected, and record those as well.
-        // if they are non-empty, add those new biomes to the result; otherwise omit them to save some memory.
-        let mut new_outermost = Biome::default();
-        for &n in [7,11,13,17].iter() {
-            let pos = recpos![min_level-1, n];
-            let num_neighbouring_bugs = Self::neighbours_of(&pos)
-                                            .iter()
-                                            .filter(|p| self.has_bug_at(p)) // note: has_bug_at() wil transparently deal with this new level number and return false for unknown levels like this one
-                                            .count();
-
-            // we only need to consider whether to change an empty spot into a bug,
-            // since these levels start off empty
-            if num_neighbouring_bugs == 1 || num_neighbouring_bugs == 2 {
-                new_outermost.0 |= Biome::bit(n);
+        // keep a copy of the previous state of each level to count neighbours in while updating them.
+        // a level with no bugs on it or on either level next to it stays empty, so it can be skipped.
+        let previous: Vec<u64> = self.levels.iter().map(|biome| biome.words[0]).collect();
+        for (i, biome) in self.levels.iter_mut().enumerate() {
+            let current = previous[i];
+            let outer = if i > 0 { previous[i-1] } else { 0 };
+            let inner = previous.get(i+1).copied().unwrap_or(0);
+            if outer | current | inner == 0 {
+                continue;
             }
-        }
 
-        let mut new_innermost = Biome::default();
-        for &n in [ 0,  1,  2,  3,  4,
-                    5,              9,
-                   10,             14,
-                   15,             19,
-                   20, 21, 22, 23, 24 ].iter()
-        {
-            let pos = recpos![max_level+1, n];
-            let num_neighbouring_bugs = Self::neighbours_of(&pos)
-                                            .iter()
-                                            .filter(|p| self.has_bug_at(p)) // note: has_bug_at() wil transparently deal with this new level number and return false for unknown levels like this one
-                                            .count();
-
-            // we only need to consider whether to change an empty spot into a bug,
-            // since these levels start off empty
-            if num_neighbouring_bugs == 1 || num_neighbouring_bugs == 2 {
-                new_innermost.0 |= Biome::bit(n);
+            let mut next = 0u64;
+            for (n, masks) in self.neighbours.iter().enumerate() {
+                if n == 12 { continue; } // skip center position
+                let num_neighbouring_bugs = (outer   & masks.outer).count_ones()
+                                          + (current & masks.same).count_ones()
+                                          + (inner   & masks.inner).count_ones();
+                if rules.next(current & (1 << n) != 0, num_neighbouring_bugs as usize) {
+                    next |= 1 << n;
+                }
             }
+            biome.words[0] = next;
         }
 
-        if !new_outermost.is_empty() {
-            result.levels.insert(min_level-1, new_outermost);
+        // only keep the new outermost and innermost levels if any bugs actually spread into them,
+        // to save some memory.
+        if self.levels.front().unwrap().is_empty() {
+            self.levels.pop_front();
+            self.outermost += 1;
         }
-        if !new_innermost.is_empty() {
-            result.levels.insert(max_level+1, new_innermost);
+        if self.levels.back().unwrap().is_empty() {
+            self.levels.pop_back();
         }
-        result
     }
     #[allow(non_snake_case)]
     pub fn visualize(&self) -> String {
         let mut result = String::new();
 
-        let mut levels: Vec<i32> = self.levels.keys().copied().collect();
-        levels.sort();
-        for L in levels {
-            let biome = &self.levels[&L];
+        for (L, biome) in (self.outermost..).zip(&self.levels) {
             result.push_str(&format!("Level {}:\n", L));
             result.push_str(&biome.visualize());
             result.push_str("\n\n");
@@ -292,6 +383,25 @@ impl RecursiveBiome {
         result.truncate(result.trim_end().len()); // right trim in place
         result
     }
+    pub fn render(&self, levels: RangeInclusive<i32>, columns: usize) -> Frame {
+        // same as visualize, but laid out side by side: the given levels go left to right and top to bottom,
+        // `columns` to a row, each as its grid underneath its level number. the center position holds the
+        // next level down, so it's drawn as a '?'.
+        let num_levels = levels.clone().count();
+        let rows = num_levels.div_ceil(columns);
+        let mut frame = Frame::new(columns*(5+2) - 2, rows*(6+1) - 1, ' ');
+        for (k, level) in levels.enumerate() {
+            let (x, y) = ((k % columns)*(5+2), (k / columns)*(6+1));
+            for (i, c) in level.to_string().chars().take(5).enumerate() {
+                frame.set(x+i, y, c);
+            }
+            for n in 0..25 {
+                let c = if n == 12 { '?' } else if self.has_bug_at(&recpos![level, n]) { '#' } else { '.' };
+                frame.set(x + n%5, y+1 + n/5, c);
+            }
+        }
+        frame
+    }
 }
 
 impl fmt::Display for RecursiveBiome {
@@ -301,14 +411,65 @@ impl fmt::Display for RecursiveBiome {
 }
 
 
-pub fn main() {
+pub fn main(args: &ArgMatches) {
     let lines: Vec<String> = util::file_read_lines("input/day24.txt");
     let biome = Biome::from(&lines.iter().map(|line| &line[..]).collect());
-    println!("{}", part1(&biome));
-    println!("{}", part2(&biome));
+    let invalid_value = |err: String| -> ! { clap::Error::with_description(&err, clap::ErrorKind::InvalidValue).exit() };
+    let rules = args.value_of("rules").map(|s| Rules::parse(s).unwrap_or_else(|err| invalid_value(err)))
+                                      .unwrap_or_default();
+    let minutes: usize = args.value_of("steps")
+                             .map(|s| s.parse().unwrap_or_else(|_| panic!("invalid number of minutes: {}", s)))
+                             .unwrap_or(200);
+    println!("{}", part1(&biome, &rules));
+    // only the recursive biome of part 2 can't deal with bugs appearing out of nowhere
+    rules.check_recursive().unwrap_or_else(|err| invalid_value(err));
+    println!("{}", part2(&biome, &rules, minutes));
+
+    if args.is_present("render") || args.is_present("gif") {
+        let delay = Duration::from_millis(args.value_of("delay").map_or(100, |s| s.parse().unwrap_or_else(|_| panic!("invalid delay: {}", s))));
+        let recorder = record_levels(&biome, &rules, minutes);
+        if args.is_present("render") {
+            recorder.play(&mut io::stdout(), delay).unwrap();
+        }
+        if let Some(filename) = args.value_of("gif") {
+            let scale = args.value_of("scale").map_or(10, |s| s.parse().unwrap_or_else(|_| panic!("invalid scale: {}", s)));
+            File::create(filename).and_then(|f| recorder.write_gif(BufWriter::new(f), scale, delay, biome_color))
+                                  .unwrap_or_else(|err| panic!("failed to write {}: {}", filename, err));
+        }
+    }
+}
+
+fn record_levels(biome: &Biome, rules: &Rules, minutes: usize) -> FrameRecorder {
+    // animation of the bugs spreading across the levels of the recursive biome, one frame per minute.
+    // levels only ever get added, so laying out all the ones there'll be by the end from the start on
+    // keeps each level in the same place throughout.
+    let initial = RecursiveBiome::new(biome);
+    let last = initial.advance_by(minutes, rules);
+    let levels = last.outermost ..= last.outermost + last.levels.len() as i32 - 1;
+    let num_levels = last.levels.len();
+    let columns = (1..).find(|c| c*c >= 2*num_levels).unwrap(); // about twice as wide as high, for the terminal
+
+    let mut recorder = FrameRecorder::new();
+    let mut current = initial;
+    recorder.record(current.render(levels.clone(), columns));
+    for _ in 0..minutes {
+        current.step(rules);
+        recorder.record(current.render(levels.clone(), columns));
+    }
+    recorder
+}
+
+fn biome_color(c: char) -> [u8; 3] {
+    match c {
+        '#' => [120, 200, 40],
+        '.' => [40, 40, 40],
+        '?' => [20, 20, 90],
+        ' ' => [0, 0, 0],
+        _   => [200, 200, 200], // level numbers
+    }
 }
 
-fn part1(biome: &Biome) -> u32 {
+fn part1(biome: &Biome, rules: &Rules) -> u64 {
     let mut seen = HashSet::<Biome>::new();
     let mut current_state = biome.clone();
     loop {
@@ -316,13 +477,13 @@ fn part1(biome: &Biome) -> u32 {
             return current_state.biodiversity_rating();
         }
         seen.insert(current_state.clone());
-        current_state = current_state.advance();
+        current_state = current_state.advance(rules);
     }
 }
 
-fn part2(biome: &Biome) -> u32 {
+fn part2(biome: &Biome, rules: &Rules, minutes: usize) -> u32 {
     let mut biome = RecursiveBiome::new(biome);
-    biome.advance_by(200).num_bugs()
+    biome.advance_by(minutes, rules).num_bugs()
 }
 
 #[cfg(test)]
@@ -368,10 +529,10 @@ mod tests {
                 "##...",
             ]),
         ];
-        assert_eq!(stages[0].advance(), stages[1]);
-        assert_eq!(stages[1].advance(), stages[2]);
-        assert_eq!(stages[2].advance(), stages[3]);
-        assert_eq!(stages[3].advance(), stages[4]);
+        assert_eq!(stages[0].advance(&Rules::default()), stages[1]);
+        assert_eq!(stages[1].advance(&Rules::default()), stages[2]);
+        assert_eq!(stages[2].advance(&Rules::default()), stages[3]);
+        assert_eq!(stages[3].advance(&Rules::default()), stages[4]);
 
         assert_eq!(Biome::from(&vec![
             ".....",
@@ -382,6 +543,30 @@ mod tests {
         ]).biodiversity_rating(), 2129920);
     }
 
+    #[test]
+    fn grid_sizes() {
+        // advancing a whole word at a time agrees with going position by position, whatever the grid's shape
+        let rules = Rules::default();
+        let mut seed = 0x2545f4914f6cdd1du64;
+        for &(w, h) in &[(5, 5), (1, 1), (3, 7), (7, 3), (8, 8), (64, 1), (1, 64), (2, 32)] {
+            for _ in 0..50 {
+                seed ^= seed << 13; seed ^= seed >> 7; seed ^= seed << 17; // xorshift
+                let mut biome = Biome::new(w, h);
+                biome.words[0] = if w*h == 64 { seed } else { seed & ((1 << (w*h)) - 1) };
+                assert_eq!(biome.advance_word(&rules), biome.advance_cellwise(&rules), "{}x{}:\n{}", w, h, biome);
+            }
+        }
+
+        // bigger grids take more than one word
+        let mut biome = Biome::new(10, 10);
+        biome.set_bug_at(55);
+        assert_eq!(biome.words.len(), 2);
+        let next = biome.advance(&Rules::default());
+        assert_eq!(next.num_bugs(), 4);
+        assert!([45, 54, 56, 65].iter().all(|&n| next.has_bug_at(n)));
+        assert_eq!(Biome::from(&vec!["#..", "..."]).advance(&Rules::default()).visualize(), ". # . \n# . .");
+    }
+
     #[test]
     fn recursive_example() {
         let mut rec_biome = RecursiveBiome::new(
@@ -393,7 +578,93 @@ mod tests {
                 "#....",
             ])
         );
-        assert_eq!(rec_biome.advance_by(10).num_bugs(), 99);
+        assert_eq!(rec_biome.advance_by(10, &Rules::default()).num_bugs(), 99);
+    }
+
+    #[test]
+    fn recursive_steps() {
+        // the neighbour table and in-place updates agree with counting neighbours one position at a time
+        let step = |rec_biome: &RecursiveBiome, rules: &Rules| -> Vec<(i32, Vec<bool>)> {
+            let first = rec_biome.outermost - 1;
+            let last = rec_biome.outermost + rec_biome.levels.len() as i32;
+            (first..=last).map(|level| {
+                (level, (0..25).map(|n| n != 12 && {
+                    let pos = recpos![level, n];
+                    let num_neighbouring_bugs = RecursiveBiome::neighbours_of(&pos).iter().filter(|p| rec_biome.has_bug_at(p)).count();
+                    rules.next(rec_biome.has_bug_at(&pos), num_neighbouring_bugs)
+                }).collect())
+            }).filter(|(_, bugs): &(i32, Vec<bool>)| bugs.iter().any(|&b| b))
+              .collect()
+        };
+        for text in &["B12/S1", "B3/S23", "B1/S012345678", "B24/S1234"] {
+            let rules = Rules::parse(text).unwrap();
+            let mut rec_biome = RecursiveBiome::new(&Biome::from(&vec!["....#", "#..#.", "#..##", "..#..", "#...."]));
+            for _ in 0..20 {
+                let expected = step(&rec_biome, &rules);
+                rec_biome = rec_biome.advance(&rules);
+                let actual: Vec<(i32, Vec<bool>)> = (rec_biome.outermost..).zip(&rec_biome.levels)
+                                                        .map(|(level, biome)| (level, (0..25).map(|n| biome.has_bug_at(n)).collect()))
+                                                        .filter(|(_, bugs): &(i32, Vec<bool>)| bugs.iter().any(|&b| b))
+                                                        .collect();
+                assert_eq!(actual, expected, "{}", text);
+            }
+        }
+    }
+
+    #[test]
+    fn rendering() {
+        let rec_biome = RecursiveBiome::new(&Biome::from(&vec!["....#", "#..#.", "#..##", "..#..", "#...."]))
+                            .advance(&Rules::default());
+        let frame = rec_biome.render(-1..=1, 2);
+        assert_eq!(frame.to_string(), [
+            "-1     0    ",
+            ".....  #..#.",
+            "..#..  ####.",
+            "..?#.  ##?.#",
+            "..#..  ##.##",
+            ".....  .##..",
+            "            ",
+            "1           ",
+            "....#       ",
+            "....#       ",
+            "..?.#       ",
+            "....#       ",
+            "#####       ",
+            "",
+        ].join("\n"));
+    }
+
+    #[test]
+    fn rules() {
+        assert_eq!(Rules::default(), Rules::parse("B12/S1").unwrap());
+        assert_eq!(Rules::parse("B3/S23").unwrap().to_string(), "B3/S23");
+        assert_eq!(Rules::parse("B/S012345678").unwrap().to_string(), "B/S012345678");
+        assert!(Rules::parse("B9/S1").is_err());
+        assert!(Rules::parse("S1/B12").is_err());
+        assert!(Rules::parse("B12").is_err());
+        assert_eq!(Rules::parse("B11/S1").unwrap(), Rules { birth: 0b10, survival: 0b10 });
+        assert_eq!(Rules::parse("B121/S11").unwrap(), Rules::default());
+        assert!(Rules::default().check_recursive().is_ok());
+        assert!(Rules::parse("B0/S1").unwrap().check_recursive().is_err());
+
+        // every count a position can have on a flat grid, under all sorts of rules
+        let mut seed = 0x9e3779b97f4a7c15u64;
+        for text in &["B3/S23", "B0/S", "B/S01234", "B24/S13", "B1234/S0"] {
+            let rules = Rules::parse(text).unwrap();
+            for &(w, h) in &[(5, 5), (3, 7), (8, 8)] {
+                for _ in 0..20 {
+                    seed ^= seed << 13; seed ^= seed >> 7; seed ^= seed << 17;
+                    let mut biome = Biome::new(w, h);
+                    biome.words[0] = if w*h == 64 { seed } else { seed & ((1 << (w*h)) - 1) };
+                    assert_eq!(biome.advance_word(&rules), biome.advance_cellwise(&rules), "{} {}x{}:\n{}", text, w, h, biome);
+                }
+            }
+        }
+
+        // a bug that survives no matter what stays put forever in a recursive biome, too
+        let rules = Rules::parse("B/S012345678").unwrap();
+        let rec_biome = RecursiveBiome::new(&Biome::from(&vec!["#....", ".....", ".....", ".....", "....#"]));
+        assert_eq!(rec_biome.advance_by(10, &rules).num_bugs(), 2);
     }
 
 }
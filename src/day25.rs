@@ -1,17 +1,213 @@
 // vim: set ai et ts=4 sts=4 sw=4:
 use crate::util;
 use crate::intcode::{CPU, CpuState};
+use clap::ArgMatches;
+use std::collections::HashSet;
+use std::fmt;
 use std::io::{self, BufRead};
 use itertools::Itertools;
 
-pub fn main() {
+// commands normally take the program some ten thousand instructions to respond to; one that takes this many is
+// considered to have sent it into an infinite loop.
+const MAX_STEPS: usize = 1_000_000;
+const CHECKPOINT: &str = "Security Checkpoint";
+
+pub fn main(args: &ArgMatches) {
     let line: String = util::file_read_lines("input/day25.txt").into_iter().next().unwrap();
     let program: Vec<i64> = line.split(",").map(|s| s.parse().unwrap()).collect();
 
-    println!("{}", part1(&program));
+    let (password, ship) = part1(&program).unwrap_or_else(|err| panic!("{}", err));
+    println!("{}", password);
+    if args.is_present("analyze") {
+        print!("{}", ship.describe());
+    }
     // no part 2
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Room {
+    name: String,
+    doors: Vec<String>,
+    items: Vec<String>,
+}
+impl Room {
+    pub fn parse(output: &str) -> Result<Room, String> {
+        // the output of a movement command describes the room the droid ends up in, e.g.:
+        //
+        //     == Hull Breach ==
+        //     You got in through a hole in the floor here. [...]
+        //
+        //     Doors here lead:
+        //     - north
+        //     - south
+        //
+        //     Items here:
+        //     - mug
+        //
+        //     Command?
+        //
+        // when the droid gets sent back somewhere, more than one room is described; the last one is where it's at.
+        let start = output.rfind("== ").ok_or_else(|| format!("no room found in output: {:?}", output))?;
+        let mut lines = output[start..].lines();
+        let name = lines.next().unwrap().trim_start_matches("== ").trim_end_matches(" ==").to_string();
+        let mut room = Room { name, doors: Vec::new(), items: Vec::new() };
+
+        let mut list: Option<&mut Vec<String>> = None;
+        for line in lines {
+            match line {
+                "Doors here lead:" => list = Some(&mut room.doors),
+                "Items here:"      => list = Some(&mut room.items),
+                _ => match (line.strip_prefix("- "), list.as_mut()) {
+                    (Some(entry), Some(list)) => list.push(entry.to_string()),
+                    _                         => list = None,
+                }
+            }
+        }
+        Ok(room)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Hazard { // what picking up a dangerous item does to the droid
+    Fatal, // the game ends
+    Hang,  // the program never responds again
+    Stuck, // the droid can't move anymore
+}
+impl fmt::Display for Hazard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Hazard::Fatal => write!(f, "ends the game"),
+            Hazard::Hang  => write!(f, "hangs the program"),
+            Hazard::Stuck => write!(f, "keeps the droid from moving"),
+        }
+    }
+}
+
+fn opposite(direction: &str) -> &'static str {
+    match direction {
+        "north" => "south",
+        "south" => "north",
+        "east"  => "west",
+        "west"  => "east",
+        _       => panic!("unknown direction: {}", direction),
+    }
+}
+
+#[derive(Clone)]
+struct Droid {
+    cpu: CPU,
+}
+impl Droid {
+    pub fn new(program: &Vec<i64>) -> (Droid, String) {
+        // boots up the droid; also returns what it says at startup, i.e. the description of the starting room
+        let mut droid = Droid { cpu: CPU::new(program) };
+        let output = droid.output();
+        (droid, output)
+    }
+    pub fn command(&mut self, command: &str) -> String {
+        self.cpu.send_input_string(&format!("{}\n", command));
+        self.output()
+    }
+    fn output(&mut self) -> String {
+        self.cpu.run().consume_output_all().into_iter().map(|n| char::from(n as u8)).collect()
+    }
+    pub fn probe(&self, item: &str, here: &Room, door: &str) -> Option<Hazard> {
+        // finds out what picking up an item does, on a copy of the droid so that the real one is none the worse
+        // for it: after taking the item, it should still be able to go through a door into another room.
+        let mut probe = self.clone();
+        for command in [format!("take {}", item), door.to_string()] {
+            probe.cpu.send_input_string(&format!("{}\n", command));
+            probe.cpu.run_limited(MAX_STEPS);
+            match probe.cpu.get_state() {
+                CpuState::Running => return Some(Hazard::Hang),
+                CpuState::Halted  => return Some(Hazard::Fatal),
+                CpuState::WaitIO  => {},
+            }
+        }
+        let output: String = probe.cpu.consume_output_all().into_iter().map(|n| char::from(n as u8)).collect();
+        match Room::parse(&output) {
+            Ok(room) if room.name != here.name => None,
+            _ => Some(Hazard::Stuck),
+        }
+    }
+}
+
+struct Ship {
+    rooms: Vec<Room>,                // in the order they were first visited
+    items: Vec<String>,              // the items the droid picked up along the way
+    hazards: Vec<(String, Hazard)>,  // the items it left where they were, and why
+    route: Vec<String>,              // directions from the starting room to the security checkpoint
+    floor_direction: Option<String>, // direction from the security checkpoint onto the pressure-sensitive floor
+}
+impl Ship {
+    pub fn explore(droid: &mut Droid, start: Room) -> Result<Ship, String> {
+        // walks through every room of the ship depth-first, picking up every item that turns out to be safe
+        // to pick up, and ends up back at the start.
+        let mut ship = Ship { rooms: Vec::new(), items: Vec::new(), hazards: Vec::new(), route: Vec::new(), floor_direction: None };
+        let mut seen = HashSet::<String>::new();
+        ship.visit(droid, start, &mut Vec::new(), &mut seen)?;
+        if ship.floor_direction.is_none() {
+            return Err(format!("the droid didn't come across the {}", CHECKPOINT));
+        }
+        Ok(ship)
+    }
+    fn visit(&mut self, droid: &mut Droid, room: Room, path: &mut Vec<String>, seen: &mut HashSet<String>) -> Result<(), String> {
+        seen.insert(room.name.clone());
+        self.rooms.push(room.clone());
+
+        let came_from = path.last().map(|dir| opposite(dir));
+        for item in &room.items {
+            // any door will do to check that the droid can still move, but the one it came in through
+            // is sure not to lead onto the pressure-sensitive floor
+            let door = came_from.unwrap_or(&room.doors[0]);
+            match droid.probe(item, &room, door) {
+                None => {
+                    droid.command(&format!("take {}", item));
+                    self.items.push(item.clone());
+                },
+                Some(hazard) => self.hazards.push((item.clone(), hazard)),
+            }
+        }
+
+        for door in &room.doors {
+            if Some(&door[..]) == came_from {
+                continue;
+            }
+            if room.name == CHECKPOINT {
+                // the door we didn't come in through leads onto the pressure-sensitive floor, and stepping
+                // onto that triggers the weight check; leave that until all the items have been collected.
+                self.route = path.clone();
+                self.floor_direction = Some(door.clone());
+                continue;
+            }
+            let next = Room::parse(&droid.command(door))?;
+            if !seen.contains(&next.name) {
+                path.push(door.clone());
+                self.visit(droid, next, path, seen)?;
+                path.pop();
+            }
+            droid.command(opposite(door));
+        }
+        Ok(())
+    }
+    pub fn describe(&self) -> String {
+        let mut result = String::new();
+        for room in &self.rooms {
+            result.push_str(&format!("{}: doors {}", room.name, room.doors.join(", ")));
+            if !room.items.is_empty() {
+                result.push_str(&format!("; items {}", room.items.join(", ")));
+            }
+            result.push('\n');
+        }
+        result.push_str(&format!("route to the {}: {}\n", CHECKPOINT, self.route.join(", ")));
+        result.push_str(&format!("collected: {}\n", self.items.join(", ")));
+        for (item, hazard) in &self.hazards {
+            result.push_str(&format!("left behind: {} ({})\n", item, hazard));
+        }
+        result
+    }
+}
+
 #[allow(dead_code)]
 fn run_interactive(cpu: &mut CPU) {
     // TODO: copy/paste from day17
@@ -37,76 +233,34 @@ fn run_interactive(cpu: &mut CPU) {
     }
 }
 
-fn part1(program: &Vec<i64>) -> i64
+fn part1(program: &Vec<i64>) -> Result<(i64, Ship), String>
 {
-    let mut cpu = CPU::new(program);
-    // from running the program in interactive mode, we know that there a bunch of collectable items to be found
-    // throughout the map, and some combination of them will be the correct weight to pass the security checkpoint.
-    // find that combination.
-    let collect_items = concat!(
-        "south\n",              // engineering
-        "south\n",              // arcade
-        "west\n",               // science lab
-        "north\n",              // warp drive maintenance
-        "north\n",              // sick bay
-        "take tambourine\n",
-        "south\n",
-        "south\n",
-        "east\n",               // arcade
-        "south\n",              // crew quarters
-        "take fixed point\n",
-        "west\n",               // kitchen
-        "take asterisk\n",
-        "east\n",               // crew quarters
-        "south\n",              // passages
-        "take festive hat\n",
-        "west\n",               // navigation
-        "west\n",               // corridor
-        "take jam\n",
-        "south\n",              // stables
-        "take easter egg\n",
-        "north\n",
-        "east\n",
-        "east\n",               // passages
-        "north\n",
-        "north\n",
-        "north\n",              // engineering
-        "west\n",               // hallway
-        "south\n",              // gift wrap center
-        "take antenna\n",
-        "north\n",              // hallway
-        "west\n",               // observatory
-        "west\n",               // storage
-        "take space heater\n",
-        "west\n",               // security checkpoint
-    );
-    cpu.send_input_string(&collect_items);
-    cpu.run();
-
-    let items = vec!["antenna", "asterisk", "easter egg", "festive hat",
-                     "fixed point", "jam", "space heater", "tambourine"];
+    // there are a bunch of collectable items to be found throughout the ship, and some combination of them will
+    // be the correct weight to pass the security checkpoint. explore the ship to collect them all, then head to
+    // the checkpoint and find that combination.
+    let (mut droid, output) = Droid::new(program);
+    let ship = Ship::explore(&mut droid, Room::parse(&output)?)?;
+    for direction in &ship.route {
+        droid.command(direction);
+    }
+    let floor_direction = ship.floor_direction.as_ref().unwrap();
 
     // first, drop all the items we've collected in the current location, then try out all different
     // combinations of items (of different lengths as well) to pass through the weight check with.
-    for item in &items {
-        cpu.send_input_string(&format!("drop {}", item));
+    for item in &ship.items {
+        droid.command(&format!("drop {}", item));
     }
-    cpu.run().consume_output_all(); // process instructions and clear output buffer
 
-    for n in 1..9 {
-        for combination in items.iter().combinations(n) {
+    for n in 1..=ship.items.len() {
+        for combination in ship.items.iter().combinations(n) {
             for item in &combination {
-                cpu.send_input_string(&format!("take {}\n", item));
+                droid.command(&format!("take {}", item));
             }
-            cpu.run().consume_output_all(); // process the take instructions and clear output buffer
-
-            // now try and pass to the west through the weight detector; if we fail, we'll get a
-            // recognizable output message and get kicked back to the security checkpoint.
-            // in that case, drop the items we were carrying and try again in the next iteration.
-            cpu.send_input_string("west\n");
-            let response: String = cpu.run().consume_output_all().into_iter()
-                                    .map(|n| char::from(n as u8)).collect::<String>();
 
+            // now try and pass through the weight detector; if we fail, we'll get a recognizable output
+            // message and get kicked back to the security checkpoint. in that case, drop the items we were
+            // carrying and try again in the next iteration.
+            let response = droid.command(floor_direction);
             if    !response.contains("Alert! Droids on this ship are heavier than the detected value!")
                && !response.contains("Alert! Droids on this ship are lighter than the detected value!")
             {
@@ -115,17 +269,72 @@ fn part1(program: &Vec<i64>) -> i64
                 //
                 // "You should be able to get in by typing XXXXXXXX on the keypad at the main airlock."
                 let match_str = "You should be able to get in by typing ";
-                let answer_start = response.find(match_str).unwrap() + match_str.len();
+                let answer_start = response.find(match_str).ok_or_else(|| format!("unexpected response: {:?}", response))?
+                                 + match_str.len();
                 let answer_end   = answer_start + response[answer_start..].find(" ").unwrap(); // first whitespace after answer_start
 
-                return response[answer_start..answer_end].parse().unwrap();
+                let password = response[answer_start..answer_end].parse().map_err(|_| format!("invalid password in response: {:?}", response))?;
+                return Ok((password, ship));
             }
 
             for item in &combination {
-                cpu.send_input_string(&format!("drop {}\n", item));
+                droid.command(&format!("drop {}", item));
             }
         }
     }
-    panic!("no solution found");
+    Err("no combination of items passes the security checkpoint".to_string())
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rooms() {
+        let output = concat!(
+            "\n\n\n== Hull Breach ==\n",
+            "You got in through a hole in the floor here. To keep your ship from also freezing, the hole has been sealed.\n",
+            "\n",
+            "Doors here lead:\n",
+            "- north\n",
+            "- south\n",
+            "\n",
+            "Items here:\n",
+            "- mug\n",
+            "- easter egg\n",
+            "\n",
+            "Command?\n",
+        );
+        assert_eq!(Room::parse(output), Ok(Room {
+            name: "Hull Breach".to_string(),
+            doors: vec!["north".to_string(), "south".to_string()],
+            items: vec!["mug".to_string(), "easter egg".to_string()],
+        }));
+
+        // getting sent back describes two rooms; the droid is in the last one
+        let output = concat!(
+            "\n\n\n== Pressure-Sensitive Floor ==\n",
+            "Analyzing...\n",
+            "\n",
+            "Doors here lead:\n",
+            "- east\n",
+            "\n",
+            "A loud, robotic voice says \"Alert! Droids on this ship are heavier than the detected value!\" and you are ejected back to the checkpoint.\n",
+            "\n\n\n== Security Checkpoint ==\n",
+            "In the next room, a pressure-sensitive floor will verify your identity.\n",
+            "\n",
+            "Doors here lead:\n",
+            "- north\n",
+            "- west\n",
+            "\n",
+            "Command?\n",
+        );
+        let room = Room::parse(output).unwrap();
+        assert_eq!(room.name, CHECKPOINT);
+        assert_eq!(room.doors, vec!["north", "west"]);
+        assert!(room.items.is_empty());
+
+        assert!(Room::parse("\nCommand?\n").is_err());
+        assert!(["north", "south", "east", "west"].iter().all(|&dir| opposite(opposite(dir)) == dir));
+    }
+}
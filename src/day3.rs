@@ -1,6 +1,8 @@
 // vim: set ai et ts=4 sts=4 sw=4:
 use crate::util;
+use clap::ArgMatches;
 use std::collections::HashMap;
+use std::fs;
 use std::convert::From;
 use std::fmt;
 
@@ -49,86 +51,245 @@ impl fmt::Display for Step {
 }
 
 type Pos = (i32,i32);
-type PathId = u32;
-type PathDist = u32;
-type PathMap = HashMap<Pos, PathMapVal>; // (x,y) => map of line_id to distance traveled to get here
-type PathMapVal = HashMap<PathId, PathDist>;
 
 #[derive(Debug)]
 struct Path {
-    id: u32,
     steps: Vec<Step>,
 }
 impl Path {
-    fn parse(line: &str, id: u32) -> Self {
+    fn parse(line: &str) -> Self {
         Path {
-            id: id,
             steps: line.split(",").map(Step::from).collect(),
         }
     }
+    fn segments(&self) -> Vec<Segment> {
+        let mut result = Vec::with_capacity(self.steps.len());
+        let mut pos = (0,0); // x,y
+        let mut dist = 0;
+        for step in &self.steps {
+            let n = step.num() as i32;
+            let end = match step {
+                Step::U(_) => (pos.0, pos.1 + n),
+                Step::D(_) => (pos.0, pos.1 - n),
+                Step::R(_) => (pos.0 + n, pos.1),
+                Step::L(_) => (pos.0 - n, pos.1),
+            };
+            result.push(Segment { start: pos, end, dist });
+            pos = end;
+            dist += step.num();
+        }
+        result
+    }
 }
 
-fn trace_path(path: &Path, map: &mut PathMap) {
-    let mut pos = (0,0); // x,y
-    let mut dist = 0;
-    for step in &path.steps {
-        for _ in 0..step.num() {
-            if !map.contains_key(&pos) {
-                map.insert(pos, PathMapVal::new());
-            }
-            let val = map.get_mut(&pos).unwrap();
-            if !val.contains_key(&path.id) { // don't overwrite an earlier distance seen for this position
-                val.insert(path.id, dist);
+// a horizontal or vertical stretch of wire, along with the distance traveled along the wire to get to its start
+#[derive(Debug)]
+struct Segment {
+    start: Pos,
+    end: Pos,
+    dist: u32,
+}
+impl Segment {
+    fn x_range(&self) -> (i32, i32) {
+        (self.start.0.min(self.end.0), self.start.0.max(self.end.0))
+    }
+    fn y_range(&self) -> (i32, i32) {
+        (self.start.1.min(self.end.1), self.start.1.max(self.end.1))
+    }
+    fn dist_to(&self, pos: Pos) -> u32 {
+        // distance traveled along the wire to get to a position on this segment
+        self.dist + util::manhattan_distance(self.start, pos)
+    }
+}
+
+fn intersections(path1: &Path, path2: &Path) -> Vec<(Pos, u32)> {
+    // all positions where the two wires cross, along with the lowest combined distance traveled along both wires
+    // to get there. since all segments are axis-aligned, the overlap of the bounding boxes of two segments is
+    // exactly the set of positions they share: either a single crossing point, or a stretch of overlapping wire
+    // if they're collinear.
+    let segments2 = path2.segments();
+    let mut result: HashMap<Pos, u32> = HashMap::new();
+    for s1 in path1.segments() {
+        let (x1_min, x1_max) = s1.x_range();
+        let (y1_min, y1_max) = s1.y_range();
+        for s2 in &segments2 {
+            let (x2_min, x2_max) = s2.x_range();
+            let (y2_min, y2_max) = s2.y_range();
+            for x in x1_min.max(x2_min)..=x1_max.min(x2_max) {
+                for y in y1_min.max(y2_min)..=y1_max.min(y2_max) {
+                    if (x,y) == (0,0) { continue; } // both wires start here, doesn't count
+                    let dist = s1.dist_to((x,y)) + s2.dist_to((x,y));
+                    let entry = result.entry((x,y)).or_insert(dist);
+                    *entry = (*entry).min(dist); // wires may pass the same position more than once
+                }
             }
-            pos = match step {
-                Step::U(_) => (pos.0, pos.1 + 1),
-                Step::D(_) => (pos.0, pos.1 - 1),
-                Step::R(_) => (pos.0 + 1, pos.1),
-                Step::L(_) => (pos.0 - 1, pos.1),
-            };
-            dist += 1;
         }
     }
+    result.into_iter().collect()
 }
 
-fn closest_intersection_to(point: &Pos,
-                           map: &PathMap)
-    -> Option<(Pos, u32)>
-{
-    map.iter().filter(|(pos,val)| *pos != point && val.len() >= 2)
-              .map(|(&pos,_)| (pos, util::manhattan_distance(*point, pos)))
-              .min_by_key(|&t| t.1)
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Intersection {
+    pub pos: Pos,
+    pub manhattan: u32, // distance from the origin
+    pub steps: u32,     // lowest combined distance traveled along both wires to get here
 }
 
-fn lowest_step_count_from(point: &Pos,
-                          map:   &PathMap,
-                          path1: &Path,
-                          path2: &Path)
-    -> Option<u32>
-{
-    map.iter().filter(|(pos,val)| *pos != point && val.len() >= 2)
-              .map(|(_,val)| val[&path1.id] + val[&path2.id])
-              .min()
+#[derive(Debug)]
+pub struct WireAnalysis {
+    pub intersections: Vec<Intersection>, // ordered by position
+}
+impl WireAnalysis {
+    fn of(path1: &Path, path2: &Path) -> Self {
+        let mut intersections: Vec<Intersection> =
+            intersections(path1, path2).into_iter()
+                                       .map(|(pos, steps)| Intersection { pos, manhattan: util::manhattan_distance((0,0), pos), steps })
+                                       .collect();
+        intersections.sort_by_key(|i| i.pos);
+        Self { intersections }
+    }
+    pub fn closest(&self) -> Option<&Intersection> {
+        // closest intersection to the origin by manhattan distance
+        self.intersections.iter().min_by_key(|i| i.manhattan)
+    }
+    pub fn fewest_steps(&self) -> Option<&Intersection> {
+        self.intersections.iter().min_by_key(|i| i.steps)
+    }
+    pub fn to_json(&self) -> String {
+        let to_json = |i: &Intersection| format!("{{\"x\":{},\"y\":{},\"manhattan\":{},\"steps\":{}}}",
+                                                 i.pos.0, i.pos.1, i.manhattan, i.steps);
+        let or_null = |i: Option<&Intersection>| i.map(to_json).unwrap_or_else(|| String::from("null"));
+        format!("{{\"intersections\":[{}],\"closest\":{},\"fewest_steps\":{}}}",
+                self.intersections.iter().map(to_json).collect::<Vec<_>>().join(","),
+                or_null(self.closest()), or_null(self.fewest_steps()))
+    }
 }
 
-pub fn main() {
-    let lines = util::file_read_lines("input/day3.txt");
-    let path1 = Path::parse(&*lines[0], 1);
-    let path2 = Path::parse(&*lines[1], 2);
+fn parse_paths<S: AsRef<str>>(lines: impl IntoIterator<Item=S>) -> (Path, Path) {
+    // each wire is parsed as soon as its line comes in, so streamed input only ever holds one line of text
+    let mut wires = lines.into_iter().map(|line| Path::parse(line.as_ref().trim()));
+    (wires.next().expect("missing first wire"), wires.next().expect("missing second wire"))
+}
 
-    let mut map = PathMap::new();
-    trace_path(&path1, &mut map);
-    trace_path(&path2, &mut map);
+pub fn main(args: &ArgMatches) {
+    let (path1, path2) = parse_paths(util::stream_lines("input/day3.txt").map(|line| line.unwrap()));
+    let analysis = WireAnalysis::of(&path1, &path2);
+    if args.is_present("json") {
+        println!("{}", analysis.to_json());
+    } else {
+        println!("{}", analysis.closest().unwrap().manhattan);
+        println!("{}", analysis.fewest_steps().unwrap().steps);
+    }
 
-    part1(&map);
-    part2(&map, &path1, &path2);
+    if args.is_present("render") {
+        print!("{}", render_ascii(&path1, &path2, &analysis, 120, 60));
+    }
+    if let Some(filename) = args.value_of("svg") {
+        fs::write(filename, render_svg(&path1, &path2, &analysis)).unwrap();
+    }
 }
 
-fn part1(map: &PathMap) {
-    println!("{}", closest_intersection_to(&(0,0), map).unwrap().1);
+fn bounds(paths: &[&Path]) -> (Pos, Pos) {
+    // (min x, min y), (max x, max y) over all wires, including the origin
+    let mut min = (0,0);
+    let mut max = (0,0);
+    for segment in paths.iter().flat_map(|p| p.segments()) {
+        for pos in &[segment.start, segment.end] {
+            min = (min.0.min(pos.0), min.1.min(pos.1));
+            max = (max.0.max(pos.0), max.1.max(pos.1));
+        }
+    }
+    (min, max)
 }
-fn part2(map: &PathMap, path1: &Path, path2: &Path) {
-    println!("{}", lowest_step_count_from(&(0,0), map, path1, path2).unwrap());
+
+fn render_ascii(path1: &Path, path2: &Path, analysis: &WireAnalysis, max_width: usize, max_height: usize) -> String {
+    // draws both wires scaled down to fit within the given size; '1' and '2' are the wires, '+' where they share
+    // a cell, '*' an intersection, 'X' the closest intersection by manhattan distance, 'S' the one with fewest
+    // steps ('#' if they're the same), and 'o' the origin.
+    let ((min_x, min_y), (max_x, max_y)) = bounds(&[path1, path2]);
+    let span_x = (max_x - min_x + 1) as usize;
+    let span_y = (max_y - min_y + 1) as usize;
+    let scale = (span_x.div_ceil(max_width)).max(span_y.div_ceil(max_height)).max(1);
+    let width = span_x.div_ceil(scale);
+    let height = span_y.div_ceil(scale);
+
+    let cell_of = |pos: Pos| -> (usize, usize) {
+        ((pos.0 - min_x) as usize / scale, (max_y - pos.1) as usize / scale) // y axis points up
+    };
+    let mut grid = vec![vec![' '; width]; height];
+    for (path, c) in &[(path1, '1'), (path2, '2')] {
+        for segment in path.segments() {
+            let len = util::manhattan_distance(segment.start, segment.end) as i32;
+            let dir = ((segment.end.0 - segment.start.0).signum(), (segment.end.1 - segment.start.1).signum());
+            // only visit one position per cell the segment passes through (plus the end point)
+            let positions = (0..len).step_by(scale).chain(std::iter::once(len))
+                                    .map(|k| (segment.start.0 + k*dir.0, segment.start.1 + k*dir.1));
+            for pos in positions {
+                let (x, y) = cell_of(pos);
+                grid[y][x] = if grid[y][x] == ' ' || grid[y][x] == *c { *c } else { '+' };
+            }
+        }
+    }
+
+    let mut mark = |pos: Pos, c: char| {
+        let (x, y) = cell_of(pos);
+        grid[y][x] = c;
+    };
+    for i in &analysis.intersections {
+        mark(i.pos, '*');
+    }
+    match (analysis.closest().map(|i| i.pos), analysis.fewest_steps().map(|i| i.pos)) {
+        (Some(a), Some(b)) if a == b => mark(a, '#'),
+        (a, b) => {
+            if let Some(pos) = a { mark(pos, 'X'); }
+            if let Some(pos) = b { mark(pos, 'S'); }
+        }
+    }
+    mark((0,0), 'o');
+
+    let mut result = format!("scale 1:{}\n", scale);
+    for row in grid {
+        result.push_str(row.iter().collect::<String>().trim_end());
+        result.push('\n');
+    }
+    result
+}
+
+fn render_svg(path1: &Path, path2: &Path, analysis: &WireAnalysis) -> String {
+    let ((min_x, min_y), (max_x, max_y)) = bounds(&[path1, path2]);
+    let size = (max_x - min_x).max(max_y - min_y).max(1) as f64;
+    let stroke = size / 800.0;
+    let margin = size / 50.0;
+
+    // svg's y axis points down, so flip all y coordinates
+    let mut result = format!(concat!("<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"{} {} {} {}\">\n",
+                                     "<rect x=\"{}\" y=\"{}\" width=\"100%\" height=\"100%\" fill=\"white\"/>\n"),
+                             min_x as f64 - margin, -max_y as f64 - margin,
+                             (max_x - min_x) as f64 + 2.0*margin, (max_y - min_y) as f64 + 2.0*margin,
+                             min_x as f64 - margin, -max_y as f64 - margin);
+    for (path, color) in &[(path1, "#1f77b4"), (path2, "#d62728")] {
+        let mut points = vec![String::from("0,0")];
+        points.extend(path.segments().iter().map(|s| format!("{},{}", s.end.0, -s.end.1)));
+        result.push_str(&format!("<polyline points=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"{}\"/>\n",
+                                 points.join(" "), color, stroke));
+    }
+
+    let highlights = [analysis.closest().map(|i| i.pos), analysis.fewest_steps().map(|i| i.pos)];
+    let mut circle = |pos: Pos, radius: f64, color: &str, title: String| {
+        result.push_str(&format!("<circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"{}\"><title>{}</title></circle>\n",
+                                 pos.0, -pos.1, radius, color, title));
+    };
+    circle((0,0), 4.0*stroke, "black", String::from("origin"));
+    for i in &analysis.intersections {
+        let (radius, color) = if highlights.contains(&Some(i.pos)) {
+            (6.0*stroke, "#2ca02c")
+        } else {
+            (3.0*stroke, "black")
+        };
+        circle(i.pos, radius, color, format!("({},{}): manhattan {}, steps {}", i.pos.0, i.pos.1, i.manhattan, i.steps));
+    }
+    result.push_str("</svg>\n");
+    result
 }
 
 #[cfg(test)]
@@ -137,22 +298,89 @@ mod tests {
 
     #[test]
     fn examples() {
-        let p1 = Path::parse("R75,D30,R83,U83,L12,D49,R71,U7,L72",          1);
-        let p2 = Path::parse("U62,R66,U55,R34,D71,R55,D58,R83",             2);
-        let p3 = Path::parse("R98,U47,R26,D63,R33,U87,L62,D20,R33,U53,R51", 3);
-        let p4 = Path::parse("U98,R91,D20,R16,D67,R40,U7,R15,U6,R7",        4);
-
-        let mut map = PathMap::new();
-        trace_path(&p1, &mut map);
-        trace_path(&p2, &mut map);
-        assert_eq!(closest_intersection_to(&(0,0), &map).unwrap().1,        159);
-        assert_eq!(lowest_step_count_from(&(0,0), &map, &p1, &p2).unwrap(), 610);
-
-        let mut map = PathMap::new();
-        trace_path(&p3, &mut map);
-        trace_path(&p4, &mut map);
-        assert_eq!(closest_intersection_to(&(0,0), &map).unwrap().1,        135);
-        assert_eq!(lowest_step_count_from(&(0,0), &map, &p3, &p4).unwrap(), 410);
+        let p1 = Path::parse("R75,D30,R83,U83,L12,D49,R71,U7,L72");
+        let p2 = Path::parse("U62,R66,U55,R34,D71,R55,D58,R83");
+        let p3 = Path::parse("R98,U47,R26,D63,R33,U87,L62,D20,R33,U53,R51");
+        let p4 = Path::parse("U98,R91,D20,R16,D67,R40,U7,R15,U6,R7");
+
+        let analysis = WireAnalysis::of(&p1, &p2);
+        assert_eq!(analysis.closest().unwrap().manhattan, 159);
+        assert_eq!(analysis.fewest_steps().unwrap().steps, 610);
+
+        let analysis = WireAnalysis::of(&p3, &p4);
+        assert_eq!(analysis.closest().unwrap().manhattan, 135);
+        assert_eq!(analysis.fewest_steps().unwrap().steps, 410);
+
+        let p5 = Path::parse("R8,U5,L5,D3");
+        let p6 = Path::parse("U7,R6,D4,L4");
+        let mut isects = intersections(&p5, &p6);
+        isects.sort();
+        assert_eq!(isects, vec![((3,3), 40), ((6,5), 30)]);
+    }
+
+    #[test]
+    fn structured_results() {
+        let (p1, p2) = parse_paths(["R8,U5,L5,D3", "U7,R6,D4,L4"]);
+        let analysis = WireAnalysis::of(&p1, &p2);
+        assert_eq!(analysis.intersections, vec![
+            Intersection { pos: (3,3), manhattan: 6,  steps: 40 },
+            Intersection { pos: (6,5), manhattan: 11, steps: 30 },
+        ]);
+        assert_eq!(analysis.closest().unwrap().pos, (3,3));
+        assert_eq!(analysis.fewest_steps().unwrap().pos, (6,5));
+        assert_eq!(analysis.to_json(), concat!(
+            r#"{"intersections":[{"x":3,"y":3,"manhattan":6,"steps":40},{"x":6,"y":5,"manhattan":11,"steps":30}],"#,
+            r#""closest":{"x":3,"y":3,"manhattan":6,"steps":40},"fewest_steps":{"x":6,"y":5,"manhattan":11,"steps":30}}"#));
+
+        let (p1, p2) = parse_paths(vec![String::from("R2"), String::from("U2")]);
+        let analysis = WireAnalysis::of(&p1, &p2);
+        assert!(analysis.closest().is_none());
+        assert_eq!(analysis.to_json(), r#"{"intersections":[],"closest":null,"fewest_steps":null}"#);
+    }
+
+    #[test]
+    fn rendering() {
+        let p1 = Path::parse("R8,U5,L5,D3");
+        let p2 = Path::parse("U7,R6,D4,L4");
+        let analysis = WireAnalysis::of(&p1, &p2);
+        assert_eq!(render_ascii(&p1, &p2, &analysis, 20, 20), concat!(
+            "scale 1:1\n",
+            "2222222\n",
+            "2     2\n",
+            "2  111S11\n",
+            "2  1  2 1\n",
+            "2 2X222 1\n",
+            "2  1    1\n",
+            "2       1\n",
+            "o11111111\n",
+        ));
+
+        // scaled down to fit
+        let scaled = render_ascii(&p1, &p2, &analysis, 5, 5);
+        assert!(scaled.starts_with("scale 1:2\n"));
+        assert_eq!(scaled.lines().count(), 1 + 4);
+        assert!(scaled.lines().skip(1).all(|line| line.len() <= 5));
+
+        let svg = render_svg(&p1, &p2, &analysis);
+        assert!(svg.starts_with("<svg") && svg.ends_with("</svg>\n"));
+        assert_eq!(svg.matches("<polyline").count(), 2);
+        assert_eq!(svg.matches("<circle").count(), 1 + analysis.intersections.len());
+        assert!(svg.contains("points=\"0,0 8,0 8,-5 3,-5 3,-2\""));
+    }
+
+    #[test]
+    fn overlapping_wires() {
+        // collinear stretches count at every shared position; revisited positions keep the lowest distance
+        let p1 = Path::parse("R10");
+        let p2 = Path::parse("U1,R3,D1,R3,U1");
+        let mut isects = intersections(&p1, &p2);
+        isects.sort();
+        assert_eq!(isects, vec![((3,0), 3 + 5), ((4,0), 4 + 6), ((5,0), 5 + 7), ((6,0), 6 + 8)]);
+
+        let p3 = Path::parse("R2,U3,L1,D5");
+        let mut isects = intersections(&p1, &p3);
+        isects.sort();
+        assert_eq!(isects, vec![((1,0), 1 + 1), ((2,0), 2 + 2)]);
     }
 
 }
@@ -1,50 +1,211 @@
 // vim: set ai et ts=4 sts=4 sw=4:
-pub fn main() {
-    part(1);
-    part(2);
+use crate::util;
+use clap::ArgMatches;
+use std::collections::HashMap;
+use std::ops::Range;
+
+pub fn main(args: &ArgMatches) {
+    let range = 231832..767346;
+    if args.is_present("brute-force") {
+        println!("{}", valid_passwords(range.clone(), &Rules::part1()).count());
+        println!("{}", valid_passwords(range, &Rules::part2()).count());
+    } else {
+        println!("{}", count_valid(range.clone(), GroupCondition::AtLeast(2)));
+        println!("{}", count_valid(range, GroupCondition::Exactly(2)));
+    }
+}
+
+pub trait Rule {
+    // checks a password, given as its sequence of digits
+    fn check(&self, digits: &[u8]) -> bool;
+}
+impl<F: Fn(&[u8]) -> bool> Rule for F {
+    fn check(&self, digits: &[u8]) -> bool {
+        self(digits)
+    }
+}
+
+pub struct NonDecreasing;
+impl Rule for NonDecreasing {
+    fn check(&self, digits: &[u8]) -> bool {
+        digits.windows(2).all(|w| w[0] <= w[1])
+    }
+}
+
+// there must be a run of at least this many equal adjacent digits
+pub struct GroupOfAtLeast(pub usize);
+impl Rule for GroupOfAtLeast {
+    fn check(&self, digits: &[u8]) -> bool {
+        util::digit_groups(digits).iter().any(|&(_, len)| len >= self.0)
+    }
+}
+
+// there must be a run of exactly this many equal adjacent digits (i.e. not part of a larger run)
+pub struct GroupOfExactly(pub usize);
+impl Rule for GroupOfExactly {
+    fn check(&self, digits: &[u8]) -> bool {
+        util::digit_groups(digits).iter().any(|&(_, len)| len == self.0)
+    }
+}
+
+pub struct Rules {
+    rules: Vec<Box<dyn Rule>>,
+}
+impl Rules {
+    pub fn new() -> Self {
+        Self { rules: Vec::new() }
+    }
+    pub fn with<R: Rule + 'static>(mut self, rule: R) -> Self {
+        self.rules.push(Box::new(rule));
+        self
+    }
+    pub fn part1() -> Self {
+        Self::new().with(NonDecreasing).with(GroupOfAtLeast(2))
+    }
+    pub fn part2() -> Self {
+        // if there is an exact pair in the number, then the rule of "no triples or more" goes away
+        Self::new().with(NonDecreasing).with(GroupOfExactly(2))
+    }
+    pub fn check(&self, password: u64) -> bool {
+        let digits = util::to_digits(password);
+        self.rules.iter().all(|rule| rule.check(&digits))
+    }
+}
+
+pub fn valid_passwords(range: Range<u64>, rules: &Rules) -> impl Iterator<Item=u64> + '_ {
+    range.filter(move |&password| rules.check(password))
 }
 
-fn part(num: i32) {
-    let mut result = 0;
-    for i in 231832..767346 {
-        if meets_conditions(i, num == 2) {
-            result += 1;
+// counting passwords without enumerating them: the digit-based rules only depend on a small amount of state
+// as we go through the digits from left to right, so we can count all passwords below some bound with a
+// dynamic programming approach over (position, last digit, length of the current run of equal digits,
+// whether the group condition has been met yet), for passwords with non-decreasing digits.
+
+#[derive(Debug, Clone, Copy)]
+pub enum GroupCondition {
+    AtLeast(usize), // same as GroupOfAtLeast
+    Exactly(usize), // same as GroupOfExactly
+}
+impl GroupCondition {
+    fn run_cap(&self) -> usize {
+        // runs longer than this are all equivalent as far as the condition is concerned
+        match self {
+            GroupCondition::AtLeast(n) => *n,
+            GroupCondition::Exactly(n) => n + 1,
         }
     }
-    println!("{}", result);
-}
-
-#[allow(unused_parens)]
-fn meets_conditions(num: i32, part2: bool) -> bool {
-    let digits = num.to_string().chars()
-                                .map(|x| x.to_string().parse().unwrap())
-                                .collect::<Vec<u32>>();
-
-    let mut has_group = false;
-    let mut has_exact_pair = false;
-
-    let mut i = 0;
-    while i < 6 {
-        let digit = digits[i];
-        // scan forward to find any groupings and to make sure we're going on ascending-or-equal order
-        let mut group_length = 1;
-        let mut k = i+1;
-        while k < 6 {
-            if digits[k] == digit { group_length += 1; }
-            else if digits[k] < digit { return false; }
-            else { break; }
-            k += 1;
+    fn completed_run_meets(&self, run: usize) -> bool {
+        match self {
+            GroupCondition::AtLeast(n) => run >= *n,
+            GroupCondition::Exactly(n) => run == *n,
         }
-        has_group      = (has_group      || group_length >= 2);
-        has_exact_pair = (has_exact_pair || group_length == 2);
+    }
+}
 
-        i = k;
+pub fn count_valid(range: Range<u64>, group: GroupCondition) -> u64 {
+    // amount of passwords in the range with non-decreasing digits and a group of digits satisfying the condition
+    if range.start >= range.end {
+        return 0;
     }
+    count_up_to(range.end - 1, group) - if range.start > 0 { count_up_to(range.start - 1, group) } else { 0 }
+}
 
-    if part2 {
-        // if there is an exact pair in the number, then the rule of "no triples or more" goes away
-        return has_exact_pair;
-    } else {
-        return has_group;
+fn count_up_to(bound: u64, group: GroupCondition) -> u64 {
+    // amount of valid passwords in 0..=bound
+    let mut counter = DigitCounter {
+        bound: util::to_digits(bound),
+        group,
+        memo: HashMap::new(),
+    };
+    counter.count(0, 0, 0, false, true)
+}
+
+struct DigitCounter {
+    bound: Vec<u8>,
+    group: GroupCondition,
+    memo: HashMap<(usize, u8, usize, bool), u64>,
+}
+impl DigitCounter {
+    fn count(&mut self, pos: usize, last: u8, run: usize, met: bool, tight: bool) -> u64 {
+        // amount of ways to fill in the digits from pos onwards. a run of 0 means no digits have been placed yet
+        // other than leading zeroes (which don't count as digits of the password); tight means all digits so far
+        // are equal to those of the bound, so the next one can't exceed the bound's digit.
+        if pos == self.bound.len() {
+            return (run > 0 && (met || self.group.completed_run_meets(run))) as u64;
+        }
+        let key = (pos, last, run, met);
+        if !tight {
+            if let Some(&result) = self.memo.get(&key) {
+                return result;
+            }
+        }
+
+        let max_digit = if tight { self.bound[pos] } else { 9 };
+        let mut result = 0;
+        for d in last..=max_digit {
+            let next_tight = tight && d == max_digit;
+            result += if run == 0 && d == 0 {
+                self.count(pos+1, 0, 0, false, next_tight) // still a leading zero
+            } else if run > 0 && d == last {
+                self.count(pos+1, d, (run+1).min(self.group.run_cap()), met, next_tight)
+            } else {
+                let met = met || (run > 0 && self.group.completed_run_meets(run));
+                self.count(pos+1, d, 1, met, next_tight)
+            };
+        }
+
+        if !tight {
+            self.memo.insert(key, result);
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn examples() {
+        let part1 = Rules::part1();
+        assert!(part1.check(111111));
+        assert!(!part1.check(223450));
+        assert!(!part1.check(123789));
+
+        let part2 = Rules::part2();
+        assert!(part2.check(112233));
+        assert!(!part2.check(123444));
+        assert!(part2.check(111122));
+    }
+
+    #[test]
+    fn counting() {
+        // cross-check against brute force enumeration
+        for range in [0..1000, 0..100_000, 231832..767346, 111..112, 10..11, 500..500, 99_998..1_000_001] {
+            assert_eq!(count_valid(range.clone(), GroupCondition::AtLeast(2)),
+                       valid_passwords(range.clone(), &Rules::part1()).count() as u64, "{:?}", range);
+            assert_eq!(count_valid(range.clone(), GroupCondition::Exactly(2)),
+                       valid_passwords(range.clone(), &Rules::part2()).count() as u64, "{:?}", range);
+            for n in 3..5 {
+                let rules = Rules::new().with(NonDecreasing).with(GroupOfExactly(n));
+                assert_eq!(count_valid(range.clone(), GroupCondition::Exactly(n)),
+                           valid_passwords(range.clone(), &rules).count() as u64, "{:?}", range);
+            }
+        }
+
+        // huge ranges shouldn't be a problem
+        assert!(count_valid(0..u64::MAX, GroupCondition::Exactly(2)) > 0);
+    }
+
+    #[test]
+    fn custom_rules() {
+        let rules = Rules::new().with(NonDecreasing)
+                                .with(GroupOfExactly(3))
+                                .with(|digits: &[u8]| digits.len() == 4);
+        assert_eq!(valid_passwords(0..10_000, &rules).take(4).collect::<Vec<u64>>(),
+                   vec![1112, 1113, 1114, 1115]);
+        assert!(rules.check(2999));
+        assert!(!rules.check(9999));
+        assert!(!rules.check(99999));
     }
 }
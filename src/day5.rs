@@ -1,24 +1,113 @@
 // vim: set ai et ts=4 sts=4 sw=4:
 use crate::util;
-use crate::intcode::CPU;
+use crate::intcode::{CPU, CpuState, Disas, Instruction, Op};
+use clap::ArgMatches;
+use std::collections::VecDeque;
+use std::convert::TryFrom;
 
-pub fn main() {
+pub fn main(args: &ArgMatches) {
     let line: &String = &util::file_read_lines("input/day5.txt")[0];
     let program: Vec<i64> = line.split(",").map(|s| s.parse().unwrap()).collect();
-    part1(&program);
-    part2(&program);
-}
 
-fn part1(program: &Vec<i64>) {
-    let mut cpu = CPU::new(program);
-    cpu.send_input(1);
-    cpu.run();
-    println!("{}", cpu.consume_output_last().unwrap());
+    // system ID 1 is the air conditioner unit (part 1), 5 the thermal radiator controller (part 2)
+    let system_ids: Vec<i64> = match args.value_of("system-id") {
+        Some(id) => vec![id.parse().unwrap_or_else(|_| panic!("invalid system ID: {}", id))],
+        None     => vec![1, 5],
+    };
+    for system_id in system_ids {
+        let mut trace = |line: String| println!("{}", line);
+        let trace: Option<&mut dyn FnMut(String)> = if args.is_present("trace") { Some(&mut trace) } else { None };
+        println!("{}", run_diagnostics(&program, system_id, trace).unwrap_or_else(|err| panic!("{}", err)));
+        if let Some(range) = args.value_of("dump") {
+            let range = util::parse_range(range).unwrap_or_else(|err| panic!("{}", err));
+            let mut cpu = CPU::new(&program);
+            cpu.send_input(system_id).run();
+            print!("{}", cpu.memory().hexdump(range));
+        }
+    }
 }
-fn part2(program: &Vec<i64>) {
+
+const TRACE_CONTEXT: usize = 8; // amount of instructions to show leading up to the output of a failing test
+
+fn run_diagnostics(program: &Vec<i64>, system_id: i64, mut trace: Option<&mut dyn FnMut(String)>)
+    -> Result<i64, String>
+{
+    // runs the TEST program and returns the diagnostic code, which is its last output. all outputs before that
+    // are the results of individual tests, and should all be zero; if one isn't, the error shows the instructions
+    // that were executed leading up to it.
+    // if given, trace is called with a line of disassembly for every instruction executed (along with the value
+    // for inputs and outputs).
     let mut cpu = CPU::new(program);
-    cpu.send_input(5);
-    cpu.run();
-    println!("{}", cpu.consume_output_last().unwrap());
+    cpu.send_input(system_id);
+
+    let mut recent_pcs: VecDeque<usize> = VecDeque::with_capacity(TRACE_CONTEXT);
+    let mut outputs: Vec<(i64, Vec<usize>)> = Vec::new(); // output values along with the recent_pcs at the time
+    cpu.run_traced(|cpu, pc| {
+        if cpu.get_state() == CpuState::WaitIO {
+            return;
+        }
+        if recent_pcs.len() == TRACE_CONTEXT {
+            recent_pcs.pop_front();
+        }
+        recent_pcs.push_back(pc);
+
+        let opcode = Instruction::try_from(cpu.memory()[pc]).map(|instr| instr.opcode());
+        if opcode == Ok(Op::Output) {
+            outputs.push((cpu.peek_output_last().unwrap(), recent_pcs.iter().cloned().collect()));
+        }
+        if let Some(trace) = trace.as_mut() {
+            let mut line = format!("{:-40}", Disas::disassemble_at(cpu.memory(), pc));
+            match opcode {
+                Ok(Op::Input)  => line += &format!(" <- {}", system_id), // the only input the program gets
+                Ok(Op::Output) => line += &format!(" -> {}", cpu.peek_output_last().unwrap()),
+                _              => {},
+            }
+            trace(line.trim_end().to_string());
+        }
+    });
+    if !cpu.is_halted() {
+        return Err(format!("program did not run to completion; state is {}", cpu.get_state()));
+    }
+
+    let (diagnostic_code, _) = outputs.pop().ok_or("program produced no output")?;
+    if let Some((idx, (value, pcs))) = outputs.iter().enumerate().find(|(_, (value, _))| *value != 0) {
+        let listing: Vec<String> = pcs.iter().map(|&pc| format!("  {}", Disas::disassemble_at(cpu.memory(), pc))).collect();
+        return Err(format!("diagnostic test {} failed with output {}; last instructions executed:\n{}",
+                           idx, value, listing.join("\n")));
+    }
+    Ok(diagnostic_code)
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diagnostics() {
+        assert_eq!(run_diagnostics(&vec![104,0,104,0,104,42,99], 1, None), Ok(42));
+        assert_eq!(run_diagnostics(&vec![3,0,4,0,99], 5, None), Ok(5));
+        assert!(run_diagnostics(&vec![99], 1, None).is_err());
+        assert!(run_diagnostics(&vec![3,0,3,0,99], 1, None).is_err()); // waits for a second input
+
+        // second test fails
+        let err = run_diagnostics(&vec![104,0,1101,1,2,13,4,13,104,42,99,0,0,0], 1, None).unwrap_err();
+        assert_eq!(err, concat!("diagnostic test 1 failed with output 3; last instructions executed:\n",
+                                "  000000  OUT    $00\n",
+                                "  000002  ADD    $01, $02, [0D]\n",
+                                "  000006  OUT    [0D]"));
+    }
+
+    #[test]
+    fn tracing() {
+        let mut lines = Vec::new();
+        let mut collect = |line: String| lines.push(line);
+        let result = run_diagnostics(&vec![3,9,1002,9,3,9,4,9,99,0], 7, Some(&mut collect));
+        assert_eq!(result, Ok(21));
+        assert_eq!(lines, vec![
+            "000000  IN     [09]                      <- 7",
+            "000002  MUL    [09], $03, [09]",
+            "000006  OUT    [09]                      -> 21",
+            "000008  HLT",
+        ]);
+    }
+}
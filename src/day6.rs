@@ -1,54 +1,178 @@
 // vim: set ai et ts=4 sts=4 sw=4:
 use crate::util;
+use crate::tree::NodeRef;
+use clap::ArgMatches;
 use std::collections::{HashMap, HashSet};
+use std::fs;
 
-pub fn main() {
+pub fn main(args: &ArgMatches) {
     let lines = util::file_read_lines("input/day6.txt");
-    let mut data = Vec::<(String,String)>::new();
-    for line in lines {
-        let parts = line.split(")").collect::<Vec<_>>();
-        data.push((parts[0].to_string(), parts[1].to_string()));
-    }
-    let mut parents = HashMap::<String, String>::new(); // maps a node name to its parent node name
-    for (parent, child) in data {
-        parents.insert(child.to_string(), parent.to_string());
+    let map = OrbitMap::parse(&lines);
+    println!("{}", part1(&map));
+    println!("{}", part2(&map));
+
+    if let Some(filename) = args.value_of("dot") {
+        fs::write(filename, map.to_dot()).unwrap();
     }
-    println!("{}", part1(&parents));
-    println!("{}", part2(&parents));
 }
 
-fn get_path(node: &String, parents: &HashMap<String, String>) -> Vec<String> {
-    let mut result = vec![node.to_string()];
-    let mut current_node: &String = node;
-    while parents.contains_key(current_node) {
-        let parent = parents.get(current_node).unwrap();
-        result.push(parent.to_string());
-        current_node = parent;
+pub struct OrbitMap {
+    pub root: NodeRef<String>,
+    nodes: HashMap<String, NodeRef<String>>, // by name
+}
+impl OrbitMap {
+    pub fn parse(lines: &[String]) -> Self {
+        // each line "A)B" means that B orbits A, i.e. B is a child of A in the tree
+        let mut nodes = HashMap::<String, NodeRef<String>>::new();
+        for line in lines {
+            let parts = line.split(")").collect::<Vec<_>>();
+            let parent = nodes.entry(parts[0].to_string()).or_insert_with(|| NodeRef::new(parts[0].to_string())).clone();
+            let child  = nodes.entry(parts[1].to_string()).or_insert_with(|| NodeRef::new(parts[1].to_string())).clone();
+            parent.add_child(&child);
+        }
+        let roots: Vec<&NodeRef<String>> = nodes.values().filter(|n| n.parent().is_none()).collect();
+        if roots.len() != 1 {
+            panic!("expected a single root object, found {}", roots.len());
+        }
+        Self {
+            root: roots[0].clone(),
+            nodes,
+        }
+    }
+    pub fn get(&self, name: &str) -> Option<&NodeRef<String>> {
+        self.nodes.get(name)
     }
-    return result;
 }
 
-fn part1(parents: &HashMap<String, String>) -> usize {
-    let mut result = 0;
-    for node in parents.keys() {
-        result += get_path(node, &parents).len()-1; // -1 because the path includes the node itself
+impl OrbitMap {
+    pub fn depths(&self) -> HashMap<String, usize> {
+        // distance of every object to the root (by name), in a single pass over the tree
+        let mut result = HashMap::with_capacity(self.nodes.len());
+        let mut stack = vec![(self.root.clone(), 0)];
+        while let Some((node, depth)) = stack.pop() {
+            stack.extend(node.children().map(|child| (child, depth+1)));
+            result.insert(node.borrow_data().clone(), depth);
+        }
+        result
+    }
+    pub fn closest_common_ancestor(&self, a: &NodeRef<String>, b: &NodeRef<String>,
+                                   depths: &HashMap<String, usize>) -> NodeRef<String>
+    {
+        // bring both nodes to the same depth, then move them up in lockstep until they meet
+        let depth = |node: &NodeRef<String>| depths[&*node.borrow_data()];
+        let (mut a, mut b) = (a.clone(), b.clone());
+        while depth(&a) > depth(&b) { a = a.parent().unwrap(); }
+        while depth(&b) > depth(&a) { b = b.parent().unwrap(); }
+        while a != b {
+            a = a.parent().unwrap();
+            b = b.parent().unwrap();
+        }
+        a
+    }
+    pub fn transfer_route(&self, from: &str, to: &str) -> Vec<NodeRef<String>> {
+        // the objects passed through when transferring from the object that `from` orbits to the one `to` orbits,
+        // going through their closest common ancestor (including both ends)
+        let from = self.get(from).unwrap().parent().unwrap();
+        let to = self.get(to).unwrap().parent().unwrap();
+        let common = self.closest_common_ancestor(&from, &to, &self.depths());
+
+        let up_to_common = |node: &NodeRef<String>| -> Vec<NodeRef<String>> {
+            let mut path: Vec<NodeRef<String>> = std::iter::once(node.clone()).chain(node.ancestors())
+                                                                               .take_while(|n| *n != common)
+                                                                               .collect();
+            path.push(common.clone());
+            path
+        };
+        let mut result = up_to_common(&from);
+        let mut down = up_to_common(&to);
+        down.pop(); // common ancestor is already in there
+        result.extend(down.into_iter().rev());
+        result
+    }
+    pub fn to_dot(&self) -> String {
+        // the orbit map as a Graphviz graph, with the transfer route from YOU to SAN highlighted (if both are present)
+        let route: Vec<NodeRef<String>> = match (self.get("YOU"), self.get("SAN")) {
+            (Some(_), Some(_)) => self.transfer_route("YOU", "SAN"),
+            _                  => Vec::new(),
+        };
+        let on_route: HashSet<String> = route.iter().map(|n| n.borrow_data().clone()).collect();
+        let is_end = |node: &NodeRef<String>| *node.borrow_data() == "YOU" || *node.borrow_data() == "SAN";
+
+        // the route goes up to the common ancestor and back down, so the edge leading into the common ancestor
+        // (i.e. the highest one on the route) is not part of it
+        let highest = route.iter().min_by_key(|n| n.ancestors().count()).cloned();
+        self.root.to_dot(|name| name.clone(),
+                         |node| if is_end(node) {
+                                    Some(String::from("color=blue, style=filled, fontcolor=white"))
+                                } else if on_route.contains(&*node.borrow_data()) {
+                                    Some(String::from("color=red, penwidth=2"))
+                                } else {
+                                    None
+                                },
+                         |node| if is_end(node) {
+                                    Some(String::from("color=blue"))
+                                } else if on_route.contains(&*node.borrow_data()) && Some(node) != highest.as_ref() {
+                                    Some(String::from("color=red, penwidth=2"))
+                                } else {
+                                    None
+                                })
     }
-    result
 }
 
-fn part2(parents: &HashMap<String, String>) -> usize {
-    let you_parent: &String = parents.get(&"YOU".to_string()).unwrap();
-    let san_parent: &String = parents.get(&"SAN".to_string()).unwrap();
-    let you_parent_path: HashSet<String> = get_path(you_parent, &parents).into_iter().collect();
-    let san_parent_path: HashSet<String> = get_path(san_parent, &parents).into_iter().collect();
-
-    // find common nodes between the two paths, and find the one with the longest distance
-    // (i.e. the one that's closest to both YOU and SAN)
-    let common = you_parent_path.intersection(&san_parent_path);
-    let mut common: Vec<String> = common.into_iter().map(|s| s.to_string()).collect();
-    common.sort_by_key(|node| get_path(node, parents).len());
-
-    let result = (you_parent_path.len() - common.len()) +
-                 (san_parent_path.len() - common.len());
-    result
+fn part1(map: &OrbitMap) -> usize {
+    // every object orbits each of its ancestors, directly or indirectly, so the total amount of orbits is just
+    // the sum of the depths of all objects
+    map.depths().values().sum()
+}
+
+fn part2(map: &OrbitMap) -> usize {
+    // amount of orbital transfers needed to get from the object YOU orbit to the object SAN orbits; that's
+    // going up from one to their closest common ancestor, and back down to the other.
+    map.transfer_route("YOU", "SAN").len() - 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> OrbitMap {
+        OrbitMap::parse(&s.split_whitespace().map(String::from).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn examples() {
+        let map = parse("COM)B B)C C)D D)E E)F B)G G)H D)I E)J J)K K)L");
+        assert_eq!(*map.root.borrow_data(), "COM");
+        assert_eq!(part1(&map), 42);
+
+        let map = parse("COM)B B)C C)D D)E E)F B)G G)H D)I E)J J)K K)L K)YOU I)SAN");
+        assert_eq!(part2(&map), 4);
+        let route: Vec<String> = map.transfer_route("YOU", "SAN").iter().map(|n| n.borrow_data().clone()).collect();
+        assert_eq!(route, vec!["K", "J", "E", "D", "I"]);
+    }
+
+    #[test]
+    fn dot() {
+        let map = parse("COM)B B)C C)YOU B)SAN");
+        let dot = map.to_dot();
+        assert!(dot.starts_with("digraph {\n"));
+        assert_eq!(dot.matches("->").count(), 4);
+        assert_eq!(dot.matches("color=red").count(), 2 + 1); // B, C, and the edge B -> C (not the edge into B)
+        assert_eq!(dot.matches("color=blue").count(), 2 * 2); // YOU and SAN, and the edges into them
+    }
+
+    #[test]
+    fn deep_chain() {
+        // a single long chain, where computing each object's path to the root separately would be quadratic.
+        // (not too long though, dropping the tree recurses through the chain)
+        let n = 10_000;
+        let mut lines: Vec<String> = (1..n).map(|i| format!("N{})N{}", i-1, i)).collect();
+        lines.push(format!("N{})YOU", n/2));
+        lines.push(format!("N{})SAN", n-1));
+        let map = OrbitMap::parse(&lines);
+
+        // the chain itself has 0 + 1 + ... + (n-1) orbits, plus YOU and SAN at depths n/2+1 and n
+        assert_eq!(part1(&map), (n-1)*n/2 + (n/2 + 1) + n);
+        assert_eq!(part2(&map), (n-1) - n/2);
+    }
 }
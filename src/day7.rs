@@ -1,64 +1,151 @@
 // vim: set ai et ts=4 sts=4 sw=4:
 use crate::util;
 use crate::intcode::{CPU};
+use clap::ArgMatches;
 use std::cmp::max;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
 use permutohedron;
 
-pub fn main() {
+pub fn main(args: &ArgMatches) {
     let line: &String = &util::file_read_lines("input/day7.txt")[0];
     let program: Vec<i64> = line.split(",").map(|s| s.parse().unwrap()).collect();
-    println!("{}", part(1, &program));
-    println!("{}", part(2, &program));
+    let wiring = match args.value_of("wiring") {
+        Some(spec) => Wiring::parse(spec).unwrap_or_else(|err| panic!("invalid wiring: {}", err)),
+        None       => Wiring::ring(5).unwrap(),
+    };
+    println!("{}", part(1, &program, &wiring));
+    println!("{}", part(2, &program, &wiring));
 }
 
-fn part(part_nr: u32, program: &Vec<i64>) -> i64 {
-    let mut phases = if part_nr == 2 { vec![5,6,7,8,9] } else { vec![0,1,2,3,4] };
+fn part(part_nr: u32, program: &Vec<i64>, wiring: &Wiring) -> i64 {
+    // in part 2, the phase settings put the amplifiers in feedback loop mode
+    let phases: Vec<i64> = if part_nr == 2 { (5..10).collect() } else { (0..5).collect() };
+    max_thruster_signal(program, wiring, &phases).expect("no amplifier configuration produced a thruster signal")
+}
+
+// describes which amplifiers feed their output into which others. the initial input signal goes into the input
+// amplifier, and the thruster signal is the last value produced by the output amplifier. an amplifier that feeds
+// several others sends each of its output values to all of them; an amplifier fed by several others gets their
+// output values interleaved in whatever order they happen to arrive in.
+// note that a feedback loop in which every amplifier is waiting for input from the next one will simply hang.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Wiring {
+    amps: usize,
+    edges: Vec<(usize, usize)>,
+    input: usize,
+    output: usize,
+}
+impl Wiring {
+    pub fn new(amps: usize, edges: Vec<(usize, usize)>, input: usize, output: usize) -> Result<Self, String> {
+        if amps == 0 {
+            return Err("need at least one amplifier".to_string());
+        }
+        for &amp in [input, output].iter().chain(edges.iter().flat_map(|(from, to)| [from, to])) {
+            if amp >= amps {
+                return Err(format!("no such amplifier: {} (have {})", amp, amps));
+            }
+        }
+
+        // amplifiers that never get any signal would just sit there waiting for it forever
+        let mut reached = vec![false; amps];
+        let mut stack = vec![input];
+        reached[input] = true;
+        while let Some(amp) = stack.pop() {
+            for &(_, to) in edges.iter().filter(|&&(from, _)| from == amp) {
+                if !reached[to] {
+                    reached[to] = true;
+                    stack.push(to);
+                }
+            }
+        }
+        if let Some(amp) = reached.iter().position(|&r| !r) {
+            return Err(format!("amplifier {} is not reachable from input amplifier {}", amp, input));
+        }
+
+        Ok(Self { amps, edges, input, output })
+    }
+    pub fn ring(amps: usize) -> Result<Self, String> {
+        // the puzzle's wiring: a linear chain, with the output of the last amplifier fed back into the first
+        let edges = (0..amps).map(|i| (i, (i+1) % amps)).collect();
+        Self::new(amps, edges, 0, amps.saturating_sub(1))
+    }
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        // comma-separated list of connections like "0->1,0->2,1->3,2->3,3->0". the input signal goes into
+        // amplifier 0, and the thruster signal is taken from the highest-numbered one.
+        let mut edges = Vec::new();
+        for conn in spec.split(',').map(str::trim) {
+            let (from, to) = conn.split_once("->").ok_or_else(|| format!("expected from->to, got '{}'", conn))?;
+            let parse_amp = |s: &str| s.trim().parse::<usize>().map_err(|_| format!("invalid amplifier number: '{}'", s));
+            edges.push((parse_amp(from)?, parse_amp(to)?));
+        }
+        let amps = edges.iter().map(|&(from, to)| max(from, to)).max().unwrap() + 1;
+        Self::new(amps, edges, 0, amps-1)
+    }
+    pub fn len(&self) -> usize {
+        self.amps
+    }
+}
+
+fn max_thruster_signal(program: &Vec<i64>, wiring: &Wiring, phases: &[i64]) -> Option<i64> {
+    // tries all assignments of the given phase values to the amplifiers (each value used once)
+    assert_eq!(phases.len(), wiring.len(), "need exactly one phase value per amplifier");
+    let mut phases = phases.to_vec();
     let mut max_output: Option<i64> = None;
     permutohedron::heap_recursive(
         &mut phases,
-        |perm| { let output = run_amplifier_chain(program, &perm.to_vec(), part_nr == 2);
-                 max_output = match max_output {
-                     None    => Some(output),
-                     Some(x) => Some(max(x, output)),
-                 };
+        |perm| { if let Some(output) = run_amplifiers(program, wiring, perm) {
+                     max_output = Some(max_output.map_or(output, |x| max(x, output)));
+                 }
                }
     );
-    max_output.unwrap()
+    max_output
 }
 
-fn run_amplifier_chain(program: &Vec<i64>, phase_settings: &Vec<u32>, _part2: bool) -> i64 {
-    let mut amp0 = CPU::new(program);
-    let mut amp1 = CPU::new(program);
-    let mut amp2 = CPU::new(program);
-    let mut amp3 = CPU::new(program);
-    let mut amp4 = CPU::new(program);
-    amp0.send_input(phase_settings[0] as i64);
-    amp1.send_input(phase_settings[1] as i64);
-    amp2.send_input(phase_settings[2] as i64);
-    amp3.send_input(phase_settings[3] as i64);
-    amp4.send_input(phase_settings[4] as i64);
-
-    amp0.send_input(0);
-
-    // works for both part1 and part2; in part1, the CPUs all exit after the first loop, in part2 they continue
-    let mut last_output: Option<i64> = None;
-    loop {
-        amp0.run();
-        amp1.run();
-        amp2.run();
-        amp3.run();
-        amp4.run();
-        if let Some(x) = amp0.consume_output() { amp1.send_input(x); }
-        if let Some(x) = amp1.consume_output() { amp2.send_input(x); }
-        if let Some(x) = amp2.consume_output() { amp3.send_input(x); }
-        if let Some(x) = amp3.consume_output() { amp4.send_input(x); }
-        if let Some(x) = amp4.consume_output() { amp0.send_input(x); last_output = Some(x); }
-
-        if amp0.is_halted() && amp1.is_halted() && amp2.is_halted() && amp3.is_halted() && amp4.is_halted() {
-            break;
-        }
+fn run_amplifiers(program: &Vec<i64>, wiring: &Wiring, phase_settings: &[i64]) -> Option<i64> {
+    // runs the amplifiers (one per phase setting) connected according to the given wiring. each amplifier runs on
+    // its own thread, reading its input from a channel and simply blocking until one of the amplifiers feeding it
+    // produces something. its output goes to a separate thread that passes each value along to every amplifier it
+    // feeds, and to us if it's the output amplifier, so we can keep track of the last value it produced.
+    //
+    // in part1, the amplifiers all exit after their first output, in part2 they continue until they halt. either
+    // way, once an amplifier is done, the channels it was writing to get closed; an amplifier that's still waiting
+    // for input once everything feeding it is done gives up.
+    let (senders, receivers): (Vec<Sender<i64>>, Vec<Receiver<i64>>) = (0..wiring.len()).map(|_| channel()).unzip();
+    let (result_tx, result_rx) = channel();
+
+    for (sender, &phase) in senders.iter().zip(phase_settings) {
+        sender.send(phase).unwrap();
     }
-    last_output.unwrap()
+    senders[wiring.input].send(0).unwrap();
+
+    thread::scope(|scope| {
+        for (i, input) in receivers.into_iter().enumerate() {
+            let (output, forward_rx) = channel();
+            scope.spawn(move || {
+                CPU::new(program).run_with_channels(&input, &output);
+            });
+
+            let mut targets: Vec<Sender<i64>> = wiring.edges.iter()
+                                                            .filter(|&&(from, _)| from == i)
+                                                            .map(|&(_, to)| senders[to].clone())
+                                                            .collect();
+            if i == wiring.output {
+                targets.push(result_tx.clone());
+            }
+            scope.spawn(move || {
+                for x in forward_rx {
+                    for target in &targets {
+                        target.send(x).ok(); // amplifier may already have halted
+                    }
+                }
+            });
+        }
+        drop(senders);
+        drop(result_tx);
+
+        result_rx.into_iter().last()
+    })
 }
 
 #[cfg(test)]
@@ -67,16 +154,58 @@ mod tests {
 
     #[test]
     fn examples() {
-        assert_eq!(part(1, &vec![3,15,3,16,1002,16,10,16,1,16,15,15,4,15,99,0,0]),   43210);
+        let ring = Wiring::ring(5).unwrap();
+        assert_eq!(part(1, &vec![3,15,3,16,1002,16,10,16,1,16,15,15,4,15,99,0,0], &ring),   43210);
         assert_eq!(part(1, &vec![3,23,3,24,1002,24,10,24,1002,23,-1,23,
-                                 101,5,23,23,1,24,23,23,4,23,99,0,0]),               54321);
+                                 101,5,23,23,1,24,23,23,4,23,99,0,0], &ring),               54321);
         assert_eq!(part(1, &vec![3,31,3,32,1002,32,10,32,1001,31,-2,31,1007,31,0,33,
-                                 1002,33,7,33,1,33,31,31,1,32,31,31,4,31,99,0,0,0]), 65210);
+                                 1002,33,7,33,1,33,31,31,1,32,31,31,4,31,99,0,0,0], &ring), 65210);
 
         assert_eq!(part(2, &vec![3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,
-                                 27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5]),         139629729);
+                                 27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5], &ring),         139629729);
         assert_eq!(part(2, &vec![3,52,1001,52,-5,52,3,53,1,52,56,54,1007,54,5,55,1005,55,26,1001,54,
                                  -5,54,1105,1,12,1,53,54,53,1008,54,0,55,1001,55,1,55,2,53,55,53,4,
-                                 53,1001,56,-1,56,1005,56,6,99,0,0,0,0,10]),         18216);
+                                 53,1001,56,-1,56,1005,56,6,99,0,0,0,0,10], &ring),         18216);
+    }
+
+    #[test]
+    fn chain_lengths() {
+        // adds its phase to its input; a chain of n amplifiers just sums all phases, regardless of order
+        let add_phase = vec![3,11,3,12,1,11,12,13,4,13,99,0,0,0];
+        assert_eq!(run_amplifiers(&add_phase, &Wiring::ring(1).unwrap(), &[7]), Some(7));
+        assert_eq!(max_thruster_signal(&add_phase, &Wiring::ring(3).unwrap(), &[1,2,3]), Some(6));
+        assert_eq!(max_thruster_signal(&add_phase, &Wiring::ring(7).unwrap(), &[0,10,20,30,40,50,60]), Some(210));
+
+        // multiplies its input by 10 before adding its phase, so the best order puts the largest phases first
+        let shift_in_phase = vec![3,15,3,16,1002,16,10,16,1,15,16,17,4,17,99,0,0,0];
+        assert_eq!(max_thruster_signal(&shift_in_phase, &Wiring::ring(4).unwrap(), &[1,2,3,4]), Some(4321));
+    }
+
+    #[test]
+    fn topologies() {
+        // starts out with its phase and keeps adding every input to it, outputting the running total each time.
+        // never halts, so it only stops once everything feeding it is done.
+        let mut running_sum = vec![3,20,3,21,1,20,21,20,4,20,1105,1,2];
+        running_sum.resize(22, 0);
+
+        // diamond: the last amplifier gets the first one's phase twice, once through each branch
+        let diamond = Wiring::parse("0->1, 0->2, 1->3, 2->3").unwrap();
+        assert_eq!(run_amplifiers(&running_sum, &diamond, &[1,2,3,4]), Some(4 + (2 + 1) + (3 + 1)));
+        assert_eq!(max_thruster_signal(&running_sum, &diamond, &[1,2,3,4]), Some(2*4 + 1 + 2 + 3));
+
+        // output taken from the middle of a chain
+        let tap = Wiring::new(3, vec![(0,1),(1,2)], 0, 1).unwrap();
+        assert_eq!(run_amplifiers(&running_sum, &tap, &[1,10,100]), Some(11));
+
+        // the output amplifier never gets anything, so there's no thruster signal either
+        let swallow = vec![3,0,3,0,99];
+        assert_eq!(run_amplifiers(&swallow, &Wiring::ring(2).unwrap(), &[1,2]), None);
+
+        assert_eq!(Wiring::parse("0->1,1->2,2->0").unwrap(), Wiring::ring(3).unwrap());
+        assert_eq!(Wiring::ring(0), Err("need at least one amplifier".to_string()));
+        assert!(Wiring::parse("0->1,2->1").is_err()); // amplifier 2 never gets a signal
+        assert!(Wiring::parse("0-1").is_err());
+        assert!(Wiring::parse("0->x").is_err());
+        assert!(Wiring::new(2, vec![(0,2)], 0, 1).is_err());
     }
 }
@@ -1,99 +1,203 @@
 // vim: set ai et ts=4 sts=4 sw=4:
 use crate::util;
-use std::ops::{Index, IndexMut};
+use crate::ocr;
+use crate::image::{Image, Layer, BLACK, WHITE, TRANSPARENT};
+use clap::ArgMatches;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
 
-pub fn main() {
+pub fn main(args: &ArgMatches) {
     let line: String = util::file_read_lines("input/day8.txt").into_iter().next().unwrap();
-    let data: Vec<u32> = line.chars().map(|c| c.to_string().parse().unwrap()).collect();
-    part1(&data);
-    part2(&data);
-}
+    let data: Vec<u32> = util::parse_digits(&line);
+    let (width, height) = match (args.value_of("width"), args.value_of("height")) {
+        (Some(w), Some(h)) => (w.parse().unwrap_or_else(|_| panic!("invalid width: {}", w)),
+                               h.parse().unwrap_or_else(|_| panic!("invalid height: {}", h))),
+        (None, None)       => detect_dimensions(&data).unwrap_or_else(|err| panic!("{}", err)),
+        _                  => panic!("--width and --height must be given together"),
+    };
+    if !data.len().is_multiple_of(width * height) {
+        panic!("image data of length {} doesn't divide into {}x{} layers", data.len(), width, height);
+    }
+    println!("{}", part1(&data, width, height));
 
-#[allow(unused)]
-struct Layer {
-    order: u32,
-    width: usize,
-    height: usize,
-    data: Vec<u32>,
+    let layer = part2(&data, width, height);
+    if args.is_present("verbose") {
+        print!("{}", render(&layer));
+    }
+    if let Some(filename) = args.value_of("image") {
+        let scale = args.value_of("scale").map_or(10, |s| s.parse().unwrap_or_else(|_| panic!("invalid scale: {}", s)));
+        write_image_file(&layer, scale, filename)
+            .unwrap_or_else(|err| panic!("failed to write {}: {}", filename, err));
+    }
+    println!("{}", ocr::decode_layer(&layer).unwrap_or_else(|err| panic!("can't read the image: {}", err)));
 }
-impl Layer {
-    pub fn new(order: u32, w: usize, h: usize, data: &[u32]) -> Self {
-        Self {
-            order,
-            width: w,
-            height: h,
-            data: data.to_vec(),
+
+// the letters in the puzzle images are drawn in the font that ocr reads, as (height, width of a letter including
+// the blank space that separates it from the next one)
+const GLYPH_CELLS: [(usize, usize); 1] = [(ocr::GLYPH_HEIGHT, ocr::GLYPH_CELL_WIDTH)];
+
+fn detect_dimensions(data: &[u32]) -> Result<(usize, usize), String> {
+    // tries all sizes that fit a whole number of letters in one of the known fonts, and that the data splits
+    // into a whole number of layers of. to tell which one is right, we rely on the image being readable: after
+    // flattening, there must be no transparent pixels left and the space between the letters must be empty.
+    // that also holds when cutting the real image up into narrower strips of whole letters and treating those as
+    // layers, so sizes that are such a strip of another candidate are discarded.
+    let mut candidates = Vec::new();
+    for &(height, cell_width) in GLYPH_CELLS.iter() {
+        for width in (cell_width..).step_by(cell_width).take_while(|w| w * height <= data.len()) {
+            if !data.len().is_multiple_of(width * height) {
+                continue;
+            }
+            let layer = Image::new(width, height, data).composite();
+            let resolved = layer.count(TRANSPARENT) == 0;
+            let spaced = (cell_width-1..width).step_by(cell_width)
+                                              .all(|x| (0..height).all(|y| layer[(x,y)] == BLACK));
+            if resolved && spaced {
+                candidates.push((width, height));
+            }
         }
     }
-}
-impl Index<(usize,usize)> for Layer {
-    type Output = u32;
-    fn index(&self, idx: (usize,usize)) -> &Self::Output { // x,y
-        &self.data[idx.1*self.width + idx.0]
+    let candidates: Vec<(usize, usize)> = candidates.iter()
+        .filter(|&&(w, h)| !candidates.iter().any(|&(w2, h2)| h2 == h && w2 > w && w2 % w == 0))
+        .cloned()
+        .collect();
+    match candidates.len() {
+        1 => Ok(candidates[0]),
+        0 => Err(format!("can't detect image dimensions for data of length {}; use --width and --height", data.len())),
+        _ => Err(format!("ambiguous image dimensions {:?}; use --width and --height", candidates)),
     }
 }
-impl IndexMut<(usize,usize)> for Layer {
-    fn index_mut(&mut self, idx: (usize,usize)) -> &mut Self::Output {
-        &mut self.data[idx.1*self.width + idx.0]
-    }
+
+fn part1(data: &[u32], width: usize, height: usize) -> usize {
+    // look at the layer with the fewest black pixels
+    let img = Image::new(width, height, data);
+    let stats = img.layers.iter().min_by_key(|layer| layer.count(BLACK)).unwrap().stats();
+    stats.white * stats.transparent
 }
-struct Image {
-    pub width: usize,
-    pub height: usize,
-    pub layers: Vec<Layer>,
+fn part2(data: &[u32], width: usize, height: usize) -> Layer {
+    Image::new(width, height, data).composite()
 }
-impl Image {
-    pub fn new(w: usize, h: usize, data: &Vec<u32>) -> Self {
-        Self {
-            width: w,
-            height: h,
-            layers: data.chunks((w * h) as usize)
-                        .enumerate()
-                        .map(|(n,chunk)| Layer::new(n as u32, w, h, &chunk))
-                        .collect()
+
+fn render(layer: &Layer) -> String {
+    let mut result = String::new();
+    for row in layer.rows() {
+        for &pixel in row {
+            result.push_str(match pixel {
+                BLACK       => " ",
+                WHITE       => "x",
+                TRANSPARENT => "?",
+                _           => panic!("invalid pixel value: {}", pixel),
+            });
         }
+        result.push('\n');
     }
-    pub fn flatten_layers(&mut self) {
-        let mut output_layer = Layer::new(0, self.width, self.height, &vec![2; (self.width*self.height) as usize]);
-        for y in 0..self.height {
-            for x in 0..self.width {
-                // look through the pixels from the top layer to the bottom one, and return the first one that's
-                // either black or white.
-                for layer in &self.layers {
-                    let pixel = layer[(x,y)];
-                    if pixel == 2 { continue; }
-                    output_layer[(x,y)] = pixel;
-                    break;
-                }
-            }
-        }
-        self.layers = vec![output_layer];
+    result
+}
+
+fn write_image_file(layer: &Layer, scale: usize, filename: &str) -> io::Result<()> {
+    // picks the format from the file extension
+    let out = BufWriter::new(File::create(filename)?);
+    if filename.ends_with(".png") {
+        write_png(layer, scale, out)
+    } else if filename.ends_with(".pbm") {
+        write_pbm(layer, scale, out)
+    } else {
+        Err(io::Error::new(io::ErrorKind::InvalidInput, "unsupported image format, expected .png or .pbm"))
     }
 }
 
-fn part1(data: &Vec<u32>) {
-    let mut img = Image::new(25, 6, data);
+fn scaled_pixels(layer: &Layer, scale: usize) -> impl Iterator<Item=u32> + '_ {
+    // each pixel of the layer blown up to a scale x scale square, row by row
+    (0..layer.height()*scale).flat_map(move |y| (0..layer.width()*scale).map(move |x| layer[(x/scale, y/scale)]))
+}
 
-    // sort by amount of 0 digits in the layers
-    img.layers.sort_by_key(|ly| ly.data.iter().filter(|&&d| d == 0).count());
-    let layer = &img.layers[0];
-    let count1 = layer.data.iter().filter(|&&d| d==1).count();
-    let count2 = layer.data.iter().filter(|&&d| d==2).count();
-    println!("{}", count1*count2);
+fn write_png<W: Write>(layer: &Layer, scale: usize, out: W) -> io::Result<()> {
+    // black and white pixels as such, transparent ones as transparent
+    let mut encoder = png::Encoder::new(out, (layer.width()*scale) as u32, (layer.height()*scale) as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let data: Vec<u8> = scaled_pixels(layer, scale).flat_map(|p| match p {
+        BLACK => [0, 0, 0, 255],
+        WHITE => [255, 255, 255, 255],
+        _     => [0, 0, 0, 0],
+    }).collect();
+    encoder.write_header()?.write_image_data(&data)?;
+    Ok(())
 }
-fn part2(data: &Vec<u32>) {
-    let mut img = Image::new(25, 6, data);
-    img.flatten_layers();
-
-    for y in 0..img.height {
-        for x in 0..img.width {
-            print!("{}", match img.layers[0][(x,y)] {
-                0 => " ",
-                1 => "x",
-                2 => "?",
-                _ => panic!(""),
-            });
+
+fn write_pbm<W: Write>(layer: &Layer, scale: usize, mut out: W) -> io::Result<()> {
+    // plain (ASCII) PBM; that format only has black and white, so transparent pixels are left white like the
+    // paper they would be drawn on. note that 1 means black in PBM.
+    let width = layer.width()*scale;
+    writeln!(out, "P1")?;
+    writeln!(out, "{} {}", width, layer.height()*scale)?;
+    let pixels: Vec<u32> = scaled_pixels(layer, scale).collect();
+    for row in pixels.chunks(width) {
+        let row: Vec<&str> = row.iter().map(|&p| if p == BLACK { "1" } else { "0" }).collect();
+        writeln!(out, "{}", row.join(" "))?;
+    }
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layered(art: &[&str], layers: usize) -> Vec<u32> {
+        // spreads the pixels of the given art over a number of layers, with each pixel showing up on a different
+        // layer depending on its position and being transparent on the layers before it
+        let pixels: Vec<u32> = art.iter().flat_map(|row| row.chars().map(|c| (c == '#') as u32)).collect();
+        let mut data = Vec::new();
+        for n in 0..layers {
+            data.extend(pixels.iter().enumerate().map(|(i, &p)| match (i % layers).cmp(&n) {
+                std::cmp::Ordering::Greater => 2,
+                std::cmp::Ordering::Equal   => p,
+                std::cmp::Ordering::Less    => 1 - p, // hidden by the pixel above it
+            }));
         }
-        println!("");
+        data
+    }
+
+    #[test]
+    fn examples() {
+        let data = util::parse_digits("123456789012");
+        assert_eq!(part1(&data, 3, 2), 1);
+        assert_eq!(part2(&util::parse_digits("0222112222120000"), 2, 2).data(), &[0,1,1,0]);
+    }
+
+    #[test]
+    fn image_export() {
+        let layer = Layer::new(0, 3, 1, &[0, 1, 2]);
+
+        let mut pbm = Vec::new();
+        write_pbm(&layer, 2, &mut pbm).unwrap();
+        assert_eq!(String::from_utf8(pbm).unwrap(), "P1\n6 2\n1 1 0 0 0 0\n1 1 0 0 0 0\n");
+
+        let mut png_data = Vec::new();
+        write_png(&layer, 2, &mut png_data).unwrap();
+        let mut reader = png::Decoder::new(io::Cursor::new(png_data)).read_info().unwrap();
+        let mut buf = vec![0; reader.output_buffer_size().unwrap()];
+        let info = reader.next_frame(&mut buf).unwrap();
+        assert_eq!((info.width, info.height, info.color_type), (6, 2, png::ColorType::Rgba));
+        let row: Vec<&[u8]> = buf[..info.line_size].chunks(4).collect();
+        assert_eq!(row, vec![[0,0,0,255], [0,0,0,255], [255,255,255,255], [255,255,255,255], [0,0,0,0], [0,0,0,0]]);
+    }
+
+    #[test]
+    fn dimensions() {
+        let art = ["#..#.###..",
+                   "#..#.#..#.",
+                   "####.###..",
+                   "#..#.#..#.",
+                   "#..#.#..#.",
+                   "#..#.###.."];
+        assert_eq!(detect_dimensions(&layered(&art, 3)), Ok((10, 6)));
+        assert_eq!(detect_dimensions(&layered(&art, 7)), Ok((10, 6)));
+
+        // too small to contain any letters
+        assert!(detect_dimensions(&util::parse_digits("0222112222120000")).is_err());
+
+        // a blank image fits any size, and comes out as a single layer
+        assert_eq!(detect_dimensions(&vec![0; 240]), Ok((40, 6)));
     }
 }
@@ -1,6 +1,8 @@
 // vim: set ai et ts=4 sts=4 sw=4:
 use crate::util;
-use crate::intcode::{CPU};
+use crate::intcode::{CPU, CpuState, Disas, Instruction};
+use std::collections::HashMap;
+use std::convert::TryFrom;
 
 pub fn main() {
     let line: String = util::file_read_lines("input/day9.txt").into_iter().next().unwrap();
@@ -10,14 +12,52 @@ pub fn main() {
 }
 
 fn part(part_nr: u32, program: &Vec<i64>) -> i64 {
-    let mut cpu = CPU::new(program);
-    cpu.send_input(match part_nr {
+    // input 1 runs BOOST in test mode, 2 in sensor boost mode
+    let mode = match part_nr {
         1 => 1,
         2 => 2,
         _ => panic!(),
+    };
+    run_boost(program, mode).unwrap_or_else(|err| panic!("{}", err))
+}
+
+fn run_boost(program: &Vec<i64>, mode: i64) -> Result<i64, String> {
+    // runs the BOOST program, which should output a single value. if its self-test finds instructions that don't
+    // behave correctly, it outputs their opcodes (including parameter modes) instead; in that case, the error
+    // describes each of them along with the place it was last executed, which is where it was being tested.
+    let mut cpu = CPU::new(program);
+    cpu.send_input(mode);
+
+    let mut last_executed: HashMap<i64, usize> = HashMap::new(); // instruction value -> address
+    cpu.run_traced(|cpu, pc| {
+        if cpu.get_state() != CpuState::WaitIO {
+            last_executed.insert(cpu.memory()[pc], pc);
+        }
     });
-    cpu.run();
-    cpu.consume_output_last().unwrap()
+    if !cpu.is_halted() {
+        return Err(format!("BOOST did not run to completion; state is {}", cpu.get_state()));
+    }
+
+    let outputs = cpu.consume_output_all();
+    match outputs.len() {
+        0 => Err("BOOST produced no output".to_string()),
+        1 => Ok(outputs[0]),
+        _ => {
+            let reports: Vec<String> = outputs.iter().map(|&value| {
+                let instr = match Instruction::try_from(value) {
+                    Ok(instr) => instr,
+                    Err(_)    => return format!("  {} (not an instruction)", value),
+                };
+                let modes: Vec<String> = (0..instr.size()-1).map(|n| format!("{:?}", instr.param_mode(n))).collect();
+                let location = match last_executed.get(&value) {
+                    Some(&pc) => format!("last executed at {}", Disas::disassemble_at(cpu.memory(), pc)),
+                    None      => "never executed".to_string(),
+                };
+                format!("  {} ({} {}), {}", value, instr, modes.join(", "), location)
+            }).collect();
+            Err(format!("BOOST reported malfunctioning opcodes:\n{}", reports.join("\n")))
+        },
+    }
 }
 
 #[cfg(test)]
@@ -41,4 +81,20 @@ mod tests {
             vec![1125899906842624]
         );
     }
+
+    #[test]
+    fn self_test() {
+        assert_eq!(run_boost(&vec![3,0,4,0,99], 7), Ok(7));
+        assert!(run_boost(&vec![99], 1).is_err());
+        assert!(run_boost(&vec![3,0,3,0,99], 1).is_err());
+
+        // reports the relative mode input instruction as broken, along with a value that isn't an instruction
+        let err = run_boost(&vec![109,10,203,0,104,203,104,12345,99], 1).unwrap_err();
+        assert_eq!(err, concat!("BOOST reported malfunctioning opcodes:\n",
+                                "  203 (IN RelativeAddress), last executed at 000002  IN     [base + 00]\n",
+                                "  12345 (not an instruction)"));
+
+        let err = run_boost(&vec![104,1101,104,0,99], 1).unwrap_err();
+        assert!(err.ends_with("  1101 (ADD Immediate, Immediate, Address), never executed\n  0 (not an instruction)"), "{}", err);
+    }
 }
@@ -2,28 +2,333 @@
 #![macro_use]
 #![allow(dead_code, unused_macros)]
 
+use std::cell::{Cell, RefCell};
 use std::ops::Drop;
+use std::env;
+use std::fs::File;
+use std::io::{self, Write, LineWriter};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 
+// debug printing state is kept per thread, so that solvers running on several threads at once each get
+// their own enabled flag and scope indentation (and don't race on them).
 pub struct DebugPrinterStatus {
-    pub enabled: bool,
-    pub indent_level: usize,
+    pub enabled: Cell<bool>,
+    pub indent_level: Cell<usize>,
+    pub scope_starts: RefCell<Vec<Instant>>, // start times of the currently open scopes, innermost last
+}
+thread_local! {
+    pub static DPRINT_STATUS: DebugPrinterStatus = const { DebugPrinterStatus {
+        enabled: Cell::new(true),
+        indent_level: Cell::new(0),
+        scope_starts: RefCell::new(Vec::new()),
+    } };
+}
+
+// optional prefixes in front of each line: time since the start of the program, and time since the start
+// of the innermost open scope. together they make long debug traces usable as a rough profile.
+static TIMESTAMPS: AtomicBool = AtomicBool::new(false);
+static SCOPE_ELAPSED: AtomicBool = AtomicBool::new(false);
+static JSON: AtomicBool = AtomicBool::new(false);
+static PROGRAM_START: OnceLock<Instant> = OnceLock::new();
+
+pub fn set_timestamps(enabled: bool) {
+    PROGRAM_START.get_or_init(Instant::now);
+    TIMESTAMPS.store(enabled, Ordering::Relaxed);
+}
+pub fn set_scope_elapsed(enabled: bool) {
+    SCOPE_ELAPSED.store(enabled, Ordering::Relaxed);
+}
+pub fn set_json(enabled: bool) {
+    // emit one JSON object per event instead of indented text, for post-processing of traces
+    JSON.store(enabled, Ordering::Relaxed);
+}
+
+fn line_prefix() -> String {
+    let mut result = String::new();
+    if TIMESTAMPS.load(Ordering::Relaxed) {
+        let elapsed = PROGRAM_START.get_or_init(Instant::now).elapsed();
+        result.push_str(&format!("[{:>12.3?}] ", elapsed));
+    }
+    if SCOPE_ELAPSED.load(Ordering::Relaxed) {
+        let scope_start = DPRINT_STATUS.with(|status| status.scope_starts.borrow().last().cloned());
+        match scope_start {
+            Some(start) => result.push_str(&format!("[+{:>11.3?}] ", start.elapsed())),
+            None        => result.push_str(&format!("[{:>12}] ", "")), // not in any scope
+        }
+    }
+    result
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Trace,
+    Debug,
+    Info,
+}
+impl Level {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Level::Trace => "trace",
+            Level::Debug => "debug",
+            Level::Info  => "info",
+        }
+    }
+    fn parse(s: &str) -> Option<Level> {
+        match s {
+            "trace" => Some(Level::Trace),
+            "debug" => Some(Level::Debug),
+            "info"  => Some(Level::Info),
+            _       => None,
+        }
+    }
+}
+
+// a filter directive says that messages from a given module (and its submodules) are to be printed from a given
+// level onwards. a directive without a module applies to every module that has no more specific directive.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Directive {
+    module: Option<String>,
+    level: Level,
+}
+
+pub fn parse_filter(spec: &str) -> Result<Vec<Directive>, String> {
+    // parses a comma-separated list of directives like "day18=trace,intcode=info", "debug" or "day23".
+    // module names are relative to the crate root; a module without a level means debug.
+    let mut result = Vec::new();
+    for part in spec.split(',').map(|p| p.trim()).filter(|p| !p.is_empty()) {
+        let directive = match part.find('=') {
+            Some(pos) => {
+                let level_str = &part[pos+1..];
+                let level = Level::parse(level_str).ok_or(format!("invalid level '{}' in '{}'", level_str, part))?;
+                Directive { module: Some(part[..pos].to_string()), level }
+            },
+            None => match Level::parse(part) {
+                Some(level) => Directive { module: None, level },
+                None        => Directive { module: Some(part.to_string()), level: Level::Debug },
+            },
+        };
+        result.push(directive);
+    }
+    Ok(result)
+}
+
+pub fn filter_level(directives: &[Directive], module_path: &str) -> Option<Level> {
+    // the minimum level at which messages from the given module are printed (None meaning not at all),
+    // as given by the most specific directive that applies to it
+    let module_path = match module_path.find("::") {
+        Some(pos) => &module_path[pos+2..], // strip the crate name
+        None      => "",
+    };
+    let matches = |module: &str| module_path == module || module_path.starts_with(&format!("{}::", module));
+
+    directives.iter()
+              .filter(|d| d.module.as_ref().is_none_or(|m| matches(m)))
+              .max_by_key(|d| d.module.as_ref().map_or(0, |m| m.len() + 1))
+              .map(|d| d.level)
+}
+
+fn env_filter() -> &'static [Directive] {
+    // without AOC_DEBUG set, debug printing is silent
+    static FILTER: OnceLock<Vec<Directive>> = OnceLock::new();
+    FILTER.get_or_init(|| match env::var("AOC_DEBUG") {
+        Ok(spec) => parse_filter(&spec).unwrap_or_else(|err| {
+            eprintln!("ignoring AOC_DEBUG: {}", err);
+            Vec::new()
+        }),
+        Err(_) => Vec::new(),
+    })
+}
+
+pub fn dprint_enabled(level: Level, module_path: &str) -> bool {
+    DPRINT_STATUS.with(|status| status.enabled.get())
+        && filter_level(env_filter(), module_path).is_some_and(|min_level| level >= min_level)
+}
+// where dprint output goes; stdout if no sink has been installed
+static SINK: Mutex<Option<Box<dyn Write + Send>>> = Mutex::new(None);
+
+pub fn set_sink(sink: Box<dyn Write + Send>) {
+    *SINK.lock().unwrap() = Some(sink);
+}
+pub fn set_sink_file(path: &str) -> io::Result<()> {
+    set_sink(Box::new(LineWriter::new(File::create(path)?)));
+    Ok(())
+}
+pub fn reset_sink() {
+    // flushes and drops the current sink, and goes back to printing to stdout
+    if let Some(mut sink) = SINK.lock().unwrap().take() {
+        sink.flush().ok();
+    }
+}
+
+// an in-memory sink whose contents can still be inspected after it's been installed, e.g. to collect
+// debug output in tests
+#[derive(Clone, Default)]
+pub struct SharedBuffer {
+    data: Arc<Mutex<Vec<u8>>>,
+}
+impl SharedBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.data.lock().unwrap()).into_owned()
+    }
+}
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.data.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn write_line(line: &str) {
+    // write the whole line at once while holding the lock, so that lines from different threads don't interleave
+    match SINK.lock().unwrap().as_mut() {
+        Some(sink) => { writeln!(sink, "{}", line).ok(); },
+        None       => println!("{}", line),
+    }
+}
+
+pub fn dprint_line(formatted: String) {
+    // the prefix goes in front of the indentation so that it lines up across scopes; continuation lines
+    // are padded to the same width instead of repeating it
+    let prefix = line_prefix();
+    let indent: String = "    ".repeat(DPRINT_STATUS.with(|status| status.indent_level.get()));
+    let continuation = " ".repeat(prefix.len()) + &indent;
+    let mut formatted = formatted.replace('\n', &("\n".to_owned() + &continuation));
+    formatted.insert_str(0, &indent);
+    formatted.insert_str(0, &prefix);
+    write_line(&formatted);
+}
+
+pub trait EventField {
+    // renders the value as a JSON value
+    fn to_json(&self) -> String;
+}
+macro_rules! impl_event_field_display {
+    ($($t:ty)*) => { $(impl EventField for $t { fn to_json(&self) -> String { self.to_string() } })* }
+}
+impl_event_field_display!(i8 i16 i32 i64 i128 isize u8 u16 u32 u64 u128 usize bool);
+impl EventField for f64 {
+    fn to_json(&self) -> String {
+        if self.is_finite() { self.to_string() } else { "null".to_string() }
+    }
+}
+impl EventField for str {
+    fn to_json(&self) -> String {
+        json_string(self)
+    }
+}
+impl EventField for String {
+    fn to_json(&self) -> String {
+        json_string(self)
+    }
+}
+impl<T: EventField + ?Sized> EventField for &T {
+    fn to_json(&self) -> String {
+        (**self).to_json()
+    }
+}
+
+pub fn json_string(s: &str) -> String {
+    let mut result = String::with_capacity(s.len() + 2);
+    result.push('"');
+    for c in s.chars() {
+        match c {
+            '"'  => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+#[cfg(feature = "tracing")]
+pub fn install_tracing_subscriber() {
+    // filtering already happens on the dprint side (through AOC_DEBUG), so the subscriber lets everything through
+    tracing_subscriber::fmt().with_max_level(tracing::Level::TRACE)
+                             .with_writer(io::stderr)
+                             .try_init()
+                             .ok();
+}
+
+#[cfg(feature = "tracing")]
+fn forward_to_tracing(level: Level, module_path: &str, message: &str, fields: &[(&str, String)]) {
+    // tracing callsites need static targets and field names, so the originating module and the event's fields
+    // travel along as field values instead
+    let fields: Vec<String> = fields.iter().map(|(name, value)| format!("{}={}", name, value)).collect();
+    let fields = fields.join(" ");
+    match level {
+        Level::Trace => tracing::trace!(module = module_path, fields = %fields, "{}", message),
+        Level::Debug => tracing::debug!(module = module_path, fields = %fields, "{}", message),
+        Level::Info  => tracing::info!(module = module_path, fields = %fields, "{}", message),
+    }
+}
+
+pub fn dprint_event(level: Level, module_path: &str, message: String, fields: &[(&str, String)]) {
+    // fields are (name, value rendered as JSON) pairs
+    #[cfg(feature = "tracing")]
+    {
+        if tracing::dispatcher::has_been_set() {
+            forward_to_tracing(level, module_path, &message, fields);
+            return;
+        }
+    }
+    if JSON.load(Ordering::Relaxed) {
+        let depth = DPRINT_STATUS.with(|status| status.indent_level.get());
+        let mut line = format!("{{\"module\":{},\"level\":\"{}\",\"depth\":{}",
+                               json_string(module_path), level.name(), depth);
+        if TIMESTAMPS.load(Ordering::Relaxed) {
+            let elapsed = PROGRAM_START.get_or_init(Instant::now).elapsed();
+            line.push_str(&format!(",\"time\":{}", elapsed.as_secs_f64()));
+        }
+        line.push_str(&format!(",\"message\":{},\"fields\":{{", json_string(&message)));
+        let fields: Vec<String> = fields.iter().map(|(name, value)| format!("{}:{}", json_string(name), value)).collect();
+        line.push_str(&fields.join(","));
+        line.push_str("}}");
+        write_line(&line);
+    } else {
+        let mut message = message;
+        for (name, value) in fields {
+            message.push_str(&format!(" {}={}", name, value));
+        }
+        dprint_line(message);
+    }
 }
-pub static mut DPRINT_STATUS: DebugPrinterStatus = DebugPrinterStatus {
-    enabled: true,
-    indent_level: 0,
-};
 
 pub struct DebugPrinterScope {
+    // when forwarding to tracing, scopes become spans so the subscriber can show the nesting
+    #[cfg(feature = "tracing")]
+    _span: tracing::span::EnteredSpan,
 }
 impl DebugPrinterScope {
     pub fn new() -> Self {
-        unsafe { DPRINT_STATUS.indent_level += 1; }
-        DebugPrinterScope { }
+        DPRINT_STATUS.with(|status| {
+            status.indent_level.set(status.indent_level.get() + 1);
+            status.scope_starts.borrow_mut().push(Instant::now());
+        });
+        DebugPrinterScope {
+            #[cfg(feature = "tracing")]
+            _span: tracing::trace_span!("dscope").entered(),
+        }
     }
 }
 impl Drop for DebugPrinterScope {
     fn drop(&mut self) {
-        unsafe { DPRINT_STATUS.indent_level -= 1; }
+        DPRINT_STATUS.with(|status| {
+            status.indent_level.set(status.indent_level.get() - 1);
+            status.scope_starts.borrow_mut().pop();
+        });
     }
 }
 
@@ -32,35 +337,223 @@ pub struct DebugPrinterDisable {
 }
 impl DebugPrinterDisable {
     pub fn new() -> Self {
-        unsafe {
-            let result = Self { old_status: DPRINT_STATUS.enabled };
-            DPRINT_STATUS.enabled = false;
-            result
-        }
+        DPRINT_STATUS.with(|status| Self { old_status: status.enabled.replace(false) })
     }
 }
 impl Drop for DebugPrinterDisable {
     fn drop(&mut self) {
-        unsafe { DPRINT_STATUS.enabled = self.old_status; }
+        DPRINT_STATUS.with(|status| status.enabled.set(self.old_status));
     }
 }
 
-macro_rules! dprint {
-    ($($arg:tt)*) => {{
-        let enabled = unsafe { DPRINT_STATUS.enabled };
-        if enabled {
-            let indent: String = unsafe { "    ".repeat(DPRINT_STATUS.indent_level) };
-            let mut formatted: String = format!($($arg)*);
-            formatted.insert_str(0, &indent);
-            println!("{}", formatted.replace('\n', &("\n".to_owned() + &indent)));
+macro_rules! dprint_at {
+    ($level:expr, $($arg:tt)*) => {{
+        if $crate::dprint::dprint_enabled($level, module_path!()) {
+            $crate::dprint::dprint_event($level, module_path!(), format!($($arg)*), &[]);
+        }
+    }}
+}
+
+// emits a structured event: a message plus named fields, e.g. devent!(Level::Debug, "expanded", queue = q.len())
+macro_rules! devent {
+    ($level:expr, $message:expr $(, $name:ident = $value:expr)* $(,)?) => {{
+        if $crate::dprint::dprint_enabled($level, module_path!()) {
+            $crate::dprint::dprint_event($level, module_path!(), ($message).to_string(),
+                                         &[$((stringify!($name), $crate::dprint::EventField::to_json(&$value))),*]);
         }
     }}
 }
 
+macro_rules! dtrace {
+    ($($arg:tt)*) => { dprint_at!($crate::dprint::Level::Trace, $($arg)*) }
+}
+
+macro_rules! dprint {
+    ($($arg:tt)*) => { dprint_at!($crate::dprint::Level::Debug, $($arg)*) }
+}
+
+macro_rules! dinfo {
+    ($($arg:tt)*) => { dprint_at!($crate::dprint::Level::Info, $($arg)*) }
+}
+
 macro_rules! dscope {
-    () => { let _dprint_scope = DebugPrinterScope::new(); }
+    () => { let _dprint_scope = $crate::dprint::DebugPrinterScope::new(); }
+}
+
+macro_rules! time_scope {
+    ($label:expr) => { let _dprint_timer = $crate::dprint::Timer::new($label, module_path!()); }
 }
 
 macro_rules! ddisable {
-    () => { let _dprint_disable = DebugPrinterDisable::new(); }
+    () => { let _dprint_disable = $crate::dprint::DebugPrinterDisable::new(); }
+}
+
+pub struct TimingStats {
+    pub label: String,
+    pub count: usize,
+    pub total: Duration,
+}
+// aggregated timings over all Timers that have been dropped so far, in order of first appearance
+pub static TIMINGS: Mutex<Vec<TimingStats>> = Mutex::new(Vec::new());
+
+pub struct Timer {
+    label: String,
+    module_path: &'static str, // where the timer was created, for filtering its output
+    start: Instant,
+}
+impl Timer {
+    pub fn new(label: &str, module_path: &'static str) -> Self {
+        Self { label: label.to_string(), module_path, start: Instant::now() }
+    }
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+impl Drop for Timer {
+    fn drop(&mut self) {
+        let elapsed = self.elapsed();
+        if dprint_enabled(Level::Debug, self.module_path) {
+            dprint_event(Level::Debug, self.module_path, format!("[{}: {:?}]", self.label, elapsed), &[]);
+        }
+
+        let mut timings = TIMINGS.lock().unwrap();
+        match timings.iter_mut().find(|t| t.label == self.label) {
+            Some(stats) => { stats.count += 1; stats.total += elapsed; },
+            None        => { timings.push(TimingStats { label: self.label.clone(), count: 1, total: elapsed }); },
+        }
+    }
+}
+
+pub fn timing_report() -> Option<String> {
+    // summary of all timed scopes so far, or None if nothing was timed
+    let timings = TIMINGS.lock().unwrap();
+    if timings.is_empty() {
+        return None;
+    }
+    let label_width = timings.iter().map(|t| t.label.len()).max().unwrap();
+    let mut result = String::from("timings:");
+    for t in timings.iter() {
+        result.push_str(&format!("\n  {:w$}  {:>6}x  total {:>12?}  avg {:>12?}",
+                                 t.label, t.count, t.total, t.total / t.count as u32, w = label_width));
+    }
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn enabled() -> bool {
+        DPRINT_STATUS.with(|status| status.enabled.get())
+    }
+    fn indent_level() -> usize {
+        DPRINT_STATUS.with(|status| status.indent_level.get())
+    }
+
+    #[test]
+    fn status_is_per_thread() {
+        dscope!();
+        ddisable!();
+        assert_eq!(indent_level(), 1);
+        assert!(!enabled());
+
+        // a fresh thread starts out with the default status, and changes there don't leak back here
+        thread::spawn(|| {
+            assert_eq!(indent_level(), 0);
+            assert!(enabled());
+            dscope!();
+            dscope!();
+            assert_eq!(indent_level(), 2);
+        }).join().unwrap();
+
+        assert_eq!(indent_level(), 1);
+        assert!(!enabled());
+    }
+
+    #[test]
+    fn sinks() {
+        let buffer = SharedBuffer::new();
+        set_sink(Box::new(buffer.clone()));
+        dprint_line("first".to_string());
+        {
+            dscope!();
+            dprint_line("second\nthird".to_string());
+        }
+        set_scope_elapsed(true);
+        {
+            dscope!();
+            dprint_line("timed\nmore".to_string());
+        }
+        set_scope_elapsed(false);
+        set_json(true);
+        {
+            dscope!();
+            dprint_event(Level::Info, "adventofcode::day18", "queue \"size\"".to_string(),
+                         &[("size", 12.to_json()), ("key", "a\nb".to_json())]);
+        }
+        set_json(false);
+        reset_sink();
+        dprint_line("not captured".to_string());
+
+        let contents = buffer.contents();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines[..3], ["first", "    second", "    third"]);
+        assert!(lines[3].starts_with("[+") && lines[3].ends_with("]     timed"), "{}", lines[3]);
+        assert_eq!(lines[4], " ".repeat(lines[3].len() - "timed".len()) + "more");
+        assert_eq!(lines[5], concat!(r#"{"module":"adventofcode::day18","level":"info","depth":1,"#,
+                                     r#""message":"queue \"size\"","fields":{"size":12,"key":"a\nb"}}"#));
+        assert_eq!(lines.len(), 6);
+    }
+
+    #[test]
+    fn timings() {
+        // other tests can time their own scopes concurrently, so only look at the lines for these labels
+        for _ in 0..3 {
+            time_scope!("timings test: repeated");
+        }
+        {
+            time_scope!("timings test: once");
+            thread::sleep(Duration::from_millis(5));
+        }
+        let report = timing_report().unwrap();
+        assert!(report.starts_with("timings:\n"), "{}", report);
+        let line = |label: &str| report.lines().find(|l| l.trim_start().starts_with(label)).unwrap().to_string();
+        let repeated = line("timings test: repeated");
+        let once = line("timings test: once");
+        assert!(repeated.contains("     3x  total "), "{}", repeated);
+        assert!(once.contains("     1x  total "), "{}", once);
+
+        let stats = TIMINGS.lock().unwrap();
+        let once = stats.iter().find(|t| t.label == "timings test: once").unwrap();
+        assert!(once.total >= Duration::from_millis(5));
+    }
+
+    #[test]
+    fn json_values() {
+        assert_eq!(json_string("plain"), r#""plain""#);
+        assert_eq!(json_string("a\"b\\c\td\u{1}"), r#""a\"b\\c\td\u0001""#);
+        assert_eq!((-3i64).to_json(), "-3");
+        assert_eq!(true.to_json(), "true");
+        assert_eq!(f64::NAN.to_json(), "null");
+        assert_eq!(String::from("x").to_json(), r#""x""#);
+    }
+
+    #[test]
+    fn filters() {
+        assert_eq!(parse_filter(""), Ok(vec![]));
+        assert!(parse_filter("day18=loud").is_err());
+
+        let filter = parse_filter("day18=trace, intcode=info,day23,info").unwrap();
+        assert_eq!(filter_level(&filter, "adventofcode::day18"), Some(Level::Trace));
+        assert_eq!(filter_level(&filter, "adventofcode::day18::tests"), Some(Level::Trace));
+        assert_eq!(filter_level(&filter, "adventofcode::intcode"), Some(Level::Info));
+        assert_eq!(filter_level(&filter, "adventofcode::day23"), Some(Level::Debug));
+        assert_eq!(filter_level(&filter, "adventofcode::day2"), Some(Level::Info)); // not day23 or day24
+        assert_eq!(filter_level(&filter, "adventofcode"), Some(Level::Info));
+
+        let filter = parse_filter("day18=trace").unwrap();
+        assert_eq!(filter_level(&filter, "adventofcode::day1"), None);
+        assert_eq!(filter_level(&filter, "adventofcode::day18"), Some(Level::Trace));
+    }
 }
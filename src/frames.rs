@@ -0,0 +1,234 @@
+// vim: set ai et ts=4 sts=4 sw=4:
+#![allow(unused)]
+// recording a sequence of character grids as an animation, which can be played back in the terminal or written
+// out as an animated GIF.
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Write};
+use std::thread;
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    width: usize,
+    height: usize,
+    cells: Vec<char>,
+}
+impl Frame {
+    pub fn new(width: usize, height: usize, fill: char) -> Self {
+        Self { width, height, cells: vec![fill; width*height] }
+    }
+    pub fn width(&self) -> usize {
+        self.width
+    }
+    pub fn height(&self) -> usize {
+        self.height
+    }
+    pub fn get(&self, x: usize, y: usize) -> char {
+        self.cells[y*self.width + x]
+    }
+    pub fn set(&mut self, x: usize, y: usize, c: char) {
+        self.cells[y*self.width + x] = c;
+    }
+    pub fn set_checked(&mut self, x: i64, y: i64, c: char) -> bool {
+        // same as set, but ignores positions that fall outside of the frame; returns whether it was inside
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            return false;
+        }
+        self.set(x as usize, y as usize, c);
+        true
+    }
+    pub fn write_png<W, P>(&self, out: W, scale: usize, palette: P) -> io::Result<()>
+        where W: Write,
+              P: Fn(char) -> [u8; 3]
+    {
+        // each cell becomes a scale x scale block of pixels, in the color the palette gives for its character
+        let mut encoder = png::Encoder::new(out, (self.width*scale) as u32, (self.height*scale) as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header()?.write_image_data(&self.scaled_pixels(scale, palette))?;
+        Ok(())
+    }
+    pub fn write_ppm<W, P>(&self, mut out: W, scale: usize, palette: P) -> io::Result<()>
+        where W: Write,
+              P: Fn(char) -> [u8; 3]
+    {
+        // same as write_png, but as a plain (ASCII) PPM
+        writeln!(out, "P3")?;
+        writeln!(out, "{} {}", self.width*scale, self.height*scale)?;
+        writeln!(out, "255")?;
+        for row in self.scaled_pixels(scale, palette).chunks(3*self.width*scale) {
+            let row: Vec<String> = row.iter().map(|v| v.to_string()).collect();
+            writeln!(out, "{}", row.join(" "))?;
+        }
+        out.flush()
+    }
+    fn scaled_pixels<P>(&self, scale: usize, palette: P) -> Vec<u8>
+        where P: Fn(char) -> [u8; 3]
+    {
+        (0..self.height*scale).flat_map(|y| (0..self.width*scale).map(move |x| (x, y)))
+                              .flat_map(|(x, y)| palette(self.get(x/scale, y/scale)))
+                              .collect()
+    }
+}
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for row in self.cells.chunks(self.width) {
+            writeln!(f, "{}", row.iter().collect::<String>())?;
+        }
+        Ok(())
+    }
+}
+
+pub struct FrameRecorder {
+    frames: Vec<Frame>,
+}
+impl FrameRecorder {
+    pub fn new() -> Self {
+        Self { frames: Vec::new() }
+    }
+    pub fn record(&mut self, frame: Frame) {
+        self.frames.push(frame);
+    }
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+    pub fn play<W: Write>(&self, out: &mut W, delay: Duration) -> io::Result<()> {
+        // draws each frame over the previous one using ANSI escape codes
+        write!(out, "\x1b[2J")?; // clear screen
+        for frame in &self.frames {
+            write!(out, "\x1b[H{}", frame)?; // cursor to top left, then draw
+            out.flush()?;
+            thread::sleep(delay);
+        }
+        Ok(())
+    }
+    pub fn write_gif<W, P>(&self, out: W, scale: usize, delay: Duration, palette: P) -> io::Result<()>
+        where W: Write,
+              P: Fn(char) -> [u8; 3]
+    {
+        // each cell becomes a scale x scale block of pixels, in the color the palette gives for its character.
+        // the frames are all drawn at the size of the largest one.
+        let width = self.frames.iter().map(|f| f.width).max().unwrap_or(0);
+        let height = self.frames.iter().map(|f| f.height).max().unwrap_or(0);
+        let (pixel_w, pixel_h) = (width*scale, height*scale);
+        if pixel_w > u16::MAX as usize || pixel_h > u16::MAX as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "animation too large for a GIF"));
+        }
+
+        // the GIF palette holds at most 256 colors, so give each distinct character an index
+        let mut indices: HashMap<char, u8> = HashMap::new();
+        let mut colors: Vec<u8> = Vec::new();
+        for &c in self.frames.iter().flat_map(|f| f.cells.iter()) {
+            if !indices.contains_key(&c) {
+                if indices.len() == 256 {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput, "too many different characters for a GIF"));
+                }
+                indices.insert(c, indices.len() as u8);
+                colors.extend_from_slice(&palette(c));
+            }
+        }
+        if colors.is_empty() {
+            colors.extend_from_slice(&[0, 0, 0]);
+        }
+
+        let mut encoder = gif::Encoder::new(out, pixel_w as u16, pixel_h as u16, &colors).map_err(io::Error::other)?;
+        encoder.set_repeat(gif::Repeat::Infinite).map_err(io::Error::other)?;
+        for frame in &self.frames {
+            let mut pixels = vec![0u8; pixel_w*pixel_h];
+            for y in 0..frame.height*scale {
+                for x in 0..frame.width*scale {
+                    pixels[y*pixel_w + x] = indices[&frame.get(x/scale, y/scale)];
+                }
+            }
+            let mut gif_frame = gif::Frame::from_indexed_pixels(pixel_w as u16, pixel_h as u16, pixels, None);
+            gif_frame.delay = (delay.as_millis() / 10) as u16; // in units of 10ms
+            encoder.write_frame(&gif_frame).map_err(io::Error::other)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recording() -> FrameRecorder {
+        let mut recorder = FrameRecorder::new();
+        let mut frame = Frame::new(3, 2, '.');
+        for x in 0..3 {
+            frame.set(x, x % 2, '#');
+            recorder.record(frame.clone());
+        }
+        recorder
+    }
+
+    #[test]
+    fn frames() {
+        let mut frame = Frame::new(3, 2, '.');
+        frame.set(1, 0, '#');
+        assert!(frame.set_checked(2, 1, '@'));
+        assert!(!frame.set_checked(3, 0, '@'));
+        assert!(!frame.set_checked(-1, 0, '@'));
+        assert_eq!(frame.get(2, 1), '@');
+        assert_eq!(frame.to_string(), ".#.\n..@\n");
+
+        let recorder = recording();
+        assert_eq!(recorder.len(), 3);
+        assert_eq!(recorder.frames()[2].to_string(), "#.#\n.#.\n");
+
+        let mut out = Vec::new();
+        recorder.play(&mut out, Duration::from_millis(0)).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("\x1b[2J\x1b[H#..\n...\n\x1b[H"));
+        assert!(out.ends_with("\x1b[H#.#\n.#.\n"));
+    }
+
+    #[test]
+    fn gif() {
+        let mut out = Vec::new();
+        recording().write_gif(&mut out, 2, Duration::from_millis(100), |c| if c == '#' { [255, 255, 255] } else { [0, 0, 0] })
+                   .unwrap();
+
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::RGBA);
+        let mut decoder = options.read_info(io::Cursor::new(out)).unwrap();
+        assert_eq!((decoder.width(), decoder.height()), (6, 4));
+        let mut frames = 0;
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            assert_eq!(frame.delay, 10);
+            if frames == 0 {
+                // only the top left cell is lit in the first frame
+                let lit: Vec<usize> = frame.buffer.chunks(4).enumerate().filter(|(_, px)| px[0] == 255).map(|(i, _)| i).collect();
+                assert_eq!(lit, vec![0, 1, 6, 7]);
+            }
+            frames += 1;
+        }
+        assert_eq!(frames, 3);
+    }
+
+    #[test]
+    fn images() {
+        let mut frame = Frame::new(2, 1, '.');
+        frame.set(1, 0, '#');
+        let palette = |c| if c == '#' { [255, 128, 0] } else { [0, 0, 0] };
+
+        let mut out = Vec::new();
+        frame.write_ppm(&mut out, 1, palette).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "P3\n2 1\n255\n0 0 0 255 128 0\n");
+
+        let mut out = Vec::new();
+        frame.write_png(&mut out, 2, palette).unwrap();
+        let mut reader = png::Decoder::new(io::Cursor::new(out)).read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size().unwrap()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+        assert_eq!((info.width, info.height), (4, 2));
+        assert_eq!(&pixels[..12], &[0, 0, 0, 0, 0, 0, 255, 128, 0, 255, 128, 0]);
+    }
+}
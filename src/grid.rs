@@ -0,0 +1,115 @@
+// vim: set ai et ts=4 sts=4 sw=4:
+#![allow(unused)]
+// an unbounded 2D grid that only stores the cells that have been set, for puzzles where something wanders around
+// on a plane of unknown size. the Y axis points down, like rows of text.
+use crate::frames::Frame;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bounds {
+    pub min_x: i64,
+    pub min_y: i64,
+    pub max_x: i64,
+    pub max_y: i64,
+}
+impl Bounds {
+    pub fn of(x: i64, y: i64) -> Self {
+        Self { min_x: x, min_y: y, max_x: x, max_y: y }
+    }
+    pub fn include(&mut self, x: i64, y: i64) {
+        self.min_x = self.min_x.min(x);
+        self.min_y = self.min_y.min(y);
+        self.max_x = self.max_x.max(x);
+        self.max_y = self.max_y.max(y);
+    }
+    pub fn union(&self, other: &Bounds) -> Bounds {
+        let mut result = *self;
+        result.include(other.min_x, other.min_y);
+        result.include(other.max_x, other.max_y);
+        result
+    }
+    pub fn contains(&self, x: i64, y: i64) -> bool {
+        self.min_x <= x && x <= self.max_x && self.min_y <= y && y <= self.max_y
+    }
+    pub fn width(&self) -> usize {
+        (self.max_x - self.min_x + 1) as usize
+    }
+    pub fn height(&self) -> usize {
+        (self.max_y - self.min_y + 1) as usize
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SparseGrid<T> {
+    cells: HashMap<(i64, i64), T>,
+}
+impl<T> SparseGrid<T> {
+    pub fn new() -> Self {
+        Self { cells: HashMap::new() }
+    }
+    pub fn get(&self, x: i64, y: i64) -> Option<&T> {
+        self.cells.get(&(x, y))
+    }
+    pub fn set(&mut self, x: i64, y: i64, value: T) -> Option<T> {
+        // returns the previous value, if any
+        self.cells.insert((x, y), value)
+    }
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+    pub fn iter(&self) -> impl Iterator<Item=((i64, i64), &T)> {
+        self.cells.iter().map(|(&pos, value)| (pos, value))
+    }
+    pub fn bounds(&self) -> Option<Bounds> {
+        // smallest area that contains all cells that have been set
+        let mut positions = self.cells.keys();
+        let &(x, y) = positions.next()?;
+        let mut bounds = Bounds::of(x, y);
+        for &(x, y) in positions {
+            bounds.include(x, y);
+        }
+        Some(bounds)
+    }
+    pub fn render<F>(&self, bounds: &Bounds, cell: F) -> Frame
+        where F: Fn(Option<&T>) -> char
+    {
+        // draws the given area of the grid, with the character for each cell given by a function of its value
+        let mut frame = Frame::new(bounds.width(), bounds.height(), ' ');
+        for y in 0..bounds.height() {
+            for x in 0..bounds.width() {
+                frame.set(x, y, cell(self.get(bounds.min_x + x as i64, bounds.min_y + y as i64)));
+            }
+        }
+        frame
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grid() {
+        let mut grid: SparseGrid<u32> = SparseGrid::new();
+        assert_eq!(grid.bounds(), None);
+        assert_eq!(grid.set(2, -1, 7), None);
+        assert_eq!(grid.set(-3, 4, 8), None);
+        assert_eq!(grid.set(2, -1, 9), Some(7));
+        assert_eq!((grid.get(2, -1), grid.get(0, 0)), (Some(&9), None));
+        assert_eq!(grid.len(), 2);
+
+        let bounds = grid.bounds().unwrap();
+        assert_eq!(bounds, Bounds { min_x: -3, min_y: -1, max_x: 2, max_y: 4 });
+        assert_eq!((bounds.width(), bounds.height()), (6, 6));
+        assert!(bounds.contains(0, 0));
+        assert!(!bounds.contains(3, 0));
+        assert_eq!(bounds.union(&Bounds::of(5, 0)), Bounds { min_x: -3, min_y: -1, max_x: 5, max_y: 4 });
+
+        let frame = grid.render(&Bounds { min_x: -3, min_y: -1, max_x: 2, max_y: 1 },
+                                |v| v.map_or('.', |&v| std::char::from_digit(v, 10).unwrap()));
+        assert_eq!(frame.to_string(), ".....9\n......\n......\n");
+    }
+}
@@ -0,0 +1,163 @@
+// vim: set ai et ts=4 sts=4 sw=4:
+#![allow(unused)]
+// layered images in the space image format from day 8: each pixel is black, white or transparent, and layers are
+// stacked on top of each other with the first one in front.
+use std::ops::{Index, IndexMut};
+
+pub const BLACK: u32 = 0;
+pub const WHITE: u32 = 1;
+pub const TRANSPARENT: u32 = 2;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layer {
+    order: u32,
+    width: usize,
+    height: usize,
+    data: Vec<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LayerStats {
+    pub black: usize,
+    pub white: usize,
+    pub transparent: usize,
+}
+
+impl Layer {
+    pub fn new(order: u32, w: usize, h: usize, data: &[u32]) -> Self {
+        assert_eq!(data.len(), w*h, "expected {}x{} pixels", w, h);
+        Self {
+            order,
+            width: w,
+            height: h,
+            data: data.to_vec(),
+        }
+    }
+    pub fn filled(w: usize, h: usize, pixel: u32) -> Self {
+        Self::new(0, w, h, &vec![pixel; w*h])
+    }
+    pub fn order(&self) -> u32 {
+        self.order
+    }
+    pub fn width(&self) -> usize {
+        self.width
+    }
+    pub fn height(&self) -> usize {
+        self.height
+    }
+    pub fn data(&self) -> &[u32] {
+        &self.data
+    }
+    pub fn pixels(&self) -> impl Iterator<Item=((usize, usize), u32)> + '_ {
+        // all pixels with their x,y position, row by row
+        self.data.iter().enumerate().map(move |(i, &p)| ((i % self.width, i / self.width), p))
+    }
+    pub fn rows(&self) -> impl Iterator<Item=&[u32]> {
+        self.data.chunks(self.width)
+    }
+    pub fn count(&self, pixel: u32) -> usize {
+        self.data.iter().filter(|&&p| p == pixel).count()
+    }
+    pub fn stats(&self) -> LayerStats {
+        let mut stats = LayerStats::default();
+        for &p in &self.data {
+            match p {
+                BLACK       => stats.black += 1,
+                WHITE       => stats.white += 1,
+                TRANSPARENT => stats.transparent += 1,
+                _           => {},
+            }
+        }
+        stats
+    }
+    pub fn crop(&self, x: usize, y: usize, w: usize, h: usize) -> Result<Layer, String> {
+        if x + w > self.width || y + h > self.height {
+            return Err(format!("cannot crop {}x{} at ({},{}) out of a {}x{} layer", w, h, x, y, self.width, self.height));
+        }
+        let data: Vec<u32> = self.rows().skip(y).take(h).flat_map(|row| row[x..x+w].iter().cloned()).collect();
+        Ok(Layer::new(self.order, w, h, &data))
+    }
+    pub fn over(&self, below: &Layer) -> Layer {
+        // this layer placed on top of another one of the same size; the one below shows through where this one's
+        // transparent
+        assert_eq!((self.width, self.height), (below.width, below.height), "can only stack layers of the same size");
+        let data: Vec<u32> = self.data.iter().zip(&below.data)
+                                      .map(|(&top, &bottom)| if top == TRANSPARENT { bottom } else { top })
+                                      .collect();
+        Layer::new(self.order, self.width, self.height, &data)
+    }
+}
+impl Index<(usize,usize)> for Layer {
+    type Output = u32;
+    fn index(&self, idx: (usize,usize)) -> &Self::Output { // x,y
+        &self.data[idx.1*self.width + idx.0]
+    }
+}
+impl IndexMut<(usize,usize)> for Layer {
+    fn index_mut(&mut self, idx: (usize,usize)) -> &mut Self::Output {
+        &mut self.data[idx.1*self.width + idx.0]
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub layers: Vec<Layer>,
+}
+impl Image {
+    pub fn new(w: usize, h: usize, data: &[u32]) -> Self {
+        assert!(data.len().is_multiple_of(w*h), "{} pixels don't divide into {}x{} layers", data.len(), w, h);
+        Self {
+            width: w,
+            height: h,
+            layers: data.chunks(w * h)
+                        .enumerate()
+                        .map(|(n,chunk)| Layer::new(n as u32, w, h, chunk))
+                        .collect()
+        }
+    }
+    pub fn composite(&self) -> Layer {
+        // what you see when looking at the stack of layers from the front: for each position, the first pixel
+        // that's either black or white, or transparent if there is none.
+        self.layers.iter().rev().fold(Layer::filled(self.width, self.height, TRANSPARENT), |below, layer| layer.over(&below))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layers() {
+        let layer = Layer::new(3, 3, 2, &[0,1,2,
+                                          1,1,0]);
+        assert_eq!(layer.order(), 3);
+        assert_eq!((layer[(1,0)], layer[(2,0)], layer[(0,1)]), (WHITE, TRANSPARENT, WHITE));
+        assert_eq!(layer.stats(), LayerStats { black: 2, white: 3, transparent: 1 });
+        assert_eq!(layer.count(WHITE), 3);
+        assert_eq!(layer.rows().collect::<Vec<_>>(), vec![&[0,1,2][..], &[1,1,0][..]]);
+        assert_eq!(layer.pixels().filter(|&(_, p)| p == BLACK).map(|(pos, _)| pos).collect::<Vec<_>>(),
+                   vec![(0,0), (2,1)]);
+
+        assert_eq!(layer.crop(1, 0, 2, 2).unwrap().data(), &[1,2,1,0]);
+        assert_eq!(layer.crop(0, 1, 3, 1).unwrap().data(), &[1,1,0]);
+        assert!(layer.crop(2, 0, 2, 1).is_err());
+
+        let under = Layer::filled(3, 2, BLACK);
+        assert_eq!(layer.over(&under).data(), &[0,1,0,1,1,0]);
+        assert_eq!(under.over(&layer), under);
+    }
+
+    #[test]
+    fn compositing() {
+        let img = Image::new(2, 2, &[0,2,2,2, 1,1,2,2, 2,2,1,2, 0,0,0,0]);
+        assert_eq!(img.layers.len(), 4);
+        assert_eq!(img.composite().data(), &[0,1,1,0]);
+        assert_eq!(img.layers.len(), 4); // still all there
+
+        // without anything behind it, a transparent pixel stays transparent
+        let img = Image::new(2, 1, &[2,0, 2,1]);
+        assert_eq!(img.composite().data(), &[2,0]);
+    }
+}
@@ -1,6 +1,8 @@
 // vim: set ai et ts=4 sts=4 sw=4:
-use std::ops::{Index, IndexMut};
+use crate::util;
+use std::ops::{Index, IndexMut, Range};
 use std::collections::{VecDeque, HashMap};
+use std::sync::mpsc::{Receiver, Sender};
 use std::convert::TryFrom;
 use std::fmt;
 
@@ -91,6 +93,9 @@ impl Instruction {
         }
         Ok(Self { opcode, num_params, param_modes })
     }
+    pub fn opcode(&self) -> Op {
+        self.opcode
+    }
     pub fn param_mode(&self, num: usize) -> ParamMode {
         self.param_modes[num]
     }
@@ -115,6 +120,7 @@ impl fmt::Display for CpuState {
     }
 }
 
+#[derive(Clone)]
 pub struct Memory {
     initial_data: Vec<i64>,
     extra: HashMap<usize, i64>,
@@ -126,6 +132,17 @@ impl Memory {
             extra: HashMap::new(),
         }
     }
+    pub fn load(&mut self, program: &[i64]) {
+        // replaces the contents with a fresh copy of the program, reusing the existing allocation
+        self.initial_data.clear();
+        self.initial_data.extend_from_slice(program);
+        self.extra.clear();
+    }
+    pub fn hexdump(&self, range: Range<usize>) -> String {
+        // for inspecting a region of memory, e.g. after a program has run
+        let values: Vec<i64> = range.clone().map(|addr| self[addr]).collect();
+        util::hexdump(&values, range.start)
+    }
 }
 impl Index<usize> for Memory {
     type Output = i64;
@@ -151,6 +168,7 @@ impl IndexMut<usize> for Memory {
     }
 }
 
+#[derive(Clone)]
 pub struct CPU
 {
     pc: usize,
@@ -175,7 +193,7 @@ impl CPU
     }
     pub fn reset(&mut self, program: &Vec<i64>) -> &mut Self {
         self.pc = 0usize;
-        self.mem = Memory::new(program.clone());
+        self.mem.load(program);
         self.input_queue.clear();
         self.output_queue.clear();
         self.state = CpuState::Halted;
@@ -190,6 +208,66 @@ impl CPU
         }
         return self;
     }
+    pub fn run_traced<F>(&mut self, mut on_step: F) -> &mut Self
+        where F: FnMut(&CPU, usize)
+    {
+        // same as run, but calls on_step after every step with the address of the instruction that was executed
+        // (or that is waiting for input, if the CPU has moved to the wait state).
+        self.state = CpuState::Running;
+        while self.state == CpuState::Running {
+            let pc = self.pc;
+            self.step();
+            on_step(self, pc);
+        }
+        self
+    }
+    pub fn run_until_output(&mut self, n: usize) -> &mut Self {
+        // same as run, but also stops as soon as there are at least n values waiting in the output queue.
+        self.state = CpuState::Running;
+        while self.state == CpuState::Running && self.output_queue.len() < n {
+            self.step();
+        }
+        self
+    }
+    pub fn run_limited(&mut self, max_steps: usize) -> &mut Self {
+        // same as run, but gives up after executing max_steps instructions, leaving the CPU in the running state
+        // if it hasn't halted or started waiting for input by then (e.g. to get out of an infinite loop).
+        self.state = CpuState::Running;
+        for _ in 0..max_steps {
+            if self.state != CpuState::Running {
+                break;
+            }
+            self.step();
+        }
+        self
+    }
+    pub fn run_with_channels(&mut self, input: &Receiver<i64>, output: &Sender<i64>) -> &mut Self {
+        // runs the CPU to completion, blocking on the input channel whenever it needs input and passing along
+        // any output to the output channel as it is produced. meant for running CPUs on their own threads,
+        // connected to each other through channels.
+        // also returns if the input channel is closed while the CPU is waiting for input; output that can't be
+        // delivered because the receiving end has gone away is dropped.
+        loop {
+            self.run();
+            for value in self.consume_output_all() {
+                output.send(value).ok();
+            }
+            if self.state != CpuState::WaitIO {
+                break;
+            }
+            match input.recv() {
+                Ok(value) => { self.send_input(value); },
+                Err(_)    => break,
+            }
+        }
+        self
+    }
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+    pub fn memory(&self) -> &Memory {
+        &self.mem
+    }
     pub fn is_halted(&self) -> bool {
         self.state == CpuState::Halted
     }
@@ -360,7 +438,18 @@ impl Disas {
 
         return result;
     }
-    pub fn disassemble_instr(program: &Vec<i64>, pc: usize, instr: &Instruction) -> String {
+    pub fn disassemble_at<M>(program: &M, pc: usize) -> String
+        where M: Index<usize, Output=i64> + ?Sized
+    {
+        // single line of disassembly for the instruction at the given address, in the same format as disassemble
+        match Instruction::try_from(program[pc]) {
+            Ok(instr) => format!("{:06X}  {}", pc, Self::disassemble_instr(program, pc, &instr)),
+            Err(_)    => format!("{:06X}  {:-6} {:02X}", pc, "", program[pc]),
+        }
+    }
+    pub fn disassemble_instr<M>(program: &M, pc: usize, instr: &Instruction) -> String
+        where M: Index<usize, Output=i64> + ?Sized
+    {
         let mut result = format!("{:-6}", instr.to_string());
         if instr.num_params > 0 {
             result += " ";
@@ -470,4 +559,84 @@ mod tests {
         assert_eq!(cpu.reset(&cmp_input_to_8).send_input(18).run().consume_output_all(), vec![1001]);
 
     }
+
+    #[test]
+    fn memory_dump() {
+        // includes addresses beyond the end of the program, once they've been written to
+        let cpu = CPU::new(&vec![1101,20,-6,9,99]).run().clone();
+        assert_eq!(cpu.memory().hexdump(4..10), "0x0004 ( 4): 99  0  0  0  0 14 | 63  0  0  0  0  e\n");
+    }
+
+    #[test]
+    fn snapshot() {
+        // a clone carries on from the same point, independently of the original
+        let program = vec![3,16,1005,16,7,99,0,1002,16,2,17,4,17,1105,1,0,0,0];
+        let mut cpu = CPU::new(&program);
+        cpu.send_input(5).run();
+        assert_eq!(cpu.consume_output(), Some(10));
+
+        let mut snapshot = cpu.clone();
+        snapshot.send_input(7).run();
+        assert_eq!(snapshot.consume_output(), Some(14));
+        assert_eq!(cpu.consume_output(), None);
+        cpu.send_input(0).run();
+        assert!(cpu.is_halted());
+        assert_eq!(snapshot.get_state(), CpuState::WaitIO);
+    }
+
+    #[test]
+    fn partial_runs() {
+        // outputs 1, 2 and 3, then waits for input
+        let mut cpu = CPU::new(&vec![104,1,104,2,104,3,3,0,99]);
+        cpu.run_until_output(2);
+        assert_eq!(cpu.get_state(), CpuState::Running);
+        assert_eq!(cpu.consume_output_all(), vec![1, 2]);
+        cpu.run_until_output(2);
+        assert_eq!(cpu.get_state(), CpuState::WaitIO);
+        assert_eq!(cpu.consume_output_all(), vec![3]);
+        cpu.send_input(0).run_until_output(2);
+        assert!(cpu.is_halted());
+
+        // jumps back to itself forever
+        let mut cpu = CPU::new(&vec![1105,1,0]);
+        cpu.run_limited(1000);
+        assert_eq!(cpu.get_state(), CpuState::Running);
+        let mut cpu = CPU::new(&vec![104,1,104,2,104,3,3,0,99]);
+        cpu.run_limited(3);
+        assert_eq!(cpu.get_state(), CpuState::Running);
+        assert_eq!(cpu.consume_output_all(), vec![1, 2, 3]);
+        cpu.run_limited(1000);
+        assert_eq!(cpu.get_state(), CpuState::WaitIO);
+    }
+
+    #[test]
+    fn channels() {
+        use std::sync::mpsc::channel;
+        use std::thread;
+
+        // doubles every input it gets, until it gets a 0
+        let program = vec![3,16,1005,16,7,99,0,1002,16,2,17,4,17,1105,1,0,0,0];
+
+        let (in_tx, in_rx) = channel();
+        let (out_tx, out_rx) = channel();
+        let handle = thread::spawn(move || {
+            let mut cpu = CPU::new(&program);
+            cpu.run_with_channels(&in_rx, &out_tx);
+            cpu.is_halted()
+        });
+        for x in 1..=3 {
+            in_tx.send(x).unwrap();
+            assert_eq!(out_rx.recv().unwrap(), 2*x);
+        }
+        in_tx.send(0).unwrap();
+        assert!(handle.join().unwrap());
+        assert!(out_rx.recv().is_err()); // sender went away with the thread
+
+        // closing the input while it's waiting makes it give up
+        let (in_tx, in_rx) = channel::<i64>();
+        let (out_tx, _out_rx) = channel();
+        let handle = thread::spawn(move || CPU::new(&vec![3,0,99]).run_with_channels(&in_rx, &out_tx).get_state());
+        drop(in_tx);
+        assert_eq!(handle.join().unwrap(), CpuState::WaitIO);
+    }
 }
@@ -1,10 +1,15 @@
 use clap::{App, Arg};
+use crate::dprint::*;
 
 mod util;
 mod intcode;
 mod path;
 mod tree;
 mod dprint;
+mod ocr;
+mod image;
+mod frames;
+mod grid;
 
 mod day1;
 mod day2;
@@ -41,37 +46,190 @@ fn main() {
                             .help("Problem number to solve")
                             .required(true)
                             .takes_value(true))
+                   .arg(Arg::with_name("parallel")
+                            .long("parallel")
+                            .help("Use the parallel implementation, for days that have one"))
+                   .arg(Arg::with_name("target")
+                            .long("target")
+                            .help("Target output value to search for (day 2)")
+                            .takes_value(true))
+                   .arg(Arg::with_name("nouns")
+                            .long("nouns")
+                            .help("Range of nouns to search, as lo..hi (day 2)")
+                            .takes_value(true))
+                   .arg(Arg::with_name("verbs")
+                            .long("verbs")
+                            .help("Range of verbs to search, as lo..hi (day 2)")
+                            .takes_value(true))
+                   .arg(Arg::with_name("brute-force")
+                            .long("brute-force")
+                            .help("Use the straightforward reference implementation, for days that have a faster one"))
+                   .arg(Arg::with_name("synthesize")
+                            .long("synthesize")
+                            .help("Search for a springscript that works instead of using the hand-written ones (day 21)"))
+                   .arg(Arg::with_name("hull")
+                            .long("hull")
+                            .help("Run the hand-written springscripts on this hull instead of on the droid, e.g. #####.#..######## (day 21)")
+                            .takes_value(true))
+                   .arg(Arg::with_name("json")
+                            .long("json")
+                            .help("Print structured results as JSON, for days that support it"))
+                   .arg(Arg::with_name("analyze")
+                            .long("analyze")
+                            .help("Print statistics about how the answer came about, for days that support it"))
+                   .arg(Arg::with_name("render")
+                            .long("render")
+                            .help("Draw a visualization to the terminal, for days that have one"))
+                   .arg(Arg::with_name("svg")
+                            .long("svg")
+                            .help("Write a visualization to this SVG file, for days that have one")
+                            .takes_value(true))
+                   .arg(Arg::with_name("system-id")
+                            .long("system-id")
+                            .help("System ID to run the diagnostics for (day 5)")
+                            .takes_value(true))
+                   .arg(Arg::with_name("trace")
+                            .long("trace")
+                            .help("Print every intcode instruction as it is executed, for days that support it"))
+                   .arg(Arg::with_name("dump")
+                            .long("dump")
+                            .help("Print this range of intcode memory as lo..hi once the program has run (day 5)")
+                            .takes_value(true))
+                   .arg(Arg::with_name("dot")
+                            .long("dot")
+                            .help("Write a graph of the puzzle to this Graphviz DOT file, for days that have one")
+                            .takes_value(true))
+                   .arg(Arg::with_name("wiring")
+                            .long("wiring")
+                            .help("Amplifier wiring as a list of connections like 0->1,1->2,2->0 (day 7)")
+                            .takes_value(true))
+                   .arg(Arg::with_name("width")
+                            .long("width")
+                            .help("Image width, instead of detecting it (day 8), or of the region drawn with --image (day 19)")
+                            .takes_value(true))
+                   .arg(Arg::with_name("height")
+                            .long("height")
+                            .help("Image height, instead of detecting it (day 8), or of the region drawn with --image (day 19)")
+                            .takes_value(true))
+                   .arg(Arg::with_name("steps")
+                            .long("steps")
+                            .help("Number of time steps to simulate (day 12, default 1000; day 24 part 2, default 200)")
+                            .takes_value(true))
+                   .arg(Arg::with_name("rules")
+                            .long("rules")
+                            .help("Rules for bugs in B/S notation, e.g. B12/S1 for the puzzle's (day 24)")
+                            .takes_value(true))
+                   .arg(Arg::with_name("max-level")
+                            .long("max-level")
+                            .help("Deepest recursion level to search before giving up (day 20, default the number of portals)")
+                            .takes_value(true))
+                   .arg(Arg::with_name("csv")
+                            .long("csv")
+                            .help("Write the simulation to this CSV file, one row per time step (day 12) or packet (day 23)")
+                            .takes_value(true))
+                   .arg(Arg::with_name("play")
+                            .long("play")
+                            .help("Play the game yourself in the terminal instead of letting the computer do it (day 13)"))
+                   .arg(Arg::with_name("strategy")
+                            .long("strategy")
+                            .help("How the computer moves the paddle: follow (the ball) or predict (where it lands) (day 13)")
+                            .takes_value(true))
+                   .arg(Arg::with_name("camera")
+                            .long("camera")
+                            .help("Read the scaffold map from this text file instead of the robot's camera (day 17)")
+                            .takes_value(true))
+                   .arg(Arg::with_name("interactive")
+                            .long("interactive")
+                            .help("Type in the robot's movement routines yourself, and watch it follow them on the live video feed (day 17; implies --render)"))
+                   .arg(Arg::with_name("phases")
+                            .long("phases")
+                            .help("Number of FFT phases to apply (day 16, default 100)")
+                            .takes_value(true))
+                   .arg(Arg::with_name("repeat")
+                            .long("repeat")
+                            .help("Number of times the input signal is repeated (day 16, default 1)")
+                            .takes_value(true))
+                   .arg(Arg::with_name("offset")
+                            .long("offset")
+                            .help("Position of the 8-digit message to read from the output signal (day 16, default 0)")
+                            .takes_value(true))
+                   .arg(Arg::with_name("image")
+                            .long("image")
+                            .help("Write the picture to this .png or .pbm file (.png or .ppm for day 19), for days that draw one")
+                            .takes_value(true))
+                   .arg(Arg::with_name("scale")
+                            .long("scale")
+                            .help("Size in pixels of each pixel or cell of what's written with --image or --gif (default 10, or 1 for day 19)")
+                            .takes_value(true))
+                   .arg(Arg::with_name("delay")
+                            .long("delay")
+                            .help("Milliseconds between the frames of --render and --gif animations (days 13, 15, 17 and 24)")
+                            .takes_value(true))
+                   .arg(Arg::with_name("gif")
+                            .long("gif")
+                            .help("Write an animation to this GIF file, for days that have one")
+                            .takes_value(true))
+                   .arg(Arg::with_name("verbose")
+                            .short("v")
+                            .long("verbose")
+                            .help("Also print the pictures that answers are read from, for days that draw them"))
+                   .arg(Arg::with_name("debug-log")
+                            .long("debug-log")
+                            .help("Write debug output to this file instead of stdout (see AOC_DEBUG)")
+                            .takes_value(true))
+                   .arg(Arg::with_name("debug-timestamps")
+                            .long("debug-timestamps")
+                            .help("Prefix debug output with the time since program start"))
+                   .arg(Arg::with_name("debug-scope-time")
+                            .long("debug-scope-time")
+                            .help("Prefix debug output with the time since the start of the innermost scope"))
+                   .arg(Arg::with_name("debug-json")
+                            .long("debug-json")
+                            .help("Emit debug output as JSON lines, one object per event"))
                     .get_matches();
 
     let day: i32 = args.value_of("day").unwrap().parse().unwrap();
+    if let Some(path) = args.value_of("debug-log") {
+        set_sink_file(path).unwrap_or_else(|err| panic!("cannot open debug log {}: {}", path, err));
+    }
+    set_timestamps(args.is_present("debug-timestamps"));
+    set_scope_elapsed(args.is_present("debug-scope-time"));
+    set_json(args.is_present("debug-json"));
+    #[cfg(feature = "tracing")]
+    install_tracing_subscriber();
 
     // would put this in a macro but concat_ident! is not yet stable :( --feb 2020
     match day {
-        1  => day1::main(),
-        2  => day2::main(),
-        3  => day3::main(),
-        4  => day4::main(),
-        5  => day5::main(),
-        6  => day6::main(),
-        7  => day7::main(),
-        8  => day8::main(),
+        1  => day1::main(&args),
+        2  => day2::main(&args),
+        3  => day3::main(&args),
+        4  => day4::main(&args),
+        5  => day5::main(&args),
+        6  => day6::main(&args),
+        7  => day7::main(&args),
+        8  => day8::main(&args),
         9  => day9::main(),
-        10 => day10::main(),
-        11 => day11::main(),
-        12 => day12::main(),
-        13 => day13::main(),
-        14 => day14::main(),
-        15 => day15::main(),
-        16 => day16::main(),
-        17 => day17::main(),
-        18 => day18::main(),
-        19 => day19::main(),
-        20 => day20::main(),
-        21 => day21::main(),
-        22 => day22::main(),
-        23 => day23::main(),
-        24 => day24::main(),
-        25 => day25::main(),
+        10 => day10::main(&args),
+        11 => day11::main(&args),
+        12 => day12::main(&args),
+        13 => day13::main(&args),
+        14 => day14::main(&args),
+        15 => day15::main(&args),
+        16 => day16::main(&args),
+        17 => day17::main(&args),
+        18 => day18::main(&args),
+        19 => day19::main(&args),
+        20 => day20::main(&args),
+        21 => day21::main(&args),
+        22 => day22::main(&args),
+        23 => day23::main(&args),
+        24 => day24::main(&args),
+        25 => day25::main(&args),
         _  => panic!("invalid day number: {}", day),
     };
+
+    if let Some(report) = timing_report() {
+        dinfo!("{}", report);
+    }
+    reset_sink();
 }
@@ -0,0 +1,135 @@
+// vim: set ai et ts=4 sts=4 sw=4:
+// reading the letters that some puzzles draw as their answer, in the font AoC uses for them. letters are 6 pixels
+// high and 4 wide (except for Y, which is 5 wide), each followed by a column of blank space.
+use crate::image::{Layer, WHITE};
+
+pub const GLYPH_HEIGHT: usize = 6;
+pub const GLYPH_CELL_WIDTH: usize = 5; // including the blank space after it
+
+const GLYPHS: [(char, [&str; GLYPH_HEIGHT]); 18] = [
+    ('A', [".##..", "#..#.", "#..#.", "####.", "#..#.", "#..#."]),
+    ('B', ["###..", "#..#.", "###..", "#..#.", "#..#.", "###.."]),
+    ('C', [".##..", "#..#.", "#....", "#....", "#..#.", ".##.."]),
+    ('E', ["####.", "#....", "###..", "#....", "#....", "####."]),
+    ('F', ["####.", "#....", "###..", "#....", "#....", "#...."]),
+    ('G', [".##..", "#..#.", "#....", "#.##.", "#..#.", ".###."]),
+    ('H', ["#..#.", "#..#.", "####.", "#..#.", "#..#.", "#..#."]),
+    ('I', [".###.", "..#..", "..#..", "..#..", "..#..", ".###."]),
+    ('J', ["..##.", "...#.", "...#.", "...#.", "#..#.", ".##.."]),
+    ('K', ["#..#.", "#.#..", "##...", "#.#..", "#.#..", "#..#."]),
+    ('L', ["#....", "#....", "#....", "#....", "#....", "####."]),
+    ('O', [".##..", "#..#.", "#..#.", "#..#.", "#..#.", ".##.."]),
+    ('P', ["###..", "#..#.", "#..#.", "###..", "#....", "#...."]),
+    ('R', ["###..", "#..#.", "#..#.", "###..", "#.#..", "#..#."]),
+    ('S', [".###.", "#....", "#....", ".##..", "...#.", "###.."]),
+    ('U', ["#..#.", "#..#.", "#..#.", "#..#.", "#..#.", ".##.."]),
+    ('Y', ["#...#", "#...#", ".#.#.", "..#..", "..#..", "..#.."]),
+    ('Z', ["####.", "...#.", "..#..", ".#...", "#....", "####."]),
+];
+
+pub fn decode<F>(width: usize, height: usize, is_lit: F) -> Result<String, String>
+    where F: Fn(usize, usize) -> bool
+{
+    // reads the letters in a width x height image, given which pixels are lit. the letters don't need to start
+    // in the top left corner; any blank space around them is ignored. some letters (like I) have a blank first
+    // column though, so the first letter can start a few columns before the leftmost lit pixel (even outside of
+    // the image, if it's cropped tightly); each of those places is tried in turn, starting from the leftmost lit
+    // pixel.
+    let lit: Vec<(usize, usize)> = (0..height).flat_map(|y| (0..width).map(move |x| (x, y)))
+                                              .filter(|&(x, y)| is_lit(x, y))
+                                              .collect();
+    if lit.is_empty() {
+        return Ok(String::new());
+    }
+    let min_x = lit.iter().map(|&(x, _)| x).min().unwrap();
+    let max_x = lit.iter().map(|&(x, _)| x).max().unwrap();
+    let min_y = lit.iter().map(|&(_, y)| y).min().unwrap();
+    let max_y = lit.iter().map(|&(_, y)| y).max().unwrap();
+    if max_y - min_y + 1 != GLYPH_HEIGHT {
+        return Err(format!("expected letters {} pixels high, found {}", GLYPH_HEIGHT, max_y - min_y + 1));
+    }
+
+    let read_from = |start_x: i64| -> Result<String, String> {
+        let mut result = String::new();
+        for cell_x in (start_x..=max_x as i64).step_by(GLYPH_CELL_WIDTH) {
+            let cell_lit = |x: usize, y: usize| {
+                let x = cell_x + x as i64;
+                x >= 0 && (x as usize) < width && is_lit(x as usize, min_y + y)
+            };
+            let glyph = GLYPHS.iter().find(|(_, rows)| {
+                rows.iter().enumerate().all(|(y, row)| row.chars().enumerate().all(|(x, c)| (c == '#') == cell_lit(x, y)))
+            });
+            match glyph {
+                Some((letter, _)) => result.push(*letter),
+                None              => return Err(format!("unrecognized letter at x={}", cell_x)),
+            }
+        }
+        Ok(result)
+    };
+    let first_attempt = read_from(min_x as i64);
+    if first_attempt.is_ok() {
+        return first_attempt;
+    }
+    (1..GLYPH_CELL_WIDTH).map(|offset| read_from(min_x as i64 - offset as i64))
+                         .find(|r| r.is_ok())
+                         .unwrap_or(first_attempt)
+}
+
+pub fn decode_layer(layer: &Layer) -> Result<String, String> {
+    // same as decode, for an image layer with the letters drawn in white
+    decode(layer.width(), layer.height(), |x, y| layer[(x,y)] == WHITE)
+}
+
+#[allow(dead_code)]
+pub fn decode_art(art: &str, lit: char) -> Result<String, String> {
+    // same as decode, but for an image drawn as lines of text with the given character for lit pixels
+    let lines: Vec<Vec<char>> = art.lines().map(|line| line.chars().collect()).collect();
+    let width = lines.iter().map(|line| line.len()).max().unwrap_or(0);
+    decode(width, lines.len(), |x, y| lines[y].get(x) == Some(&lit))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn letters() {
+        // every letter should read back as itself, also when following another one
+        for (letter, rows) in GLYPHS.iter() {
+            let art: Vec<String> = (0..GLYPH_HEIGHT).map(|y| format!("{}{}", GLYPHS[0].1[y], rows[y])).collect();
+            assert_eq!(decode_art(&art.join("\n"), '#'), Ok(format!("A{}", letter)));
+        }
+    }
+
+    #[test]
+    fn puzzle_answers() {
+        // as drawn by day 8 and day 11, with some blank space around the letters
+        let day8 = "x    x  x  xx  xxx  x  x \n\
+                    x    x  x x  x x  x x  x \n\
+                    x    xxxx x    x  x xxxx \n\
+                    x    x  x x    xxx  x  x \n\
+                    x    x  x x  x x    x  x \n\
+                    xxxx x  x  xx  x    x  x ";
+        assert_eq!(decode_art(day8, 'x'), Ok("LHCPH".to_string()));
+
+        let day11 = "\n\
+                     \x20#  # #     ##  ####  ##  ####  ##  #  #   \n\
+                     \x20# #  #    #  #    # #  # #    #  # #  #   \n\
+                     \x20##   #    #      #  #  # ###  #    #  #   \n\
+                     \x20# #  #    #     #   #### #    # ## #  #   \n\
+                     \x20# #  #    #  # #    #  # #    #  # #  #   \n\
+                     \x20#  # ####  ##  #### #  # ####  ###  ##    \n";
+        assert_eq!(decode_art(day11, '#'), Ok("KLCZAEGU".to_string()));
+
+        // letters with a blank first column, also when there's no blank space before them
+        let starts_blank = [".###.####.", "..#.....#.", "..#....#..", "..#...#...", "..#..#....", ".###.####."].join("\n");
+        assert_eq!(decode_art(&starts_blank, '#'), Ok("IZ".to_string()));
+        let shifted: Vec<String> = starts_blank.lines().map(|line| format!("   {}", line)).collect();
+        assert_eq!(decode_art(&shifted.join("\n"), '#'), Ok("IZ".to_string()));
+        assert_eq!(decode_art(&starts_blank.lines().map(|line| &line[1..]).collect::<Vec<_>>().join("\n"), '#'), Ok("IZ".to_string()));
+
+        assert_eq!(decode_art("", '#'), Ok(String::new()));
+        assert!(decode_art("####\n#..#", '#').is_err());
+        assert!(decode_art(&["####."; 6].join("\n"), '#').is_err());
+    }
+}
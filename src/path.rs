@@ -101,6 +101,36 @@ pub fn astar<N,M,H,W>(map: &M,
     None
 }
 
+pub fn bfs<M,N,W>(map: &M,
+                  source: &N,
+                  is_walkable: W) -> (HashMap<N, usize>, HashMap<N,N>)
+    where N: Node,
+          M: Map<Node=N>,
+          W: Fn(&M, &N) -> bool, // is a given node on the map walkable?
+{
+    // number of steps to every node reachable from the source, ignoring step costs. nodes are visited in order of
+    // distance, so the first time one is reached is also the shortest way to it.
+    let mut dist      = HashMap::<N, usize>::new();
+    let mut came_from = HashMap::<N, N>::new();
+
+    let mut queue = VecDeque::<N>::new();
+    dist.insert(source.clone(), 0);
+    queue.push_back(source.clone());
+
+    while let Some(node) = queue.pop_front() {
+        for (nb, _) in map.neighbours(&node) {
+            if dist.contains_key(&nb) || !is_walkable(map, &nb) {
+                continue;
+            }
+            dist.insert(nb.clone(), dist[&node] + 1);
+            came_from.insert(nb.clone(), node.clone());
+            queue.push_back(nb);
+        }
+    }
+
+    (dist, came_from)
+}
+
 pub fn dijkstra<M,N,W>(map: &M,
                        source: &N,
                        is_walkable: W) -> (HashMap<N, M::Cost>, HashMap<N,N>)
@@ -120,7 +150,9 @@ pub fn dijkstra_to_target<M,N,W>(map: &M,
           W: Fn(&M, &N) -> bool, // is a given node on the map walkable?
 {
     let (dists, came_from) = dijkstra_impl(map, source, Some(target), is_walkable);
-    assert!(dists.contains_key(target));
+    if !dists.contains_key(target) {
+        return None;
+    }
     Some(Path {
         nodes: Path::<N,M>::reconstruct_from(target, &came_from),
         cost: dists[target],
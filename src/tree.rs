@@ -219,6 +219,38 @@ impl<T> Iterator for Children<T> {
     }
 }
 
+impl<T> NodeRef<T> {
+    pub fn to_dot<L, N, E>(&self, label: L, node_attrs: N, edge_attrs: E) -> String
+        where L: Fn(&T) -> String,
+              N: Fn(&NodeRef<T>) -> Option<String>,
+              E: Fn(&NodeRef<T>) -> Option<String>
+    {
+        // renders the tree rooted at this node as a Graphviz digraph. label gives the text for each node, and
+        // node_attrs and edge_attrs can give extra attributes (e.g. "color=red") for a node and for the edge from
+        // its parent to it, respectively.
+        let mut result = String::from("digraph {\n");
+        let mut next_id = 0;
+        let mut stack: Vec<(NodeRef<T>, Option<usize>)> = vec![(self.clone(), None)]; // (node, id of its parent)
+        while let Some((node, parent_id)) = stack.pop() {
+            let id = next_id;
+            next_id += 1;
+
+            let label = label(&node.borrow_data()).replace('\\', "\\\\").replace('"', "\\\"");
+            let extra = node_attrs(&node).map(|s| format!(", {}", s)).unwrap_or_default();
+            result.push_str(&format!("    n{} [label=\"{}\"{}];\n", id, label, extra));
+            if let Some(parent_id) = parent_id {
+                let extra = edge_attrs(&node).map(|s| format!(" [{}]", s)).unwrap_or_default();
+                result.push_str(&format!("    n{} -> n{}{};\n", parent_id, id, extra));
+            }
+
+            let children: Vec<NodeRef<T>> = node.children().collect();
+            stack.extend(children.into_iter().rev().map(|child| (child, Some(id)))); // visit in order
+        }
+        result.push_str("}\n");
+        result
+    }
+}
+
 impl<T: fmt::Display> fmt::Display for NodeRef<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
 
@@ -271,4 +303,26 @@ mod tests {
         assert_ne!(cloned_tree, node);
         assert_ne!(hash_of!(cloned_tree), hash_of!(node));
     }
+
+    #[test]
+    fn dot() {
+        let root = NodeRef::new(String::from("root"));
+        let a = NodeRef::new(String::from("a"));
+        let b = NodeRef::new(String::from("say \"b\""));
+        root.add_child(&a);
+        a.add_child(&b);
+
+        let red = |n: &NodeRef<String>| if *n == b { Some(String::from("color=red")) } else { None };
+        let dot = root.to_dot(|s| s.clone(), red, red);
+        assert_eq!(dot, concat!("digraph {\n",
+                                "    n0 [label=\"root\"];\n",
+                                "    n1 [label=\"a\"];\n",
+                                "    n0 -> n1;\n",
+                                "    n2 [label=\"say \\\"b\\\"\", color=red];\n",
+                                "    n1 -> n2 [color=red];\n",
+                                "}\n"));
+
+        // the subtree only; the edge to the node's parent is left out
+        assert!(!a.to_dot(|s| s.clone(), |_| None, |_| None).contains("root"));
+    }
 }
@@ -5,10 +5,12 @@ use num;
 use std::str;
 use std::fs::File;
 use std::cmp::{min,max,PartialEq,Ordering};
-use std::ops::Rem;
-use std::io::{BufReader, BufRead};
+use std::ops::{Rem, Add, Sub, Neg, AddAssign, SubAssign, Index, IndexMut, Range};
+use std::str::FromStr;
+use std::cell::OnceCell;
+use std::io::{self, BufReader, BufRead, Read};
 use std::vec::Vec;
-use std::fmt::{Debug, Display};
+use std::fmt::{self, Debug, Display};
 use std::f64::consts::PI;
 
 pub fn file_read_lines(filename: &str) -> Vec<String> {
@@ -21,6 +23,123 @@ pub fn file_read_i64s(filename: &str) -> Vec<i64> {
                              .map(|s| s.parse().unwrap())
                              .collect()
 }
+fn open_streaming<T>(filename: &str) -> (Option<BufReader<File>>, Option<io::Result<T>>) {
+    // opens a file for streaming; a failure to open it is reported as the first (and only) item of the stream
+    match File::open(filename) {
+        Ok(file) => (Some(BufReader::new(file)), None),
+        Err(e)   => (None, Some(Err(e))),
+    }
+}
+pub fn stream_lines(filename: &str) -> impl Iterator<Item=io::Result<String>> {
+    // like file_read_lines, but reads lines on demand instead of loading the whole file into memory
+    let (reader, open_error) = open_streaming(filename);
+    open_error.into_iter().chain(reader.into_iter().flat_map(|r| r.lines()))
+}
+pub fn stream_parsed<T>(filename: &str) -> impl Iterator<Item=Result<T, String>>
+    where T: FromStr,
+          T::Err: Debug,
+{
+    // parses each line of the file as it is read; errors carry the file name and (1-based) line number
+    let filename = filename.to_string();
+    stream_lines(&filename).enumerate().map(move |(idx, line)| {
+        let line = line.map_err(|e| format!("{}:{}: {}", filename, idx+1, e))?;
+        line.trim().parse::<T>().map_err(|e| format!("{}:{}: cannot parse '{}': {:?}", filename, idx+1, line, e))
+    })
+}
+pub fn stream_bytes(filename: &str) -> impl Iterator<Item=io::Result<u8>> {
+    let (reader, open_error) = open_streaming(filename);
+    open_error.into_iter().chain(reader.into_iter().flat_map(|r| r.bytes()))
+}
+pub fn stream_split(filename: &str, separator: u8) -> impl Iterator<Item=io::Result<String>> {
+    // streams separator-delimited tokens (e.g. the comma-separated moves of a single huge line). line breaks
+    // delimit tokens as well, so the last token on one line and the first on the next come out separately.
+    let (reader, open_error) = open_streaming(filename);
+    let tokens = reader.into_iter().flat_map(move |r| r.split(separator)).flat_map(|token| {
+        let tokens: Vec<io::Result<String>> = match token.map(String::from_utf8) {
+            Ok(Ok(token)) => token.trim_end().split('\n').map(|t| Ok(t.trim().to_string())).collect(),
+            Ok(Err(e))    => vec![Err(io::Error::new(io::ErrorKind::InvalidData, e))],
+            Err(e)        => vec![Err(e)],
+        };
+        tokens
+    });
+    open_error.into_iter().chain(tokens)
+}
+
+pub struct Input {
+    // a day's input, read once and shared between parts; parsed views are computed on first use
+    lines: Vec<String>,
+    digits: OnceCell<Vec<u8>>,
+}
+impl Input {
+    pub fn for_day(day: u32) -> Self {
+        Self::from_lines(file_read_lines(&format!("input/day{}.txt", day)))
+    }
+    pub fn from_lines(lines: Vec<String>) -> Self {
+        Self { lines, digits: OnceCell::new() }
+    }
+    pub fn from_string(s: &str) -> Self {
+        Self::from_lines(s.lines().map(String::from).collect())
+    }
+    pub fn lines(&self) -> &[String] {
+        &self.lines
+    }
+    pub fn line(&self) -> &str {
+        // for single-line inputs
+        &self.lines[0]
+    }
+    pub fn digits(&self) -> &[u8] {
+        // the single input line as a sequence of decimal digits
+        self.digits.get_or_init(|| parse_digits(self.line()))
+    }
+}
+pub fn parse_range<T>(s: &str) -> Result<Range<T>, String>
+    where T: FromStr,
+          T::Err: Display,
+{
+    // parses "lo..hi" (with hi exclusive)
+    let (lo, hi) = s.split_once("..").ok_or_else(|| format!("invalid range '{}'; expected lo..hi", s))?;
+    let lo = lo.trim().parse::<T>().map_err(|e| format!("invalid range '{}': {}", s, e))?;
+    let hi = hi.trim().parse::<T>().map_err(|e| format!("invalid range '{}': {}", s, e))?;
+    Ok(lo..hi)
+}
+pub fn parse_digits<T: From<u8>>(s: &str) -> Vec<T> {
+    s.trim().bytes().map(|b| {
+        assert!(b.is_ascii_digit(), "not a digit: '{}'", b as char);
+        T::from(b - b'0')
+    }).collect()
+}
+pub fn to_digits(mut n: u64) -> Vec<u8> {
+    // decimal digits of n, most significant first
+    let mut result = vec![(n % 10) as u8];
+    while n >= 10 {
+        n /= 10;
+        result.push((n % 10) as u8);
+    }
+    result.reverse();
+    result
+}
+pub fn from_digits<T>(digits: &[T]) -> u64
+    where T: num::ToPrimitive
+{
+    // inverse of to_digits; also accepts wider digit types (e.g. the output of a computation mod 10)
+    digits.iter().fold(0, |acc, d| {
+        let d = d.to_u64().filter(|&d| d < 10).expect("not a decimal digit");
+        acc*10 + d
+    })
+}
+pub fn digit_groups<T>(digits: &[T]) -> Vec<(T, usize)>
+    where T: PartialEq + Copy
+{
+    // runs of consecutive equal digits, as (digit, run length) pairs
+    let mut result: Vec<(T, usize)> = Vec::new();
+    for &d in digits {
+        match result.last_mut() {
+            Some((last, count)) if *last == d => *count += 1,
+            _ => result.push((d, 1)),
+        }
+    }
+    result
+}
 pub fn manhattan_distance(p1: (i32,i32), p2: (i32,i32)) -> u32 {
     ((p2.0 - p1.0).abs() + (p2.1 - p1.1).abs()) as u32
 }
@@ -32,11 +151,98 @@ pub fn mod_mult_inverse<T>(a: T, n: T) -> T
     assert_eq!(gcd_ex.gcd, T::one()); // otherwise the modular multiplicative inverse does not exist
     gcd_ex.x
 }
+pub fn mod_add(a: i128, b: i128, m: i128) -> i128 {
+    // computes a+b mod m without overflowing, for any positive m that fits in an i128
+    let (a, b) = (a.rem_euclid(m), b.rem_euclid(m));
+    if a >= m - b { a - (m - b) } else { a + b }
+}
+pub fn mod_mul(a: i128, b: i128, m: i128) -> i128 {
+    // computes a*b mod m without overflowing, for any positive m that fits in an i128. if the product fits
+    // it's computed directly, otherwise by doubling and adding (russian peasant multiplication) in u128,
+    // where the operands stay below m < 2^127 so that adding two of them can't overflow.
+    let (a, b) = (a.rem_euclid(m), b.rem_euclid(m));
+    if let Some(product) = a.checked_mul(b) {
+        return product % m;
+    }
+    let (mut a, mut b, m) = (a as u128, b as u128, m as u128);
+    let mut result = 0u128;
+    while b > 0 {
+        if b & 1 == 1 {
+            result = (result + a) % m;
+        }
+        a = (a + a) % m;
+        b >>= 1;
+    }
+    result as i128
+}
+#[cfg(test)]
+pub fn mod_pow(base: i128, exp: u64, m: i128) -> i128 {
+    // computes base^exp mod m by repeated squaring; only used to check the results of other ways of doing that
+    let mut result = 1 % m;
+    let mut square = base.rem_euclid(m);
+    let mut exp = exp;
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result = mod_mul(result, square, m);
+        }
+        square = mod_mul(square, square, m);
+        exp >>= 1;
+    }
+    result
+}
 pub fn gcd<T>(a: T, b: T) -> T
     where T: num::Integer
 {
     a.gcd(&b)
 }
+fn midpoint<T: num::Integer + Copy>(lo: T, hi: T, round_up: bool) -> T {
+    // (lo + hi)/2, rounded down or up, without overflowing when lo and hi are near the ends of T's range (hi - lo
+    // can overflow too, for signed types). halve both separately, then add back the halves of the remainders.
+    let two = T::one() + T::one();
+    let (lo_rem, hi_rem) = (lo.mod_floor(&two), hi.mod_floor(&two));
+    let rem_sum = lo_rem + hi_rem + if round_up { T::one() } else { T::zero() };
+    lo.div_floor(&two) + hi.div_floor(&two) + rem_sum.div_floor(&two)
+}
+pub fn binary_search_max<T, P>(lo: T, hi: T, mut predicate: P) -> Option<T>
+    where T: num::Integer + Copy,
+          P: FnMut(T) -> bool,
+{
+    // finds the largest x in [lo, hi] for which predicate(x) holds, assuming the predicate is monotone
+    // (i.e. true up to some point and false from there on). returns None if it doesn't hold for lo.
+    if lo > hi || !predicate(lo) {
+        return None;
+    }
+    let (mut lo, mut hi) = (lo, hi); // invariant: predicate(lo) holds, and it fails for everything beyond hi
+    while lo < hi {
+        let mid = midpoint(lo, hi, true); // round up so that lo always makes progress
+        if predicate(mid) {
+            lo = mid;
+        } else {
+            hi = mid - T::one();
+        }
+    }
+    Some(lo)
+}
+pub fn binary_search_min<T, P>(lo: T, hi: T, mut predicate: P) -> Option<T>
+    where T: num::Integer + Copy,
+          P: FnMut(T) -> bool,
+{
+    // finds the smallest x in [lo, hi] for which predicate(x) holds, assuming the predicate is monotone
+    // (i.e. false up to some point and true from there on). returns None if it doesn't hold for hi.
+    if lo > hi || !predicate(hi) {
+        return None;
+    }
+    let (mut lo, mut hi) = (lo, hi); // invariant: predicate(hi) holds, and it fails for everything before lo
+    while lo < hi {
+        let mid = midpoint(lo, hi, false);
+        if predicate(mid) {
+            hi = mid;
+        } else {
+            lo = mid + T::one();
+        }
+    }
+    Some(hi)
+}
 pub fn rad2deg(rad: f64) -> f64 {
     rad*180f64/PI
 }
@@ -174,6 +380,198 @@ pub fn ordered_permutations_r<T,O,C>(working_copy: &mut Vec<T>,
     }
 }
 
+pub const HEXDUMP_ROW_LEN: usize = 8;
+pub fn hexdump(values: &[i64], base_addr: usize) -> String {
+    // formats a memory region as rows of HEXDUMP_ROW_LEN values, each prefixed by the address of its first
+    // value in both hex and decimal, and followed by the same values in hex. values are right-aligned to a
+    // common width so that columns line up, also in a last row that's shorter than the others.
+    let hex = |v: i64| format!("{}{:x}", if v < 0 { "-" } else { "" }, v.unsigned_abs());
+    let width = values.iter().map(|v| v.to_string().len()).max().unwrap_or(1);
+    let hex_width = values.iter().map(|&v| hex(v).len()).max().unwrap_or(1);
+    let addr_width = format!("{}", base_addr + values.len()).len();
+    let mut result = String::new();
+    for (row_idx, row) in values.chunks(HEXDUMP_ROW_LEN).enumerate() {
+        let addr = base_addr + row_idx * HEXDUMP_ROW_LEN;
+        let cells: Vec<String> = row.iter().map(|v| format!("{:>1$}", v, width)).collect();
+        let hex_cells: Vec<String> = row.iter().map(|&v| format!("{:>1$}", hex(v), hex_width)).collect();
+        let dec_len = values.len().min(HEXDUMP_ROW_LEN) * (width + 1) - 1;
+        result.push_str(&format!("0x{:04x} ({:>4$}): {:<5$} | {}\n", addr, addr, cells.join(" "), hex_cells.join(" "), addr_width, dec_len));
+    }
+    result
+}
+
+pub struct PrefixSums<T> {
+    // sums[i] = sum of the first i values, so that any range sum is the difference of two entries
+    sums: Vec<T>,
+}
+impl<T> PrefixSums<T>
+    where T: Copy + Default + Add<Output=T> + Sub<Output=T>
+{
+    pub fn new(values: &[T]) -> Self {
+        let mut sums = Vec::with_capacity(values.len() + 1);
+        let mut acc = T::default();
+        sums.push(acc);
+        for &v in values {
+            acc = acc + v;
+            sums.push(acc);
+        }
+        Self { sums }
+    }
+    pub fn len(&self) -> usize {
+        self.sums.len() - 1
+    }
+    pub fn range_sum(&self, from: usize, to: usize) -> T {
+        // sum of values[from..to] (exclusive end)
+        self.sums[to] - self.sums[from]
+    }
+    pub fn prefix_sum(&self, to: usize) -> T {
+        // sum of values[..to]
+        self.sums[to]
+    }
+    pub fn suffix_sum(&self, from: usize) -> T {
+        // sum of values[from..]
+        self.range_sum(from, self.len())
+    }
+}
+
+pub struct SummedAreaTable<T> {
+    // table[y][x] = sum of all values in the rectangle [0..x) x [0..y), with an extra
+    // row and column of zeroes at the top and left to avoid edge cases in queries
+    w: usize,
+    h: usize,
+    table: Vec<T>,
+}
+impl<T> SummedAreaTable<T>
+    where T: Copy + Default + Add<Output=T> + Sub<Output=T>
+{
+    pub fn new(w: usize, h: usize, values: &[T]) -> Self { // values in row-major order
+        assert_eq!(values.len(), w*h);
+        let stride = w + 1;
+        let mut table = vec![T::default(); stride*(h + 1)];
+        for y in 0..h {
+            let mut row_sum = T::default();
+            for x in 0..w {
+                row_sum = row_sum + values[y*w + x];
+                table[(y+1)*stride + (x+1)] = table[y*stride + (x+1)] + row_sum;
+            }
+        }
+        Self { w, h, table }
+    }
+    pub fn width(&self) -> usize { self.w }
+    pub fn height(&self) -> usize { self.h }
+    pub fn area_sum(&self, x0: usize, y0: usize, x1: usize, y1: usize) -> T {
+        // sum of all values in the rectangle [x0..x1) x [y0..y1) (exclusive ends)
+        let stride = self.w + 1;
+        self.table[y1*stride + x1] + self.table[y0*stride + x0]
+            - self.table[y0*stride + x1] - self.table[y1*stride + x0]
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug, Default)]
+pub struct Vec3<T> {
+    pub x: T,
+    pub y: T,
+    pub z: T,
+}
+impl<T> Vec3<T> {
+    pub fn new(x: T, y: T, z: T) -> Self {
+        Self { x, y, z }
+    }
+}
+impl<T> Vec3<T>
+    where T: num::Signed + Copy
+{
+    pub fn signum(&self) -> Self {
+        // per-component signum, i.e. -1, 0 or 1 for each axis
+        Self::new(self.x.signum(), self.y.signum(), self.z.signum())
+    }
+    pub fn manhattan_norm(&self) -> T {
+        self.x.abs() + self.y.abs() + self.z.abs()
+    }
+}
+impl<T: Add<Output=T>> Add for Vec3<T> {
+    type Output = Self;
+    fn add(self, other: Self) -> Self::Output {
+        Self::new(self.x + other.x, self.y + other.y, self.z + other.z)
+    }
+}
+impl<T: Sub<Output=T>> Sub for Vec3<T> {
+    type Output = Self;
+    fn sub(self, other: Self) -> Self::Output {
+        Self::new(self.x - other.x, self.y - other.y, self.z - other.z)
+    }
+}
+impl<T: Neg<Output=T>> Neg for Vec3<T> {
+    type Output = Self;
+    fn neg(self) -> Self::Output {
+        Self::new(-self.x, -self.y, -self.z)
+    }
+}
+impl<T: AddAssign> AddAssign for Vec3<T> {
+    fn add_assign(&mut self, other: Self) {
+        self.x += other.x;
+        self.y += other.y;
+        self.z += other.z;
+    }
+}
+impl<T: SubAssign> SubAssign for Vec3<T> {
+    fn sub_assign(&mut self, other: Self) {
+        self.x -= other.x;
+        self.y -= other.y;
+        self.z -= other.z;
+    }
+}
+impl<T> Index<usize> for Vec3<T> {
+    type Output = T;
+    fn index(&self, axis: usize) -> &Self::Output { // 0 = x, 1 = y, 2 = z
+        match axis {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            _ => panic!("invalid axis: {}", axis),
+        }
+    }
+}
+impl<T> IndexMut<usize> for Vec3<T> {
+    fn index_mut(&mut self, axis: usize) -> &mut Self::Output {
+        match axis {
+            0 => &mut self.x,
+            1 => &mut self.y,
+            2 => &mut self.z,
+            _ => panic!("invalid axis: {}", axis),
+        }
+    }
+}
+impl<T> FromStr for Vec3<T>
+    where T: FromStr + Default
+{
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // parses strings of the form "<x=1, y=-2, z=3>"; axes that are not mentioned default to 0
+        let inner = s.trim().trim_start_matches('<').trim_end_matches('>');
+        let mut result = Self::new(T::default(), T::default(), T::default());
+        for coord_s in inner.split(',').map(|c| c.trim()) {
+            let parts: Vec<&str> = coord_s.split('=').collect();
+            if parts.len() != 2 {
+                return Err(format!("invalid coordinate '{}' in '{}'", coord_s, s));
+            }
+            let value: T = parts[1].trim().parse().map_err(|_| format!("invalid value '{}' in '{}'", parts[1], s))?;
+            match parts[0].trim() {
+                "x" => { result.x = value; }
+                "y" => { result.y = value; }
+                "z" => { result.z = value; }
+                axis => { return Err(format!("invalid axis '{}' in '{}'", axis, s)); }
+            }
+        }
+        Ok(result)
+    }
+}
+impl<T: Display> fmt::Display for Vec3<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<x={:3}, y={:3}, z={:3}>", self.x, self.y, self.z)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -193,4 +591,150 @@ mod tests {
         assert_eq!(longest_repeated_substring_no_overlap("L,R,U,D,8,L,2,L,R,D,U"), "L,R,");
     }
 
+    #[test]
+    fn modular_arithmetic() {
+        assert_eq!(mod_add(7, 8, 10), 5);
+        assert_eq!(mod_add(-7, 3, 10), 6);
+        assert_eq!(mod_add(i128::MAX - 1, i128::MAX - 2, i128::MAX), i128::MAX - 3);
+        assert_eq!(mod_mul(7, 8, 10), 6);
+        assert_eq!(mod_mul(-7, 8, 10), 4);
+        assert_eq!(mod_mul(i128::MAX - 1, i128::MAX - 1, i128::MAX), 1);
+        assert_eq!(mod_mul(i128::MAX - 1, 2, i128::MAX), i128::MAX - 2);
+        assert_eq!(mod_mul(1 << 100, 1 << 100, i128::MAX), 1 << 73); // 2^127 = 1 mod 2^127-1
+        assert_eq!(mod_pow(3, 0, 7), 1);
+        assert_eq!(mod_pow(3, 6, 7), 1); // fermat
+        assert_eq!(mod_pow(-2, 3, 7), 6);
+        assert_eq!(mod_pow(5, 10, 1), 0);
+        assert_eq!(mod_pow(2, 127, i128::MAX), 1);
+        let p = 119_315_717_514_047;
+        assert_eq!(mod_mul(mod_pow(12345, p as u64 - 2, p), 12345, p), 1);
+    }
+
+    #[test]
+    fn prefix_sums() {
+        let ps = PrefixSums::new(&[3, 1, 4, 1, 5, 9, 2, 6]);
+        assert_eq!(ps.len(), 8);
+        assert_eq!(ps.range_sum(0, 8), 31);
+        assert_eq!(ps.range_sum(2, 5), 10);
+        assert_eq!(ps.range_sum(4, 4), 0);
+        assert_eq!(ps.prefix_sum(3), 8);
+        assert_eq!(ps.suffix_sum(5), 17);
+
+        // 1 2 3
+        // 4 5 6
+        let sat = SummedAreaTable::new(3, 2, &[1, 2, 3, 4, 5, 6]);
+        assert_eq!(sat.area_sum(0, 0, 3, 2), 21);
+        assert_eq!(sat.area_sum(1, 0, 3, 2), 16);
+        assert_eq!(sat.area_sum(0, 1, 2, 2), 9);
+        assert_eq!(sat.area_sum(2, 1, 3, 2), 6);
+        assert_eq!(sat.area_sum(1, 1, 1, 2), 0);
+    }
+
+    #[test]
+    fn input_views() {
+        let input = Input::from_string("0123\n456");
+        assert_eq!(input.lines(), &["0123".to_string(), "456".to_string()]);
+        assert_eq!(input.line(), "0123");
+        assert_eq!(input.digits(), &[0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn streaming() {
+        let expected = file_read_i64s("input/day1.txt");
+        let streamed: Vec<i64> = stream_parsed("input/day1.txt").map(|r| r.unwrap()).collect();
+        assert_eq!(streamed, expected);
+        assert_eq!(stream_bytes("input/day1.txt").filter(|b| *b.as_ref().unwrap() == b'\n').count(), expected.len());
+
+        let first_line = &file_read_lines("input/day3.txt")[0];
+        let tokens: Vec<String> = stream_split("input/day3.txt", b',').map(|r| r.unwrap()).take(3).collect();
+        assert_eq!(tokens, first_line.split(',').take(3).map(String::from).collect::<Vec<_>>());
+        let num_tokens: usize = file_read_lines("input/day3.txt").iter().map(|l| l.split(',').count()).sum();
+        let tokens: Vec<String> = stream_split("input/day3.txt", b',').map(|r| r.unwrap()).collect();
+        assert_eq!(tokens.len(), num_tokens);
+        assert!(tokens.iter().all(|t| !t.contains('\n')));
+
+        // errors surface lazily, with positions
+        let mut parsed = stream_parsed::<i64>("input/day3.txt");
+        assert!(parsed.next().unwrap().unwrap_err().starts_with("input/day3.txt:1: cannot parse"));
+        assert!(stream_lines("input/does_not_exist.txt").next().unwrap().is_err());
+    }
+
+    #[test]
+    fn binary_search() {
+        assert_eq!(binary_search_max(0, 100, |x| x*x <= 50), Some(7));
+        assert_eq!(binary_search_max(0, 100, |_| true), Some(100));
+        assert_eq!(binary_search_max(0, 100, |x| x < 0), None);
+        assert_eq!(binary_search_max(5, 5, |x| x == 5), Some(5));
+        assert_eq!(binary_search_max(0u64, 1_000_000_000_000, |x| x <= 123_456_789), Some(123_456_789));
+        assert_eq!(binary_search_max(-10i32, 10, |x| x < -3), Some(-4));
+        assert_eq!(binary_search_max(0u8, u8::MAX, |_| true), Some(u8::MAX));
+        assert_eq!(binary_search_max(0u64, u64::MAX, |x| x < u64::MAX), Some(u64::MAX - 1));
+        assert_eq!(binary_search_max(0, 1, |x| x == 0), Some(0));
+        assert_eq!(binary_search_max(i64::MIN, i64::MAX, |_| true), Some(i64::MAX));
+        assert_eq!(binary_search_max(i64::MIN, i64::MAX, |x| x < -5), Some(-6));
+        assert_eq!(binary_search_max(i8::MIN, i8::MAX, |x| x == i8::MIN), Some(i8::MIN));
+        assert_eq!(binary_search_min(i64::MIN, i64::MAX, |_| true), Some(i64::MIN));
+        assert_eq!(binary_search_min(i64::MIN, i64::MAX, |x| x > 5), Some(6));
+        assert_eq!(binary_search_min(i8::MIN, i8::MAX, |x| x == i8::MAX), Some(i8::MAX));
+        assert_eq!(binary_search_min(0u64, u64::MAX, |x| x >= u64::MAX - 1), Some(u64::MAX - 1));
+        for lo in -4i8..4 {
+            for hi in lo..6 {
+                assert_eq!(midpoint(lo, hi, false), (lo + hi).div_euclid(2), "{} {}", lo, hi);
+                assert_eq!(midpoint(lo, hi, true), (lo + hi + 1).div_euclid(2), "{} {}", lo, hi);
+            }
+        }
+
+        assert_eq!(binary_search_min(0, 100, |x| x*x >= 50), Some(8));
+        assert_eq!(binary_search_min(0, 100, |_| true), Some(0));
+        assert_eq!(binary_search_min(0, 100, |x| x > 100), None);
+        assert_eq!(binary_search_min(-10i32, 10, |x| x >= -3), Some(-3));
+    }
+
+    #[test]
+    fn hexdump_rows() {
+        assert_eq!(hexdump(&[], 0), "");
+        assert_eq!(hexdump(&[1, 2, 3], 0), "0x0000 (0): 1 2 3 | 1 2 3\n");
+        let values: Vec<i64> = vec![1, -20, 300, 4, 5, 6, 7, 8, 99];
+        assert_eq!(hexdump(&values, 16),
+                   concat!("0x0010 (16):   1 -20 300   4   5   6   7   8 |   1 -14 12c   4   5   6   7   8\n",
+                           "0x0018 (24):  99                             |  63\n"));
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(parse_range::<i64>("0..100"), Ok(0..100));
+        assert_eq!(parse_range::<i64>("-5..5"), Ok(-5..5));
+        assert_eq!(parse_range::<usize>(" 3 .. 7 "), Ok(3..7));
+        assert!(parse_range::<usize>("-5..5").is_err());
+        assert!(parse_range::<i64>("0-100").is_err());
+        assert!(parse_range::<i64>("a..b").is_err());
+    }
+
+    #[test]
+    fn digits() {
+        assert_eq!(to_digits(0), vec![0]);
+        assert_eq!(to_digits(7), vec![7]);
+        assert_eq!(to_digits(1230), vec![1, 2, 3, 0]);
+        assert_eq!(from_digits(&to_digits(9876543210)), 9876543210);
+        assert_eq!(from_digits::<u8>(&[]), 0);
+        assert_eq!(from_digits(&[0i32, 4, 2]), 42);
+        assert_eq!(parse_digits::<u32>(" 0123\n"), vec![0, 1, 2, 3]);
+        assert_eq!(digit_groups(&[1, 1, 2, 3, 3, 3, 1]), vec![(1, 2), (2, 1), (3, 3), (1, 1)]);
+        assert_eq!(digit_groups::<u8>(&[]), vec![]);
+    }
+
+    #[test]
+    fn vec3() {
+        let a: Vec3<i64> = "<x=-1, y=0, z=2>".parse().unwrap();
+        let b: Vec3<i64> = "<x=2, y=-10, z=-7>".parse().unwrap();
+        assert_eq!(a, Vec3::new(-1, 0, 2));
+        assert_eq!(a + b, Vec3::new(1, -10, -5));
+        assert_eq!(a - b, Vec3::new(-3, 10, 9));
+        assert_eq!(-a, Vec3::new(1, 0, -2));
+        assert_eq!((b - a).signum(), Vec3::new(1, -1, -1));
+        assert_eq!(b.manhattan_norm(), 19);
+        assert_eq!(b[1], -10);
+        assert!("<x=1, w=2>".parse::<Vec3<i64>>().is_err());
+    }
+
 }